@@ -603,6 +603,27 @@ fn vec_quadratic_peak_pos(x: &[f32], pos: usize) -> f32 {
     pos as f32 + 0.5 * (s0 - s2) / (s0 - 2.0 * s1 + s2)
 }
 
+/// Quadratic interpolation for peak magnitude
+/// Transcribed from aubio/src/mathutils.c (fvec_quadratic_peak_mag)
+fn vec_quadratic_peak_mag(x: &[f32], pos: f32) -> f32 {
+    if pos >= x.len() as f32 || pos < 0.0 {
+        return 0.0;
+    }
+    // C casts (pos - .5) to uint_t, which truncates towards zero
+    let index = (pos - 0.5) as usize + 1;
+    if index as f32 == pos {
+        return x[index];
+    }
+    // C doesn't have bounds checking here - we need to add it to avoid panics
+    if index + 1 >= x.len() {
+        return x[x.len() - 1];
+    }
+    let x0 = x[index - 1];
+    let x1 = x[index];
+    let x2 = x[index + 1];
+    x1 - 0.25 * (x0 - x2) * (pos - index as f32)
+}
+
 /// Biquad IIR filter
 /// Transcription of aubio biquad filter from aubio/src/temporal/biquad.c
 struct Biquad {
@@ -1237,6 +1258,18 @@ impl BeatTracking {
             0.0
         }
     }
+
+    /// Get confidence of the current BPM estimate
+    /// Transcribed from aubio/src/tempo/beattracking.c (aubio_beattracking_get_confidence)
+    fn get_confidence(&self) -> f32 {
+        if self.gp != 0.0 {
+            let acf_sum: f32 = self.acfout.iter().sum();
+            if acf_sum != 0.0 {
+                return vec_quadratic_peak_mag(&self.acfout, self.gp) / acf_sum;
+            }
+        }
+        0.0
+    }
 }
 
 /*
@@ -1255,7 +1288,7 @@ fn next_power_of_two(a: usize) -> usize {
 
 /// Compute mean energy (sum of squares / length)
 /// Transcribed from aubio/src/mathutils.c:328-340
-fn level_lin(data: &[f32]) -> f32 {
+pub(crate) fn level_lin(data: &[f32]) -> f32 {
     let mut energy = 0.0;
     for &x in data {
         energy += x * x;
@@ -1271,7 +1304,7 @@ fn db_spl(data: &[f32]) -> f32 {
 
 /// Silence detection (returns true if dB SPL < threshold)
 /// Transcribed from aubio/src/mathutils.c:615-618
-fn is_silence(data: &[f32], threshold: f32) -> bool {
+pub(crate) fn is_silence(data: &[f32], threshold: f32) -> bool {
     db_spl(data) < threshold
 }
 
@@ -1447,4 +1480,29 @@ impl Tempo {
     pub fn get_bpm(&self) -> f32 {
         self.bt.get_bpm()
     }
+
+    /// Get the position of the last beat, in samples
+    /// Transcribed from aubio/src/tempo/tempo.c (aubio_tempo_get_last)
+    pub fn get_last(&self) -> usize {
+        self.last_beat
+    }
+
+    /// Get confidence of the current BPM estimate
+    /// Transcribed from aubio/src/tempo/tempo.c (aubio_tempo_get_confidence)
+    pub fn get_confidence(&self) -> f32 {
+        self.bt.get_confidence()
+    }
+
+    /// Get the onset detection function value (spectral flux) for the
+    /// last processed hop.
+    pub fn get_onset_strength(&self) -> f32 {
+        self.of
+    }
+
+    /// Get the peak picker output for the last processed hop, i.e. the
+    /// position of the onset found in the peak picking window if any,
+    /// 0.0 otherwise.
+    pub fn get_onset(&self) -> f32 {
+        self.onset
+    }
 }
@@ -5,9 +5,9 @@
 //! (minor / major).
 extern crate noisy_float;
 
-use crate::utils::stft;
 use crate::utils::{hz_to_octs_inplace, Normalize};
-use crate::{BlissError, BlissResult};
+use crate::utils::{stft, StftStream};
+use crate::{BlissError, BlissResult, Key, Mode};
 use ndarray::{arr1, arr2, concatenate, s, Array, Array1, Array2, Axis, Zip};
 use ndarray_stats::interpolate::Midpoint;
 use ndarray_stats::QuantileExt;
@@ -21,13 +21,39 @@ use noisy_float::prelude::*;
  *
  * Contrary to the other descriptors that can be used with streaming
  * without consequences, this one performs better if the full song is used at
- * once.
+ * once, since the tuning is estimated over the whole song.
+ *
+ * A streaming mode is available through `ChromaDesc::new_streaming`, which
+ * estimates the tuning on the first frames of the song only, and then keeps
+ * running sums of the interval features instead of the whole chromagram.
  */
 #[doc(hidden)]
 pub struct ChromaDesc {
     sample_rate: u32,
     n_chroma: u32,
     values_chroma: Array2<f64>,
+    stream: Option<ChromaStream>,
+}
+
+/**
+ * State of a `ChromaDesc` used in streaming mode.
+ *
+ * The STFT frames are computed as soon as enough samples are available,
+ * and kept until `tuning_frames` of them have been gathered. The tuning
+ * is then estimated once and for all on these frames, and every frame
+ * is turned into interval features right away, which are summed.
+ */
+struct ChromaStream {
+    tuning_frames: usize,
+    stft: StftStream,
+    // Frames waiting for the tuning to be estimated.
+    pending_frames: Vec<Array1<f64>>,
+    chroma_filter: Option<Array2<f64>>,
+    interval_features_sum: Array1<f64>,
+    // Sum of the chroma vectors of the frames, used to estimate the key.
+    chroma_sum: Array1<f64>,
+    number_interval_frames: usize,
+    finished: bool,
 }
 
 impl Normalize for ChromaDesc {
@@ -56,11 +82,43 @@ impl ChromaDesc {
     /// We are using atan2 to keep the ratio bounded.
     pub const MAX_TRIAD_INTERVAL_RATIO: f32 = std::f32::consts::FRAC_PI_2;
 
+    pub const HOP_SIZE: usize = 2205;
+    /// Default number of frames used to estimate the tuning in streaming
+    /// mode, i.e. roughly the first 30 seconds of the song.
+    pub const STREAMING_TUNING_FRAMES: usize = 300;
+
     pub fn new(sample_rate: u32, n_chroma: u32) -> ChromaDesc {
         ChromaDesc {
             sample_rate,
             n_chroma,
             values_chroma: Array2::zeros((n_chroma as usize, 0)),
+            stream: None,
+        }
+    }
+
+    /**
+     * Create a chroma descriptor in streaming mode, to which successive
+     * chunks of a song can be fed using `do_`, using a bounded amount of memory.
+     *
+     * The tuning is estimated on the first `tuning_frames` STFT frames
+     * (see `ChromaDesc::STREAMING_TUNING_FRAMES`), instead of the whole song.
+     * If the song is shorter than that, the results are the same as the
+     * ones obtained by feeding the whole song at once to a non-streaming
+     * descriptor.
+     */
+    pub fn new_streaming(sample_rate: u32, n_chroma: u32, tuning_frames: usize) -> ChromaDesc {
+        ChromaDesc {
+            stream: Some(ChromaStream {
+                tuning_frames: tuning_frames.max(1),
+                stft: StftStream::new(ChromaDesc::WINDOW_SIZE, ChromaDesc::HOP_SIZE),
+                pending_frames: Vec::new(),
+                chroma_filter: None,
+                interval_features_sum: Array1::zeros(10),
+                chroma_sum: Array1::zeros(n_chroma as usize),
+                number_interval_frames: 0,
+                finished: false,
+            }),
+            ..ChromaDesc::new(sample_rate, n_chroma)
         }
     }
 
@@ -69,9 +127,15 @@ impl ChromaDesc {
      *
      * Passing a full song here once instead of streaming smaller parts of the
      * song will greatly improve accuracy.
+     *
+     * In streaming mode, `signal` is treated as the chunk following the
+     * previous ones, and can have any size.
      */
     pub fn do_(&mut self, signal: &[f32]) -> BlissResult<()> {
-        let mut stft = stft(signal, ChromaDesc::WINDOW_SIZE, 2205);
+        if self.stream.is_some() {
+            return self.do_streaming(signal);
+        }
+        let mut stft = stft(signal, ChromaDesc::WINDOW_SIZE, ChromaDesc::HOP_SIZE);
         let tuning = estimate_tuning(self.sample_rate, &stft, ChromaDesc::WINDOW_SIZE, 0.01, 12)?;
         let chroma = chroma_stft(
             self.sample_rate,
@@ -95,7 +159,7 @@ impl ChromaDesc {
      * Music").
      */
     pub fn get_values(&mut self) -> BlissResult<Vec<f32>> {
-        let mut raw_features = chroma_interval_features(&self.values_chroma)?;
+        let mut raw_features = self.interval_features()?;
         let (mut interval_class, mut interval_class_mode) =
             raw_features.view_mut().split_at(Axis(0), 6);
         // Compute those two norms separately because the values for the IC1-6 and IC7-10 don't
@@ -126,15 +190,190 @@ impl ChromaDesc {
     }
 
     pub(crate) fn get_values_version_1(&mut self) -> BlissResult<Vec<f32>> {
-        Ok(chroma_interval_features(&self.values_chroma)?
+        Ok(self
+            .interval_features()?
             .mapv(|x| 2. * (x as f32 - 0.) / (0.12 - 0.) - 1.)
             .to_vec())
     }
+
+    /**
+     * Estimate the song's key, by correlating its average chroma vector
+     * with the Krumhansl-Kessler key profiles.
+     *
+     * Returns `None` if the key can't be estimated, e.g. for silent songs.
+     */
+    pub fn get_key(&mut self) -> BlissResult<Option<Key>> {
+        let mean_chroma = match self.stream {
+            None => self
+                .values_chroma
+                .mean_axis(Axis(1))
+                .ok_or(BlissError::AnalysisError(String::from(EMPTY_CHROMA_ERROR)))?,
+            Some(_) => {
+                self.finish_streaming()?;
+                let stream = self.stream.as_ref().unwrap();
+                if stream.number_interval_frames == 0 {
+                    return Err(BlissError::AnalysisError(String::from(EMPTY_CHROMA_ERROR)));
+                }
+                &stream.chroma_sum / stream.number_interval_frames as f64
+            }
+        };
+        Ok(estimate_key(&mean_chroma))
+    }
+
+    fn interval_features(&mut self) -> BlissResult<Array1<f64>> {
+        if self.stream.is_none() {
+            return chroma_interval_features(&self.values_chroma);
+        }
+        self.finish_streaming()?;
+        let stream = self.stream.as_ref().unwrap();
+        if stream.number_interval_frames == 0 {
+            return Err(BlissError::AnalysisError(String::from(EMPTY_CHROMA_ERROR)));
+        }
+        Ok(&stream.interval_features_sum / stream.number_interval_frames as f64)
+    }
+
+    fn do_streaming(&mut self, chunk: &[f32]) -> BlissResult<()> {
+        let stream = self.stream.as_mut().unwrap();
+        if stream.finished {
+            return Err(BlissError::AnalysisError(String::from(
+                "in chroma: tried to stream samples after the values were computed.",
+            )));
+        }
+        let frames = stream.stft.push(chunk);
+        self.process_frames(frames)
+    }
+
+    fn finish_streaming(&mut self) -> BlissResult<()> {
+        let stream = self.stream.as_mut().unwrap();
+        if stream.finished {
+            return Ok(());
+        }
+        stream.finished = true;
+        let frames = stream.stft.finish().ok_or_else(|| {
+            BlissError::AnalysisError(String::from(
+                "in chroma: the signal is too short to be analyzed.",
+            ))
+        })?;
+        self.process_frames(frames)?;
+
+        let stream = self.stream.as_mut().unwrap();
+        if stream.chroma_filter.is_none() && !stream.pending_frames.is_empty() {
+            let pending_frames = std::mem::take(&mut stream.pending_frames);
+            self.estimate_streaming_tuning(pending_frames)?;
+        }
+        Ok(())
+    }
+
+    fn process_frames(&mut self, frames: Vec<Array1<f64>>) -> BlissResult<()> {
+        let stream = self.stream.as_mut().unwrap();
+        if stream.chroma_filter.is_some() {
+            return self.add_interval_features(frames);
+        }
+        stream.pending_frames.extend(frames);
+        if stream.pending_frames.len() >= stream.tuning_frames {
+            let pending_frames = std::mem::take(&mut stream.pending_frames);
+            self.estimate_streaming_tuning(pending_frames)?;
+        }
+        Ok(())
+    }
+
+    fn estimate_streaming_tuning(&mut self, frames: Vec<Array1<f64>>) -> BlissResult<()> {
+        let spectrum = frames_to_spectrum(&frames)?;
+        let tuning = estimate_tuning(
+            self.sample_rate,
+            &spectrum,
+            ChromaDesc::WINDOW_SIZE,
+            0.01,
+            12,
+        )?;
+        let filter = chroma_filter(
+            self.sample_rate,
+            ChromaDesc::WINDOW_SIZE,
+            self.n_chroma,
+            tuning,
+        )?;
+        self.stream.as_mut().unwrap().chroma_filter = Some(filter);
+        self.add_interval_features(frames)
+    }
+
+    fn add_interval_features(&mut self, frames: Vec<Array1<f64>>) -> BlissResult<()> {
+        if frames.is_empty() {
+            return Ok(());
+        }
+        let stream = self.stream.as_mut().unwrap();
+        let mut spectrum = frames_to_spectrum(&frames)?;
+        let chroma = apply_chroma_filter(stream.chroma_filter.as_ref().unwrap(), &mut spectrum);
+        stream.chroma_sum += &chroma.sum_axis(Axis(1));
+        stream.interval_features_sum += &interval_feature_matrix(&chroma).sum_axis(Axis(1));
+        stream.number_interval_frames += frames.len();
+        Ok(())
+    }
+}
+
+const EMPTY_CHROMA_ERROR: &str = "Tried to run the chroma descriptor on an empty array. Run `.do_()` on at least a sample before trying to get values.";
+
+// Stack STFT frames into a spectrum of the same shape as `stft`'s.
+pub(crate) fn frames_to_spectrum(frames: &[Array1<f64>]) -> BlissResult<Array2<f64>> {
+    let views = frames.iter().map(|x| x.view()).collect::<Vec<_>>();
+    Ok(ndarray::stack(Axis(0), &views)
+        .map_err(|e| BlissError::AnalysisError(format!("in chroma: {e}")))?
+        .permuted_axes((1, 0)))
+}
+
+// Krumhansl-Kessler key profiles, starting from the tonic.
+const MAJOR_PROFILE: [f64; 12] = [
+    6.35, 2.23, 3.48, 2.33, 4.38, 4.09, 2.52, 5.19, 2.39, 3.66, 2.29, 2.88,
+];
+const MINOR_PROFILE: [f64; 12] = [
+    6.33, 2.68, 3.52, 5.38, 2.60, 3.53, 2.54, 4.75, 3.98, 2.69, 3.34, 3.17,
+];
+
+// Pearson correlation coefficient between two vectors.
+fn correlation(x: &Array1<f64>, y: &Array1<f64>) -> f64 {
+    let x = x - x.mean().unwrap_or_default();
+    let y = y - y.mean().unwrap_or_default();
+    x.dot(&y) / (x.dot(&x) * y.dot(&y)).sqrt()
+}
+
+// Find the key whose profile correlates the best with `mean_chroma`,
+// whose first bin is C.
+fn estimate_key(mean_chroma: &Array1<f64>) -> Option<Key> {
+    if mean_chroma.len() != 12 {
+        return None;
+    }
+    let mut best_key: Option<Key> = None;
+    for (mode, profile) in [(Mode::Major, MAJOR_PROFILE), (Mode::Minor, MINOR_PROFILE)] {
+        for tonic in 0..12 {
+            let mut rotated = profile.to_vec();
+            rotated.rotate_right(tonic);
+            let confidence = correlation(mean_chroma, &arr1(&rotated)) as f32;
+            // NaN if the chroma is flat, e.g. for silent songs.
+            if confidence.is_nan() {
+                return None;
+            }
+            if best_key.is_none_or(|key| confidence > key.confidence) {
+                best_key = Some(Key {
+                    tonic: tonic as u8,
+                    mode,
+                    confidence,
+                });
+            }
+        }
+    }
+    best_key
 }
 
 // Functions below are Rust versions of python notebooks by AudioLabs Erlang
 // (https://www.audiolabs-erlangen.de/resources/MIR/FMP/C0/C0.html)
 fn chroma_interval_features(chroma: &Array2<f64>) -> BlissResult<Array1<f64>> {
+    interval_feature_matrix(chroma)
+        .mean_axis(Axis(1))
+        .ok_or(BlissError::AnalysisError(String::from(EMPTY_CHROMA_ERROR)))
+}
+
+// Interval features of each frame of the chromagram, before they are
+// averaged by `chroma_interval_features`.
+fn interval_feature_matrix(chroma: &Array2<f64>) -> Array2<f64> {
     let chroma = normalize_feature_sequence(&chroma.mapv(|x| (x * 15.).exp()));
     let templates = arr2(&[
         [1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
@@ -150,8 +389,7 @@ fn chroma_interval_features(chroma: &Array2<f64>) -> BlissResult<Array1<f64>> {
         [0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         [0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
     ]);
-    let interval_feature_matrix = extract_interval_features(&chroma, &templates);
-    interval_feature_matrix.mean_axis(Axis(1)).ok_or(BlissError::AnalysisError(String::from("Tried to run the chroma descriptor on an empty array. Run `.do_()` on at least a sample before trying to get values.")))
+    extract_interval_features(&chroma, &templates)
 }
 
 fn extract_interval_features(chroma: &Array2<f64>, templates: &Array2<i32>) -> Array2<f64> {
@@ -194,7 +432,7 @@ fn normalize_feature_sequence(feature: &Array2<f64>) -> Array2<f64> {
 // Could be precomputed, but it takes very little time to compute it
 // on the fly compared to the rest of the functions, and we'd lose the
 // possibility to tweak parameters.
-fn chroma_filter(
+pub(crate) fn chroma_filter(
     sample_rate: u32,
     n_fft: usize,
     n_chroma: u32,
@@ -397,10 +635,16 @@ fn chroma_stft(
     n_chroma: u32,
     tuning: f64,
 ) -> Result<Array2<f64>, BlissError> {
-    spectrum.mapv_inplace(|x| x * x);
-    let mut raw_chroma = chroma_filter(sample_rate, n_fft, n_chroma, tuning)?;
+    let raw_chroma = chroma_filter(sample_rate, n_fft, n_chroma, tuning)?;
+    Ok(apply_chroma_filter(&raw_chroma, spectrum))
+}
 
-    raw_chroma = raw_chroma.dot(spectrum);
+pub(crate) fn apply_chroma_filter(
+    chroma_filter: &Array2<f64>,
+    spectrum: &mut Array2<f64>,
+) -> Array2<f64> {
+    spectrum.mapv_inplace(|x| x * x);
+    let mut raw_chroma = chroma_filter.dot(spectrum);
     for mut row in raw_chroma.columns_mut() {
         let mut sum = row.mapv(|x| x.abs()).sum();
         if sum < f64::MIN_POSITIVE {
@@ -408,7 +652,7 @@ fn chroma_stft(
         }
         row /= sum;
     }
-    Ok(raw_chroma)
+    raw_chroma
 }
 
 #[cfg(feature = "bench")]
@@ -492,7 +736,7 @@ mod test {
     use pretty_assertions::assert_eq;
     use std::fs::File;
     #[cfg(feature = "ffmpeg")]
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn test_chroma_interval_features() {
@@ -864,4 +1108,166 @@ mod test {
             }
         }
     }
+
+    fn max_difference(expected: &[f32], actual: &[f32]) -> f32 {
+        assert_eq!(expected.len(), actual.len());
+        expected
+            .iter()
+            .zip(actual)
+            .map(|(x, y)| (x - y).abs())
+            .fold(0., f32::max)
+    }
+
+    #[test]
+    fn test_chroma_streaming_synthetic() {
+        // A C major chord with a bit of vibrato, so the tuning is not obvious.
+        let signal = (0..22050 * 4)
+            .map(|i| {
+                let t = i as f32 / 22050.;
+                let detune = 1. + 0.01 * (2. * std::f32::consts::PI * 0.5 * t).sin();
+                [261.63, 329.63, 392.]
+                    .iter()
+                    .map(|f| (2. * std::f32::consts::PI * f * detune * t).sin())
+                    .sum::<f32>()
+            })
+            .collect::<Vec<f32>>();
+        let mut chroma_desc = ChromaDesc::new(22050, 12);
+        chroma_desc.do_(&signal).unwrap();
+        let expected_values = chroma_desc.get_values().unwrap();
+
+        for chunk_size in [1000, 8192, signal.len()] {
+            let mut streaming_desc =
+                ChromaDesc::new_streaming(22050, 12, ChromaDesc::STREAMING_TUNING_FRAMES);
+            for chunk in signal.chunks(chunk_size) {
+                streaming_desc.do_(chunk).unwrap();
+            }
+            let values = streaming_desc.get_values().unwrap();
+            assert!(1e-6 > max_difference(&expected_values, &values));
+            // Getting the values twice doesn't change anything.
+            assert_eq!(values, streaming_desc.get_values().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_get_key() {
+        // One second per chord, since a lone triad is ambiguous, e.g. C-E-G
+        // correlates about as well with E minor as with C major.
+        let progression = |chords: &[[f32; 3]]| {
+            chords
+                .iter()
+                .flat_map(|chord| {
+                    (0..22050).map(move |i| {
+                        let t = i as f32 / 22050.;
+                        chord
+                            .iter()
+                            .map(|f| (2. * std::f32::consts::PI * f * t).sin())
+                            .sum::<f32>()
+                    })
+                })
+                .collect::<Vec<f32>>()
+        };
+        let (c, d, e, f, g, a, b) = (261.63, 293.66, 329.63, 349.23, 392., 440., 493.88);
+        let expected = [
+            // C - F - G - C
+            (
+                progression(&[[c, e, g], [f, a, c], [g, b, d], [c, e, g]]),
+                0,
+                Mode::Major,
+            ),
+            // Am - Dm - E - Am
+            (
+                progression(&[[a / 2., c, e], [d, f, a], [e, 415.3, b], [a / 2., c, e]]),
+                9,
+                Mode::Minor,
+            ),
+            // G - C - D - G
+            (
+                progression(&[
+                    [g / 2., b / 2., d],
+                    [c, e, g],
+                    [d, 369.99, a],
+                    [g / 2., b / 2., d],
+                ]),
+                7,
+                Mode::Major,
+            ),
+        ];
+        for (signal, tonic, mode) in expected {
+            let mut chroma_desc = ChromaDesc::new(22050, 12);
+            chroma_desc.do_(&signal).unwrap();
+            let key = chroma_desc.get_key().unwrap().unwrap();
+            assert_eq!((key.tonic, key.mode), (tonic, mode));
+            assert!(key.confidence > 0.5);
+
+            let mut streaming_desc =
+                ChromaDesc::new_streaming(22050, 12, ChromaDesc::STREAMING_TUNING_FRAMES);
+            for chunk in signal.chunks(8192) {
+                streaming_desc.do_(chunk).unwrap();
+            }
+            let streaming_key = streaming_desc.get_key().unwrap().unwrap();
+            assert_eq!((streaming_key.tonic, streaming_key.mode), (tonic, mode));
+        }
+    }
+
+    #[test]
+    fn test_estimate_key_flat_chroma() {
+        assert_eq!(estimate_key(&Array1::ones(12)), None);
+        assert_eq!(estimate_key(&Array1::ones(24)), None);
+    }
+
+    #[test]
+    fn test_chroma_streaming_too_short() {
+        let mut chroma_desc = ChromaDesc::new_streaming(22050, 12, 10);
+        chroma_desc.do_(&[0.; 100]).unwrap();
+        assert_eq!(
+            chroma_desc.get_values().unwrap_err(),
+            BlissError::AnalysisError(String::from(
+                "in chroma: the signal is too short to be analyzed."
+            ))
+        );
+        assert_eq!(
+            chroma_desc.do_(&[0.; 100]).unwrap_err(),
+            BlissError::AnalysisError(String::from(
+                "in chroma: tried to stream samples after the values were computed."
+            ))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "ffmpeg")]
+    fn test_chroma_streaming_drift() {
+        let mut paths = std::fs::read_dir("data/chroma")
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        paths.push(PathBuf::from("data/s16_mono_22_5kHz.flac"));
+        paths.push(PathBuf::from("data/piano.flac"));
+
+        for path in paths {
+            let song = Decoder::decode(&path).unwrap();
+            let mut chroma_desc = ChromaDesc::new(SAMPLE_RATE, 12);
+            chroma_desc.do_(&song.sample_array).unwrap();
+            let expected_values = chroma_desc.get_values().unwrap();
+
+            // All the fixtures are shorter than the default tuning window,
+            // so streaming should not make any difference.
+            let mut streaming_desc =
+                ChromaDesc::new_streaming(SAMPLE_RATE, 12, ChromaDesc::STREAMING_TUNING_FRAMES);
+            for chunk in song.sample_array.chunks(4321) {
+                streaming_desc.do_(chunk).unwrap();
+            }
+            let values = streaming_desc.get_values().unwrap();
+            assert!(1e-6 > max_difference(&expected_values, &values));
+
+            // Estimating the tuning on the first second only makes the
+            // values drift a bit.
+            let mut streaming_desc = ChromaDesc::new_streaming(SAMPLE_RATE, 12, 10);
+            for chunk in song.sample_array.chunks(4321) {
+                streaming_desc.do_(chunk).unwrap();
+            }
+            let values = streaming_desc.get_values().unwrap();
+            let drift = max_difference(&expected_values, &values);
+            assert!(0.07 > drift, "{} drifted by {drift}", path.display());
+        }
+    }
 }
@@ -10,7 +10,10 @@
 #[cfg(feature = "analysis")]
 use crate::song::decoder::Decoder as DecoderTrait;
 #[cfg(feature = "analysis")]
-use crate::{Analysis, AnalysisOptions, BlissError, BlissResult, Song, SAMPLE_RATE};
+use crate::{
+    song::AnalysisOutput, AnalysisBudget, AnalysisOptions, AnalysisSegment, BlissError,
+    BlissResult, Song, Tags, SAMPLE_RATE,
+};
 #[cfg(feature = "analysis")]
 use rcue::cue::{Cue, Track};
 #[cfg(feature = "analysis")]
@@ -53,11 +56,11 @@ pub struct BlissCue<D: ?Sized> {
 #[allow(missing_docs)]
 #[derive(Default, Debug, PartialEq, Clone)]
 struct BlissCueFile {
-    sample_array: Vec<f32>,
     album: Option<String>,
     artist: Option<String>,
     genre: Option<String>,
     disc_number: Option<i32>,
+    comments: Vec<(String, String)>,
     tracks: Vec<Track>,
     cue_path: PathBuf,
     audio_file_path: PathBuf,
@@ -82,26 +85,25 @@ impl<D: ?Sized + DecoderTrait> BlissCue<D> {
     /// Each returned [Song] has a populated [cue_info](Song::cue_info) object, that can be
     /// be used to retrieve which CUE sheet was used to extract it, as well
     /// as the corresponding audio file.
+    ///
+    /// The audio files are not decoded as a whole: each track is decoded
+    /// using [decode_range](crate::decoder::Decoder::decode_range), and
+    /// analyzed before decoding the next one. The
+    /// [timeout](AnalysisOptions::timeout) applies to the decoding and
+    /// analysis of each track.
+    ///
+    /// The default implementation of
+    /// [decode_range_with_budget](crate::decoder::Decoder::decode_range_with_budget)
+    /// decodes the whole audio file for each track, so decoders that don't
+    /// override it decode each audio file as many times as it has tracks.
     pub fn songs_from_path_with_options<P: AsRef<Path>>(
         path: P,
         analysis_options: &AnalysisOptions,
     ) -> BlissResult<Vec<BlissResult<Song>>> {
         let cue: BlissCue<D> = BlissCue::from_path(&path)?;
-        let cue_files = cue.files();
         let mut songs = Vec::new();
-        for cue_file in cue_files.into_iter() {
-            match cue_file {
-                Ok(f) => {
-                    if !f.sample_array.is_empty() {
-                        songs.extend_from_slice(&f.get_songs(analysis_options));
-                    } else {
-                        songs.push(Err(BlissError::DecodingError(
-                            "empty audio file associated to CUE sheet".into(),
-                        )));
-                    }
-                }
-                Err(e) => songs.push(Err(e)),
-            }
+        for cue_file in cue.files() {
+            songs.extend(cue_file.get_songs::<D>(analysis_options));
         }
         Ok(songs)
     }
@@ -123,7 +125,7 @@ impl<D: ?Sized + DecoderTrait> BlissCue<D> {
     }
 
     // List all BlissCueFile from a BlissCue.
-    fn files(&self) -> Vec<BlissResult<BlissCueFile>> {
+    fn files(&self) -> Vec<BlissCueFile> {
         let mut cue_files = Vec::new();
         for cue_file in self.cue.files.iter() {
             let audio_file_path = match &self.cue_path.parent() {
@@ -145,22 +147,16 @@ impl<D: ?Sized + DecoderTrait> BlissCue<D> {
                     c_uppercase == "DISCNUMBER" || c_uppercase == "DISC"
                 })
                 .and_then(|(_, v)| v.to_owned().parse::<i32>().ok());
-            let raw_song = D::decode(Path::new(&audio_file_path));
-            if let Ok(song) = raw_song {
-                let bliss_cue_file = BlissCueFile {
-                    sample_array: song.sample_array,
-                    genre,
-                    artist: self.cue.performer.to_owned(),
-                    album: self.cue.title.to_owned(),
-                    tracks: cue_file.tracks.to_owned(),
-                    audio_file_path,
-                    disc_number,
-                    cue_path: self.cue_path.to_owned(),
-                };
-                cue_files.push(Ok(bliss_cue_file))
-            } else {
-                cue_files.push(Err(raw_song.unwrap_err()));
-            }
+            cue_files.push(BlissCueFile {
+                genre,
+                artist: self.cue.performer.to_owned(),
+                album: self.cue.title.to_owned(),
+                tracks: cue_file.tracks.to_owned(),
+                audio_file_path,
+                disc_number,
+                comments: self.cue.comments.to_owned(),
+                cue_path: self.cue_path.to_owned(),
+            });
         }
         cue_files
     }
@@ -170,12 +166,28 @@ impl<D: ?Sized + DecoderTrait> BlissCue<D> {
 impl BlissCueFile {
     fn create_song(
         &self,
-        analysis: BlissResult<Analysis>,
+        analysis: BlissResult<AnalysisOutput>,
+        segments: Option<Vec<AnalysisSegment>>,
         current_track: &Track,
         duration: Duration,
         index: usize,
     ) -> BlissResult<Song> {
-        if let Ok(a) = analysis {
+        // Track-level tags take precedence over the CUE sheet's.
+        let mut tags = Tags::default();
+        if let Some(isrc) = &current_track.isrc {
+            tags.set("isrc", isrc);
+        }
+        for (key, value) in current_track.comments.iter().chain(&self.comments) {
+            tags.set(key, value);
+        }
+        if let Ok(AnalysisOutput {
+            analysis: a,
+            diagnostics,
+            key,
+            boundaries,
+            fingerprint,
+        }) = analysis
+        {
             let song = Song {
                 path: PathBuf::from(format!(
                     "{}/CUE_TRACK{:03}",
@@ -196,6 +208,13 @@ impl BlissCueFile {
                     cue_path: self.cue_path.to_owned(),
                     audio_file_path: self.audio_file_path.to_owned(),
                 }),
+                segments,
+                diagnostics,
+                key,
+                loudness: None,
+                boundaries,
+                fingerprint,
+                tags,
             };
             Ok(song)
         } else {
@@ -204,42 +223,56 @@ impl BlissCueFile {
     }
 
     // Get all songs from a BlissCueFile, using Song::analyze, each song being
-    // located using the sample_array and the timestamp delimiter.
-    fn get_songs(&self, analysis_options: &AnalysisOptions) -> Vec<BlissResult<Song>> {
+    // decoded from the audio file between its timestamp delimiters.
+    //
+    // If the first track can't be decoded, the audio file most likely
+    // can't be decoded at all, so only that error is returned. Running out
+    // of time on the first track doesn't say anything about the others,
+    // which are still analyzed.
+    fn get_songs<D: ?Sized + DecoderTrait>(
+        &self,
+        analysis_options: &AnalysisOptions,
+    ) -> Vec<BlissResult<Song>> {
         let mut songs = Vec::new();
-        for (index, tuple) in (self.tracks[..]).windows(2).enumerate() {
-            let (current_track, next_track) = (tuple[0].to_owned(), tuple[1].to_owned());
-            if let Some((_, start_current)) = current_track.indices.first() {
-                if let Some((_, end_current)) = next_track.indices.first() {
-                    let start_current = (start_current.as_secs_f32() * SAMPLE_RATE as f32) as usize;
-                    let end_current = (end_current.as_secs_f32() * SAMPLE_RATE as f32) as usize;
-                    let duration = Duration::from_secs_f32(
-                        (end_current - start_current) as f32 / SAMPLE_RATE as f32,
-                    );
-                    let analysis = Song::analyze_with_options(
-                        &self.sample_array[start_current..end_current],
-                        analysis_options,
-                    );
-
-                    let song = self.create_song(analysis, &current_track, duration, index + 1);
-                    songs.push(song);
-                }
-            }
-        }
-        // Take care of the last track, since the windows iterator doesn't.
-        if let Some(last_track) = self.tracks.last() {
-            if let Some((_, start_current)) = last_track.indices.first() {
-                let start_current = (start_current.as_secs_f32() * SAMPLE_RATE as f32) as usize;
-                let duration = Duration::from_secs_f32(
-                    (self.sample_array.len() - start_current) as f32 / SAMPLE_RATE as f32,
-                );
-                let analysis = Song::analyze_with_options(
-                    &self.sample_array[start_current..],
-                    analysis_options,
-                );
-                let song = self.create_song(analysis, last_track, duration, self.tracks.len());
-                songs.push(song);
+        for (index, current_track) in self.tracks.iter().enumerate() {
+            let Some((_, start)) = current_track.indices.first() else {
+                continue;
+            };
+            let end = match self.tracks.get(index + 1) {
+                Some(next_track) => match next_track.indices.first() {
+                    Some((_, end)) => Some(*end),
+                    None => continue,
+                },
+                None => None,
+            };
+            let budget = AnalysisBudget::new(analysis_options);
+            let (sample_array, warnings) =
+                match D::decode_range_with_budget(&self.audio_file_path, *start, end, &budget) {
+                    Ok(song) => (song.sample_array, song.warnings),
+                    Err(e @ BlissError::DecodingError(_)) if songs.is_empty() => {
+                        return vec![Err(e)]
+                    }
+                    Err(e) => {
+                        songs.push(Err(e));
+                        continue;
+                    }
+                };
+            if songs.is_empty() && sample_array.is_empty() {
+                return vec![Err(BlissError::DecodingError(
+                    "empty audio file associated to CUE sheet".into(),
+                ))];
             }
+            let duration = Duration::from_secs_f32(sample_array.len() as f32 / SAMPLE_RATE as f32);
+            let analysis =
+                Song::analyze_output(&sample_array, analysis_options, &budget).map(|mut output| {
+                    output.diagnostics.warnings.extend(warnings);
+                    output
+                });
+            let song = Song::segments_from_options(&sample_array, analysis_options, &budget)
+                .and_then(|segments| {
+                    self.create_song(analysis, segments, current_track, duration, index + 1)
+                });
+            songs.push(song);
         }
         songs
     }
@@ -247,7 +280,7 @@ impl BlissCueFile {
 
 #[cfg(test)]
 mod tests {
-    #[cfg(feature = "ffmpeg")]
+    #[cfg(feature = "analysis")]
     use super::*;
     #[cfg(feature = "ffmpeg")]
     use crate::decoder::ffmpeg::FFmpegDecoder;
@@ -266,10 +299,72 @@ mod tests {
         );
     }
 
+    // Runs out of time on the first track of `testcue.flac`, and decodes
+    // the other tracks as silence.
+    #[cfg(feature = "analysis")]
+    struct SlowStartDecoder;
+
+    #[cfg(feature = "analysis")]
+    impl DecoderTrait for SlowStartDecoder {
+        fn decode(path: &Path) -> BlissResult<crate::decoder::PreAnalyzedSong> {
+            Self::decode_range(path, Duration::ZERO, None)
+        }
+
+        fn decode_range_with_budget(
+            path: &Path,
+            start: Duration,
+            _: Option<Duration>,
+            _: &AnalysisBudget,
+        ) -> BlissResult<crate::decoder::PreAnalyzedSong> {
+            if !path.exists() {
+                return Err(BlissError::DecodingError(format!(
+                    "{} does not exist",
+                    path.display()
+                )));
+            }
+            if start.is_zero() {
+                return Err(BlissError::TimedOut(Duration::from_secs(1)));
+            }
+            Ok(crate::decoder::PreAnalyzedSong {
+                path: path.to_owned(),
+                sample_array: vec![0.; SAMPLE_RATE as usize * 2],
+                ..Default::default()
+            })
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_cue_first_track_timed_out() {
+        let songs = BlissCue::<SlowStartDecoder>::songs_from_path("data/testcue.cue").unwrap();
+        assert_eq!(
+            songs
+                .iter()
+                .map(|song| song.as_ref().map(|song| song.title.to_owned()))
+                .collect::<Vec<_>>(),
+            vec![
+                Err(&BlissError::TimedOut(Duration::from_secs(1))),
+                Ok(Some(String::from("Piano"))),
+                Ok(Some(String::from("Tone"))),
+                Err(&BlissError::DecodingError(String::from(
+                    "data/not-existing.wav does not exist"
+                ))),
+            ]
+        );
+    }
+
     #[test]
     #[cfg(feature = "ffmpeg")]
     fn test_cue_analysis() {
-        let songs = BlissCue::<FFmpegDecoder>::songs_from_path("data/testcue.cue").unwrap();
+        // The expected values were computed with the second features version.
+        let songs = BlissCue::<FFmpegDecoder>::songs_from_path_with_options(
+            "data/testcue.cue",
+            &AnalysisOptions {
+                features_version: FeaturesVersion::Version2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
         let expected = vec![
             Ok(Song {
                 path: Path::new("data/testcue.cue/CUE_TRACK001").to_path_buf(),
@@ -299,14 +394,17 @@ mod tests {
                         -0.58482605,
                         -0.078823924,
                     ],
-                    features_version: FeaturesVersion::LATEST,
+                    features_version: FeaturesVersion::Version2,
+                    extension: None,
+                    partial: false,
+                    tempo_confidence: None,
                 },
                 album: Some(String::from("Album for CUE test")),
                 artist: Some(String::from("David TMX")),
                 title: Some(String::from("Renaissance")),
                 genre: Some(String::from("Random")),
                 track_number: Some(1),
-                features_version: FeaturesVersion::LATEST,
+                features_version: FeaturesVersion::Version2,
                 album_artist: Some(String::from("Polochon_street")),
                 duration: Duration::from_secs_f32(11.066666603),
                 disc_number: Some(1),
@@ -314,6 +412,11 @@ mod tests {
                     cue_path: PathBuf::from("data/testcue.cue"),
                     audio_file_path: PathBuf::from("data/testcue.flac"),
                 }),
+                tags: Tags {
+                    year: Some(2022),
+                    date: Some(String::from("2022")),
+                    ..Default::default()
+                },
                 ..Default::default()
             }),
             Ok(Song {
@@ -344,9 +447,12 @@ mod tests {
                         -0.66400576,
                         0.15099311,
                     ],
-                    features_version: FeaturesVersion::LATEST,
+                    features_version: FeaturesVersion::Version2,
+                    extension: None,
+                    partial: false,
+                    tempo_confidence: None,
                 },
-                features_version: FeaturesVersion::LATEST,
+                features_version: FeaturesVersion::Version2,
                 album: Some(String::from("Album for CUE test")),
                 artist: Some(String::from("Polochon_street")),
                 title: Some(String::from("Piano")),
@@ -359,6 +465,11 @@ mod tests {
                     cue_path: PathBuf::from("data/testcue.cue"),
                     audio_file_path: PathBuf::from("data/testcue.flac"),
                 }),
+                tags: Tags {
+                    year: Some(2022),
+                    date: Some(String::from("2022")),
+                    ..Default::default()
+                },
                 ..Default::default()
             }),
             Ok(Song {
@@ -389,14 +500,17 @@ mod tests {
                         -0.99999785,
                         -0.99993163,
                     ],
-                    features_version: FeaturesVersion::LATEST,
+                    features_version: FeaturesVersion::Version2,
+                    extension: None,
+                    partial: false,
+                    tempo_confidence: None,
                 },
                 album: Some(String::from("Album for CUE test")),
                 artist: Some(String::from("Polochon_street")),
                 title: Some(String::from("Tone")),
                 genre: Some(String::from("Random")),
                 track_number: Some(3),
-                features_version: FeaturesVersion::LATEST,
+                features_version: FeaturesVersion::Version2,
                 album_artist: Some(String::from("Polochon_street")),
                 disc_number: Some(1),
                 duration: Duration::from_secs_f32(5.586666584),
@@ -404,6 +518,11 @@ mod tests {
                     cue_path: PathBuf::from("data/testcue.cue"),
                     audio_file_path: PathBuf::from("data/testcue.flac"),
                 }),
+                tags: Tags {
+                    year: Some(2022),
+                    date: Some(String::from("2022")),
+                    ..Default::default()
+                },
                 ..Default::default()
             }),
             Err(BlissError::DecodingError(String::from(
@@ -454,6 +573,9 @@ mod tests {
                         -0.9596871,
                     ],
                     features_version: FeaturesVersion::Version1,
+                    extension: None,
+                    partial: false,
+                    tempo_confidence: None,
                 },
                 FeaturesVersion::Version1,
             ),
@@ -483,6 +605,9 @@ mod tests {
                         -0.96498996,
                     ],
                     features_version: FeaturesVersion::Version1,
+                    extension: None,
+                    partial: false,
+                    tempo_confidence: None,
                 },
                 FeaturesVersion::Version1,
             ),
@@ -512,6 +637,9 @@ mod tests {
                         -0.99999976,
                     ],
                     features_version: FeaturesVersion::Version1,
+                    extension: None,
+                    partial: false,
+                    tempo_confidence: None,
                 },
                 FeaturesVersion::Version1,
             ),
@@ -527,7 +655,15 @@ mod tests {
     #[test]
     #[cfg(feature = "ffmpeg")]
     fn test_cue_minimal() {
-        let songs = BlissCue::<FFmpegDecoder>::songs_from_path("data/no-tags-cue.cue").unwrap();
+        // The expected values were computed with the second features version.
+        let songs = BlissCue::<FFmpegDecoder>::songs_from_path_with_options(
+            "data/no-tags-cue.cue",
+            &AnalysisOptions {
+                features_version: FeaturesVersion::Version2,
+                ..Default::default()
+            },
+        )
+        .unwrap();
         let expected = vec![
             Ok(Song {
                 path: Path::new("data/no-tags-cue.cue/CUE_TRACK001").to_path_buf(),
@@ -557,14 +693,17 @@ mod tests {
                         -0.58482605,
                         -0.078823924,
                     ],
-                    features_version: FeaturesVersion::LATEST,
+                    features_version: FeaturesVersion::Version2,
+                    extension: None,
+                    partial: false,
+                    tempo_confidence: None,
                 },
                 album: None,
                 artist: Some(String::from("David TMX")),
                 title: Some(String::from("Renaissance")),
                 genre: None,
                 track_number: Some(1),
-                features_version: FeaturesVersion::LATEST,
+                features_version: FeaturesVersion::Version2,
                 album_artist: None,
                 duration: Duration::from_secs_f32(11.066666603),
                 disc_number: None,
@@ -602,9 +741,12 @@ mod tests {
                         -0.66400576,
                         0.15099311,
                     ],
-                    features_version: FeaturesVersion::LATEST,
+                    features_version: FeaturesVersion::Version2,
+                    extension: None,
+                    partial: false,
+                    tempo_confidence: None,
                 },
-                features_version: FeaturesVersion::LATEST,
+                features_version: FeaturesVersion::Version2,
                 album: None,
                 artist: Some(String::from("Polochon_street")),
                 title: Some(String::from("Piano")),
@@ -647,14 +789,17 @@ mod tests {
                         -0.99999785,
                         -0.99993163,
                     ],
-                    features_version: FeaturesVersion::LATEST,
+                    features_version: FeaturesVersion::Version2,
+                    extension: None,
+                    partial: false,
+                    tempo_confidence: None,
                 },
                 album: None,
                 artist: Some(String::from("Polochon_street")),
                 title: Some(String::from("Tone")),
                 genre: None,
                 track_number: Some(3),
-                features_version: FeaturesVersion::LATEST,
+                features_version: FeaturesVersion::Version2,
                 album_artist: None,
                 disc_number: None,
                 duration: Duration::from_secs_f32(5.586666584),
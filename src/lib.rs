@@ -122,12 +122,22 @@ pub mod utils;
 extern crate serde;
 
 use ndarray::{arr1, Array1, Array2};
+use std::time::Duration;
 use strum::EnumCount;
 use thiserror::Error;
 
 #[cfg(feature = "analysis")]
 pub use song::decoder;
-pub use song::{Analysis, AnalysisIndex, AnalysisOptions, Song, NUMBER_FEATURES};
+pub use song::{
+    Analysis, AnalysisBudget, AnalysisDiagnostics, AnalysisEvent, AnalysisExtension, AnalysisIndex,
+    AnalysisOptions, AnalysisSegment, AnalysisWarning, Boundaries, CancellationToken,
+    DeliveryOptions, Descriptor, ExcerptOptions, ExtensionOptions, Fingerprint, Key, Loudness,
+    Mode, ProgressSender, SegmentOptions, Song, Tags, TrimOptions, NUMBER_FEATURES,
+};
+#[cfg(feature = "analysis")]
+pub use song::{LoudnessMeter, SampleLayout, StreamingAnalyzer};
+#[cfg(feature = "analysis")]
+pub use temporal::{Beat, BeatTrack, TempoEstimate};
 
 use crate::playlist::mahalanobis_distance_builder;
 
@@ -150,7 +160,15 @@ const SAMPLE_RATE: u32 = 22050;
 /// one another, as they might have a different set of features, etc.
 pub enum FeaturesVersion {
     #[default]
-    /// The latest iteration, increasing chroma features accuracy and
+    /// The latest iteration, adding rhythm features (pulse clarity,
+    /// beat strength variance and onset density) to better tell songs
+    /// with the same tempo but a different rhythmic character apart.
+    Version4 = 4,
+    /// The third iteration, adding mel-frequency cepstral coefficients
+    /// (MFCC) to better tell timbres apart, and correcting the tempo
+    /// for half / double tempo errors.
+    Version3 = 3,
+    /// The second iteration, increasing chroma features accuracy and
     /// making feature normalization more coherent.
     Version2 = 2,
     /// The first iteration of the features. The 4 last chroma features
@@ -162,17 +180,23 @@ pub enum FeaturesVersion {
 impl FeaturesVersion {
     /// Always points to the latest features' version. In case of doubt,
     /// use this one.
-    pub const LATEST: FeaturesVersion = FeaturesVersion::Version2;
+    pub const LATEST: FeaturesVersion = FeaturesVersion::Version4;
 
     /// Feature weights for the distance function that yields the best results.
     pub fn feature_weights(self) -> Array2<f32> {
         match self {
+            FeaturesVersion::Version4 => Array2::from_diag(&arr1(&VERSION4_WEIGHTS)),
+            FeaturesVersion::Version3 => Array2::from_diag(&arr1(&VERSION3_WEIGHTS)),
             FeaturesVersion::Version2 => Array2::from_diag(&arr1(&VERSION2_WEIGHTS)),
             FeaturesVersion::Version1 => Array2::eye(self.feature_count()),
         }
     }
 
     /// Distance metric that yields the best result with this features' version.
+    ///
+    /// It is meant to be used on [Analysis::as_arr1](crate::Analysis::as_arr1),
+    /// which, from [FeaturesVersion::Version3] on, gives less weight to the
+    /// tempo of songs the beat tracker is unsure of.
     pub fn distance_metric(self) -> impl Fn(&Array1<f32>, &Array1<f32>) -> f32 {
         mahalanobis_distance_builder(self.feature_weights())
     }
@@ -180,7 +204,9 @@ impl FeaturesVersion {
     /// Number of features for this version.
     pub const fn feature_count(self) -> usize {
         match self {
-            FeaturesVersion::Version2 => AnalysisIndex::COUNT,
+            FeaturesVersion::Version4 => AnalysisIndex::COUNT,
+            FeaturesVersion::Version3 => 49,
+            FeaturesVersion::Version2 => 23,
             FeaturesVersion::Version1 => 20,
         }
     }
@@ -197,6 +223,8 @@ impl TryFrom<u16> for FeaturesVersion {
 
     fn try_from(value: u16) -> Result<Self, Self::Error> {
         match value {
+            4 => Ok(FeaturesVersion::Version4),
+            3 => Ok(FeaturesVersion::Version3),
             2 => Ok(FeaturesVersion::Version2),
             1 => Ok(FeaturesVersion::Version1),
             _ => Err(BlissError::ProviderError(format!(
@@ -233,6 +261,45 @@ const VERSION2_WEIGHTS: [f32; 23] = [
     3. / 13.,
 ];
 
+// Version 3 corrects the tempo for half / double tempo errors, and comes with
+// the beat tracker's confidence, which scales the tempo feature in
+// `Analysis::as_arr1`, so the tempo keeps the same weight as in version 2.
+const VERSION3_WEIGHTS: [f32; 49] = concat_weights(&VERSION2_WEIGHTS, &MFCC_WEIGHTS);
+
+// MFCC, interleaved means and standard deviations: timbre gets ~4 dims of
+// total weight instead of 26, mostly from the means.
+const MFCC_WEIGHTS: [f32; 26] = {
+    let mut weights = [1. / 13.; 26];
+    let mut i = 0;
+    while i < weights.len() {
+        weights[i] = 3. / 13.;
+        i += 2;
+    }
+    weights
+};
+
+/// Weights of the features of a version, followed by the weights of the
+/// features it adds to a previous version.
+const fn concat_weights<const N: usize>(previous: &[f32], added: &[f32]) -> [f32; N] {
+    assert!(previous.len() + added.len() == N);
+    let mut weights = [0.; N];
+    let mut i = 0;
+    while i < previous.len() {
+        weights[i] = previous[i];
+        i += 1;
+    }
+    while i < N {
+        weights[i] = added[i - previous.len()];
+        i += 1;
+    }
+    weights
+}
+
+// Version 4 keeps the tempo and the weights of version 3, and adds rhythm
+// features (pulse clarity, beat strength variance, onset density), which get
+// ~1.5 dims of total weight, like the tempo they refine.
+const VERSION4_WEIGHTS: [f32; 52] = concat_weights(&VERSION3_WEIGHTS, &[0.5, 0.5, 0.5]);
+
 #[derive(Error, Clone, Debug, PartialEq, Eq)]
 /// Umbrella type for bliss error types
 pub enum BlissError {
@@ -246,6 +313,20 @@ pub enum BlissError {
     /// An error happened with the music library provider.
     /// Useful to report errors when you implement bliss for an audio player.
     ProviderError(String),
+    #[error("the analysis was cancelled")]
+    /// The analysis was cancelled through a
+    /// [CancellationToken](crate::CancellationToken).
+    Cancelled,
+    #[error("the analysis timed out after {0:?}")]
+    /// The decoding and analysis of a song took longer than
+    /// [AnalysisOptions::timeout](crate::AnalysisOptions::timeout).
+    TimedOut(Duration),
+    #[error("the analysis panicked - {0}")]
+    /// The decoding or the analysis of a song panicked. Holds the panic's
+    /// message. Reported by bulk analysis functions such as
+    /// [Decoder::analyze_paths](crate::decoder::Decoder::analyze_paths),
+    /// which go on with the next songs.
+    Panicked(String),
 }
 
 /// bliss error type
@@ -267,6 +348,14 @@ mod tests {
             FeaturesVersion::Version2.feature_weights().shape(),
             &[23, 23]
         );
+        assert_eq!(
+            FeaturesVersion::Version3.feature_weights().shape(),
+            &[49, 49]
+        );
+        assert_eq!(
+            FeaturesVersion::Version4.feature_weights().shape(),
+            &[52, 52]
+        );
     }
 
     #[test]
@@ -288,6 +377,24 @@ mod tests {
             ),
             3.4999998,
         );
+
+        let metric = FeaturesVersion::Version3.distance_metric();
+        assert_eq!(
+            metric(
+                &Array::from_vec(vec![0_f32; 49]),
+                &Array::from_vec(vec![1_f32; 49]),
+            ),
+            4.031129,
+        );
+
+        let metric = FeaturesVersion::Version4.distance_metric();
+        assert_eq!(
+            metric(
+                &Array::from_vec(vec![0_f32; 52]),
+                &Array::from_vec(vec![1_f32; 52]),
+            ),
+            4.213075,
+        );
     }
 
     #[test]
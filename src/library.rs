@@ -150,7 +150,10 @@ use std::sync::Mutex;
 
 use crate::decoder::Decoder as DecoderTrait;
 use crate::Song;
-use crate::{Analysis, BlissError, NUMBER_FEATURES};
+use crate::{
+    Analysis, AnalysisExtension, AnalysisSegment, BlissError, Boundaries, Fingerprint, Key,
+    Loudness, Mode, Tags, NUMBER_FEATURES,
+};
 use rusqlite::types::ToSqlOutput;
 use rusqlite::Error as RusqliteError;
 use rusqlite::{
@@ -174,6 +177,51 @@ impl FromSql for FeaturesVersion {
     }
 }
 
+impl ToSql for Fingerprint {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(
+            self.codes
+                .iter()
+                .flat_map(|code| code.to_le_bytes())
+                .collect::<Vec<u8>>(),
+        ))
+    }
+}
+
+impl FromSql for Fingerprint {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        let bytes = value.as_blob()?;
+        if !bytes.len().is_multiple_of(4) {
+            return Err(rusqlite::types::FromSqlError::InvalidType);
+        }
+        Ok(Fingerprint {
+            codes: bytes
+                .chunks_exact(4)
+                .map(|code| u32::from_le_bytes(code.try_into().unwrap()))
+                .collect(),
+        })
+    }
+}
+
+impl ToSql for Mode {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(match self {
+            Mode::Major => "major",
+            Mode::Minor => "minor",
+        }))
+    }
+}
+
+impl FromSql for Mode {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "major" => Ok(Mode::Major),
+            "minor" => Ok(Mode::Minor),
+            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+        }
+    }
+}
+
 /// Configuration trait, used for instance to customize
 /// the format in which the configuration file should be written.
 pub trait AppConfigTrait: Serialize + Sized + DeserializeOwned {
@@ -515,7 +563,36 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
                 version integer not null,
                 analyzed boolean default false,
                 extra_info json,
-                error text
+                error text,
+                analysis_diagnostics json,
+                key_tonic integer,
+                key_mode text,
+                key_confidence float,
+                loudness_integrated float,
+                loudness_range float,
+                true_peak float,
+                extension_version integer,
+                extension_features json,
+                partial boolean default false,
+                audio_start float,
+                audio_end float,
+                fade_out_start float,
+                fingerprint blob,
+                year integer,
+                date text,
+                composer text,
+                musicbrainz_recording_id text,
+                musicbrainz_release_id text,
+                musicbrainz_artist_id text,
+                isrc text,
+                bpm float,
+                initial_key text,
+                replay_gain_track_gain float,
+                replay_gain_track_peak float,
+                replay_gain_album_gain float,
+                replay_gain_album_peak float,
+                has_cover_art boolean default false,
+                tempo_confidence float
             );
             pragma foreign_keys = on;
             create table feature (
@@ -525,6 +602,17 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
                 feature_index integer not null,
                 unique(song_id, feature_index),
                 foreign key(song_id) references song(id) on delete cascade
+            );
+            create table segment (
+                id integer primary key,
+                song_id integer not null,
+                segment_index integer not null,
+                start_time float not null,
+                end_time float not null,
+                features json not null,
+                tempo_confidence float,
+                unique(song_id, segment_index),
+                foreign key(song_id) references song(id) on delete cascade
             )
         ";
     const SQLITE_MIGRATIONS: &'static [&'static str] = &[
@@ -588,6 +676,71 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
             drop table song;
             alter table song_bak rename to song;
         ",
+        // Per-segment analysis of songs, see `AnalysisOptions::segments`.
+        "
+            create table segment (
+                id integer primary key,
+                song_id integer not null,
+                segment_index integer not null,
+                start_time float not null,
+                end_time float not null,
+                features json not null,
+                unique(song_id, segment_index),
+                foreign key(song_id) references song(id) on delete cascade
+            )
+        ",
+        // Warnings raised while analyzing songs, see `Song::diagnostics`.
+        "alter table song add column analysis_diagnostics json;",
+        // Estimated musical key of songs, see `Song::key`.
+        "
+            alter table song add column key_tonic integer;
+            alter table song add column key_mode text;
+            alter table song add column key_confidence float;
+        ",
+        // Beat tracker confidence in songs' tempo, see
+        // `Analysis::tempo_confidence`.
+        "
+            alter table song add column tempo_confidence float;
+            alter table segment add column tempo_confidence float;
+        ",
+        // EBU R128 loudness of songs, see `Song::loudness`.
+        "
+            alter table song add column loudness_integrated float;
+            alter table song add column loudness_range float;
+            alter table song add column true_peak float;
+        ",
+        // Features computed by custom descriptors, see `Analysis::extension`.
+        "
+            alter table song add column extension_version integer;
+            alter table song add column extension_features json;
+        ",
+        // Songs of which only an excerpt was analyzed, see `Analysis::partial`.
+        "alter table song add column partial boolean default false;",
+        // Where songs' audio starts and ends, see `Song::boundaries`.
+        "
+            alter table song add column audio_start float;
+            alter table song add column audio_end float;
+            alter table song add column fade_out_start float;
+        ",
+        // Acoustic fingerprints of songs, see `Song::fingerprint`.
+        "alter table song add column fingerprint blob;",
+        // Tags of songs besides their title, artist, etc., see `Song::tags`.
+        "
+            alter table song add column year integer;
+            alter table song add column date text;
+            alter table song add column composer text;
+            alter table song add column musicbrainz_recording_id text;
+            alter table song add column musicbrainz_release_id text;
+            alter table song add column musicbrainz_artist_id text;
+            alter table song add column isrc text;
+            alter table song add column bpm float;
+            alter table song add column initial_key text;
+            alter table song add column replay_gain_track_gain float;
+            alter table song add column replay_gain_track_peak float;
+            alter table song add column replay_gain_album_gain float;
+            alter table song add column replay_gain_album_peak float;
+            alter table song add column has_cover_art boolean default false;
+        ",
     ];
 
     /// Create a new [Library] object from the given Config struct that
@@ -904,7 +1057,7 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
             delete_everything_else,
             show_progress_bar,
             |x, _, _| x,
-            self.config.base_config().analysis_options,
+            self.config.base_config().analysis_options.clone(),
         )
     }
 
@@ -964,7 +1117,7 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
             delete_everything_else,
             show_progress_bar,
             |extra_info, _, _| extra_info,
-            self.config.base_config().analysis_options,
+            self.config.base_config().analysis_options.clone(),
         )
     }
 
@@ -997,6 +1150,11 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
     /// of cores and the version of the features you want your library to be
     /// analyzed with. It will reanalyze songs that have features version older
     /// than latest's, and set the config file's features_version to the specified version.
+    /// It will also reanalyze songs whose extension features (see
+    /// [AnalysisOptions::extensions]) have a different version than the
+    /// specified one, and songs of which only an excerpt was analyzed,
+    /// unless `analysis_options` asks for excerpts too (see
+    /// [AnalysisOptions::excerpt]).
     pub fn update_library_convert_extra_info<
         T: Serialize + DeserializeOwned + Clone,
         U,
@@ -1018,14 +1176,22 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
                 "
                 select
                     path
-                    from song where analyzed = true and version = ? order by id
+                    from song where analyzed = true and version = ?1
+                    and extension_version is ?2 and (partial is not true or ?3)
+                    order by id
                 ",
             )?;
+            let extension_version = analysis_options.extensions.as_ref().map(|e| e.version);
             #[allow(clippy::let_and_return)]
             let return_value = path_statement
-                .query_map([analysis_options.features_version], |row| {
-                    Ok(row.get_unwrap::<usize, String>(0))
-                })?
+                .query_map(
+                    params![
+                        analysis_options.features_version,
+                        extension_version,
+                        analysis_options.excerpt.is_some(),
+                    ],
+                    |row| Ok(row.get_unwrap::<usize, String>(0)),
+                )?
                 .map(|x| PathBuf::from(x.unwrap()))
                 .collect::<HashSet<PathBuf>>();
             return_value
@@ -1105,7 +1271,7 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
         show_progress_bar: bool,
     ) -> Result<()> {
         let paths_extra_info = paths.into_iter().map(|path| (path, ())).collect::<Vec<_>>();
-        let analysis_options = self.config.base_config().analysis_options;
+        let analysis_options = self.config.base_config().analysis_options.clone();
         self.analyze_paths_convert_extra_info(
             paths_extra_info,
             show_progress_bar,
@@ -1184,6 +1350,12 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
     ///
     /// `convert_extra_info` is a function that you should specify
     /// to convert that extra info to something serializable.
+    ///
+    /// If the analysis is cancelled using [AnalysisOptions::cancellation],
+    /// the songs that were not analyzed are not stored, not even as failed
+    /// songs, so that [Library::update_library] picks them up next time.
+    /// Songs that timed out (see [AnalysisOptions::timeout]) or whose
+    /// decoding or analysis panicked are stored as failed songs.
     pub fn analyze_paths_convert_extra_info<
         T: Serialize + DeserializeOwned + Clone,
         U,
@@ -1194,6 +1366,31 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
         show_progress_bar: bool,
         convert_extra_info: fn(U, &Song, &Self) -> T,
         analysis_options: AnalysisOptions,
+    ) -> Result<()> {
+        self._analyze_paths_convert_extra_info(
+            paths_extra_info,
+            show_progress_bar,
+            convert_extra_info,
+            analysis_options,
+            |library, path, e, features_version| {
+                library.store_failed_song(path, e, features_version)
+            },
+        )
+    }
+
+    // Same as `analyze_paths_convert_extra_info`, storing the songs whose
+    // analysis failed with `store_failed_song`.
+    fn _analyze_paths_convert_extra_info<
+        T: Serialize + DeserializeOwned + Clone,
+        U,
+        P: Into<PathBuf>,
+    >(
+        &mut self,
+        paths_extra_info: Vec<(P, U)>,
+        show_progress_bar: bool,
+        convert_extra_info: fn(U, &Song, &Self) -> T,
+        analysis_options: AnalysisOptions,
+        store_failed_song: fn(&mut Self, PathBuf, BlissError, FeaturesVersion) -> Result<()>,
     ) -> Result<()> {
         let number_songs = paths_extra_info.len();
         if number_songs == 0 {
@@ -1217,9 +1414,11 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
             .collect();
         let mut cue_extra_info: HashMap<PathBuf, String> = HashMap::new();
 
-        let results = D::analyze_paths_with_options(paths_extra_info.keys(), analysis_options);
+        let results =
+            D::analyze_paths_with_options(paths_extra_info.keys(), analysis_options.clone());
         let mut success_count = 0;
         let mut failure_count = 0;
+        let mut cancelled_count = 0;
         for (path, result) in results {
             if show_progress_bar {
                 pb.set_message(format!("Analyzing {}", path.display()));
@@ -1267,6 +1466,12 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
                     self.store_song(&library_song)?;
                     success_count += 1;
                 }
+                // Cancelled songs were not analyzed, and will be analyzed
+                // again next time, so they shouldn't be stored as failed.
+                Err(BlissError::Cancelled) => {
+                    log::info!("Analysis of song '{}' was cancelled.", path.display());
+                    cancelled_count += 1;
+                }
                 Err(e) => {
                     log::error!(
                         "Analysis of song '{}' failed: {} The error has been stored.",
@@ -1274,7 +1479,7 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
                         e
                     );
 
-                    self.store_failed_song(path, e, analysis_options.features_version)?;
+                    store_failed_song(self, path, e, analysis_options.features_version)?;
                     failure_count += 1;
                 }
             };
@@ -1285,13 +1490,88 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
         ));
 
         log::info!("Analyzed {success_count} song(s) successfully. {failure_count} Failure(s).",);
+        if cancelled_count > 0 {
+            log::info!("The analysis was cancelled while analyzing {cancelled_count} song(s).");
+        }
 
-        self.config.base_config_mut().analysis_options = analysis_options;
+        // The cancellation token and the progress sender are only meant
+        // for this analysis.
+        self.config.base_config_mut().analysis_options = AnalysisOptions {
+            cancellation: None,
+            progress: None,
+            ..analysis_options
+        };
         self.config.write()?;
 
         Ok(())
     }
 
+    /// Reanalyze as a whole the songs of which only an excerpt was analyzed
+    /// (see [AnalysisOptions::excerpt]), keeping their extra info.
+    ///
+    /// Meant to be run in the background after a quick first analysis of
+    /// a large library, so that playlists get more accurate over time.
+    /// The songs are analyzed using the library's analysis options, without
+    /// their excerpt options, which are left as is in the configuration.
+    ///
+    /// Songs whose full analysis fails keep their partial analysis, and the
+    /// error is reported by [Library::get_failed_songs] until they are
+    /// successfully upgraded.
+    pub fn upgrade_partial_songs<T: Serialize + DeserializeOwned + Clone>(
+        &mut self,
+        show_progress_bar: bool,
+    ) -> Result<()> {
+        let paths_extra_info = {
+            let connection = self
+                .sqlite_conn
+                .lock()
+                .map_err(|e| BlissError::ProviderError(e.to_string()))?;
+            // CUE tracks are analyzed from their CUE file, and all have the
+            // same extra info.
+            let mut path_statement = connection.prepare(
+                "
+                select
+                    coalesce(cue_path, path), extra_info
+                    from song where analyzed = true and partial = true order by id
+                ",
+            )?;
+            let mut seen_paths = HashSet::new();
+            #[allow(clippy::let_and_return)]
+            let return_value = path_statement
+                .query_map([], |row| {
+                    Ok((
+                        row.get::<usize, String>(0)?,
+                        row.get::<usize, Option<String>>(1)?,
+                    ))
+                })?
+                .filter(|row| {
+                    row.as_ref()
+                        .map_or(true, |(path, _)| seen_paths.insert(path.to_owned()))
+                })
+                .map(|row| {
+                    let (path, extra_info) = row?;
+                    let extra_info: T =
+                        serde_json::from_str(extra_info.as_deref().unwrap_or("null"))?;
+                    Ok((PathBuf::from(path), extra_info))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            return_value
+        };
+
+        let mut analysis_options = self.config.base_config().analysis_options.clone();
+        let excerpt = analysis_options.excerpt.take();
+        self._analyze_paths_convert_extra_info(
+            paths_extra_info,
+            show_progress_bar,
+            |extra_info, _, _| extra_info,
+            analysis_options,
+            |library, path, e, _| library._store_failed_upgrade(path, e),
+        )?;
+        self.config.base_config_mut().analysis_options.excerpt = excerpt;
+        self.config.write()?;
+        Ok(())
+    }
+
     // Get songs from a songs / features statement.
     // BEWARE that the two songs and features query MUST be the same
     fn _songs_from_statement<T: Serialize + DeserializeOwned + Clone, P: Params + Clone>(
@@ -1307,7 +1587,7 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
         let mut songs_statement = connection.prepare(songs_statement)?;
         let mut features_statement = connection.prepare(features_statement)?;
         let song_rows = songs_statement.query_map(params.to_owned(), |row| {
-            Ok((row.get(13)?, Self::_song_from_row_closure(row)?))
+            Ok((row.get(42)?, Self::_song_from_row_closure(row)?))
         })?;
         let feature_rows =
             features_statement.query_map(params, |row| Ok((row.get(1)?, row.get(0)?)))?;
@@ -1329,7 +1609,19 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
                     break;
                 };
             }
-            let mut song = row.unwrap().1;
+            let mut song: LibrarySong<T> = row.unwrap().1;
+            song.bliss_song.segments = Self::_segments_from_statement(
+                &connection,
+                "
+                select start_time, end_time, features, tempo_confidence from segment
+                    where song_id = ? order by segment_index
+                ",
+                params![song_id],
+                song.bliss_song.features_version,
+            )?;
+            let extension = song.bliss_song.analysis.extension.take();
+            let partial = song.bliss_song.analysis.partial;
+            let tempo_confidence = song.bliss_song.analysis.tempo_confidence;
             song.bliss_song.analysis = Analysis::new(chunk, song.bliss_song.features_version)
                 .map_err(|_| {
                     BlissError::ProviderError(format!(
@@ -1340,6 +1632,9 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
                         song.bliss_song.path.display(),
                     ))
                 })?;
+            song.bliss_song.analysis.extension = extension;
+            song.bliss_song.analysis.partial = partial;
+            song.bliss_song.analysis.tempo_confidence = tempo_confidence;
             songs.push(song);
         }
         Ok(songs)
@@ -1360,7 +1655,14 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
             select
                 path, artist, title, album, album_artist,
                 track_number, disc_number, genre, duration, version, extra_info, cue_path,
-                audio_file_path, id
+                audio_file_path, analysis_diagnostics, key_tonic, key_mode, key_confidence,
+                loudness_integrated, loudness_range, true_peak, extension_version,
+                extension_features, partial, audio_start, audio_end, fade_out_start,
+                fingerprint,
+                year, date, composer, musicbrainz_recording_id, musicbrainz_release_id,
+                musicbrainz_artist_id, isrc, bpm, initial_key, replay_gain_track_gain,
+                replay_gain_track_peak, replay_gain_album_gain, replay_gain_album_peak,
+                has_cover_art, tempo_confidence, id
                 from song where analyzed = true and version = ? order by id
             ";
         let features_statement = "
@@ -1372,6 +1674,35 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
         self._songs_from_statement(songs_statement, features_statement, params)
     }
 
+    /// Find the songs of the library that are most likely duplicates of
+    /// each other, using their [fingerprints](Fingerprint::matches).
+    ///
+    /// Contrary to the title / artist check done when making playlists,
+    /// this also catches the same recording released on different albums,
+    /// e.g. on a remaster or a compilation.
+    ///
+    /// Returns groups of duplicate songs, ordered by the order in which they
+    /// were added to the library. Only the songs returned by
+    /// [Library::songs_from_library] and having a fingerprint are taken
+    /// into account.
+    pub fn find_duplicates<T: Serialize + DeserializeOwned + Clone>(
+        &self,
+    ) -> Result<Vec<Vec<LibrarySong<T>>>> {
+        let songs = self
+            .songs_from_library::<T>()?
+            .into_iter()
+            .filter(|song| song.bliss_song.fingerprint.is_some())
+            .collect::<Vec<_>>();
+        let fingerprints = songs
+            .iter()
+            .filter_map(|song| song.bliss_song.fingerprint.as_ref())
+            .collect::<Vec<_>>();
+        Ok(Fingerprint::group_duplicates(&fingerprints)
+            .into_iter()
+            .map(|group| group.into_iter().map(|i| songs[i].to_owned()).collect())
+            .collect())
+    }
+
     /// Get a LibrarySong from a given album title.
     ///
     /// This will return all songs with corresponding bliss "album" tag,
@@ -1388,7 +1719,14 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
             select
                 path, artist, title, album, album_artist,
                 track_number, disc_number, genre, duration, version, extra_info, cue_path,
-                audio_file_path, id
+                audio_file_path, analysis_diagnostics, key_tonic, key_mode, key_confidence,
+                loudness_integrated, loudness_range, true_peak, extension_version,
+                extension_features, partial, audio_start, audio_end, fade_out_start,
+                fingerprint,
+                year, date, composer, musicbrainz_recording_id, musicbrainz_release_id,
+                musicbrainz_artist_id, isrc, bpm, initial_key, replay_gain_track_gain,
+                replay_gain_track_peak, replay_gain_album_gain, replay_gain_album_peak,
+                has_cover_art, tempo_confidence, id
                 from song where album = ? and analyzed = true and version = ?
                 order
                 by disc_number, track_number;
@@ -1431,7 +1769,14 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
             select
                 path, artist, title, album, album_artist,
                 track_number, disc_number, genre, duration, version, extra_info,
-                cue_path, audio_file_path
+                cue_path, audio_file_path, analysis_diagnostics, key_tonic, key_mode,
+                key_confidence, loudness_integrated, loudness_range, true_peak,
+                extension_version, extension_features, partial, audio_start, audio_end,
+                fade_out_start, fingerprint,
+                year, date, composer, musicbrainz_recording_id, musicbrainz_release_id,
+                musicbrainz_artist_id, isrc, bpm, initial_key, replay_gain_track_gain,
+                replay_gain_track_peak, replay_gain_album_gain, replay_gain_album_peak,
+                has_cover_art, tempo_confidence
                 from song where path=? and analyzed = true
             ",
             params![song_path_str],
@@ -1446,7 +1791,7 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
                 where song.path = ? order by feature_index
             ",
         )?;
-        let analysis = Analysis::new(
+        let mut analysis = Analysis::new(
             stmt.query_map(params![song_path_str], |row| row.get(0))
                 .unwrap()
                 .map(|x| x.unwrap())
@@ -1458,10 +1803,60 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
                 "song has more or less than {NUMBER_FEATURES} features",
             ))
         })?;
+        analysis.extension = song.bliss_song.analysis.extension.take();
+        analysis.partial = song.bliss_song.analysis.partial;
+        analysis.tempo_confidence = song.bliss_song.analysis.tempo_confidence;
         song.bliss_song.analysis = analysis;
+        song.bliss_song.segments = Self::_segments_from_statement(
+            &connection,
+            "
+            select start_time, end_time, features, segment.tempo_confidence from segment
+                join song on song.id = segment.song_id
+                where song.path = ? order by segment_index
+            ",
+            params![song_path_str],
+            song.bliss_song.features_version,
+        )?;
         Ok(song)
     }
 
+    // Get a song's segments from a statement selecting their start time,
+    // end time, features and tempo confidence, returning `None` if the song
+    // has no segments.
+    fn _segments_from_statement<P: Params>(
+        connection: &Connection,
+        statement: &str,
+        params: P,
+        features_version: FeaturesVersion,
+    ) -> Result<Option<Vec<AnalysisSegment>>> {
+        let mut statement = connection.prepare_cached(statement)?;
+        let segments = statement
+            .query_map(params, |row| {
+                Ok((
+                    row.get::<_, f64>(0)?,
+                    row.get::<_, f64>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<f32>>(3)?,
+                ))
+            })?
+            .map(|row| {
+                let (start, end, features, tempo_confidence) = row?;
+                let features: Vec<f32> = serde_json::from_str(&features)?;
+                let mut analysis = Analysis::new(features, features_version)?;
+                analysis.tempo_confidence = tempo_confidence;
+                Ok(AnalysisSegment {
+                    start: Duration::from_secs_f64(start),
+                    end: Duration::from_secs_f64(end),
+                    analysis,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if segments.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(segments))
+    }
+
     fn _song_from_row_closure<T: Serialize + DeserializeOwned + Clone>(
         row: &Row,
     ) -> Result<LibrarySong<T>, RusqliteError> {
@@ -1476,6 +1871,20 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
                 audio_file_path: PathBuf::from(audio_file_path.unwrap()),
             })
         };
+        // Songs analyzed before diagnostics were stored have none.
+        let diagnostics: Option<String> = row.get(13)?;
+        let diagnostics = diagnostics
+            .and_then(|d| serde_json::from_str(&d).ok())
+            .unwrap_or_default();
+        let key = key_from_columns(row.get(14)?, row.get(15)?, row.get(16)?);
+        let loudness = loudness_from_columns(row.get(17)?, row.get(18)?, row.get(19)?);
+        let extension = extension_from_columns(row.get(20)?, row.get(21)?);
+        // Songs analyzed before partial analysis existed were analyzed whole.
+        let partial: Option<bool> = row.get(22)?;
+        let boundaries = boundaries_from_columns(row.get(23)?, row.get(24)?, row.get(25)?);
+        let fingerprint = row.get(26)?;
+        let tags = tags_from_columns(row, 27)?;
+        let tempo_confidence = row.get(41)?;
 
         let song = Song {
             path: PathBuf::from(path),
@@ -1524,10 +1933,20 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
             analysis: Analysis {
                 internal_analysis: vec![0.; NUMBER_FEATURES],
                 features_version: row.get(9).unwrap(),
+                extension,
+                partial: partial.unwrap_or_default(),
+                tempo_confidence,
             },
             duration: Duration::from_secs_f64(row.get(8).unwrap()),
             features_version: row.get(9).unwrap(),
             cue_info,
+            segments: None,
+            diagnostics,
+            key,
+            loudness,
+            boundaries,
+            fingerprint,
+            tags,
         };
 
         let serialized: Option<String> = row.get(10).unwrap();
@@ -1568,10 +1987,19 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
             insert into song (
                 path, artist, title, album, album_artist,
                 duration, track_number, disc_number, genre, analyzed, version, extra_info,
-                cue_path, audio_file_path
+                cue_path, audio_file_path, analysis_diagnostics, key_tonic, key_mode,
+                key_confidence, loudness_integrated, loudness_range, true_peak,
+                extension_version, extension_features, partial, audio_start, audio_end,
+                fade_out_start, fingerprint,
+                year, date, composer, musicbrainz_recording_id, musicbrainz_release_id,
+                musicbrainz_artist_id, isrc, bpm, initial_key, replay_gain_track_gain,
+                replay_gain_track_peak, replay_gain_album_gain, replay_gain_album_peak,
+                has_cover_art, tempo_confidence
             )
             values (
-                ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14
+                ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+                ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32,
+                ?33, ?34, ?35, ?36, ?37, ?38, ?39, ?40, ?41, ?42, ?43
             )
             on conflict(path)
             do update set
@@ -1587,7 +2015,37 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
                 version=excluded.version,
                 extra_info=excluded.extra_info,
                 cue_path=excluded.cue_path,
-                audio_file_path=excluded.audio_file_path
+                audio_file_path=excluded.audio_file_path,
+                error=null,
+                analysis_diagnostics=excluded.analysis_diagnostics,
+                key_tonic=excluded.key_tonic,
+                key_mode=excluded.key_mode,
+                key_confidence=excluded.key_confidence,
+                loudness_integrated=excluded.loudness_integrated,
+                loudness_range=excluded.loudness_range,
+                true_peak=excluded.true_peak,
+                extension_version=excluded.extension_version,
+                extension_features=excluded.extension_features,
+                partial=excluded.partial,
+                audio_start=excluded.audio_start,
+                audio_end=excluded.audio_end,
+                fade_out_start=excluded.fade_out_start,
+                fingerprint=excluded.fingerprint,
+                year=excluded.year,
+                date=excluded.date,
+                composer=excluded.composer,
+                musicbrainz_recording_id=excluded.musicbrainz_recording_id,
+                musicbrainz_release_id=excluded.musicbrainz_release_id,
+                musicbrainz_artist_id=excluded.musicbrainz_artist_id,
+                isrc=excluded.isrc,
+                bpm=excluded.bpm,
+                initial_key=excluded.initial_key,
+                replay_gain_track_gain=excluded.replay_gain_track_gain,
+                replay_gain_track_peak=excluded.replay_gain_track_peak,
+                replay_gain_album_gain=excluded.replay_gain_album_gain,
+                replay_gain_album_peak=excluded.replay_gain_album_peak,
+                has_cover_art=excluded.has_cover_art,
+                tempo_confidence=excluded.tempo_confidence
             ",
             params![
                 song_path_str,
@@ -1605,6 +2063,43 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
                     .map_err(|e| BlissError::ProviderError(e.to_string()))?,
                 cue_path,
                 audio_file_path,
+                serde_json::to_string(&song.diagnostics)
+                    .map_err(|e| BlissError::ProviderError(e.to_string()))?,
+                song.key.map(|k| k.tonic),
+                song.key.map(|k| k.mode),
+                song.key.map(|k| k.confidence),
+                song.loudness.map(|l| l.integrated),
+                song.loudness.map(|l| l.range),
+                song.loudness.map(|l| l.true_peak),
+                song.analysis.extension.as_ref().map(|e| e.version),
+                song.analysis
+                    .extension
+                    .as_ref()
+                    .map(|e| serde_json::to_string(&e.features))
+                    .transpose()
+                    .map_err(|e| BlissError::ProviderError(e.to_string()))?,
+                song.analysis.partial,
+                song.boundaries.map(|b| b.audio_start.as_secs_f64()),
+                song.boundaries.map(|b| b.audio_end.as_secs_f64()),
+                song.boundaries
+                    .and_then(|b| b.fade_out_start)
+                    .map(|f| f.as_secs_f64()),
+                song.fingerprint,
+                song.tags.year,
+                song.tags.date,
+                song.tags.composer,
+                song.tags.musicbrainz_recording_id,
+                song.tags.musicbrainz_release_id,
+                song.tags.musicbrainz_artist_id,
+                song.tags.isrc,
+                song.tags.bpm,
+                song.tags.initial_key,
+                song.tags.replay_gain_track_gain,
+                song.tags.replay_gain_track_peak,
+                song.tags.replay_gain_album_gain,
+                song.tags.replay_gain_album_peak,
+                song.tags.has_cover_art,
+                song.analysis.tempo_confidence,
             ],
         )
         .map_err(|e| BlissError::ProviderError(e.to_string()))?;
@@ -1627,6 +2122,34 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
             )
             .map_err(|e| BlissError::ProviderError(e.to_string()))?;
         }
+
+        // Override existing segments.
+        tx.execute(
+            "delete from segment where song_id in (select id from song where path = ?1);",
+            params![song_path_str],
+        )
+        .map_err(|e| BlissError::ProviderError(e.to_string()))?;
+
+        for (index, segment) in song.segments.iter().flatten().enumerate() {
+            tx.execute(
+                "
+                insert into segment (
+                    song_id, segment_index, start_time, end_time, features, tempo_confidence
+                )
+                values ((select id from song where path = ?1), ?2, ?3, ?4, ?5, ?6);
+                ",
+                params![
+                    song_path_str,
+                    index as u32,
+                    segment.start.as_secs_f64(),
+                    segment.end.as_secs_f64(),
+                    serde_json::to_string(&segment.analysis.as_vec())
+                        .map_err(|e| BlissError::ProviderError(e.to_string()))?,
+                    segment.analysis.tempo_confidence,
+                ],
+            )
+            .map_err(|e| BlissError::ProviderError(e.to_string()))?;
+        }
         tx.commit()
             .map_err(|e| BlissError::ProviderError(e.to_string()))?;
         Ok(())
@@ -1667,6 +2190,30 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
         Ok(())
     }
 
+    // Record the error raised while upgrading the partial analysis of the
+    // song at `song_path`, or of the tracks of the CUE file at `song_path`,
+    // without replacing their partial analysis.
+    fn _store_failed_upgrade(&mut self, song_path: impl AsRef<Path>, e: BlissError) -> Result<()> {
+        let song_path_str = song_path.as_ref().to_str().ok_or_else(|| {
+            BlissError::ProviderError(format!(
+                "path contains invalid UTF-8: {}",
+                song_path.as_ref().display()
+            ))
+        })?;
+        self.sqlite_conn
+            .lock()
+            .unwrap()
+            .execute(
+                "
+            update song set error = ?2
+                where (path = ?1 or cue_path = ?1) and analyzed = true and partial = true
+            ",
+                params![song_path_str, e.to_string()],
+            )
+            .map_err(|e| BlissError::ProviderError(e.to_string()))?;
+        Ok(())
+    }
+
     /// Return all the songs that failed the analysis.
     pub fn get_failed_songs(&self) -> Result<Vec<ProcessingError>> {
         let conn = self.sqlite_conn.lock().unwrap();
@@ -1749,6 +2296,74 @@ impl<Config: AppConfigTrait, D: ?Sized + DecoderTrait> Library<Config, D> {
     }
 }
 
+// Build a song's loudness from its loudness columns, if it has one.
+fn loudness_from_columns(
+    integrated: Option<f32>,
+    range: Option<f32>,
+    true_peak: Option<f32>,
+) -> Option<Loudness> {
+    Some(Loudness {
+        integrated: integrated?,
+        range: range?,
+        true_peak: true_peak?,
+    })
+}
+
+// Build a song's tags from the 14 columns starting at `first_column`.
+fn tags_from_columns(row: &Row, first_column: usize) -> rusqlite::Result<Tags> {
+    let column = |i: usize| first_column + i;
+    Ok(Tags {
+        year: row.get(column(0))?,
+        date: row.get(column(1))?,
+        composer: row.get(column(2))?,
+        musicbrainz_recording_id: row.get(column(3))?,
+        musicbrainz_release_id: row.get(column(4))?,
+        musicbrainz_artist_id: row.get(column(5))?,
+        isrc: row.get(column(6))?,
+        bpm: row.get(column(7))?,
+        initial_key: row.get(column(8))?,
+        replay_gain_track_gain: row.get(column(9))?,
+        replay_gain_track_peak: row.get(column(10))?,
+        replay_gain_album_gain: row.get(column(11))?,
+        replay_gain_album_peak: row.get(column(12))?,
+        // Songs stored before tags were stored have no cover art flag.
+        has_cover_art: row.get::<_, Option<bool>>(column(13))?.unwrap_or_default(),
+    })
+}
+
+// Build a song's boundaries from their columns, if it has some.
+fn boundaries_from_columns(
+    audio_start: Option<f64>,
+    audio_end: Option<f64>,
+    fade_out_start: Option<f64>,
+) -> Option<Boundaries> {
+    Some(Boundaries {
+        audio_start: Duration::from_secs_f64(audio_start?),
+        audio_end: Duration::from_secs_f64(audio_end?),
+        fade_out_start: fade_out_start.map(Duration::from_secs_f64),
+    })
+}
+
+// Build a song's extension features from their columns, if it has some.
+fn extension_from_columns(
+    version: Option<u32>,
+    features: Option<String>,
+) -> Option<AnalysisExtension> {
+    Some(AnalysisExtension {
+        version: version?,
+        features: serde_json::from_str(&features?).ok()?,
+    })
+}
+
+// Build a song's key from its key columns, if it has one.
+fn key_from_columns(tonic: Option<u8>, mode: Option<Mode>, confidence: Option<f32>) -> Option<Key> {
+    Some(Key {
+        tonic: tonic?,
+        mode: mode?,
+        confidence: confidence?,
+    })
+}
+
 // Copied from
 // https://docs.rs/rusqlite/latest/rusqlite/struct.ParamsFromIter.html#realistic-use-case
 fn repeat_vars(count: usize) -> String {
@@ -1774,7 +2389,10 @@ fn config_local_dir() -> Option<PathBuf> {
 // TODO the tests should really open a songs.db
 mod test {
     use super::*;
-    use crate::{decoder::PreAnalyzedSong, Analysis, NUMBER_FEATURES};
+    use crate::{
+        decoder::{fallback::FallbackDecoder, PreAnalyzedSong},
+        Analysis, AnalysisDiagnostics, AnalysisWarning, NUMBER_FEATURES,
+    };
     use ndarray::Array1;
     use pretty_assertions::assert_eq;
     use serde::{de::DeserializeOwned, Deserialize};
@@ -1798,6 +2416,53 @@ mod test {
         }
     }
 
+    struct PanickingDecoder;
+
+    // Here to test that a panicking decoder doesn't stop the analysis
+    impl DecoderTrait for PanickingDecoder {
+        fn decode(path: &Path) -> crate::BlissResult<crate::decoder::PreAnalyzedSong> {
+            if path.to_string_lossy().contains("panic") {
+                panic!("could not decode {}", path.display());
+            }
+            Ok(PreAnalyzedSong {
+                path: path.to_owned(),
+                sample_array: vec![0.; crate::SAMPLE_RATE as usize * 2],
+                ..Default::default()
+            })
+        }
+    }
+
+    struct ExistingFileDecoder;
+
+    // Here to test songs that can't be decoded anymore
+    impl DecoderTrait for ExistingFileDecoder {
+        fn decode(path: &Path) -> crate::BlissResult<crate::decoder::PreAnalyzedSong> {
+            if !path.exists() {
+                return Err(BlissError::DecodingError(format!(
+                    "{} does not exist",
+                    path.display()
+                )));
+            }
+            Ok(PreAnalyzedSong {
+                path: path.to_owned(),
+                sample_array: vec![0.; crate::SAMPLE_RATE as usize * 4],
+                ..Default::default()
+            })
+        }
+    }
+
+    struct FailingDecoder;
+
+    // Here to test a library using a fallback decoder
+    impl DecoderTrait for FailingDecoder {
+        fn decode(path: &Path) -> crate::BlissResult<crate::decoder::PreAnalyzedSong> {
+            if path.to_string_lossy().contains("fallback") {
+                return Err(BlissError::DecodingError(String::from("unsupported codec")));
+            }
+            PanickingDecoder::decode(path)
+        }
+    }
+
     #[derive(Deserialize, Serialize, Debug, PartialEq, Clone, Default)]
     struct ExtraInfo {
         ignore: bool,
@@ -1875,10 +2540,20 @@ mod test {
             analysis: Analysis {
                 internal_analysis: analysis_vector,
                 features_version: FeaturesVersion::LATEST,
+                extension: None,
+                partial: false,
+                tempo_confidence: None,
             },
             duration: Duration::from_secs(310),
             features_version: FeaturesVersion::LATEST,
             cue_info: None,
+            segments: None,
+            diagnostics: AnalysisDiagnostics::default(),
+            key: None,
+            loudness: None,
+            boundaries: None,
+            fingerprint: None,
+            tags: Tags::default(),
         };
         let first_song = LibrarySong {
             bliss_song: song,
@@ -1904,10 +2579,20 @@ mod test {
             analysis: Analysis {
                 internal_analysis: analysis_vector,
                 features_version: FeaturesVersion::LATEST,
+                extension: None,
+                partial: false,
+                tempo_confidence: None,
             },
             duration: Duration::from_secs(410),
             features_version: FeaturesVersion::LATEST,
             cue_info: None,
+            segments: None,
+            diagnostics: AnalysisDiagnostics::default(),
+            key: None,
+            loudness: None,
+            boundaries: None,
+            fingerprint: None,
+            tags: Tags::default(),
         };
         let second_song = LibrarySong {
             bliss_song: song,
@@ -1933,10 +2618,20 @@ mod test {
             analysis: Analysis {
                 internal_analysis: analysis_vector,
                 features_version: FeaturesVersion::LATEST,
+                extension: None,
+                partial: false,
+                tempo_confidence: None,
             },
             duration: Duration::from_secs(410),
             features_version: FeaturesVersion::LATEST,
             cue_info: None,
+            segments: None,
+            diagnostics: AnalysisDiagnostics::default(),
+            key: None,
+            loudness: None,
+            boundaries: None,
+            fingerprint: None,
+            tags: Tags::default(),
         };
         let second_song_dupe = LibrarySong {
             bliss_song: song,
@@ -1962,10 +2657,20 @@ mod test {
             analysis: Analysis {
                 internal_analysis: analysis_vector,
                 features_version: FeaturesVersion::LATEST,
+                extension: None,
+                partial: false,
+                tempo_confidence: None,
             },
             duration: Duration::from_secs(610),
             features_version: FeaturesVersion::LATEST,
             cue_info: None,
+            segments: None,
+            diagnostics: AnalysisDiagnostics::default(),
+            key: None,
+            loudness: None,
+            boundaries: None,
+            fingerprint: None,
+            tags: Tags::default(),
         };
         let third_song = LibrarySong {
             bliss_song: song,
@@ -1991,10 +2696,20 @@ mod test {
             analysis: Analysis {
                 internal_analysis: analysis_vector,
                 features_version: FeaturesVersion::LATEST,
+                extension: None,
+                partial: false,
+                tempo_confidence: None,
             },
             duration: Duration::from_secs(710),
             features_version: FeaturesVersion::LATEST,
             cue_info: None,
+            segments: None,
+            diagnostics: AnalysisDiagnostics::default(),
+            key: None,
+            loudness: None,
+            boundaries: None,
+            fingerprint: None,
+            tags: Tags::default(),
         };
         let fourth_song = LibrarySong {
             bliss_song: song,
@@ -2020,10 +2735,20 @@ mod test {
             analysis: Analysis {
                 internal_analysis: analysis_vector,
                 features_version: FeaturesVersion::LATEST,
+                extension: None,
+                partial: false,
+                tempo_confidence: None,
             },
             duration: Duration::from_secs(810),
             features_version: FeaturesVersion::LATEST,
             cue_info: None,
+            segments: None,
+            diagnostics: AnalysisDiagnostics::default(),
+            key: None,
+            loudness: None,
+            boundaries: None,
+            fingerprint: None,
+            tags: Tags::default(),
         };
         let fifth_song = LibrarySong {
             bliss_song: song,
@@ -2049,6 +2774,9 @@ mod test {
             analysis: Analysis {
                 internal_analysis: analysis_vector,
                 features_version: FeaturesVersion::LATEST,
+                extension: None,
+                partial: false,
+                tempo_confidence: None,
             },
             duration: Duration::from_secs(810),
             features_version: FeaturesVersion::LATEST,
@@ -2056,6 +2784,13 @@ mod test {
                 cue_path: PathBuf::from("/path/to/cuetrack.cue"),
                 audio_file_path: PathBuf::from("/path/to/cuetrack.flac"),
             }),
+            segments: None,
+            diagnostics: AnalysisDiagnostics::default(),
+            key: None,
+            loudness: None,
+            boundaries: None,
+            fingerprint: None,
+            tags: Tags::default(),
         };
         let sixth_song = LibrarySong {
             bliss_song: song,
@@ -2081,6 +2816,9 @@ mod test {
             analysis: Analysis {
                 internal_analysis: analysis_vector,
                 features_version: FeaturesVersion::LATEST,
+                extension: None,
+                partial: false,
+                tempo_confidence: None,
             },
             duration: Duration::from_secs(910),
             features_version: FeaturesVersion::LATEST,
@@ -2088,6 +2826,13 @@ mod test {
                 cue_path: PathBuf::from("/path/to/cuetrack.cue"),
                 audio_file_path: PathBuf::from("/path/to/cuetrack.flac"),
             }),
+            segments: None,
+            diagnostics: AnalysisDiagnostics::default(),
+            key: None,
+            loudness: None,
+            boundaries: None,
+            fingerprint: None,
+            tags: Tags::default(),
         };
         let seventh_song = LibrarySong {
             bliss_song: song,
@@ -2270,7 +3015,14 @@ mod test {
             select
                 path, artist, title, album, album_artist,
                 track_number, disc_number, genre, duration, version, extra_info,
-                cue_path, audio_file_path
+                cue_path, audio_file_path, analysis_diagnostics, key_tonic, key_mode,
+                key_confidence, loudness_integrated, loudness_range, true_peak,
+                extension_version, extension_features, partial, audio_start, audio_end,
+                fade_out_start, fingerprint,
+                year, date, composer, musicbrainz_recording_id, musicbrainz_release_id,
+                musicbrainz_artist_id, isrc, bpm, initial_key, replay_gain_track_gain,
+                replay_gain_track_peak, replay_gain_album_gain, replay_gain_album_peak,
+                has_cover_art, tempo_confidence
                 from song where path=?
             ",
                 params![song_path],
@@ -2286,6 +3038,28 @@ mod test {
                         })
                     };
                     let features_version: FeaturesVersion = row.get(9).unwrap();
+                    let diagnostics: Option<String> = row.get(13).unwrap();
+                    let key = key_from_columns(
+                        row.get(14).unwrap(),
+                        row.get(15).unwrap(),
+                        row.get(16).unwrap(),
+                    );
+                    let loudness = loudness_from_columns(
+                        row.get(17).unwrap(),
+                        row.get(18).unwrap(),
+                        row.get(19).unwrap(),
+                    );
+                    let extension =
+                        extension_from_columns(row.get(20).unwrap(), row.get(21).unwrap());
+                    let partial: bool = row.get(22).unwrap();
+                    let boundaries = boundaries_from_columns(
+                        row.get(23).unwrap(),
+                        row.get(24).unwrap(),
+                        row.get(25).unwrap(),
+                    );
+                    let fingerprint = row.get(26).unwrap();
+                    let tags = tags_from_columns(row, 27).unwrap();
+                    let tempo_confidence = row.get(41).unwrap();
                     let song = Song {
                         path: PathBuf::from(path),
                         artist: row.get(1).unwrap(),
@@ -2298,10 +3072,22 @@ mod test {
                         analysis: Analysis {
                             internal_analysis: vec![0.; features_version.feature_count()],
                             features_version: features_version,
+                            extension,
+                            partial,
+                            tempo_confidence,
                         },
                         duration: Duration::from_secs_f64(row.get(8).unwrap()),
                         features_version: features_version,
                         cue_info,
+                        segments: None,
+                        diagnostics: diagnostics
+                            .map(|d| serde_json::from_str(&d).unwrap())
+                            .unwrap_or_default(),
+                        key,
+                        loudness,
+                        boundaries,
+                        fingerprint,
+                        tags,
                     };
 
                     let serialized: String = row.get(10).unwrap();
@@ -2332,6 +3118,9 @@ mod test {
                 .try_into()
                 .unwrap(),
             features_version: song.bliss_song.analysis.features_version,
+            extension: song.bliss_song.analysis.extension.take(),
+            partial: song.bliss_song.analysis.partial,
+            tempo_confidence: song.bliss_song.analysis.tempo_confidence,
         };
         song.bliss_song.analysis = analysis_vector;
         song
@@ -2360,11 +3149,21 @@ mod test {
                         genre: row.get(7).unwrap(),
                         analysis: Analysis {
                             internal_analysis: vec![0.; NUMBER_FEATURES],
-                            features_version: FeaturesVersion::Version2,
+                            features_version: FeaturesVersion::LATEST,
+                            extension: None,
+                            partial: false,
+                            tempo_confidence: None,
                         },
                         duration: Duration::from_secs_f64(row.get(8).unwrap()),
                         features_version: row.get(9).unwrap(),
                         cue_info: None,
+                        segments: None,
+                        diagnostics: AnalysisDiagnostics::default(),
+                        key: None,
+                        loudness: None,
+                        boundaries: None,
+                        fingerprint: None,
+                        tags: Tags::default(),
                     })
                 },
             )
@@ -2390,7 +3189,10 @@ mod test {
                     BlissError::ProviderError(format!("Could not retrieve analysis for song {} that was supposed to be analyzed: {:?}.", song_path, v))
                 })
                 .unwrap(),
-                features_version: FeaturesVersion::Version2,
+                features_version: FeaturesVersion::LATEST,
+            extension: None,
+            partial: false,
+            tempo_confidence: None,
         };
         expected_song.analysis = expected_analysis_vector;
         expected_song
@@ -2413,11 +3215,21 @@ mod test {
             genre: Some("Electronica".into()),
             analysis: Analysis {
                 internal_analysis: analysis_vector,
-                features_version: FeaturesVersion::Version2,
+                features_version: FeaturesVersion::LATEST,
+                extension: None,
+                partial: false,
+                tempo_confidence: None,
             },
             duration: Duration::from_secs(80),
             features_version: FeaturesVersion::LATEST,
             cue_info: None,
+            segments: None,
+            diagnostics: AnalysisDiagnostics::default(),
+            key: None,
+            loudness: None,
+            boundaries: None,
+            fingerprint: None,
+            tags: Tags::default(),
         }
     }
 
@@ -2433,6 +3245,25 @@ mod test {
         }
     }
 
+    // Store `song` in `library`, which shouldn't hold any other song, and
+    // check that it reads back the same.
+    fn _assert_store_round_trip(
+        library: &mut Library<BaseConfig, DummyDecoder>,
+        song: &LibrarySong<ExtraInfo>,
+    ) {
+        library.store_song(song).unwrap();
+        assert_eq!(
+            &library
+                .song_from_path::<ExtraInfo>(song.bliss_song.path.to_str().unwrap())
+                .unwrap(),
+            song,
+        );
+        assert_eq!(
+            library.songs_from_library::<ExtraInfo>().unwrap(),
+            vec![song.to_owned()],
+        );
+    }
+
     fn first_factor_distance(a: &Array1<f32>, b: &Array1<f32>) -> f32 {
         (a[1] - b[1]).abs()
     }
@@ -3235,6 +4066,9 @@ mod test {
                         18.
                     ],
                     features_version: FeaturesVersion::Version1,
+                    extension: None,
+                    partial: false,
+                    tempo_confidence: None,
                 }
             )
         }
@@ -3427,6 +4261,126 @@ mod test {
         );
     }
 
+    #[test]
+    #[cfg(feature = "ffmpeg")]
+    fn test_upgrade_partial_songs() {
+        use crate::ExcerptOptions;
+
+        let (mut library, _temp_dir, _) = setup_test_library();
+        let excerpt = Some(ExcerptOptions::Middle(Duration::from_secs(2)));
+        library.config.base_config_mut().analysis_options = AnalysisOptions {
+            excerpt,
+            ..Default::default()
+        };
+        let extra_info = ExtraInfo {
+            ignore: true,
+            metadata_bliss_does_not_have: String::from("coucou"),
+        };
+        let paths = vec!["./data/s16_mono_22_5kHz.flac", "./data/testcue.cue"];
+        library
+            .update_library_extra_info(
+                paths
+                    .iter()
+                    .map(|path| (*path, extra_info.clone()))
+                    .collect(),
+                false,
+                false,
+            )
+            .unwrap();
+        let analyzed_paths = [
+            "./data/s16_mono_22_5kHz.flac",
+            "./data/testcue.cue/CUE_TRACK001",
+            "./data/testcue.cue/CUE_TRACK002",
+            "./data/testcue.cue/CUE_TRACK003",
+        ];
+        for path in analyzed_paths {
+            let song = library.song_from_path::<ExtraInfo>(path).unwrap();
+            assert!(song.bliss_song.analysis.partial);
+        }
+
+        // Asking for excerpts again doesn't reanalyze anything.
+        library
+            .update_library_with_options(
+                vec!["./data/s16_mono_22_5kHz.flac"],
+                false,
+                false,
+                library.config.base_config().analysis_options.clone(),
+            )
+            .unwrap();
+        let song = library
+            .song_from_path::<ExtraInfo>(analyzed_paths[0])
+            .unwrap();
+        assert!(song.bliss_song.analysis.partial);
+
+        library.upgrade_partial_songs::<ExtraInfo>(false).unwrap();
+        for path in analyzed_paths {
+            let song = library.song_from_path::<ExtraInfo>(path).unwrap();
+            assert!(!song.bliss_song.analysis.partial);
+            assert_eq!(song.extra_info, extra_info);
+        }
+        let song = library
+            .song_from_path::<ExtraInfo>(analyzed_paths[0])
+            .unwrap();
+        assert_eq!(
+            song.bliss_song,
+            Decoder::song_from_path(analyzed_paths[0]).unwrap()
+        );
+        assert_eq!(
+            library.config.base_config().analysis_options.excerpt,
+            excerpt
+        );
+    }
+
+    #[test]
+    fn test_upgrade_partial_songs_failure() {
+        use crate::ExcerptOptions;
+
+        let config_dir = TempDir::new("bliss-test").unwrap();
+        let mut library = Library::<BaseConfig, ExistingFileDecoder>::new_from_base(
+            Some(config_dir.path().join("config.json")),
+            Some(config_dir.path().join("songs.db")),
+            None,
+        )
+        .unwrap();
+        library.config.base_config_mut().analysis_options = AnalysisOptions {
+            excerpt: Some(ExcerptOptions::Middle(Duration::from_secs(1))),
+            ..Default::default()
+        };
+        let song_path = config_dir.path().join("song.flac");
+        std::fs::write(&song_path, "").unwrap();
+        library
+            .update_library(vec![song_path.to_owned()], false, false)
+            .unwrap();
+        let partial_song = library.song_from_path::<()>(&song_path).unwrap();
+        assert!(partial_song.bliss_song.analysis.partial);
+
+        // The song can't be decoded anymore, but its partial analysis is kept.
+        std::fs::remove_file(&song_path).unwrap();
+        library.upgrade_partial_songs::<()>(false).unwrap();
+        assert_eq!(
+            library.song_from_path::<()>(&song_path).unwrap(),
+            partial_song
+        );
+        assert_eq!(
+            library.get_failed_songs().unwrap(),
+            vec![ProcessingError {
+                song_path: song_path.to_owned(),
+                error: format!(
+                    "error happened while decoding file - {} does not exist",
+                    song_path.display()
+                ),
+                features_version: FeaturesVersion::LATEST,
+            }]
+        );
+
+        // It is upgraded once it can be decoded again.
+        std::fs::write(&song_path, "").unwrap();
+        library.upgrade_partial_songs::<()>(false).unwrap();
+        let song = library.song_from_path::<()>(&song_path).unwrap();
+        assert!(!song.bliss_song.analysis.partial);
+        assert_eq!(library.get_failed_songs().unwrap(), vec![]);
+    }
+
     #[test]
     #[cfg(feature = "ffmpeg")]
     fn test_update_extra_info() {
@@ -3608,11 +4562,21 @@ mod test {
             genre: Some("Electronica2001".into()),
             analysis: Analysis {
                 internal_analysis: analysis_vector,
-                features_version: FeaturesVersion::Version2,
+                features_version: FeaturesVersion::LATEST,
+                extension: None,
+                partial: false,
+                tempo_confidence: None,
             },
             duration: Duration::from_secs(410),
-            features_version: FeaturesVersion::Version2,
+            features_version: FeaturesVersion::LATEST,
             cue_info: None,
+            segments: None,
+            diagnostics: AnalysisDiagnostics::default(),
+            key: None,
+            loudness: None,
+            boundaries: None,
+            fingerprint: None,
+            tags: Tags::default(),
         };
         let expected_song = LibrarySong {
             bliss_song: song,
@@ -3628,6 +4592,165 @@ mod test {
         assert_eq!(song, expected_song)
     }
 
+    #[test]
+    fn test_store_song_round_trip() {
+        let config_dir = TempDir::new("bliss-test").unwrap();
+        let mut library = Library::<BaseConfig, DummyDecoder>::new_from_base(
+            Some(config_dir.path().join("config.json")),
+            Some(config_dir.path().join("songs.db")),
+            None,
+        )
+        .unwrap();
+
+        let mut song = _generate_library_song(Some("/path/to/song".into()));
+        song.bliss_song.analysis.tempo_confidence = Some(0.8);
+        song.bliss_song.segments = Some(
+            (0..3)
+                .map(|i| {
+                    let mut analysis = Analysis::new(
+                        vec![i as f32 + 0.5; NUMBER_FEATURES],
+                        FeaturesVersion::LATEST,
+                    )
+                    .unwrap();
+                    analysis.tempo_confidence = Some(i as f32 / 4.);
+                    AnalysisSegment {
+                        start: Duration::from_secs(5 * i),
+                        end: Duration::from_secs(5 * i + 10),
+                        analysis,
+                    }
+                })
+                .collect(),
+        );
+        song.bliss_song.diagnostics = AnalysisDiagnostics {
+            warnings: vec![
+                AnalysisWarning::NoBeatsFound,
+                AnalysisWarning::MostlySilent { silent_ratio: 0.9 },
+                AnalysisWarning::SampleCountMismatch {
+                    expected: 22050,
+                    actual: 11025,
+                },
+                AnalysisWarning::DecodeErrors { skipped: 3 },
+                AnalysisWarning::Truncated,
+            ],
+        };
+        song.bliss_song.key = Some(Key {
+            tonic: 9,
+            mode: Mode::Minor,
+            confidence: 0.8,
+        });
+        song.bliss_song.loudness = Some(Loudness {
+            integrated: -14.5,
+            range: 6.2,
+            true_peak: -0.8,
+        });
+        song.bliss_song.analysis.extension = Some(AnalysisExtension {
+            version: 3,
+            features: vec![0.5, -1., 0.25],
+        });
+        song.bliss_song.analysis.partial = true;
+        song.bliss_song.boundaries = Some(Boundaries {
+            audio_start: Duration::from_secs_f64(12345. / 22050.),
+            audio_end: Duration::from_secs(200),
+            fade_out_start: Some(Duration::from_millis(190500)),
+        });
+        song.bliss_song.fingerprint = Some(Fingerprint {
+            codes: vec![0, 1, 0xFFFFFF, 123456],
+        });
+        song.bliss_song.tags = Tags {
+            year: Some(2021),
+            date: Some(String::from("2021-03-14")),
+            composer: Some(String::from("A Composer")),
+            musicbrainz_recording_id: Some(String::from("4b4e2bd2-2b44-4f2b-bf25-c3b0e1b8a1a6")),
+            musicbrainz_release_id: Some(String::from("f1a1d0c2-6c26-4f8c-9a4e-b7f2c3c3d2f1")),
+            musicbrainz_artist_id: Some(String::from("0383dadf-2a4e-4d10-a46a-e9e041da8eb3")),
+            isrc: Some(String::from("USRC17607839")),
+            bpm: Some(128.5),
+            initial_key: Some(String::from("Am")),
+            replay_gain_track_gain: Some(-6.48),
+            replay_gain_track_peak: Some(0.988553),
+            replay_gain_album_gain: Some(1.2),
+            replay_gain_album_peak: Some(1.),
+            has_cover_art: true,
+        };
+        _assert_store_round_trip(&mut library, &song);
+
+        // Songs stored before diagnostics existed have none.
+        library
+            .sqlite_conn
+            .lock()
+            .unwrap()
+            .execute("update song set analysis_diagnostics = null", [])
+            .unwrap();
+        let mut expected_song = song.to_owned();
+        expected_song.bliss_song.diagnostics = AnalysisDiagnostics::default();
+        assert_eq!(
+            library
+                .song_from_path::<ExtraInfo>("/path/to/song")
+                .unwrap(),
+            expected_song,
+        );
+
+        // Storing the song again without them clears the optional fields.
+        song.bliss_song.analysis.tempo_confidence = None;
+        song.bliss_song.segments = None;
+        song.bliss_song.diagnostics = AnalysisDiagnostics::default();
+        song.bliss_song.key = None;
+        song.bliss_song.loudness = None;
+        song.bliss_song.analysis.extension = None;
+        song.bliss_song.analysis.partial = false;
+        song.bliss_song.boundaries = Some(Boundaries {
+            audio_start: Duration::ZERO,
+            audio_end: Duration::from_secs(200),
+            fade_out_start: None,
+        });
+        song.bliss_song.fingerprint = None;
+        song.bliss_song.tags = Tags::default();
+        _assert_store_round_trip(&mut library, &song);
+        let count: u32 = library
+            .sqlite_conn
+            .lock()
+            .unwrap()
+            .query_row("select count(*) from segment", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_find_duplicates() {
+        let config_dir = TempDir::new("bliss-test").unwrap();
+        let mut library = Library::<BaseConfig, DummyDecoder>::new_from_base(
+            Some(config_dir.path().join("config.json")),
+            Some(config_dir.path().join("songs.db")),
+            None,
+        )
+        .unwrap();
+        assert!(library.find_duplicates::<ExtraInfo>().unwrap().is_empty());
+
+        let codes = (0..100).map(|i| i * 7919 % (1 << 24)).collect::<Vec<u32>>();
+        let fingerprints = [
+            Some(codes.to_owned()),
+            Some(codes.iter().map(|c| !c & 0xFFFFFF).collect()),
+            None,
+            // The first song, from a compilation.
+            Some(codes[5..].to_vec()),
+        ];
+        let songs = fingerprints
+            .into_iter()
+            .enumerate()
+            .map(|(i, codes)| {
+                let mut song = _generate_library_song(Some(format!("/path/to/song{i}")));
+                song.bliss_song.fingerprint = codes.map(|codes| Fingerprint { codes });
+                library.store_song(&song).unwrap();
+                song
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            library.find_duplicates::<ExtraInfo>().unwrap(),
+            vec![vec![songs[0].to_owned(), songs[3].to_owned()]],
+        );
+    }
+
     #[test]
     #[cfg(target_family = "unix")]
     fn test_store_song_utf8_failure() {
@@ -3962,6 +5085,14 @@ mod test {
             Some(AnalysisOptions {
                 number_cores: nzus(1),
                 features_version: FeaturesVersion::Version1,
+                segments: None,
+                extensions: None,
+                excerpt: None,
+                trim: None,
+                cancellation: None,
+                timeout: None,
+                delivery: None,
+                progress: None,
             }),
         )
         .unwrap();
@@ -3985,7 +5116,7 @@ mod test {
         let version: u32 = sqlite_conn
             .query_row("pragma user_version", [], |row| row.get(0))
             .unwrap();
-        assert_eq!(version, 5);
+        assert_eq!(version, 15);
         // Make sure we can call this over and over without any problem
         Library::<BaseConfig, DummyDecoder>::new_from_base(
             Some(config_dir.path().join("config.txt")),
@@ -3999,7 +5130,7 @@ mod test {
         let version: u32 = sqlite_conn
             .query_row("pragma user_version", [], |row| row.get(0))
             .unwrap();
-        assert_eq!(version, 5);
+        assert_eq!(version, 15);
     }
 
     #[test]
@@ -4029,7 +5160,7 @@ mod test {
         let version: u32 = sqlite_conn
             .query_row("pragma user_version", [], |row| row.get(0))
             .unwrap();
-        assert_eq!(version, 5);
+        assert_eq!(version, 15);
     }
 
     #[test]
@@ -4060,6 +5191,14 @@ mod test {
             Some(AnalysisOptions {
                 number_cores: NonZeroUsize::new(4).unwrap(),
                 features_version: FeaturesVersion::Version1,
+                segments: None,
+                extensions: None,
+                excerpt: None,
+                trim: None,
+                cancellation: None,
+                timeout: None,
+                delivery: None,
+                progress: None,
             }),
         )
         .unwrap();
@@ -4074,7 +5213,15 @@ mod test {
                 database_path: database_path,
                 analysis_options: AnalysisOptions {
                     number_cores: NonZeroUsize::new(4).unwrap(),
-                    features_version: FeaturesVersion::Version1
+                    features_version: FeaturesVersion::Version1,
+                    segments: None,
+                    extensions: None,
+                    excerpt: None,
+                    trim: None,
+                    cancellation: None,
+                    timeout: None,
+                    delivery: None,
+                    progress: None,
                 },
                 m: default_m(),
             }
@@ -4109,6 +5256,14 @@ mod test {
                 analysis_options: AnalysisOptions {
                     number_cores: cores,
                     features_version: FeaturesVersion::LATEST,
+                    segments: None,
+                    extensions: None,
+                    excerpt: None,
+                    trim: None,
+                    cancellation: None,
+                    timeout: None,
+                    delivery: None,
+                    progress: None,
                 },
                 m: default_m(),
             }
@@ -4285,7 +5440,15 @@ mod test {
                 database_path: PathBuf::from_str("/tmp/bliss-rs/songs.db").unwrap(),
                 analysis_options: AnalysisOptions {
                     features_version: FeaturesVersion::Version1,
-                    number_cores: NonZeroUsize::new(8).unwrap()
+                    number_cores: NonZeroUsize::new(8).unwrap(),
+                    segments: None,
+                    extensions: None,
+                    excerpt: None,
+                    trim: None,
+                    cancellation: None,
+                    timeout: None,
+                    delivery: None,
+                    progress: None,
                 },
                 m,
             }
@@ -4302,7 +5465,15 @@ mod test {
                 database_path: PathBuf::from_str("/tmp/bliss-rs/songs.db").unwrap(),
                 analysis_options: AnalysisOptions {
                     features_version: FeaturesVersion::Version1,
-                    number_cores: NonZeroUsize::new(8).unwrap()
+                    number_cores: NonZeroUsize::new(8).unwrap(),
+                    segments: None,
+                    extensions: None,
+                    excerpt: None,
+                    trim: None,
+                    cancellation: None,
+                    timeout: None,
+                    delivery: None,
+                    progress: None,
                 },
                 m: Array2::eye(NUMBER_FEATURES),
             }
@@ -4323,6 +5494,14 @@ mod test {
             Some(AnalysisOptions {
                 number_cores: nzus(1),
                 features_version: FeaturesVersion::Version1,
+                segments: None,
+                extensions: None,
+                excerpt: None,
+                trim: None,
+                cancellation: None,
+                timeout: None,
+                delivery: None,
+                progress: None,
             }),
         )
         .unwrap();
@@ -4348,7 +5527,7 @@ mod test {
             library.version_sanity_check().unwrap(),
             vec![
                 SanityError::MultipleVersionsInDB {
-                    versions: vec![FeaturesVersion::Version1, FeaturesVersion::Version2]
+                    versions: vec![FeaturesVersion::Version1, FeaturesVersion::LATEST]
                 },
                 SanityError::OldFeaturesVersionInDB {
                     version: FeaturesVersion::Version1
@@ -4374,6 +5553,25 @@ mod test {
         assert!(library.version_sanity_check().unwrap().is_empty());
     }
 
+    #[test]
+    #[cfg(feature = "ffmpeg")]
+    fn test_library_sanity_check_previous_version() {
+        let (mut library, _temp_dir, _) = setup_test_library();
+        {
+            let sqlite_conn =
+                Connection::open(&library.config.base_config().database_path).unwrap();
+            sqlite_conn
+                .execute("update song set version = ?1", [FeaturesVersion::Version3])
+                .unwrap();
+        }
+        assert_eq!(
+            library.version_sanity_check().unwrap(),
+            vec![SanityError::OldFeaturesVersionInDB {
+                version: FeaturesVersion::Version3
+            }],
+        );
+    }
+
     #[test]
     fn test_config_number_cpus() {
         let config_dir = TempDir::new("bliss-tests").unwrap();
@@ -4503,6 +5701,81 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_analyze_store_panicked_songs() {
+        let config_dir = TempDir::new("bliss-test").unwrap();
+        let mut library = Library::<BaseConfig, PanickingDecoder>::new_from_base(
+            Some(config_dir.path().join("config.json")),
+            Some(config_dir.path().join("songs.db")),
+            None,
+        )
+        .unwrap();
+        // A single worker, to make sure it goes on after the panic.
+        library
+            .config
+            .base_config_mut()
+            .analysis_options
+            .number_cores = nzus(1);
+
+        library
+            .analyze_paths(vec!["/path/to/panic", "/path/to/song"], false)
+            .unwrap();
+        assert_eq!(
+            library.get_failed_songs().unwrap(),
+            vec![ProcessingError {
+                song_path: PathBuf::from("/path/to/panic"),
+                error: String::from("the analysis panicked - could not decode /path/to/panic"),
+                features_version: FeaturesVersion::LATEST,
+            }],
+        );
+        assert_eq!(
+            library
+                .songs_from_library::<()>()
+                .unwrap()
+                .into_iter()
+                .map(|s| s.bliss_song.path)
+                .collect::<Vec<_>>(),
+            vec![PathBuf::from("/path/to/song")],
+        );
+    }
+
+    #[test]
+    fn test_analyze_store_fallback_decoder() {
+        let config_dir = TempDir::new("bliss-test").unwrap();
+        let mut library =
+            Library::<BaseConfig, FallbackDecoder<FailingDecoder, PanickingDecoder>>::new_from_base(
+                Some(config_dir.path().join("config.json")),
+                Some(config_dir.path().join("songs.db")),
+                None,
+            )
+            .unwrap();
+
+        library
+            .analyze_paths(vec!["/path/to/song", "/path/to/fallback"], false)
+            .unwrap();
+        assert!(library.get_failed_songs().unwrap().is_empty());
+        let song = library
+            .song_from_path::<()>("/path/to/song")
+            .unwrap()
+            .bliss_song;
+        assert!(!song
+            .diagnostics
+            .warnings
+            .iter()
+            .any(|w| matches!(w, AnalysisWarning::DecodedByFallback { .. })));
+        let song = library
+            .song_from_path::<()>("/path/to/fallback")
+            .unwrap()
+            .bliss_song;
+        assert!(song
+            .diagnostics
+            .warnings
+            .contains(&AnalysisWarning::DecodedByFallback {
+                decoder: std::any::type_name::<PanickingDecoder>().to_owned(),
+                error: String::from("unsupported codec"),
+            }));
+    }
+
     #[test]
     #[cfg(feature = "ffmpeg")]
     fn test_analyze_store_failed_songs() {
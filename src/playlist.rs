@@ -6,7 +6,7 @@
 //!
 //! They will yield different styles of playlists, so don't hesitate to
 //! experiment with them if the [default][crate::FeaturesVersion::distance_metric] does not suit you.
-use crate::{BlissError, BlissResult, Song, NUMBER_FEATURES};
+use crate::{BlissError, BlissResult, FeaturesVersion, Song, NUMBER_FEATURES};
 use extended_isolation_forest::{Forest, ForestOptions};
 use ndarray::{Array, Array1, Array2, Axis};
 use ndarray_stats::QuantileExt;
@@ -97,27 +97,22 @@ pub fn cosine_distance(a: &Array1<f32>, b: &Array1<f32>) -> f32 {
 /// // pulled from a database.
 /// let first_song = Song {
 ///     path: "path-to-first".into(),
-///         analysis: Analysis::new(vec![
-///             1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1.,
-///               1., 1.
-///         ], FeaturesVersion::LATEST).unwrap(),
-///         ..Default::default()
-///     };
+///     analysis: Analysis::new(vec![1.; NUMBER_FEATURES], FeaturesVersion::LATEST).unwrap(),
+///     ..Default::default()
+/// };
+/// let mut second_analysis = vec![1.; NUMBER_FEATURES];
+/// second_analysis[0] = 1.5;
 /// let second_song = Song {
 ///     path: "path-to-second".into(),
-///     analysis: Analysis::new(vec![
-///         1.5, 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1.,
-///           1., 1.
-///     ], FeaturesVersion::LATEST).unwrap(),
+///     analysis: Analysis::new(second_analysis, FeaturesVersion::LATEST).unwrap(),
 ///     ..Default::default()
 /// };
 ///
+/// let mut third_analysis = vec![2.; NUMBER_FEATURES];
+/// third_analysis[16] = 1.9;
 /// let third_song = Song {
 ///     path: "path-to-third".into(),
-///     analysis: Analysis::new(vec![
-///         2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 1.9, 1., 1., 1., 1.,
-///           1., 1.
-///     ], FeaturesVersion::LATEST).unwrap(),
+///     analysis: Analysis::new(third_analysis, FeaturesVersion::LATEST).unwrap(),
 ///     ..Default::default()
 /// };
 /// // The weights of the features, here, equal to the identity matrix, i.e.,
@@ -220,33 +215,50 @@ pub fn variance_based_weight_matrix(seeds: &[Array1<f32>]) -> BlissResult<Array2
     Ok(m)
 }
 
-fn feature_array1_to_array(f: &Array1<f32>) -> [f32; NUMBER_FEATURES] {
+fn feature_array1_to_array<const N: usize>(f: &Array1<f32>) -> [f32; N] {
     f.as_slice()
         .expect("Couldn't convert feature vector to slice")
         .try_into()
         .expect("Couldn't convert slice to array")
 }
 
+fn build_forest<const N: usize>(
+    options: &ForestOptions,
+    vectors: &[Array1<f32>],
+) -> Box<dyn DistanceMetric> {
+    let a = &*vectors
+        .iter()
+        .map(feature_array1_to_array::<N>)
+        .collect::<Vec<_>>();
+
+    if options.sample_size > vectors.len() {
+        let mut opts = options.clone();
+        opts.sample_size = options.sample_size.min(vectors.len());
+        Box::new(Forest::from_slice(a, &opts).unwrap())
+    } else {
+        Box::new(Forest::from_slice(a, options).unwrap())
+    }
+}
+
 impl DistanceMetricBuilder for ForestOptions {
     fn build(&self, vectors: &[Array1<f32>]) -> Box<dyn DistanceMetric> {
-        let a = &*vectors
-            .iter()
-            .map(feature_array1_to_array)
-            .collect::<Vec<_>>();
-
-        if self.sample_size > vectors.len() {
-            let mut opts = self.clone();
-            opts.sample_size = self.sample_size.min(vectors.len());
-            Box::new(Forest::from_slice(a, &opts).unwrap())
-        } else {
-            Box::new(Forest::from_slice(a, self).unwrap())
+        // The forest needs to know the number of features at compile time,
+        // so pick the one matching the songs' features version.
+        const VERSION1_COUNT: usize = FeaturesVersion::Version1.feature_count();
+        const VERSION2_COUNT: usize = FeaturesVersion::Version2.feature_count();
+        const VERSION3_COUNT: usize = FeaturesVersion::Version3.feature_count();
+        match vectors.first().map(|v| v.len()) {
+            Some(VERSION1_COUNT) => build_forest::<VERSION1_COUNT>(self, vectors),
+            Some(VERSION2_COUNT) => build_forest::<VERSION2_COUNT>(self, vectors),
+            Some(VERSION3_COUNT) => build_forest::<VERSION3_COUNT>(self, vectors),
+            _ => build_forest::<NUMBER_FEATURES>(self, vectors),
         }
     }
 }
 
-impl DistanceMetric for Forest<f32, NUMBER_FEATURES> {
+impl<const N: usize> DistanceMetric for Forest<f32, N> {
     fn distance(&self, vector: &Array1<f32>) -> f32 {
-        self.score(&feature_array1_to_array(vector)) as f32
+        self.score(&feature_array1_to_array::<N>(vector)) as f32
     }
 }
 
@@ -273,6 +285,9 @@ struct SongToSongIterator<'a, T: AsRef<Song> + Clone> {
     pool: Vec<T>,
     vectors: Vec<Array1<f32>>,
     metric_builder: &'a dyn DistanceMetricBuilder,
+    // Whether to compare the end of the previous song with the beginning of
+    // the candidates, instead of using the whole songs' analysis.
+    transitions: bool,
 }
 
 impl<T: AsRef<Song> + Clone> Iterator for SongToSongIterator<'_, T> {
@@ -284,7 +299,12 @@ impl<T: AsRef<Song> + Clone> Iterator for SongToSongIterator<'_, T> {
         }
         let metric = self.metric_builder.build(&self.vectors);
         let distances: Array1<f32> = Array::from_shape_fn(self.pool.len(), |j| {
-            metric.distance(&self.pool[j].as_ref().analysis.as_arr1())
+            let song = self.pool[j].as_ref();
+            if self.transitions {
+                metric.distance(&song.intro_analysis().as_arr1())
+            } else {
+                metric.distance(&song.analysis.as_arr1())
+            }
         });
         let idx = distances.argmin().unwrap();
         // TODO instead of having a vector that's of size n and then
@@ -292,7 +312,11 @@ impl<T: AsRef<Song> + Clone> Iterator for SongToSongIterator<'_, T> {
         // or something
         self.vectors.clear();
         let song = self.pool.remove(idx);
-        self.vectors.push(song.as_ref().analysis.as_arr1());
+        if self.transitions {
+            self.vectors.push(song.as_ref().outro_analysis().as_arr1());
+        } else {
+            self.vectors.push(song.as_ref().analysis.as_arr1());
+        }
         Some(song)
     }
 }
@@ -321,6 +345,39 @@ pub fn song_to_song<'a, T: AsRef<Song> + Clone + 'a>(
         vectors,
         metric_builder,
         pool,
+        transitions: false,
+    };
+    iterator.into_iter()
+}
+
+/// Return an iterator of sorted songs from `candidate_songs` using
+/// `metric_builder` to build a metric to quantify the distance between songs,
+/// chaining each song to the song whose beginning is the closest to its end.
+///
+/// Same as [song_to_song], except that the distance is computed between
+/// the outro of a song (its last segment) and the intro of the next song
+/// (its first segment), which makes for smoother transitions than comparing
+/// whole-song averages. Songs without [segments](Song::segments) fall back
+/// to their whole-song analysis, so make sure the songs were analyzed with
+/// [AnalysisOptions::segments](crate::AnalysisOptions::segments) set.
+///
+/// The segments should have the same features version as the
+/// songs' analysis.
+pub fn outro_to_intro<'a, T: AsRef<Song> + Clone + 'a>(
+    initial_songs: &[T],
+    candidate_songs: &[T],
+    metric_builder: &'a dyn DistanceMetricBuilder,
+) -> impl Iterator<Item = T> + 'a {
+    let vectors = initial_songs
+        .iter()
+        .map(|s| s.as_ref().outro_analysis().as_arr1())
+        .collect::<Vec<_>>();
+    let pool = candidate_songs.to_vec();
+    let iterator = SongToSongIterator {
+        vectors,
+        metric_builder,
+        pool,
+        transitions: true,
     };
     iterator.into_iter()
 }
@@ -328,8 +385,9 @@ pub fn song_to_song<'a, T: AsRef<Song> + Clone + 'a>(
 /// Remove duplicate songs from a playlist.
 ///
 /// Two songs are considered duplicates if they either have the same,
-/// non-empty title and artist name, or if they are close enough in terms
-/// of distance.
+/// non-empty title and artist name, if they are close enough in terms
+/// of distance, or if their [fingerprints](crate::Fingerprint::matches)
+/// match, e.g. for the same song released on different albums.
 ///
 /// # Arguments
 ///
@@ -351,8 +409,9 @@ pub fn dedup_playlist<'a, T: AsRef<Song>>(
 /// metric.
 ///
 /// Two songs are considered duplicates if they either have the same,
-/// non-empty title and artist name, or if they are close enough in terms
-/// of distance.
+/// non-empty title and artist name, if they are close enough in terms
+/// of distance, or if their [fingerprints](crate::Fingerprint::matches)
+/// match, e.g. for the same song released on different albums.
 ///
 /// # Arguments
 ///
@@ -385,7 +444,12 @@ pub fn dedup_playlist_custom_distance<'a, T: AsRef<Song>>(
                             && s1_ref.artist.is_some()
                             && s2_ref.artist.is_some()
                             && s1_ref.title == s2_ref.title
-                            && s1_ref.artist == s2_ref.artist);
+                            && s1_ref.artist == s2_ref.artist)
+                        || s1_ref
+                            .fingerprint
+                            .as_ref()
+                            .zip(s2_ref.fingerprint.as_ref())
+                            .is_some_and(|(f1, f2)| f1.matches(f2));
                     if is_same {
                         peekable.next();
                         continue;
@@ -487,9 +551,10 @@ pub fn closest_album_to_group<T: AsRef<Song> + Clone>(
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{Analysis, FeaturesVersion};
+    use crate::{Analysis, AnalysisSegment, FeaturesVersion, Fingerprint};
     use ndarray::arr1;
     use std::path::Path;
+    use std::time::Duration;
 
     #[derive(Debug, Clone, PartialEq)]
     struct CustomSong {
@@ -512,7 +577,7 @@ mod test {
                     1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1.,
                     1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -524,7 +589,7 @@ mod test {
                     1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1.,
                     1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -537,7 +602,7 @@ mod test {
                     2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 1.9, 1., 1.,
                     1., 1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             title: Some(String::from("dupe-title")),
@@ -553,7 +618,7 @@ mod test {
                     2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2.5, 1., 1.,
                     1., 1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -567,7 +632,7 @@ mod test {
                     2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 0., 1., 1., 1.,
                     1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -579,7 +644,7 @@ mod test {
                     2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 0.001, 1., 1.,
                     1., 1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -730,6 +795,106 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_dedup_playlist_fingerprint() {
+        let codes = (0..100).map(|i| i * 7919 % (1 << 24)).collect::<Vec<u32>>();
+        let first_song = Song {
+            path: Path::new("path-to-first").to_path_buf(),
+            analysis: Analysis::new(vec![1.; 23], FeaturesVersion::Version2).unwrap(),
+            fingerprint: Some(Fingerprint {
+                codes: codes.to_owned(),
+            }),
+            ..Default::default()
+        };
+        // Same recording from another album, slightly offset and
+        // analyzed differently.
+        let first_song_remaster = Song {
+            path: Path::new("path-to-remaster").to_path_buf(),
+            analysis: Analysis::new(vec![2.; 23], FeaturesVersion::Version2).unwrap(),
+            fingerprint: Some(Fingerprint {
+                codes: codes[2..].to_vec(),
+            }),
+            ..Default::default()
+        };
+        let second_song = Song {
+            path: Path::new("path-to-second").to_path_buf(),
+            analysis: Analysis::new(vec![3.; 23], FeaturesVersion::Version2).unwrap(),
+            fingerprint: Some(Fingerprint {
+                codes: codes.iter().map(|c| !c & 0xFFFFFF).collect(),
+            }),
+            ..Default::default()
+        };
+        let third_song = Song {
+            path: Path::new("path-to-third").to_path_buf(),
+            analysis: Analysis::new(vec![4.; 23], FeaturesVersion::Version2).unwrap(),
+            ..Default::default()
+        };
+
+        let playlist = vec![
+            first_song.to_owned(),
+            first_song_remaster.to_owned(),
+            second_song.to_owned(),
+            third_song.to_owned(),
+        ];
+        let playlist = dedup_playlist(playlist.into_iter(), None).collect::<Vec<_>>();
+        assert_eq!(playlist, vec![first_song, second_song, third_song]);
+    }
+
+    #[test]
+    fn test_outro_to_intro() {
+        let analysis = |value: f32| {
+            Analysis::new(vec![value; NUMBER_FEATURES], FeaturesVersion::LATEST).unwrap()
+        };
+        let segment = |value: f32| AnalysisSegment {
+            start: Duration::ZERO,
+            end: Duration::from_secs(10),
+            analysis: analysis(value),
+        };
+        // Sounds like the first song overall, but its intro is very different
+        // from the first song's outro.
+        let similar_song = Song {
+            path: Path::new("path-to-similar").to_path_buf(),
+            analysis: analysis(0.),
+            segments: Some(vec![segment(5.), segment(0.)]),
+            ..Default::default()
+        };
+        let first_song = Song {
+            path: Path::new("path-to-first").to_path_buf(),
+            analysis: analysis(0.),
+            segments: Some(vec![segment(0.), segment(10.)]),
+            ..Default::default()
+        };
+        // Different from the first song overall, but starts the way the
+        // first song ends.
+        let transition_song = Song {
+            path: Path::new("path-to-transition").to_path_buf(),
+            analysis: analysis(10.),
+            segments: Some(vec![segment(10.), segment(4.)]),
+            ..Default::default()
+        };
+        // No segments: uses the whole-song analysis.
+        let no_segments_song = Song {
+            path: Path::new("path-to-no-segments").to_path_buf(),
+            analysis: analysis(4.5),
+            ..Default::default()
+        };
+
+        let songs = [&similar_song, &transition_song, &no_segments_song];
+        let playlist =
+            song_to_song(&[&first_song], &songs, &euclidean_distance).collect::<Vec<_>>();
+        assert_eq!(
+            playlist,
+            vec![&similar_song, &no_segments_song, &transition_song],
+        );
+
+        let playlist =
+            outro_to_intro(&[&first_song], &songs, &euclidean_distance).collect::<Vec<_>>();
+        assert_eq!(
+            playlist,
+            vec![&transition_song, &no_segments_song, &similar_song],
+        );
+    }
+
     #[test]
     fn test_song_to_song() {
         let first_song = Song {
@@ -739,7 +904,7 @@ mod test {
                     1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1.,
                     1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -751,7 +916,7 @@ mod test {
                     1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1.,
                     1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -764,7 +929,7 @@ mod test {
                     2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 1.9, 1., 1.,
                     1., 1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -776,7 +941,7 @@ mod test {
                     2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2.5, 1., 1.,
                     1., 1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -788,7 +953,7 @@ mod test {
                     2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 0., 1., 1., 1.,
                     1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -866,7 +1031,7 @@ mod test {
                     1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1.,
                     1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -878,7 +1043,7 @@ mod test {
                     1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1.,
                     1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -891,7 +1056,7 @@ mod test {
                     2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 1.9, 1., 1.,
                     1., 1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -903,7 +1068,7 @@ mod test {
                     2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2.5, 1., 1.,
                     1., 1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -915,7 +1080,7 @@ mod test {
                     2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 0., 1., 1., 1.,
                     1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -927,7 +1092,7 @@ mod test {
                     2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 2., 0., 1., 1., 1.,
                     1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -1032,7 +1197,7 @@ mod test {
                     1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1.,
                     1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -1044,7 +1209,7 @@ mod test {
                     1.5, 5., 6., 5., 6., 6., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1.,
                     1., 1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
@@ -1056,12 +1221,12 @@ mod test {
                     5., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1., 1.,
                     1., 1., 1.,
                 ],
-                FeaturesVersion::LATEST,
+                FeaturesVersion::Version2,
             )
             .unwrap(),
             ..Default::default()
         };
-        let m = Array2::eye(NUMBER_FEATURES)
+        let m = Array2::eye(FeaturesVersion::Version2.feature_count())
             * arr1(&[
                 1.0, 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0., 0.,
                 0., 0., 0.,
@@ -1292,7 +1457,7 @@ mod test {
                         -0.983834,
                         -0.983834,
                     ],
-                    FeaturesVersion::LATEST,
+                    FeaturesVersion::Version2,
                 )
                 .unwrap(),
                 ..Default::default()
@@ -1325,7 +1490,7 @@ mod test {
                         -0.9903612,
                         -0.9903612,
                     ],
-                    FeaturesVersion::LATEST,
+                    FeaturesVersion::Version2,
                 )
                 .unwrap(),
                 ..Default::default()
@@ -1358,7 +1523,7 @@ mod test {
                         -0.9756616,
                         -0.9756616,
                     ],
-                    FeaturesVersion::LATEST,
+                    FeaturesVersion::Version2,
                 )
                 .unwrap(),
                 ..Default::default()
@@ -1394,7 +1559,7 @@ mod test {
                         -0.98359185,
                         -0.98359185,
                     ],
-                    FeaturesVersion::LATEST,
+                    FeaturesVersion::Version2,
                 )
                 .unwrap(),
                 ..Default::default()
@@ -1427,7 +1592,7 @@ mod test {
                         -0.98231846,
                         -0.98231846,
                     ],
-                    FeaturesVersion::LATEST,
+                    FeaturesVersion::Version2,
                 )
                 .unwrap(),
                 ..Default::default()
@@ -1460,7 +1625,7 @@ mod test {
                         -0.98729765,
                         -0.98729765,
                     ],
-                    FeaturesVersion::LATEST,
+                    FeaturesVersion::Version2,
                 )
                 .unwrap(),
                 ..Default::default()
@@ -1493,7 +1658,7 @@ mod test {
                         -0.9776932,
                         -0.98088175,
                     ],
-                    FeaturesVersion::LATEST,
+                    FeaturesVersion::Version2,
                 )
                 .unwrap(),
                 ..Default::default()
@@ -1526,7 +1691,7 @@ mod test {
                         -0.99011236,
                         -0.99011236,
                     ],
-                    FeaturesVersion::LATEST,
+                    FeaturesVersion::Version2,
                 )
                 .unwrap(),
                 ..Default::default()
@@ -1562,7 +1727,7 @@ mod test {
                         -0.98385316,
                         -0.98385316,
                     ],
-                    FeaturesVersion::LATEST,
+                    FeaturesVersion::Version2,
                 )
                 .unwrap(),
                 ..Default::default()
@@ -1595,7 +1760,7 @@ mod test {
                         -0.9905396,
                         -0.9905396,
                     ],
-                    FeaturesVersion::LATEST,
+                    FeaturesVersion::Version2,
                 )
                 .unwrap(),
                 ..Default::default()
@@ -1628,7 +1793,7 @@ mod test {
                         -0.9764576,
                         -0.9764576,
                     ],
-                    FeaturesVersion::LATEST,
+                    FeaturesVersion::Version2,
                 )
                 .unwrap(),
                 ..Default::default()
@@ -0,0 +1,272 @@
+//! Silence and fade detection module.
+//!
+//! Finds where the audio of a song actually starts and ends, ignoring its
+//! leading and trailing silence, and where its fade-out starts, if it has
+//! one.
+#[cfg(feature = "analysis")]
+use crate::aubio::{is_silence, level_lin};
+#[cfg(feature = "analysis")]
+use crate::SAMPLE_RATE;
+#[cfg(feature = "analysis")]
+use std::ops::Range;
+use std::time::Duration;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+/// What should be excluded from the analysis of a song. See
+/// [AnalysisOptions::trim](crate::AnalysisOptions::trim).
+pub enum TrimOptions {
+    /// Exclude the leading and trailing silence.
+    Silence,
+    /// Exclude the leading and trailing silence, as well as the fade-out.
+    SilenceAndFadeOut,
+}
+
+/// Where the audio of a song starts and ends, from the beginning of the
+/// song. See [Song::boundaries](crate::Song::boundaries).
+///
+/// Useful for gapless playback and crossfading, e.g. to skip a song's
+/// trailing silence, or to start the next song when the current one
+/// starts fading out.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Boundaries {
+    /// The end of the song's leading silence.
+    pub audio_start: Duration,
+    /// The start of the song's trailing silence, or the end of the song if
+    /// it has none.
+    pub audio_end: Duration,
+    /// The start of the song's fade-out, if it has one.
+    pub fade_out_start: Option<Duration>,
+}
+
+#[cfg(feature = "analysis")]
+impl Boundaries {
+    /// The samples of a song that should be analyzed, according to `trim`.
+    pub(crate) fn trimmed_range(&self, trim: TrimOptions) -> Range<usize> {
+        let to_samples = |d: Duration| (d.as_secs_f64() * SAMPLE_RATE as f64).round() as usize;
+        let end = match trim {
+            TrimOptions::Silence => self.audio_end,
+            TrimOptions::SilenceAndFadeOut => self.fade_out_start.unwrap_or(self.audio_end),
+        };
+        to_samples(self.audio_start)..to_samples(end)
+    }
+}
+
+/**
+ * Silence and fade-out detection object.
+ *
+ * Splits the song in frames, using aubio's silence detection on each
+ * of them to find the song's leading and trailing silence. The fade-out
+ * is found by following the song's level backwards from its end, until
+ * it gets back to the song's usual level.
+ */
+#[cfg(feature = "analysis")]
+#[derive(Default)]
+pub(crate) struct BoundaryDesc {
+    // Samples of the frame being filled.
+    frame: Vec<f32>,
+    // Mean energy of each frame.
+    energies: Vec<f32>,
+    first_sound: Option<usize>,
+    last_sound: Option<usize>,
+    number_samples: usize,
+}
+
+#[cfg(feature = "analysis")]
+impl BoundaryDesc {
+    pub const FRAME_SIZE: usize = 1024;
+    // Level under which a frame is considered silent, in dB. The same
+    // threshold as the one used to report mostly silent songs.
+    pub(crate) const SILENCE_DB: f32 = -60.;
+    // Number of frames averaged to follow the song's level during a
+    // fade-out (about half a second).
+    const FADE_BLOCK_SIZE: usize = 11;
+    // How far under its median level a song has to end to be considered
+    // fading out, in dB.
+    const FADE_DEPTH_DB: f32 = 20.;
+    // How far under its median level a song is considered to have
+    // started fading out, in dB.
+    const FADE_START_DB: f32 = 6.;
+    // How much the level can rise from one block to the next while
+    // fading out, in dB.
+    const FADE_TOLERANCE_DB: f32 = 3.;
+    // Minimum length of a fade-out, in blocks (about two seconds).
+    const MIN_FADE_BLOCKS: usize = 4;
+
+    pub fn do_(&mut self, mut chunk: &[f32]) {
+        while !chunk.is_empty() {
+            let missing = (BoundaryDesc::FRAME_SIZE - self.frame.len()).min(chunk.len());
+            self.frame.extend_from_slice(&chunk[..missing]);
+            chunk = &chunk[missing..];
+            if self.frame.len() == BoundaryDesc::FRAME_SIZE {
+                self.process_frame();
+            }
+        }
+    }
+
+    fn process_frame(&mut self) {
+        let index = self.energies.len();
+        if !is_silence(&self.frame, BoundaryDesc::SILENCE_DB) {
+            self.first_sound.get_or_insert(index);
+            self.last_sound = Some(index);
+        }
+        self.energies.push(level_lin(&self.frame));
+        self.number_samples += self.frame.len();
+        self.frame.clear();
+    }
+
+    /// The song's boundaries, or `None` if the whole song is silent.
+    pub fn get_value(mut self) -> Option<Boundaries> {
+        // The last, incomplete frame is used too.
+        if !self.frame.is_empty() {
+            self.process_frame();
+        }
+        let (first_sound, last_sound) = (self.first_sound?, self.last_sound?);
+        let to_duration = |frame: usize| {
+            let samples = (frame * BoundaryDesc::FRAME_SIZE).min(self.number_samples);
+            Duration::from_secs_f64(samples as f64 / SAMPLE_RATE as f64)
+        };
+        Some(Boundaries {
+            audio_start: to_duration(first_sound),
+            audio_end: to_duration(last_sound + 1),
+            fade_out_start: self
+                .fade_out_start(first_sound, last_sound + 1)
+                .map(to_duration),
+        })
+    }
+
+    // The frame at which the song fades out, if it does, looking only at
+    // the frames between `start` and `end`.
+    fn fade_out_start(&self, start: usize, end: usize) -> Option<usize> {
+        // The level of each block, in dB, from the end of the song backwards.
+        let levels = self.energies[start..end]
+            .rchunks(BoundaryDesc::FADE_BLOCK_SIZE)
+            .map(|block| 10. * (block.iter().sum::<f32>() / block.len() as f32).log10())
+            .collect::<Vec<f32>>();
+        let mut sorted_levels = levels.clone();
+        sorted_levels.sort_by(f32::total_cmp);
+        let median_level = sorted_levels[sorted_levels.len() / 2];
+        if levels[0] > median_level - BoundaryDesc::FADE_DEPTH_DB {
+            return None;
+        }
+
+        // Going backwards, the level should keep rising until it gets
+        // back to the song's usual level.
+        for (number_blocks, blocks) in (1..).zip(levels.windows(2)) {
+            let (later, earlier) = (blocks[0], blocks[1]);
+            if earlier >= median_level - BoundaryDesc::FADE_START_DB {
+                return BoundaryDesc::is_fade_out(&levels[..number_blocks])
+                    .then_some(end - number_blocks * BoundaryDesc::FADE_BLOCK_SIZE);
+            }
+            if earlier + BoundaryDesc::FADE_TOLERANCE_DB < later {
+                return None;
+            }
+        }
+        // The song never gets to its usual level: it is not a fade-out.
+        None
+    }
+
+    // Whether the level keeps decreasing over the given blocks (from the
+    // last one backwards), instead of only dropping once, e.g. for a quiet
+    // outro.
+    fn is_fade_out(levels: &[f32]) -> bool {
+        if levels.len() < BoundaryDesc::MIN_FADE_BLOCKS {
+            return false;
+        }
+        let (last, middle, first) = (
+            levels[0],
+            levels[levels.len() / 2],
+            levels[levels.len() - 1],
+        );
+        first - middle >= BoundaryDesc::FADE_TOLERANCE_DB
+            && middle - last >= BoundaryDesc::FADE_TOLERANCE_DB
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "analysis")]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    // A 440 Hz sine, whose amplitude is given for each sample.
+    fn sine(amplitudes: impl Iterator<Item = f32>) -> Vec<f32> {
+        amplitudes
+            .enumerate()
+            .map(|(i, amplitude)| {
+                amplitude * (2. * std::f32::consts::PI * 440. * i as f32 / SAMPLE_RATE as f32).sin()
+            })
+            .collect()
+    }
+
+    fn find_boundaries(sample_array: &[f32]) -> Option<Boundaries> {
+        let mut boundary_desc = BoundaryDesc::default();
+        for chunk in sample_array.chunks(1000) {
+            boundary_desc.do_(chunk);
+        }
+        boundary_desc.get_value()
+    }
+
+    #[test]
+    fn test_boundaries_silence() {
+        let second = SAMPLE_RATE as usize;
+        assert_eq!(find_boundaries(&[]), None);
+        assert_eq!(find_boundaries(&vec![0.; 10 * second]), None);
+
+        let sample_array = [
+            vec![0.; 2 * second],
+            sine(std::iter::repeat_n(0.5, 5 * second)),
+            vec![0.; 3 * second],
+        ]
+        .concat();
+        let boundaries = find_boundaries(&sample_array).unwrap();
+        assert!(0.05 > (2. - boundaries.audio_start.as_secs_f32()).abs());
+        assert!(0.05 > (7. - boundaries.audio_end.as_secs_f32()).abs());
+        // The song stops abruptly.
+        assert_eq!(boundaries.fade_out_start, None);
+
+        let range = boundaries.trimmed_range(TrimOptions::Silence);
+        assert!(sample_array[..range.start].iter().all(|x| *x == 0.));
+        assert!(sample_array[range.end..].iter().all(|x| *x == 0.));
+        assert_eq!(
+            range,
+            boundaries.trimmed_range(TrimOptions::SilenceAndFadeOut)
+        );
+    }
+
+    #[test]
+    fn test_boundaries_fade_out() {
+        let second = SAMPLE_RATE as usize;
+        // Five seconds at full level, followed by a five seconds linear
+        // fade-out, then silence.
+        let sample_array = [
+            sine(std::iter::repeat_n(0.5, 5 * second)),
+            sine((0..5 * second).map(|i| 0.5 * (1. - i as f32 / (5 * second) as f32))),
+            vec![0.; second],
+        ]
+        .concat();
+        let boundaries = find_boundaries(&sample_array).unwrap();
+        assert_eq!(boundaries.audio_start, Duration::ZERO);
+        assert!(0.1 > (10. - boundaries.audio_end.as_secs_f32()).abs());
+        // The level is 6 dB under the usual one halfway through the fade.
+        let fade_out_start = boundaries.fade_out_start.unwrap().as_secs_f32();
+        assert!(1. > (7.5 - fade_out_start).abs(), "{fade_out_start}");
+
+        let range = boundaries.trimmed_range(TrimOptions::SilenceAndFadeOut);
+        assert_eq!(
+            range.end,
+            (fade_out_start as f64 * SAMPLE_RATE as f64).round() as usize
+        );
+
+        // A quiet outro is not a fade-out.
+        let sample_array = [
+            sine(std::iter::repeat_n(0.5, 5 * second)),
+            sine(std::iter::repeat_n(0.02, 5 * second)),
+        ]
+        .concat();
+        let boundaries = find_boundaries(&sample_array).unwrap();
+        assert!(0.1 > (10. - boundaries.audio_end.as_secs_f32()).abs());
+        assert_eq!(boundaries.fade_out_start, None);
+    }
+}
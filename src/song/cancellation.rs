@@ -0,0 +1,168 @@
+//! Cancellation module.
+//!
+//! Holds the [CancellationToken] used to stop analyses that are running,
+//! and the [AnalysisBudget] that decoders and descriptors check in their
+//! loops to know whether they should stop early, either because the
+//! analysis was cancelled or because it took too long.
+use crate::{AnalysisOptions, BlissError, BlissResult};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// A token used to cancel running analyses, e.g. when the user clicks
+/// on a "Stop scanning" button, or when the application shuts down.
+///
+/// Set it in [AnalysisOptions::cancellation], and keep a clone of it
+/// around: calling [CancellationToken::cancel] on any of the clones
+/// cancels every analysis that uses it. Cancelled songs come back as
+/// [Cancelled](BlissError::Cancelled) errors, and the songs that
+/// weren't started yet are not analyzed at all.
+///
+/// * Example:
+/// ```
+/// use bliss_audio::{AnalysisOptions, CancellationToken};
+///
+/// let token = CancellationToken::new();
+/// let analysis_options = AnalysisOptions {
+///     cancellation: Some(token.clone()),
+///     ..Default::default()
+/// };
+/// // Later on, e.g. from another thread.
+/// token.cancel();
+/// assert!(analysis_options.cancellation.unwrap().is_cancelled());
+/// ```
+#[derive(Default, Clone)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Create a new token, that is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel every analysis using this token, or one of its clones.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether [CancellationToken::cancel] was called.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for CancellationToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancellationToken {}
+
+impl fmt::Debug for CancellationToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CancellationToken")
+            .field("cancelled", &self.is_cancelled())
+            .finish()
+    }
+}
+
+/// What is left of the time allotted to the analysis of a song, along
+/// with the token that can cancel it.
+///
+/// Created from the [cancellation](AnalysisOptions::cancellation) and
+/// [timeout](AnalysisOptions::timeout) options when a song's analysis
+/// starts, and checked regularly while the song is decoded and analyzed.
+/// Custom decoders should call [AnalysisBudget::check] in their decoding
+/// loop (see [Decoder::decode_with_budget](crate::decoder::Decoder::decode_with_budget)).
+#[derive(Default, Debug, Clone)]
+pub struct AnalysisBudget {
+    cancellation: Option<CancellationToken>,
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+}
+
+impl AnalysisBudget {
+    /// Start the budget of a song's analysis using `analysis_options`.
+    /// The timeout, if any, starts now.
+    pub fn new(analysis_options: &AnalysisOptions) -> Self {
+        AnalysisBudget {
+            cancellation: analysis_options.cancellation.clone(),
+            timeout: analysis_options.timeout,
+            deadline: analysis_options
+                .timeout
+                .and_then(|timeout| Instant::now().checked_add(timeout)),
+        }
+    }
+
+    /// Return an error if the analysis was cancelled, or if it has been
+    /// running for longer than its timeout.
+    pub fn check(&self) -> BlissResult<()> {
+        if self
+            .cancellation
+            .as_ref()
+            .is_some_and(|token| token.is_cancelled())
+        {
+            return Err(BlissError::Cancelled);
+        }
+        match (self.timeout, self.deadline) {
+            (Some(timeout), Some(deadline)) if Instant::now() >= deadline => {
+                Err(BlissError::TimedOut(timeout))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        assert_eq!(token, clone);
+        assert_ne!(token, CancellationToken::new());
+        clone.cancel();
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn test_budget_cancelled() {
+        let token = CancellationToken::new();
+        let budget = AnalysisBudget::new(&AnalysisOptions {
+            cancellation: Some(token.clone()),
+            ..Default::default()
+        });
+        assert_eq!(budget.check(), Ok(()));
+        token.cancel();
+        assert_eq!(budget.check(), Err(BlissError::Cancelled));
+    }
+
+    #[test]
+    fn test_budget_timed_out() {
+        let budget = AnalysisBudget::new(&AnalysisOptions {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        });
+        assert_eq!(budget.check(), Err(BlissError::TimedOut(Duration::ZERO)));
+
+        let budget = AnalysisBudget::new(&AnalysisOptions {
+            timeout: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        });
+        assert_eq!(budget.check(), Ok(()));
+    }
+
+    #[test]
+    fn test_budget_unlimited() {
+        assert_eq!(AnalysisBudget::default().check(), Ok(()));
+        assert_eq!(
+            AnalysisBudget::new(&AnalysisOptions::default()).check(),
+            Ok(())
+        );
+    }
+}
@@ -3,7 +3,9 @@
 //! Contains the generic code used to interface the decoding of songs. The [ffmpeg]
 //! submodule contains the code to decode songs with [FFmpeg](https://www.ffmpeg.org/),
 //! while [symphonia] contains the code to decode songs with
-//! [Symphonia](https://github.com/pdeljanov/symphonia).
+//! [Symphonia](https://github.com/pdeljanov/symphonia). The [fallback]
+//! submodule chains two decoders, so that songs one of them can't decode
+//! are decoded by the other one.
 //!
 //! Also holds the `Decoder` trait, that you can use to decode songs
 //! with the ffmpeg or symphonia struct that implements that trait,
@@ -12,13 +14,23 @@
 //! to implement other decoders is a good starting point.
 use log::info;
 
-use crate::{cue::BlissCue, song::AnalysisOptions, BlissError, BlissResult, Song};
+use crate::{
+    cue::BlissCue,
+    song::{sample_count_mismatch, DecodingIssues},
+    song::{AnalysisOptions, AnalysisOutput},
+    AnalysisBudget, AnalysisEvent, AnalysisSegment, AnalysisWarning, BlissError, BlissResult,
+    DeliveryOptions, Loudness, Song, StreamingAnalyzer, Tags, SAMPLE_RATE,
+};
 use std::{
+    any::Any,
+    collections::BTreeMap,
+    io::{Read, Seek},
     num::NonZeroUsize,
+    panic::{self, AssertUnwindSafe},
     path::{Path, PathBuf},
-    sync::mpsc,
+    sync::{mpsc, Arc, Condvar, Mutex, OnceLock},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 #[derive(Default, Debug)]
@@ -48,8 +60,26 @@ pub struct PreAnalyzedSong {
     pub disc_number: Option<i32>,
     /// Song's genre, read from the metadata
     pub genre: Option<String>,
+    /// Song's other tags, read from the metadata
+    pub tags: Tags,
     /// The song's duration
     pub duration: Duration,
+    /// The song's duration as announced by the file itself, before
+    /// decoding, if known.
+    ///
+    /// Used to check that the whole song was decoded: see
+    /// [SampleCountMismatch](crate::AnalysisWarning::SampleCountMismatch).
+    pub expected_duration: Option<Duration>,
+    /// The song's loudness, measured while decoding it at its native
+    /// sample rate, if the decoder measures it (see
+    /// [LoudnessMeter](crate::LoudnessMeter)).
+    pub loudness: Option<Loudness>,
+    /// The warnings raised while decoding the song, e.g. packets that
+    /// couldn't be decoded, or a truncated file.
+    ///
+    /// They are added to the song's [diagnostics](Song::diagnostics) once
+    /// it is analyzed, and can be used to flag damaged files.
+    pub warnings: Vec<AnalysisWarning>,
     /// An array of the song's decoded sample which should be,
     /// prior to analysis, resampled to f32le, one channel, with a sampling rate
     /// of 22050 Hz. Anything other than that will yield wrong results.
@@ -64,6 +94,43 @@ pub struct PreAnalyzedSong {
     pub sample_array: Vec<f32>,
 }
 
+/// A song that is being decoded, whose samples are yielded chunk by chunk
+/// instead of being stored in a single sample array.
+///
+/// Returned by [Decoder::decode_stream], to pipe decoding and analysis
+/// (see [StreamingAnalyzer](crate::StreamingAnalyzer)) without having
+/// the whole decoded song in memory.
+pub struct PreAnalyzedSongStream {
+    /// The song's metadata, known before the song is decoded.
+    ///
+    /// Its `sample_array` is always empty, and its `duration` is not
+    /// computed, since the song hasn't been decoded yet. Its `warnings`
+    /// only hold the warnings known before decoding the song.
+    pub song: PreAnalyzedSong,
+    /// An iterator over chunks of the song's decoded samples, in the
+    /// same format as [PreAnalyzedSong::sample_array], i.e.
+    /// f32le, one channel, with a sampling rate of 22050 Hz.
+    ///
+    /// Chunks can have any size. A decoding error stops the iteration.
+    pub samples: Box<dyn Iterator<Item = BlissResult<Vec<f32>>> + Send>,
+    /// The song's loudness, set by the decoder once all the samples have
+    /// been decoded, if it measures it.
+    pub loudness: Arc<OnceLock<Loudness>>,
+    /// The warnings raised while decoding the song (see
+    /// [PreAnalyzedSong::warnings]), set by the decoder once all the
+    /// samples have been decoded.
+    pub warnings: Arc<OnceLock<Vec<AnalysisWarning>>>,
+}
+
+/// A source of encoded audio that can be read and seeked, such as a
+/// [File](std::fs::File) or an in-memory [Cursor](std::io::Cursor),
+/// decoded with [Decoder::decode_reader].
+///
+/// Implemented for every type that is [Read], [Seek], [Send] and [Sync].
+pub trait ReadSeek: Read + Seek + Send + Sync {}
+
+impl<T: Read + Seek + Send + Sync> ReadSeek for T {}
+
 #[cfg(feature = "ffmpeg")]
 /// Decoder that uses ffmpeg by default. Uses the `symphonia` feature
 /// without ffmpeg to use symphonia instead.
@@ -77,13 +144,43 @@ impl TryFrom<PreAnalyzedSong> for Song {
     type Error = BlissError;
 
     fn try_from(raw_song: PreAnalyzedSong) -> BlissResult<Song> {
-        raw_song.to_song_with_options(AnalysisOptions::default())
+        raw_song.to_song_with_options(&AnalysisOptions::default(), &AnalysisBudget::default())
     }
 }
 
 impl PreAnalyzedSong {
-    fn to_song_with_options(&self, analysis_options: AnalysisOptions) -> BlissResult<Song> {
-        Ok(Song {
+    fn to_song_with_options(
+        &self,
+        analysis_options: &AnalysisOptions,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<Song> {
+        let mut output = Song::analyze_output(&self.sample_array, analysis_options, budget)?;
+        output
+            .diagnostics
+            .warnings
+            .extend_from_slice(&self.warnings);
+        output
+            .diagnostics
+            .check_sample_count(self.expected_duration, self.sample_array.len());
+        let segments = Song::segments_from_options(&self.sample_array, analysis_options, budget)?;
+        Ok(self.to_song_with_analysis(output, segments, self.duration, self.loudness))
+    }
+
+    fn to_song_with_analysis(
+        &self,
+        output: AnalysisOutput,
+        segments: Option<Vec<AnalysisSegment>>,
+        duration: Duration,
+        loudness: Option<Loudness>,
+    ) -> Song {
+        let AnalysisOutput {
+            analysis,
+            diagnostics,
+            key,
+            boundaries,
+            fingerprint,
+        } = output;
+        Song {
             path: self.path.clone(),
             artist: self.artist.clone(),
             album_artist: self.album_artist.clone(),
@@ -92,11 +189,56 @@ impl PreAnalyzedSong {
             track_number: self.track_number,
             disc_number: self.disc_number,
             genre: self.genre.clone(),
-            duration: self.duration,
-            analysis: Song::analyze_with_options(&self.sample_array, &analysis_options)?,
-            features_version: analysis_options.features_version,
+            tags: self.tags.clone(),
+            duration,
+            features_version: analysis.features_version,
+            analysis,
             cue_info: None,
-        })
+            segments,
+            diagnostics,
+            key,
+            loudness,
+            boundaries,
+            fingerprint,
+        }
+    }
+
+    /// Set the song's [warnings](PreAnalyzedSong::warnings) to the ones
+    /// raised by `issues`, and to a
+    /// [SampleCountMismatch](AnalysisWarning::SampleCountMismatch) if the
+    /// decoded sample array doesn't match the song's expected duration.
+    pub(crate) fn set_warnings(&mut self, issues: DecodingIssues) {
+        self.warnings = issues.get_warnings();
+        self.warnings.extend(sample_count_mismatch(
+            self.expected_duration,
+            self.sample_array.len(),
+        ));
+    }
+}
+
+impl PreAnalyzedSongStream {
+    fn into_song_with_options(self, analysis_options: AnalysisOptions) -> BlissResult<Song> {
+        let mut analyzer = StreamingAnalyzer::with_options(analysis_options)?;
+        for chunk in self.samples {
+            analyzer.push(&chunk?)?;
+        }
+        let number_samples = analyzer.number_samples();
+        let duration = Duration::from_secs_f64(number_samples as f64 / SAMPLE_RATE as f64);
+        let mut output = analyzer.finish_output()?;
+        output
+            .diagnostics
+            .warnings
+            .extend_from_slice(&self.song.warnings);
+        if let Some(warnings) = self.warnings.get() {
+            output.diagnostics.warnings.extend_from_slice(warnings);
+        }
+        output
+            .diagnostics
+            .check_sample_count(self.song.expected_duration, number_samples);
+        let loudness = self.loudness.get().copied();
+        Ok(self
+            .song
+            .to_song_with_analysis(output, None, duration, loudness))
     }
 }
 
@@ -128,6 +270,146 @@ pub trait Decoder {
     /// you the raw sample array as it should look like, if you're not into computing checksums)
     fn decode(path: &Path) -> BlissResult<PreAnalyzedSong>;
 
+    /// Same as [decode](Decoder::decode), but gives up as soon as `budget`
+    /// runs out, i.e. when the analysis is cancelled or times out (see
+    /// [AnalysisOptions::cancellation] and [AnalysisOptions::timeout]),
+    /// returning the error given by [AnalysisBudget::check].
+    ///
+    /// The default implementation only checks `budget` before and after
+    /// decoding the song. Decoders should override it to check `budget`
+    /// in their decoding loop, so that a song that takes too long to
+    /// decode doesn't block the analysis.
+    fn decode_with_budget(path: &Path, budget: &AnalysisBudget) -> BlissResult<PreAnalyzedSong> {
+        budget.check()?;
+        let song = Self::decode(path)?;
+        budget.check()?;
+        Ok(song)
+    }
+
+    /// Same as [decode](Decoder::decode), but decodes the song from
+    /// `reader` instead of from a file, e.g. from a song that has been
+    /// downloaded in memory, or from an archive.
+    ///
+    /// `hint` is the extension of the format the song is encoded with,
+    /// e.g. `"flac"` or `"mp3"`, if known. Decoders probe the format of the
+    /// song by themselves, but the hint can help them for the formats that
+    /// are hard to probe.
+    ///
+    /// Since the song has no path, the `path` of the returned
+    /// [PreAnalyzedSong] is empty.
+    ///
+    /// The default implementation returns a
+    /// [DecodingError](BlissError::DecodingError), since not all decoders
+    /// can decode from readers.
+    fn decode_reader(
+        reader: Box<dyn ReadSeek>,
+        hint: Option<&str>,
+    ) -> BlissResult<PreAnalyzedSong> {
+        let _ = (reader, hint);
+        Err(BlissError::DecodingError(String::from(
+            "this decoder cannot decode songs from readers.",
+        )))
+    }
+
+    /// Same as [decode_reader](Decoder::decode_reader), but gives up as soon
+    /// as `budget` runs out, like
+    /// [decode_with_budget](Decoder::decode_with_budget).
+    ///
+    /// The default implementation only checks `budget` before and after
+    /// decoding the song.
+    fn decode_reader_with_budget(
+        reader: Box<dyn ReadSeek>,
+        hint: Option<&str>,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<PreAnalyzedSong> {
+        budget.check()?;
+        let song = Self::decode_reader(reader, hint)?;
+        budget.check()?;
+        Ok(song)
+    }
+
+    /// Same as [decode](Decoder::decode), but only decodes the part of the
+    /// song between `start` and `end`, or the end of the song if `end` is
+    /// `None`.
+    ///
+    /// The returned sample array is the same as the corresponding slice of
+    /// the sample array returned by [decode](Decoder::decode), but decoders
+    /// can seek to `start` instead of decoding the whole song, and should
+    /// only keep the samples of the range in memory. This is what
+    /// [BlissCue](crate::cue::BlissCue) uses to analyze the tracks of CUE
+    /// sheets one at a time.
+    ///
+    /// The [loudness](PreAnalyzedSong::loudness) of the range is not
+    /// measured.
+    fn decode_range(
+        path: &Path,
+        start: Duration,
+        end: Option<Duration>,
+    ) -> BlissResult<PreAnalyzedSong> {
+        Self::decode_range_with_budget(path, start, end, &AnalysisBudget::default())
+    }
+
+    /// Same as [decode_range](Decoder::decode_range), but gives up as soon
+    /// as `budget` runs out, like
+    /// [decode_with_budget](Decoder::decode_with_budget).
+    ///
+    /// The default implementation decodes the whole song using
+    /// [decode_with_budget](Decoder::decode_with_budget), and keeps the
+    /// samples of the range. Decoders should override it to seek to the
+    /// beginning of the range instead.
+    fn decode_range_with_budget(
+        path: &Path,
+        start: Duration,
+        end: Option<Duration>,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<PreAnalyzedSong> {
+        let mut song = Self::decode_with_budget(path, budget)?;
+        let start_index = sample_index(start).min(song.sample_array.len());
+        let end_index = end.map_or(song.sample_array.len(), |end| {
+            sample_index(end).clamp(start_index, song.sample_array.len())
+        });
+        song.sample_array.truncate(end_index);
+        song.sample_array.drain(..start_index);
+        song.expected_duration = range_duration(song.expected_duration, start, end);
+        // The whole song's sample count doesn't matter anymore, only the
+        // range's.
+        song.warnings
+            .retain(|w| !matches!(w, AnalysisWarning::SampleCountMismatch { .. }));
+        song.warnings.extend(sample_count_mismatch(
+            song.expected_duration,
+            song.sample_array.len(),
+        ));
+        song.duration =
+            Duration::from_secs_f64(song.sample_array.len() as f64 / SAMPLE_RATE as f64);
+        song.loudness = None;
+        Ok(song)
+    }
+
+    /// A function that should decode and resample a song chunk by chunk,
+    /// returning the song's metadata right away, along with an iterator
+    /// over the decoded samples.
+    ///
+    /// The samples should have the same format as the ones returned by
+    /// [decode](Decoder::decode), and the concatenation of all the chunks
+    /// should ideally be the same as `decode`'s sample array.
+    ///
+    /// The default implementation decodes the whole song using
+    /// [decode](Decoder::decode), and yields it as a single chunk. Decoders
+    /// should override it to actually decode the song lazily, keeping the
+    /// memory usage bounded.
+    fn decode_stream(path: &Path) -> BlissResult<PreAnalyzedSongStream> {
+        let mut song = Self::decode(path)?;
+        let sample_array = std::mem::take(&mut song.sample_array);
+        let loudness = song.loudness.take().map(OnceLock::from).unwrap_or_default();
+        let warnings = OnceLock::from(std::mem::take(&mut song.warnings));
+        Ok(PreAnalyzedSongStream {
+            song,
+            samples: Box::new(std::iter::once(Ok(sample_array))),
+            loudness: Arc::new(loudness),
+            warnings: Arc::new(warnings),
+        })
+    }
+
     /// Returns a decoded [Song] given a file path, or an error if the song
     /// could not be analyzed for some reason.
     ///
@@ -158,6 +440,7 @@ pub trait Decoder {
     /// * `analysis_options`: An [AnalysisOptions] struct holding various
     ///   analysis options, such as the feature version. The `number_cores`
     ///   parameter is not used here, since only a single song is processed.
+    ///   The `timeout` covers both the decoding and the analysis of the song.
     ///
     /// # Errors
     ///
@@ -172,7 +455,63 @@ pub trait Decoder {
         path: P,
         analysis_options: AnalysisOptions,
     ) -> BlissResult<Song> {
-        Self::decode(path.as_ref())?.to_song_with_options(analysis_options)
+        let budget = AnalysisBudget::new(&analysis_options);
+        Self::decode_with_budget(path.as_ref(), &budget)?
+            .to_song_with_options(&analysis_options, &budget)
+    }
+
+    /// Returns a decoded [Song] read from `reader`, or an error if the song
+    /// could not be analyzed for some reason. See
+    /// [decode_reader](Decoder::decode_reader) for the meaning of `hint`.
+    ///
+    /// The [path](Song::path) of the returned song is empty, and is up to
+    /// the caller to fill, if needed.
+    ///
+    /// # Errors
+    ///
+    /// Same as [song_from_path](Decoder::song_from_path).
+    fn song_from_reader<R: ReadSeek + 'static>(reader: R, hint: Option<&str>) -> BlissResult<Song> {
+        Self::decode_reader(Box::new(reader), hint)?.try_into()
+    }
+
+    /// Same as [song_from_reader](Decoder::song_from_reader), but processes
+    /// the song with the options `analysis_options`, like
+    /// [song_from_path_with_options](Decoder::song_from_path_with_options).
+    ///
+    /// # Errors
+    ///
+    /// Same as [song_from_path](Decoder::song_from_path).
+    fn song_from_reader_with_options<R: ReadSeek + 'static>(
+        reader: R,
+        hint: Option<&str>,
+        analysis_options: AnalysisOptions,
+    ) -> BlissResult<Song> {
+        let budget = AnalysisBudget::new(&analysis_options);
+        Self::decode_reader_with_budget(Box::new(reader), hint, &budget)?
+            .to_song_with_options(&analysis_options, &budget)
+    }
+
+    /// Returns a decoded [Song] given a file path, processed with the options
+    /// `analysis_options`, decoding and analyzing the song chunk by chunk
+    /// using [decode_stream](Decoder::decode_stream) and a
+    /// [StreamingAnalyzer].
+    ///
+    /// Useful for very long songs (DJ mixes...), since the whole decoded
+    /// song is never kept in memory. Note that the chroma features can
+    /// be slightly different from the ones computed by
+    /// [song_from_path](Decoder::song_from_path): see [StreamingAnalyzer]
+    /// for more details. Per-segment analysis (see
+    /// [AnalysisOptions::segments]) is not computed either, since it needs
+    /// the whole song.
+    ///
+    /// # Errors
+    ///
+    /// Same as [song_from_path](Decoder::song_from_path).
+    fn song_from_path_streaming<P: AsRef<Path>>(
+        path: P,
+        analysis_options: AnalysisOptions,
+    ) -> BlissResult<Song> {
+        Self::decode_stream(path.as_ref())?.into_song_with_options(analysis_options)
     }
 
     /// Analyze songs in `paths` using multiple threads, and return the
@@ -235,6 +574,14 @@ fn main() -> BlissResult<()> {
     /// use the simpler `analyze_paths` functions, which autodetects the number
     /// of cores in your system.
     ///
+    /// Songs are decoded and analyzed by a pool of `number_cores` threads,
+    /// each picking the next song as soon as it is done with the previous
+    /// one, so songs can come back in a different order than `paths`,
+    /// unless [AnalysisOptions::delivery] says otherwise. It can also limit
+    /// how many analyzed songs can wait to be consumed from the returned
+    /// iterator. Set [AnalysisOptions::progress] to follow the analysis of
+    /// each song.
+    ///
     /// Return an iterator, whose items are a tuple made of
     /// the song path (to display to the user in case the analysis failed),
     /// and a `Result<Song>`.
@@ -253,6 +600,19 @@ fn main() -> BlissResult<()> {
     /// to `analyze_paths`, and it will return [Song]s from both files, with
     /// more information about which file it is extracted from in the
     /// [cue info field](Song::cue_info).
+    ///
+    /// If [AnalysisOptions::cancellation] is set, cancelling the token stops
+    /// the analysis: the songs being analyzed come back as
+    /// [Cancelled](BlissError::Cancelled) errors, the remaining songs are
+    /// not analyzed, and the returned iterator ends once all the threads
+    /// have stopped. If [AnalysisOptions::timeout] is set, songs taking
+    /// longer than that to be decoded and analyzed come back as
+    /// [TimedOut](BlissError::TimedOut) errors, and the analysis goes on with
+    /// the next songs.
+    ///
+    /// If decoding or analyzing a song panics, the song comes back as a
+    /// [Panicked](BlissError::Panicked) error, and the analysis goes on with
+    /// the next songs.
     #[cfg_attr(
         feature = "ffmpeg",
         doc = r##"
@@ -288,52 +648,482 @@ fn main() -> BlissResult<()> {
             cores = desired_number_cores;
         }
         let paths: Vec<PathBuf> = paths.into_iter().map(|p| p.into()).collect();
-        #[allow(clippy::type_complexity)]
-        let (tx, rx): (
-            mpsc::Sender<(PathBuf, BlissResult<Song>)>,
-            mpsc::Receiver<(PathBuf, BlissResult<Song>)>,
-        ) = mpsc::channel();
+        let delivery = analysis_options.delivery.unwrap_or_default();
+        let (tx, rx) = match delivery.max_pending_results {
+            Some(max_pending_results) => {
+                let (tx, rx) = mpsc::sync_channel(max_pending_results.get());
+                (ResultSender::Bounded(tx), rx)
+            }
+            None => {
+                let (tx, rx) = mpsc::channel();
+                (ResultSender::Unbounded(tx), rx)
+            }
+        };
         if paths.is_empty() {
             return rx.into_iter();
         }
-        let mut handles = Vec::new();
-        let mut chunk_length = paths.len() / cores;
-        if chunk_length == 0 {
-            chunk_length = paths.len();
-        }
-        for chunk in paths.chunks(chunk_length) {
-            let tx_thread = tx.clone();
-            let owned_chunk = chunk.to_owned();
-            let child = thread::spawn(move || {
-                for path in owned_chunk {
-                    info!("Analyzing file '{path:?}'");
-                    if let Some(extension) = Path::new(&path).extension() {
-                        let extension = extension.to_string_lossy().to_lowercase();
-                        if extension == "cue" {
-                            match BlissCue::<Self>::songs_from_path(&path) {
-                                Ok(songs) => {
-                                    for song in songs {
-                                        tx_thread.send((path.to_owned(), song)).unwrap();
-                                    }
-                                }
-                                Err(e) => tx_thread.send((path.to_owned(), Err(e))).unwrap(),
-                            };
-                            continue;
+        // A single work-stealing pool runs both the decoding of the songs
+        // and the tasks of their descriptors (see [Song::analyze]), so that
+        // no more than `cores` threads are busy at any given time. Each of
+        // the `cores` path loops picks the next path as soon as it's done
+        // with the previous one, so a slow song doesn't leave the other
+        // cores idle.
+        //
+        // The path loops run on threads of their own, and only hand the
+        // songs themselves over to the pool: a pool thread waiting for the
+        // descriptors of its song picks up other tasks of the pool in the
+        // meantime, which would otherwise include whole path loops, keeping
+        // it from getting back to its song until that loop ran out of paths.
+        let pool = match rayon::ThreadPoolBuilder::new()
+            .num_threads(cores.get())
+            .thread_name(|i| format!("bliss-analysis-{i}"))
+            .build()
+        {
+            Ok(pool) => pool,
+            Err(e) => {
+                // Sent from another thread, since the channel can be bounded.
+                thread::spawn(move || {
+                    for path in paths {
+                        let error = BlissError::AnalysisError(format!(
+                            "could not start the analysis threads: {e}"
+                        ));
+                        if tx.send((path, Err(error))).is_err() {
+                            break;
                         }
                     }
-                    let song = Self::song_from_path_with_options(&path, analysis_options);
-                    tx_thread.send((path.to_owned(), song)).unwrap();
+                });
+                return rx.into_iter();
+            }
+        };
+        let paths = Mutex::new(paths.into_iter().enumerate());
+        let results = ResultDelivery::new(tx, delivery);
+        thread::spawn(move || {
+            thread::scope(|s| {
+                for _ in 0..cores.get() {
+                    let pool = &pool;
+                    let paths = &paths;
+                    let results = &results;
+                    let analysis_options = &analysis_options;
+                    s.spawn(move || loop {
+                        if analysis_options
+                            .cancellation
+                            .as_ref()
+                            .is_some_and(|token| token.is_cancelled())
+                        {
+                            info!("Analysis cancelled, stopping.");
+                            break;
+                        }
+                        let Some((index, path)) = paths.lock().unwrap().next() else {
+                            break;
+                        };
+                        results.wait_turn(index);
+                        info!("Analyzing file '{path:?}'");
+                        let started = Instant::now();
+                        report(analysis_options, || AnalysisEvent::Started {
+                            path: path.to_owned(),
+                        });
+                        // A panic while decoding or analyzing a song only
+                        // fails that song, and the loop goes on with the
+                        // next one.
+                        let songs = panic::catch_unwind(AssertUnwindSafe(|| {
+                            pool.install(|| analyze_path::<Self>(&path, analysis_options, started))
+                        }))
+                        .unwrap_or_else(|payload| vec![Err(panic_to_error(payload))]);
+                        for song in &songs {
+                            report(analysis_options, || match song {
+                                Ok(_) => AnalysisEvent::Analyzed {
+                                    path: path.to_owned(),
+                                    elapsed: started.elapsed(),
+                                },
+                                Err(e) => AnalysisEvent::Failed {
+                                    path: path.to_owned(),
+                                    error: e.to_owned(),
+                                    elapsed: started.elapsed(),
+                                },
+                            });
+                        }
+                        // Nobody is listening anymore, no need to go on.
+                        if !results.deliver(index, path, songs) {
+                            break;
+                        }
+                    });
                 }
             });
-            handles.push(child);
-        }
+        });
 
         rx.into_iter()
     }
 }
 
+/// Decode and analyze the song(s) at `path`: the song itself, or the
+/// tracks of a CUE sheet if `path` points to one.
+///
+/// `started` is when the song started being processed, to report how long
+/// it took to decode it.
+fn analyze_path<D: ?Sized + Decoder>(
+    path: &Path,
+    analysis_options: &AnalysisOptions,
+    started: Instant,
+) -> Vec<BlissResult<Song>> {
+    if let Some(extension) = path.extension() {
+        let extension = extension.to_string_lossy().to_lowercase();
+        if extension == "cue" {
+            return match BlissCue::<D>::songs_from_path_with_options(path, analysis_options) {
+                Ok(songs) => songs,
+                Err(e) => vec![Err(e)],
+            };
+        }
+    }
+    let budget = AnalysisBudget::new(analysis_options);
+    let song = D::decode_with_budget(path, &budget).and_then(|song| {
+        report(analysis_options, || AnalysisEvent::Decoded {
+            path: path.to_owned(),
+            elapsed: started.elapsed(),
+        });
+        song.to_song_with_options(analysis_options, &budget)
+    });
+    vec![song]
+}
+
+/// The index of the sample at `time` in a sample array sampled at
+/// [SAMPLE_RATE].
+pub(crate) fn sample_index(time: Duration) -> usize {
+    (time.as_secs_f32() * SAMPLE_RATE as f32) as usize
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// The duration of the range between `start` and `end` of a song lasting
+/// `duration`, if known.
+pub(crate) fn range_duration(
+    duration: Option<Duration>,
+    start: Duration,
+    end: Option<Duration>,
+) -> Option<Duration> {
+    duration.map(|duration| {
+        end.map_or(duration, |end| end.min(duration))
+            .saturating_sub(start)
+    })
+}
+
+/// How much of a song is decoded before and after the range given to
+/// [Decoder::decode_range], so that the resampler has warmed up by the
+/// time it reaches the range, and isn't flushed before its end.
+const RANGE_MARGIN: Duration = Duration::from_secs(1);
+
+/// Which decoded samples are needed to get the samples of a range of a
+/// song, resampled the same way as when decoding the whole song. Used by
+/// the decoders' [Decoder::decode_range_with_budget].
+///
+/// Decoders seek to [seek_time](DecodingRange::seek_time), and only
+/// resample the decoded samples from the
+/// [start offset](DecodingRange::start_offset) of the first frame that
+/// starts the range, until the frames that are
+/// [past its end](DecodingRange::is_past_end), before
+/// [cutting](DecodingRange::cut) the resampled samples.
+pub(crate) struct DecodingRange {
+    start: Duration,
+    end: Option<Duration>,
+    sample_rate: u32,
+    alignment: u64,
+    /// The index, at `sample_rate`, of the first resampled sample.
+    first_index: Option<u64>,
+}
+
+impl DecodingRange {
+    /// Track the range between `start` and `end` of a song sampled at
+    /// `sample_rate`, whose resampler gives the same samples as when
+    /// resampling the whole song if it starts at a multiple of
+    /// `resampler_alignment` samples.
+    pub fn new(
+        start: Duration,
+        end: Option<Duration>,
+        sample_rate: u32,
+        resampler_alignment: u64,
+    ) -> Self {
+        // The first resampled sample must also fall on a sample of the
+        // whole resampled song.
+        let rate_alignment =
+            u64::from(sample_rate) / gcd(u64::from(sample_rate), u64::from(SAMPLE_RATE));
+        let resampler_alignment = resampler_alignment.max(1);
+        let alignment =
+            resampler_alignment / gcd(resampler_alignment, rate_alignment) * rate_alignment;
+        DecodingRange {
+            start,
+            end,
+            sample_rate,
+            alignment,
+            first_index: None,
+        }
+    }
+
+    /// Where decoders should seek to before decoding the range. Decoders
+    /// shouldn't seek at all if it is zero.
+    pub fn seek_time(&self) -> Duration {
+        self.start.saturating_sub(RANGE_MARGIN)
+    }
+
+    /// The index, at the song's sample rate, of the sample at `time`.
+    fn input_index(&self, time: Duration) -> u64 {
+        (time.as_secs_f64() * self.sample_rate as f64) as u64
+    }
+
+    /// Where resampling should start in the decoded frame of `length`
+    /// samples starting at the sample `index`, if it should start in that
+    /// frame or has already started. Decoders that can't split decoded
+    /// frames should pass a `length` of 1, to only start resampling at
+    /// the beginning of a frame.
+    ///
+    /// Resampling starts at the first aligned sample close enough to the
+    /// beginning of the range, or right away if there is none, in which
+    /// case the samples can be very slightly different from the ones of
+    /// the whole song.
+    pub fn start_offset(&mut self, index: u64, length: usize) -> Option<usize> {
+        if self.first_index.is_some() {
+            return Some(0);
+        }
+        let latest_start = self.input_index(self.start.saturating_sub(RANGE_MARGIN / 2));
+        let aligned = index.next_multiple_of(self.alignment);
+        let first_index = if aligned < index + length as u64 && aligned <= latest_start {
+            aligned
+        } else if index + length as u64 > latest_start {
+            index
+        } else {
+            return None;
+        };
+        self.first_index = Some(first_index);
+        Some((first_index - index) as usize)
+    }
+
+    /// Whether the decoded frame starting at the sample `index` is far
+    /// enough past the end of the range for the decoding to stop.
+    pub fn is_past_end(&self, index: u64) -> bool {
+        self.end
+            .is_some_and(|end| index > self.input_index(end + RANGE_MARGIN))
+    }
+
+    /// Keep the samples of the range from `resampled`, the samples
+    /// resampled since the [start offset](DecodingRange::start_offset).
+    pub fn cut(&self, mut resampled: Vec<f32>) -> Vec<f32> {
+        let Some(first_index) = self.first_index else {
+            return Vec::new();
+        };
+        let first =
+            (first_index as f64 * SAMPLE_RATE as f64 / self.sample_rate as f64).round() as usize;
+        let start = sample_index(self.start)
+            .saturating_sub(first)
+            .min(resampled.len());
+        let end = self.end.map_or(resampled.len(), |end| {
+            sample_index(end)
+                .saturating_sub(first)
+                .clamp(start, resampled.len())
+        });
+        resampled.truncate(end);
+        resampled.drain(..start);
+        resampled
+    }
+}
+
+/// Send the event made by `event` to [AnalysisOptions::progress], if set.
+fn report(analysis_options: &AnalysisOptions, event: impl FnOnce() -> AnalysisEvent) {
+    if let Some(progress) = &analysis_options.progress {
+        progress.send(event());
+    }
+}
+
+/// The sending half of the channel bulk analyses hand out their results
+/// through, bounded if [DeliveryOptions::max_pending_results] is set.
+enum ResultSender<T> {
+    Unbounded(mpsc::Sender<T>),
+    Bounded(mpsc::SyncSender<T>),
+}
+
+impl<T> ResultSender<T> {
+    fn send(&self, t: T) -> Result<(), mpsc::SendError<T>> {
+        match self {
+            ResultSender::Unbounded(sender) => sender.send(t),
+            ResultSender::Bounded(sender) => sender.send(t),
+        }
+    }
+}
+
+/// Hands out the songs analyzed by
+/// [Decoder::analyze_paths_with_options], following [DeliveryOptions].
+struct ResultDelivery {
+    sender: ResultSender<(PathBuf, BlissResult<Song>)>,
+    options: DeliveryOptions,
+    ordered: Mutex<OrderedResults>,
+    /// Notified each time songs are handed out in order.
+    handed_out: Condvar,
+}
+
+/// The songs that have been analyzed, but are waiting for the songs
+/// before them to be analyzed to be handed out.
+#[derive(Default)]
+struct OrderedResults {
+    /// The index of the next path whose songs should be handed out.
+    next: usize,
+    pending: BTreeMap<usize, (PathBuf, Vec<BlissResult<Song>>)>,
+    /// Whether a thread is handing out songs, in which case it also hands
+    /// out the songs that become ready in the meantime.
+    delivering: bool,
+    /// Whether the receiving half of the channel was dropped.
+    disconnected: bool,
+}
+
+impl ResultDelivery {
+    fn new(sender: ResultSender<(PathBuf, BlissResult<Song>)>, options: DeliveryOptions) -> Self {
+        ResultDelivery {
+            sender,
+            options,
+            ordered: Mutex::new(OrderedResults::default()),
+            handed_out: Condvar::new(),
+        }
+    }
+
+    /// Block until the path at `index` can be analyzed without getting
+    /// more than [DeliveryOptions::max_pending_results] paths ahead of
+    /// the next songs to be handed out in order.
+    ///
+    /// Only called from the path loops' own threads, so that waiting never
+    /// holds up a thread of the analysis pool.
+    fn wait_turn(&self, index: usize) {
+        let (true, Some(max_pending_results)) =
+            (self.options.ordered, self.options.max_pending_results)
+        else {
+            return;
+        };
+        let mut ordered = self.ordered.lock().unwrap();
+        while index >= ordered.next + max_pending_results.get() {
+            ordered = self.handed_out.wait(ordered).unwrap();
+        }
+    }
+
+    /// Hand out `songs`, analyzed from the path at `index`, right away
+    /// or once the songs of all the previous paths have been handed out,
+    /// depending on [DeliveryOptions::ordered]. In order, the songs are
+    /// handed out by whichever thread is already handing out songs, if any.
+    ///
+    /// Return false if the receiving half of the channel was dropped.
+    fn deliver(&self, index: usize, path: PathBuf, songs: Vec<BlissResult<Song>>) -> bool {
+        if !self.options.ordered {
+            return songs
+                .into_iter()
+                .all(|song| self.sender.send((path.to_owned(), song)).is_ok());
+        }
+        let mut ordered = self.ordered.lock().unwrap();
+        ordered.pending.insert(index, (path, songs));
+        if ordered.delivering {
+            return !ordered.disconnected;
+        }
+        ordered.delivering = true;
+        loop {
+            let mut ready = Vec::new();
+            while let Some(songs) = {
+                let next = ordered.next + ready.len();
+                ordered.pending.remove(&next)
+            } {
+                ready.push(songs);
+            }
+            if ready.is_empty() {
+                break;
+            }
+            // Sending blocks while a bounded channel is full, so don't keep
+            // the other threads from storing their songs or checking their
+            // turn meanwhile.
+            drop(ordered);
+            let number_ready = ready.len();
+            let mut connected = true;
+            // Keep going even if nobody listens anymore, so that the path
+            // loops waiting for their turn don't wait forever.
+            for (path, songs) in ready {
+                for song in songs {
+                    connected &= self.sender.send((path.to_owned(), song)).is_ok();
+                }
+            }
+            ordered = self.ordered.lock().unwrap();
+            ordered.disconnected |= !connected;
+            ordered.next += number_ready;
+            self.handed_out.notify_all();
+        }
+        ordered.delivering = false;
+        !ordered.disconnected
+    }
+}
+
+/// Turn the payload of a caught panic into a [Panicked](BlissError::Panicked)
+/// error, keeping the panic's message if there is one.
+fn panic_to_error(payload: Box<dyn Any + Send>) -> BlissError {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.to_owned()
+    } else {
+        String::from("unknown panic")
+    };
+    BlissError::Panicked(message)
+}
+
+pub mod fallback;
+
 #[cfg(feature = "symphonia")]
 pub mod symphonia;
 
 #[cfg(feature = "ffmpeg")]
 pub mod ffmpeg;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::NonZero;
+
+    /// Decodes two seconds of silence, taking longer for the songs at the
+    /// start of the paths, so that they would come back last if unordered.
+    struct SlowFirstDecoder;
+
+    impl Decoder for SlowFirstDecoder {
+        fn decode(path: &Path) -> BlissResult<PreAnalyzedSong> {
+            let index: u64 = path.to_string_lossy().parse().unwrap();
+            thread::sleep(Duration::from_millis(20 * (8 - index)));
+            Ok(PreAnalyzedSong {
+                path: path.to_owned(),
+                sample_array: vec![0.; SAMPLE_RATE as usize * 2],
+                ..Default::default()
+            })
+        }
+    }
+
+    #[test]
+    fn test_analyze_paths_ordered_bounded() {
+        let paths = (0..8).map(|i| i.to_string()).collect::<Vec<_>>();
+        let mut results = SlowFirstDecoder::analyze_paths_with_options(
+            &paths,
+            AnalysisOptions {
+                number_cores: NonZero::new(4).unwrap(),
+                delivery: Some(DeliveryOptions {
+                    ordered: true,
+                    max_pending_results: Some(NonZero::new(1).unwrap()),
+                }),
+                ..Default::default()
+            },
+        );
+        // Let the analysis fill the channel, so that handing out the next
+        // songs blocks until they are consumed.
+        thread::sleep(Duration::from_millis(300));
+        let mut analyzed = Vec::new();
+        for (path, song) in &mut results {
+            assert!(song.is_ok());
+            analyzed.push(path);
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            analyzed,
+            paths.iter().map(PathBuf::from).collect::<Vec<_>>()
+        );
+    }
+}
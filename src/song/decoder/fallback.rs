@@ -0,0 +1,342 @@
+//! Decoder chaining two decoders, so that the songs one of them can't
+//! decode are decoded by the other one.
+
+use std::{
+    any::type_name,
+    io::{self, Read, Seek, SeekFrom},
+    marker::PhantomData,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::{AnalysisBudget, AnalysisWarning, BlissError, BlissResult};
+
+use super::{Decoder, PreAnalyzedSong, PreAnalyzedSongStream, ReadSeek};
+
+/// A decoder that decodes songs with the decoder `A`, and falls back to
+/// the decoder `B` for the songs `A` fails to decode.
+///
+/// Useful since some songs can only be decoded by FFmpeg, and others only
+/// by Symphonia, e.g. because of a codec missing from an FFmpeg build.
+/// Only [DecodingError](BlissError::DecodingError)s make it fall back to
+/// `B`: a cancelled or timed out decoding is not retried.
+///
+/// Songs decoded by `B` get a
+/// [DecodedByFallback](AnalysisWarning::DecodedByFallback) warning in
+/// their [warnings](PreAnalyzedSong::warnings), which ends up in their
+/// [diagnostics](crate::Song::diagnostics), recording which decoder
+/// decoded them.
+///
+/// Streams are only decoded by `B` if `A` fails to open them: errors
+/// raised while iterating over their samples are not retried.
+#[cfg_attr(
+    all(feature = "ffmpeg", feature = "symphonia"),
+    doc = r##"
+# Example
+
+```no_run
+use bliss_audio::BlissResult;
+use bliss_audio::decoder::Decoder as DecoderTrait;
+use bliss_audio::decoder::fallback::FallbackDecoder;
+use bliss_audio::decoder::ffmpeg::FFmpegDecoder;
+use bliss_audio::decoder::symphonia::SymphoniaDecoder;
+
+type Decoder = FallbackDecoder<FFmpegDecoder, SymphoniaDecoder>;
+
+fn main() -> BlissResult<()> {
+    let song = Decoder::song_from_path("/path/to/song")?;
+    println!("Decoding warnings: {:?}", song.diagnostics.warnings);
+    Ok(())
+}
+```"##
+)]
+pub struct FallbackDecoder<A, B> {
+    decoders: PhantomData<fn() -> (A, B)>,
+}
+
+impl<A: Decoder, B: Decoder> FallbackDecoder<A, B> {
+    /// Call `decode_first` with `A`, and, if it fails to decode the song,
+    /// `decode_second` with `B`, recording it in the song's warnings with
+    /// `add_warning`.
+    fn fall_back<T>(
+        decode_first: impl FnOnce() -> BlissResult<T>,
+        decode_second: impl FnOnce() -> BlissResult<T>,
+        add_warning: impl FnOnce(&mut T, AnalysisWarning),
+    ) -> BlissResult<T> {
+        let first_error = match decode_first() {
+            Err(BlissError::DecodingError(e)) => e,
+            result => return result,
+        };
+        match decode_second() {
+            Ok(mut decoded) => {
+                let warning = AnalysisWarning::DecodedByFallback {
+                    decoder: type_name::<B>().to_owned(),
+                    error: first_error,
+                };
+                add_warning(&mut decoded, warning);
+                Ok(decoded)
+            }
+            Err(BlissError::DecodingError(second_error)) => Err(BlissError::DecodingError(
+                format!("{first_error}, and with the fallback decoder: {second_error}"),
+            )),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn fall_back_song(
+        decode_first: impl FnOnce() -> BlissResult<PreAnalyzedSong>,
+        decode_second: impl FnOnce() -> BlissResult<PreAnalyzedSong>,
+    ) -> BlissResult<PreAnalyzedSong> {
+        Self::fall_back(decode_first, decode_second, |song, warning| {
+            song.warnings.push(warning)
+        })
+    }
+}
+
+impl<A: Decoder, B: Decoder> Decoder for FallbackDecoder<A, B> {
+    fn decode(path: &Path) -> BlissResult<PreAnalyzedSong> {
+        Self::fall_back_song(|| A::decode(path), || B::decode(path))
+    }
+
+    fn decode_with_budget(path: &Path, budget: &AnalysisBudget) -> BlissResult<PreAnalyzedSong> {
+        Self::fall_back_song(
+            || A::decode_with_budget(path, budget),
+            || B::decode_with_budget(path, budget),
+        )
+    }
+
+    fn decode_reader(
+        reader: Box<dyn ReadSeek>,
+        hint: Option<&str>,
+    ) -> BlissResult<PreAnalyzedSong> {
+        Self::decode_reader_with_budget(reader, hint, &AnalysisBudget::default())
+    }
+
+    /// Decode the song read from `reader` with `A`, and, if it fails, rewind
+    /// `reader` to decode it with `B`.
+    fn decode_reader_with_budget(
+        reader: Box<dyn ReadSeek>,
+        hint: Option<&str>,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<PreAnalyzedSong> {
+        let reader = SharedReader::new(reader)
+            .map_err(|e| BlissError::DecodingError(format!("while reading song: {e}")))?;
+        let second_reader = reader.clone();
+        Self::fall_back_song(
+            || A::decode_reader_with_budget(Box::new(reader), hint, budget),
+            || {
+                let reader = second_reader
+                    .rewind()
+                    .map_err(|e| BlissError::DecodingError(format!("while rewinding song: {e}")))?;
+                B::decode_reader_with_budget(Box::new(reader), hint, budget)
+            },
+        )
+    }
+
+    fn decode_range_with_budget(
+        path: &Path,
+        start: Duration,
+        end: Option<Duration>,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<PreAnalyzedSong> {
+        Self::fall_back_song(
+            || A::decode_range_with_budget(path, start, end, budget),
+            || B::decode_range_with_budget(path, start, end, budget),
+        )
+    }
+
+    fn decode_stream(path: &Path) -> BlissResult<PreAnalyzedSongStream> {
+        Self::fall_back(
+            || A::decode_stream(path),
+            || B::decode_stream(path),
+            |stream, warning| stream.song.warnings.push(warning),
+        )
+    }
+}
+
+/// A reader shared by the two decoders of a [FallbackDecoder], so that
+/// the second one can read the song again once the first one has failed,
+/// without copying it.
+#[derive(Clone)]
+struct SharedReader {
+    reader: Arc<Mutex<Box<dyn ReadSeek>>>,
+    // The position of the song in the reader.
+    start: u64,
+}
+
+impl SharedReader {
+    fn new(mut reader: Box<dyn ReadSeek>) -> io::Result<Self> {
+        let start = reader.stream_position()?;
+        Ok(SharedReader {
+            reader: Arc::new(Mutex::new(reader)),
+            start,
+        })
+    }
+
+    /// Seek back to the beginning of the song.
+    fn rewind(self) -> io::Result<Self> {
+        self.lock().seek(SeekFrom::Start(self.start))?;
+        Ok(self)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Box<dyn ReadSeek>> {
+        // The reader is rewound before being read again, so a poisoned
+        // lock doesn't matter.
+        self.reader.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Read for SharedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.lock().read(buf)
+    }
+}
+
+impl Seek for SharedReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.lock().seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::Decoder as DecoderTrait;
+    use crate::{AnalysisOptions, CancellationToken, SAMPLE_RATE};
+    use pretty_assertions::assert_eq;
+    use std::io::Cursor;
+
+    /// Decodes the songs whose path or content contains "good".
+    struct GoodDecoder;
+
+    impl DecoderTrait for GoodDecoder {
+        fn decode(path: &Path) -> BlissResult<PreAnalyzedSong> {
+            if !path.to_string_lossy().contains("good") {
+                return Err(BlissError::DecodingError(format!(
+                    "cannot decode {}",
+                    path.display()
+                )));
+            }
+            Ok(PreAnalyzedSong {
+                path: path.to_owned(),
+                sample_array: vec![0.; SAMPLE_RATE as usize * 2],
+                ..Default::default()
+            })
+        }
+
+        fn decode_reader(
+            mut reader: Box<dyn ReadSeek>,
+            _: Option<&str>,
+        ) -> BlissResult<PreAnalyzedSong> {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content).unwrap();
+            Self::decode(Path::new(&*String::from_utf8_lossy(&content)))
+        }
+    }
+
+    /// Decodes the songs whose path or content contains "ok".
+    struct OkDecoder;
+
+    impl DecoderTrait for OkDecoder {
+        fn decode(path: &Path) -> BlissResult<PreAnalyzedSong> {
+            GoodDecoder::decode(Path::new(&path.to_string_lossy().replace("ok", "good")))
+                .map_err(|_| BlissError::DecodingError(String::from("not ok")))
+        }
+
+        fn decode_reader(
+            mut reader: Box<dyn ReadSeek>,
+            _: Option<&str>,
+        ) -> BlissResult<PreAnalyzedSong> {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content).unwrap();
+            Self::decode(Path::new(&*String::from_utf8_lossy(&content)))
+        }
+    }
+
+    type Decoder = FallbackDecoder<GoodDecoder, OkDecoder>;
+
+    fn fallback_warning(error: &str) -> AnalysisWarning {
+        AnalysisWarning::DecodedByFallback {
+            decoder: type_name::<OkDecoder>().to_owned(),
+            error: error.to_owned(),
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        let song = Decoder::decode(Path::new("/path/to/good")).unwrap();
+        assert_eq!(song.warnings, vec![]);
+
+        let song = Decoder::decode(Path::new("/path/to/ok")).unwrap();
+        assert_eq!(
+            song.warnings,
+            vec![fallback_warning("cannot decode /path/to/ok")]
+        );
+
+        assert_eq!(
+            Decoder::decode(Path::new("/path/to/bad")).unwrap_err(),
+            BlissError::DecodingError(String::from(
+                "cannot decode /path/to/bad, and with the fallback decoder: not ok"
+            )),
+        );
+    }
+
+    #[test]
+    fn test_decode_reader() {
+        let song = Decoder::decode_reader(Box::new(Cursor::new("good")), None).unwrap();
+        assert_eq!(song.warnings, vec![]);
+
+        // The second decoder reads the whole song again.
+        let song = Decoder::decode_reader(Box::new(Cursor::new("ok")), None).unwrap();
+        assert_eq!(song.path, Path::new("good"));
+        assert_eq!(song.warnings, vec![fallback_warning("cannot decode ok")]);
+    }
+
+    #[test]
+    fn test_song_from_path() {
+        let song = Decoder::song_from_path("/path/to/ok").unwrap();
+        assert!(song
+            .diagnostics
+            .warnings
+            .contains(&fallback_warning("cannot decode /path/to/ok")));
+    }
+
+    #[test]
+    fn test_cancelled_not_retried() {
+        let cancellation = CancellationToken::new();
+        cancellation.cancel();
+        let analysis_options = AnalysisOptions {
+            cancellation: Some(cancellation),
+            ..Default::default()
+        };
+        assert_eq!(
+            Decoder::song_from_path_with_options("/path/to/ok", analysis_options).unwrap_err(),
+            BlissError::Cancelled,
+        );
+    }
+
+    #[cfg(feature = "symphonia-flac")]
+    #[test]
+    fn test_fall_back_to_symphonia() {
+        use crate::decoder::symphonia::SymphoniaDecoder;
+
+        let path = Path::new("data/s16_mono_22_5kHz.flac");
+        let song = FallbackDecoder::<GoodDecoder, SymphoniaDecoder>::decode(path).unwrap();
+        let expected = SymphoniaDecoder::decode(path).unwrap();
+        assert_eq!(song.sample_array, expected.sample_array);
+        assert_eq!(
+            song.warnings,
+            vec![AnalysisWarning::DecodedByFallback {
+                decoder: type_name::<SymphoniaDecoder>().to_owned(),
+                error: String::from("cannot decode data/s16_mono_22_5kHz.flac"),
+            }]
+        );
+
+        let reader = Cursor::new(std::fs::read(path).unwrap());
+        let song =
+            FallbackDecoder::<GoodDecoder, SymphoniaDecoder>::decode_reader(Box::new(reader), None)
+                .unwrap();
+        assert_eq!(song.sample_array, expected.sample_array);
+    }
+}
@@ -1,21 +1,33 @@
 //! The default decoder module. It uses [ffmpeg](https://ffmpeg.org/) in
 //! order to decode and resample songs. A very good choice for most users.
 
-use crate::decoder::{Decoder, PreAnalyzedSong};
-use crate::{BlissError, BlissResult, CHANNELS, SAMPLE_RATE};
-use ::log::warn;
+use crate::decoder::{
+    range_duration, Decoder, DecodingRange, PreAnalyzedSong, PreAnalyzedSongStream, ReadSeek,
+};
+use crate::song::DecodingIssues;
+use crate::{
+    AnalysisBudget, AnalysisWarning, BlissError, BlissResult, Loudness, LoudnessMeter, CHANNELS,
+    SAMPLE_RATE,
+};
 use ffmpeg_next;
+use ffmpeg_next::codec::decoder::Audio as AudioDecoder;
 use ffmpeg_next::codec::threading::{Config, Type as ThreadingType};
+use ffmpeg_next::ffi;
+use ffmpeg_next::format::context::Input;
+use ffmpeg_next::format::stream::Disposition;
 use ffmpeg_next::util::channel_layout::ChannelLayout;
 use ffmpeg_next::util::error::Error;
-use ffmpeg_next::util::error::EINVAL;
+use ffmpeg_next::util::error::{EINVAL, EIO};
 use ffmpeg_next::util::format::sample::{Sample, Type};
 use ffmpeg_next::util::frame::audio::Audio;
-use ffmpeg_next::util::log;
-use ffmpeg_next::util::log::level::Level;
 use ffmpeg_next::{media, util};
+use std::ffi::{c_int, c_void, CString};
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::ops::{ControlFlow, Deref, DerefMut};
+use std::ptr;
 use std::sync::mpsc;
 use std::sync::mpsc::Receiver;
+use std::sync::{Arc, OnceLock};
 use std::thread;
 use std::time::Duration;
 
@@ -26,23 +38,182 @@ use std::path::Path;
 /// To use it, one might write `use FFmpegDecoder as Decoder;`,
 /// `use super::decoder::Decoder as DecoderTrait;`, and then use
 /// `Decoder::song_from_path`
+///
+/// The problems met while decoding a song are reported in
+/// [PreAnalyzedSong::warnings]. FFmpeg's own log level is left untouched:
+/// applications that don't want FFmpeg's logs can silence them with
+/// `ffmpeg_next::util::log::set_level`.
 pub struct FFmpegDecoder;
 
 struct SendChannelLayout(ChannelLayout);
 // Safe because the other thread just reads the channel layout
 unsafe impl Send for SendChannelLayout {}
 
-impl FFmpegDecoder {
-    fn resample_frame(
-        rx: Receiver<Audio>,
+/// The number of resampled chunks that can be waiting in
+/// [FFmpegDecoder::decode_stream]'s channel before the decoding thread
+/// blocks, to keep the memory usage bounded.
+const STREAM_CHANNEL_BOUND: usize = 64;
+
+/// The size of the buffer FFmpeg reads the songs decoded by
+/// [FFmpegDecoder::decode_reader] into.
+const READER_BUFFER_SIZE: usize = 64 * 1024;
+
+// The values of `whence` given to `seek_reader`, as defined by C's `stdio.h`.
+const SEEK_SET: c_int = 0;
+const SEEK_CUR: c_int = 1;
+const SEEK_END: c_int = 2;
+
+/// A custom FFmpeg IO context, making FFmpeg read songs from a [ReadSeek]
+/// reader instead of from a file.
+struct ReaderIo {
+    context: *mut ffi::AVIOContext,
+    // Boxed again, since FFmpeg can only hold thin pointers.
+    reader: *mut Box<dyn ReadSeek>,
+}
+
+impl ReaderIo {
+    fn new(reader: Box<dyn ReadSeek>) -> BlissResult<Self> {
+        unsafe {
+            let buffer = ffi::av_malloc(READER_BUFFER_SIZE) as *mut u8;
+            if buffer.is_null() {
+                return Err(BlissError::DecodingError(String::from(
+                    "could not allocate the buffer to read the song into.",
+                )));
+            }
+            let reader = Box::into_raw(Box::new(reader));
+            let context = ffi::avio_alloc_context(
+                buffer,
+                READER_BUFFER_SIZE as c_int,
+                0,
+                reader as *mut c_void,
+                Some(read_reader),
+                None,
+                Some(seek_reader),
+            );
+            if context.is_null() {
+                ffi::av_free(buffer as *mut c_void);
+                drop(Box::from_raw(reader));
+                return Err(BlissError::DecodingError(String::from(
+                    "could not allocate the context to read the song with.",
+                )));
+            }
+            Ok(ReaderIo { context, reader })
+        }
+    }
+}
+
+impl Drop for ReaderIo {
+    fn drop(&mut self) {
+        unsafe {
+            // FFmpeg can reallocate the buffer, so free the current one
+            // rather than the one allocated in `new`.
+            ffi::av_freep(&mut (*self.context).buffer as *mut *mut u8 as *mut c_void);
+            ffi::avio_context_free(&mut self.context);
+            drop(Box::from_raw(self.reader));
+        }
+    }
+}
+
+/// Called by FFmpeg to read up to `buf_size` bytes of the reader held by
+/// a [ReaderIo] into `buf`.
+unsafe extern "C" fn read_reader(opaque: *mut c_void, buf: *mut u8, buf_size: c_int) -> c_int {
+    let reader = &mut *(opaque as *mut Box<dyn ReadSeek>);
+    let buf = std::slice::from_raw_parts_mut(buf, buf_size.max(0) as usize);
+    loop {
+        match reader.read(buf) {
+            Ok(0) => return ffi::AVERROR_EOF,
+            Ok(read) => return read as c_int,
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(_) => return ffi::AVERROR(EIO),
+        }
+    }
+}
+
+/// Called by FFmpeg to seek in the reader held by a [ReaderIo], or to get
+/// its size if `whence` is `AVSEEK_SIZE`.
+unsafe extern "C" fn seek_reader(opaque: *mut c_void, offset: i64, whence: c_int) -> i64 {
+    let reader = &mut *(opaque as *mut Box<dyn ReadSeek>);
+    let position = match whence & !ffi::AVSEEK_FORCE {
+        ffi::AVSEEK_SIZE => reader.stream_position().and_then(|position| {
+            let size = reader.seek(SeekFrom::End(0))?;
+            reader.seek(SeekFrom::Start(position))?;
+            Ok(size)
+        }),
+        SEEK_SET => match u64::try_from(offset) {
+            Ok(offset) => reader.seek(SeekFrom::Start(offset)),
+            Err(_) => return i64::from(ffi::AVERROR(EINVAL)),
+        },
+        SEEK_CUR => reader.seek(SeekFrom::Current(offset)),
+        SEEK_END => reader.seek(SeekFrom::End(offset)),
+        _ => return i64::from(ffi::AVERROR(EINVAL)),
+    };
+    match position.map(i64::try_from) {
+        Ok(Ok(position)) => position,
+        _ => i64::from(ffi::AVERROR(EIO)),
+    }
+}
+
+/// An opened FFmpeg [Input], along with the [ReaderIo] it reads from,
+/// if it doesn't read from a file.
+struct InputContext {
+    // Declared first, so that it is dropped before the `ReaderIo` it uses.
+    input: Input,
+    _reader_io: Option<ReaderIo>,
+}
+
+impl Deref for InputContext {
+    type Target = Input;
+
+    fn deref(&self) -> &Input {
+        &self.input
+    }
+}
+
+impl DerefMut for InputContext {
+    fn deref_mut(&mut self) -> &mut Input {
+        &mut self.input
+    }
+}
+
+/// An audio file that has been opened, and whose metadata has been read,
+/// ready to be decoded.
+struct InputFile {
+    song: PreAnalyzedSong,
+    ictx: InputContext,
+    decoder: AudioDecoder,
+    stream: usize,
+    expected_sample_number: f32,
+    in_channel_layout: ChannelLayout,
+    empty_in_channel_layout: bool,
+}
+
+/// Resample decoded frames to f32le, one channel, 22050 Hz samples,
+/// measuring their loudness on the way.
+struct FrameResampler {
+    resample_context: ffmpeg_next::software::resampling::context::Context,
+    resampled: Audio,
+    // Converts decoded frames to interleaved f32le samples, keeping their
+    // channels and sample rate, to measure their loudness.
+    loudness_context: ffmpeg_next::software::resampling::context::Context,
+    loudness_frame: Audio,
+    loudness_meter: LoudnessMeter,
+    in_codec_format: Sample,
+    in_channel_layout: ChannelLayout,
+    in_rate: u32,
+    empty_in_channel_layout: bool,
+    something_happened: bool,
+    // The number of decoded frames skipped because of their wrong format.
+    skipped_frames: usize,
+}
+
+impl FrameResampler {
+    fn new(
         in_codec_format: Sample,
-        sent_in_channel_layout: SendChannelLayout,
+        in_channel_layout: ChannelLayout,
         in_rate: u32,
-        mut sample_array: Vec<f32>,
         empty_in_channel_layout: bool,
-    ) -> BlissResult<Vec<f32>> {
-        let in_channel_layout = sent_in_channel_layout.0;
-        let mut resample_context = ffmpeg_next::software::resampling::context::Context::get(
+    ) -> BlissResult<Self> {
+        let resample_context = ffmpeg_next::software::resampling::context::Context::get(
             in_codec_format,
             in_channel_layout,
             in_rate,
@@ -55,67 +226,157 @@ impl FFmpegDecoder {
                 "while trying to allocate resampling context: {e:?}",
             ))
         })?;
+        let loudness_context = ffmpeg_next::software::resampling::context::Context::get(
+            in_codec_format,
+            in_channel_layout,
+            in_rate,
+            Sample::F32(Type::Packed),
+            in_channel_layout,
+            in_rate,
+        )
+        .map_err(|e| {
+            BlissError::DecodingError(format!(
+                "while trying to allocate loudness conversion context: {e:?}",
+            ))
+        })?;
+        let loudness_meter =
+            LoudnessMeter::new(in_rate, in_channel_layout.channels().max(0) as usize).map_err(
+                |e| BlissError::DecodingError(format!("while creating loudness meter: {e}")),
+            )?;
+        Ok(FrameResampler {
+            resample_context,
+            resampled: ffmpeg_next::frame::Audio::empty(),
+            loudness_context,
+            loudness_frame: ffmpeg_next::frame::Audio::empty(),
+            loudness_meter,
+            in_codec_format,
+            in_channel_layout,
+            in_rate,
+            empty_in_channel_layout,
+            something_happened: false,
+            skipped_frames: 0,
+        })
+    }
 
-        let mut resampled = ffmpeg_next::frame::Audio::empty();
-        let mut something_happened = false;
-        for mut decoded in rx.iter() {
-            #[cfg(not(feature = "ffmpeg_7_0"))]
-            let is_channel_layout_empty = decoded.channel_layout() == ChannelLayout::empty();
-            #[cfg(feature = "ffmpeg_7_0")]
-            let is_channel_layout_empty = decoded.channel_layout().is_empty();
-
-            // If the decoded layout is empty, it means we forced the
-            // "in_channel_layout" to something default, not that
-            // the format is wrong.
-            if empty_in_channel_layout && is_channel_layout_empty {
-                decoded.set_channel_layout(in_channel_layout);
-            } else if in_codec_format != decoded.format()
-                || (in_channel_layout != decoded.channel_layout())
-                || in_rate != decoded.rate()
-            {
-                warn!("received decoded packet with wrong format; file might be corrupted.");
-                continue;
-            }
-            something_happened = true;
-            resampled = ffmpeg_next::frame::Audio::empty();
-            resample_context
-                .run(&decoded, &mut resampled)
-                .map_err(|e| {
-                    BlissError::DecodingError(format!("while trying to resample song: {e:?}"))
-                })?;
-            FFmpegDecoder::push_to_sample_array(&resampled, &mut sample_array);
+    /// Resample `decoded`, and push the resulting samples to `sample_array`.
+    ///
+    /// Frames with an unexpected format, which might come from a corrupted
+    /// file, are skipped and counted.
+    fn resample(&mut self, mut decoded: Audio, sample_array: &mut Vec<f32>) -> BlissResult<()> {
+        #[cfg(not(feature = "ffmpeg_7_0"))]
+        let is_channel_layout_empty = decoded.channel_layout() == ChannelLayout::empty();
+        #[cfg(feature = "ffmpeg_7_0")]
+        let is_channel_layout_empty = decoded.channel_layout().is_empty();
+
+        // If the decoded layout is empty, it means we forced the
+        // "in_channel_layout" to something default, not that
+        // the format is wrong.
+        if self.empty_in_channel_layout && is_channel_layout_empty {
+            decoded.set_channel_layout(self.in_channel_layout);
+        } else if self.in_codec_format != decoded.format()
+            || (self.in_channel_layout != decoded.channel_layout())
+            || self.in_rate != decoded.rate()
+        {
+            self.skipped_frames += 1;
+            return Ok(());
         }
-        if !something_happened {
-            return Ok(sample_array);
+        self.something_happened = true;
+        self.resampled = ffmpeg_next::frame::Audio::empty();
+        self.resample_context
+            .run(&decoded, &mut self.resampled)
+            .map_err(|e| {
+                BlissError::DecodingError(format!("while trying to resample song: {e:?}"))
+            })?;
+        FFmpegDecoder::push_to_sample_array(&self.resampled, sample_array);
+
+        self.loudness_frame = ffmpeg_next::frame::Audio::empty();
+        self.loudness_context
+            .run(&decoded, &mut self.loudness_frame)
+            .map_err(|e| {
+                BlissError::DecodingError(format!(
+                    "while trying to convert song to measure its loudness: {e:?}"
+                ))
+            })?;
+        let mut interleaved = Vec::new();
+        FFmpegDecoder::push_interleaved_samples(&self.loudness_frame, &mut interleaved);
+        self.loudness_meter.push(&interleaved);
+        Ok(())
+    }
+
+    /// Flush the samples still buffered in the resampler to `sample_array`,
+    /// and return the loudness of the song.
+    ///
+    /// The loudness conversion context doesn't change the sample rate, so it
+    /// doesn't buffer any samples and doesn't need flushing.
+    fn flush(mut self, sample_array: &mut Vec<f32>) -> BlissResult<Option<Loudness>> {
+        if !self.something_happened {
+            return Ok(None);
         }
         // TODO when ffmpeg-next will be active again: shouldn't we allocate
         // `resampled` again?
         loop {
-            match resample_context.flush(&mut resampled).map_err(|e| {
-                BlissError::DecodingError(format!("while trying to resample song: {e:?}"))
-            })? {
+            match self
+                .resample_context
+                .flush(&mut self.resampled)
+                .map_err(|e| {
+                    BlissError::DecodingError(format!("while trying to resample song: {e:?}"))
+                })? {
                 Some(_) => {
-                    FFmpegDecoder::push_to_sample_array(&resampled, &mut sample_array);
+                    FFmpegDecoder::push_to_sample_array(&self.resampled, sample_array);
                 }
                 None => {
-                    if resampled.samples() == 0 {
+                    if self.resampled.samples() == 0 {
                         break;
                     }
-                    FFmpegDecoder::push_to_sample_array(&resampled, &mut sample_array);
+                    FFmpegDecoder::push_to_sample_array(&self.resampled, sample_array);
                 }
             };
         }
-        Ok(sample_array)
+        Ok(self.loudness_meter.finish())
+    }
+}
+
+impl FFmpegDecoder {
+    fn resample_frame(
+        rx: Receiver<Audio>,
+        in_codec_format: Sample,
+        sent_in_channel_layout: SendChannelLayout,
+        in_rate: u32,
+        mut sample_array: Vec<f32>,
+        empty_in_channel_layout: bool,
+    ) -> BlissResult<(Vec<f32>, Option<Loudness>, usize)> {
+        let mut resampler = FrameResampler::new(
+            in_codec_format,
+            sent_in_channel_layout.0,
+            in_rate,
+            empty_in_channel_layout,
+        )?;
+        for decoded in rx.iter() {
+            resampler.resample(decoded, &mut sample_array)?;
+        }
+        let skipped_frames = resampler.skipped_frames;
+        let loudness = resampler.flush(&mut sample_array)?;
+        Ok((sample_array, loudness, skipped_frames))
     }
 
     fn push_to_sample_array(frame: &ffmpeg_next::frame::Audio, sample_array: &mut Vec<f32>) {
+        FFmpegDecoder::push_samples(frame, CHANNELS, sample_array);
+    }
+
+    /// Push the samples of `frame`, which are f32le and interleaved,
+    /// to `samples`, keeping all of its channels.
+    fn push_interleaved_samples(frame: &ffmpeg_next::frame::Audio, samples: &mut Vec<f32>) {
+        FFmpegDecoder::push_samples(frame, frame.channels(), samples);
+    }
+
+    fn push_samples(frame: &ffmpeg_next::frame::Audio, channels: u16, sample_array: &mut Vec<f32>) {
         if frame.samples() == 0 {
             return;
         }
         // Account for the padding
         let actual_size = util::format::sample::Buffer::size(
             Sample::F32(Type::Packed),
-            CHANNELS,
+            channels,
             frame.samples(),
             false,
         );
@@ -129,10 +390,9 @@ impl FFmpegDecoder {
             .collect();
         sample_array.extend_from_slice(&f32_frame);
     }
-}
 
-impl Decoder for FFmpegDecoder {
-    fn decode(path: &Path) -> BlissResult<PreAnalyzedSong> {
+    /// Initialize FFmpeg before decoding the song at `path`.
+    fn init(path: &Path) -> BlissResult<()> {
         ffmpeg_next::init().map_err(|e| {
             BlissError::DecodingError(format!(
                 "ffmpeg init error while decoding file '{}': {:?}.",
@@ -140,18 +400,76 @@ impl Decoder for FFmpegDecoder {
                 e
             ))
         })?;
-        log::set_level(Level::Quiet);
-        let mut song = PreAnalyzedSong {
-            path: path.into(),
-            ..Default::default()
-        };
-        let mut ictx = ffmpeg_next::format::input(&path).map_err(|e| {
+        Ok(())
+    }
+
+    /// Open the file at `path`, set up its decoder, and read its metadata.
+    fn open(path: &Path) -> BlissResult<InputFile> {
+        FFmpegDecoder::init(path)?;
+        let input = ffmpeg_next::format::input(&path).map_err(|e| {
             BlissError::DecodingError(format!(
                 "while opening format for file '{}': {:?}.",
                 path.display(),
                 e
             ))
         })?;
+        let ictx = InputContext {
+            input,
+            _reader_io: None,
+        };
+        FFmpegDecoder::open_input(ictx, path)
+    }
+
+    /// Open the song read from `reader`, set up its decoder, and read its
+    /// metadata. `hint` is the song's extension, if known.
+    fn open_reader(reader: Box<dyn ReadSeek>, hint: Option<&str>) -> BlissResult<InputFile> {
+        let path = Path::new("");
+        FFmpegDecoder::init(path)?;
+        let reader_io = ReaderIo::new(reader)?;
+        // There is no file to open, so FFmpeg only uses this name to guess
+        // the song's format from its extension.
+        let url = CString::new(format!("reader.{}", hint.unwrap_or_default())).map_err(|e| {
+            BlissError::DecodingError(format!("invalid format hint {hint:?}: {e}."))
+        })?;
+        let opening_error = |e: Error| {
+            BlissError::DecodingError(format!("while opening format for reader: {e:?}."))
+        };
+        let input = unsafe {
+            let mut ps = ffi::avformat_alloc_context();
+            if ps.is_null() {
+                return Err(BlissError::DecodingError(String::from(
+                    "could not allocate the format context for reader.",
+                )));
+            }
+            (*ps).pb = reader_io.context;
+            (*ps).flags |= ffi::AVFMT_FLAG_CUSTOM_IO;
+            // Frees `ps` on failure.
+            match ffi::avformat_open_input(&mut ps, url.as_ptr(), ptr::null_mut(), ptr::null_mut())
+            {
+                0 => {
+                    let input = Input::wrap(ps);
+                    match ffi::avformat_find_stream_info(ps, ptr::null_mut()) {
+                        r if r >= 0 => input,
+                        e => return Err(opening_error(Error::from(e))),
+                    }
+                }
+                e => return Err(opening_error(Error::from(e))),
+            }
+        };
+        let ictx = InputContext {
+            input,
+            _reader_io: Some(reader_io),
+        };
+        FFmpegDecoder::open_input(ictx, path)
+    }
+
+    /// Set up the decoder of the song opened in `ictx`, and read its
+    /// metadata.
+    fn open_input(ictx: InputContext, path: &Path) -> BlissResult<InputFile> {
+        let mut song = PreAnalyzedSong {
+            path: path.into(),
+            ..Default::default()
+        };
         let (mut decoder, stream, expected_sample_number) = {
             let input = ictx.streams().best(media::Type::Audio).ok_or_else(|| {
                 BlissError::DecodingError(format!(
@@ -194,9 +512,13 @@ impl Decoder for FFmpegDecoder {
                 / input.time_base().denominator() as f32)
                 .ceil()
                 + SAMPLE_RATE as f32;
+            if input.duration() > 0 {
+                song.expected_duration = Some(Duration::from_secs_f64(
+                    input.duration() as f64 * f64::from(input.time_base()),
+                ));
+            }
             (decoder, input.index(), expected_sample_number)
         };
-        let sample_array: Vec<f32> = Vec::with_capacity(expected_sample_number as usize);
         if let Some(title) = ictx.metadata().get("title") {
             song.title = match title {
                 "" => None,
@@ -245,6 +567,19 @@ impl Decoder for FFmpegDecoder {
                 t => Some(t.to_string()),
             };
         };
+        for (key, value) in ictx.metadata().iter() {
+            song.tags.set(key, value);
+        }
+        // Some formats, e.g. Ogg, store their tags in the audio stream
+        // instead.
+        if let Some(audio_stream) = ictx.stream(stream) {
+            for (key, value) in audio_stream.metadata().iter() {
+                song.tags.set(key, value);
+            }
+        }
+        song.tags.has_cover_art = ictx
+            .streams()
+            .any(|s| s.disposition().contains(Disposition::ATTACHED_PIC));
 
         #[cfg(not(feature = "ffmpeg_7_0"))]
         let is_channel_layout_empty = decoder.channel_layout() == ChannelLayout::empty();
@@ -260,22 +595,39 @@ impl Decoder for FFmpegDecoder {
         };
         decoder.set_channel_layout(in_channel_layout);
 
-        let in_channel_layout_to_send = SendChannelLayout(in_channel_layout);
+        Ok(InputFile {
+            song,
+            ictx,
+            decoder,
+            stream,
+            expected_sample_number,
+            in_channel_layout,
+            empty_in_channel_layout,
+        })
+    }
 
-        let (tx, rx) = mpsc::channel();
-        let in_codec_format = decoder.format();
-        let in_codec_rate = decoder.rate();
-        let child = thread::spawn(move || {
-            FFmpegDecoder::resample_frame(
-                rx,
-                in_codec_format,
-                in_channel_layout_to_send,
-                in_codec_rate,
-                sample_array,
-                empty_in_channel_layout,
-            )
-        });
+    /// Decode all the packets of `stream`, flushing the decoder at the end,
+    /// and call `process_frame` on every decoded frame.
+    ///
+    /// A premature EOF is not considered as an error: the frames decoded
+    /// so far are kept, and the song is marked as truncated in `issues`.
+    /// Packets that can't be decoded are skipped, and counted in `issues`.
+    /// Stops as soon as `budget` runs out, or as soon as `process_frame`
+    /// breaks, without flushing the decoder.
+    fn decode_packets<F>(
+        ictx: &mut Input,
+        decoder: &mut AudioDecoder,
+        stream: usize,
+        path: &Path,
+        budget: &AnalysisBudget,
+        issues: &mut DecodingIssues,
+        mut process_frame: F,
+    ) -> BlissResult<()>
+    where
+        F: FnMut(Audio) -> BlissResult<ControlFlow<()>>,
+    {
         for (s, packet) in ictx.packets() {
+            budget.check()?;
             if s.index() != stream {
                 continue;
             }
@@ -288,28 +640,19 @@ impl Decoder for FFmpegDecoder {
                     )))
                 }
                 Err(Error::Eof) => {
-                    warn!(
-                        "Premature EOF reached while decoding file '{}'.",
-                        path.display()
-                    );
-                    drop(tx);
-                    song.sample_array = child.join().unwrap()?;
-                    return Ok(song);
+                    issues.truncated = true;
+                    return Ok(());
                 }
-                Err(e) => warn!("{} when decoding file '{}'", e, path.display()),
+                Err(_) => issues.skipped += 1,
             };
 
             loop {
                 let mut decoded = ffmpeg_next::frame::Audio::empty();
                 match decoder.receive_frame(&mut decoded) {
                     Ok(_) => {
-                        tx.send(decoded).map_err(|e| {
-                        BlissError::DecodingError(format!(
-                            "while sending decoded frame to the resampling thread for file '{}': {:?}",
-                            path.display(),
-                            e,
-                        ))
-                    })?;
+                        if process_frame(decoded)?.is_break() {
+                            return Ok(());
+                        }
                     }
                     Err(_) => break,
                 }
@@ -327,54 +670,345 @@ impl Decoder for FFmpegDecoder {
                 )))
             }
             Err(Error::Eof) => {
-                warn!(
-                    "Premature EOF reached while decoding file '{}'.",
-                    path.display()
-                );
-                drop(tx);
-                song.sample_array = child.join().unwrap()?;
-                return Ok(song);
+                issues.truncated = true;
+                return Ok(());
             }
-            Err(e) => warn!("error while decoding {}: {}", path.display(), e),
+            Err(_) => issues.skipped += 1,
         };
 
         loop {
             let mut decoded = ffmpeg_next::frame::Audio::empty();
             match decoder.receive_frame(&mut decoded) {
                 Ok(_) => {
-                    tx.send(decoded).map_err(|e| {
-                        BlissError::DecodingError(format!(
-                        "while sending decoded frame to the resampling thread for file '{}': {:?}",
-                        path.display(),
-                        e
-                    ))
-                    })?;
+                    if process_frame(decoded)?.is_break() {
+                        return Ok(());
+                    }
                 }
                 Err(_) => break,
             }
         }
+        Ok(())
+    }
+
+    /// Decode and resample the song opened in `input_file`, checking
+    /// `budget` before decoding each packet.
+    fn decode_input_file(
+        input_file: InputFile,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<PreAnalyzedSong> {
+        let InputFile {
+            mut song,
+            mut ictx,
+            mut decoder,
+            stream,
+            expected_sample_number,
+            in_channel_layout,
+            empty_in_channel_layout,
+        } = input_file;
+        let path = song.path.clone();
+        let path = path.as_path();
+        let sample_array: Vec<f32> = Vec::with_capacity(expected_sample_number as usize);
+
+        let in_channel_layout_to_send = SendChannelLayout(in_channel_layout);
+
+        let (tx, rx) = mpsc::channel();
+        let in_codec_format = decoder.format();
+        let in_codec_rate = decoder.rate();
+        let child = thread::spawn(move || {
+            FFmpegDecoder::resample_frame(
+                rx,
+                in_codec_format,
+                in_channel_layout_to_send,
+                in_codec_rate,
+                sample_array,
+                empty_in_channel_layout,
+            )
+        });
+        let mut issues = DecodingIssues::default();
+        FFmpegDecoder::decode_packets(
+            &mut ictx,
+            &mut decoder,
+            stream,
+            path,
+            budget,
+            &mut issues,
+            |decoded| {
+                tx.send(decoded).map_err(|e| {
+                    BlissError::DecodingError(format!(
+                        "while sending decoded frame to the resampling thread for file '{}': {:?}",
+                        path.display(),
+                        e,
+                    ))
+                })?;
+                Ok(ControlFlow::Continue(()))
+            },
+        )?;
 
         drop(tx);
-        song.sample_array = child.join().unwrap()?;
+        let skipped_frames;
+        (song.sample_array, song.loudness, skipped_frames) = child.join().unwrap()?;
+        issues.skipped += skipped_frames;
         let duration_seconds = song.sample_array.len() as f32 / SAMPLE_RATE as f32;
         song.duration = Duration::from_nanos((duration_seconds * 1e9_f32).round() as u64);
+        song.set_warnings(issues);
+        Ok(song)
+    }
+
+    /// Decode and resample the file at `path` frame by frame, sending
+    /// its metadata through `song_tx` as soon as the file is opened, and
+    /// the resampled chunks through `samples_tx`. The song's loudness and
+    /// decoding warnings are set in `loudness` and `warnings` once the
+    /// whole file is decoded.
+    ///
+    /// Stops as soon as the receiving end of `samples_tx` is dropped.
+    fn stream_samples(
+        path: &Path,
+        song_tx: mpsc::Sender<BlissResult<PreAnalyzedSong>>,
+        samples_tx: &mpsc::SyncSender<BlissResult<Vec<f32>>>,
+        loudness: &OnceLock<Loudness>,
+        warnings: &OnceLock<Vec<AnalysisWarning>>,
+    ) -> BlissResult<()> {
+        let (input_file, mut resampler) = match FFmpegDecoder::open(path).and_then(|input_file| {
+            let resampler = FrameResampler::new(
+                input_file.decoder.format(),
+                input_file.in_channel_layout,
+                input_file.decoder.rate(),
+                input_file.empty_in_channel_layout,
+            )?;
+            Ok((input_file, resampler))
+        }) {
+            Ok(opened) => opened,
+            Err(e) => {
+                // Nobody to report the error to if the receiver is gone.
+                let _ = song_tx.send(Err(e));
+                return Ok(());
+            }
+        };
+        let InputFile {
+            song,
+            mut ictx,
+            mut decoder,
+            stream,
+            ..
+        } = input_file;
+        if song_tx.send(Ok(song)).is_err() {
+            return Ok(());
+        }
+
+        let send_chunk = |chunk: Vec<f32>| {
+            if chunk.is_empty() {
+                return Ok(());
+            }
+            samples_tx.send(Ok(chunk)).map_err(|_| {
+                BlissError::DecodingError(format!(
+                    "stopped decoding file '{}' since its samples are not received anymore.",
+                    path.display()
+                ))
+            })
+        };
+        // The budget is checked by whoever consumes the samples, e.g. a
+        // `StreamingAnalyzer`.
+        let budget = AnalysisBudget::default();
+        let mut issues = DecodingIssues::default();
+        FFmpegDecoder::decode_packets(
+            &mut ictx,
+            &mut decoder,
+            stream,
+            path,
+            &budget,
+            &mut issues,
+            |decoded| {
+                let mut chunk = Vec::new();
+                resampler.resample(decoded, &mut chunk)?;
+                send_chunk(chunk)?;
+                Ok(ControlFlow::Continue(()))
+            },
+        )?;
+        issues.skipped += resampler.skipped_frames;
+        let mut chunk = Vec::new();
+        if let Some(song_loudness) = resampler.flush(&mut chunk)? {
+            let _ = loudness.set(song_loudness);
+        }
+        let _ = warnings.set(issues.get_warnings());
+        send_chunk(chunk)
+    }
+}
+
+impl Decoder for FFmpegDecoder {
+    fn decode(path: &Path) -> BlissResult<PreAnalyzedSong> {
+        Self::decode_with_budget(path, &AnalysisBudget::default())
+    }
+
+    /// Decode the song, checking `budget` before decoding each packet.
+    fn decode_with_budget(path: &Path, budget: &AnalysisBudget) -> BlissResult<PreAnalyzedSong> {
+        budget.check()?;
+        FFmpegDecoder::decode_input_file(FFmpegDecoder::open(path)?, budget)
+    }
+
+    fn decode_reader(
+        reader: Box<dyn ReadSeek>,
+        hint: Option<&str>,
+    ) -> BlissResult<PreAnalyzedSong> {
+        Self::decode_reader_with_budget(reader, hint, &AnalysisBudget::default())
+    }
+
+    /// Decode the song read from `reader` through a custom FFmpeg IO
+    /// context, checking `budget` before decoding each packet.
+    fn decode_reader_with_budget(
+        reader: Box<dyn ReadSeek>,
+        hint: Option<&str>,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<PreAnalyzedSong> {
+        budget.check()?;
+        FFmpegDecoder::decode_input_file(FFmpegDecoder::open_reader(reader, hint)?, budget)
+    }
+
+    /// Decode the part of the song between `start` and `end`, seeking
+    /// right before `start`, and stopping right after `end`.
+    fn decode_range_with_budget(
+        path: &Path,
+        start: Duration,
+        end: Option<Duration>,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<PreAnalyzedSong> {
+        budget.check()?;
+        let InputFile {
+            song,
+            mut ictx,
+            mut decoder,
+            stream,
+            in_channel_layout,
+            empty_in_channel_layout,
+            ..
+        } = FFmpegDecoder::open(path)?;
+        let sample_rate = decoder.rate();
+        let time_base = ictx
+            .stream(stream)
+            .map(|s| f64::from(s.time_base()))
+            .unwrap_or_default();
+        // Decoded frames can't be split, so resampling starts at the
+        // beginning of a frame.
+        let mut range = DecodingRange::new(start, end, sample_rate, 1);
+        let seek_time = range.seek_time();
+        let seeking = !seek_time.is_zero() && time_base > 0. && {
+            let timestamp = seek_time.as_micros() as i64;
+            // Decode the song from its beginning instead if seeking fails,
+            // e.g. if it is shorter than `start`.
+            ictx.seek(timestamp, ..timestamp).is_ok()
+        };
+        let mut resampler = FrameResampler::new(
+            decoder.format(),
+            in_channel_layout,
+            sample_rate,
+            empty_in_channel_layout,
+        )?;
+
+        let mut index = 0;
+        let mut resampled = Vec::new();
+        let mut issues = DecodingIssues::default();
+        FFmpegDecoder::decode_packets(
+            &mut ictx,
+            &mut decoder,
+            stream,
+            path,
+            budget,
+            &mut issues,
+            |decoded| {
+                // Without seeking, count the samples from the first packet,
+                // like when decoding the whole song.
+                if let Some(timestamp) = decoded.timestamp().filter(|_| seeking) {
+                    index = (timestamp.max(0) as f64 * time_base * f64::from(sample_rate)).round()
+                        as u64;
+                }
+                let frame_index = index;
+                index += decoded.samples() as u64;
+                if range.is_past_end(frame_index) {
+                    return Ok(ControlFlow::Break(()));
+                }
+                if range.start_offset(frame_index, 1).is_some() {
+                    resampler.resample(decoded, &mut resampled)?;
+                }
+                Ok(ControlFlow::Continue(()))
+            },
+        )?;
+        issues.skipped += resampler.skipped_frames;
+        resampler.flush(&mut resampled)?;
+        budget.check()?;
+
+        let sample_array = range.cut(resampled);
+        let mut song = PreAnalyzedSong {
+            duration: Duration::from_secs_f64(sample_array.len() as f64 / SAMPLE_RATE as f64),
+            expected_duration: range_duration(song.expected_duration, start, end),
+            sample_array,
+            ..song
+        };
+        song.set_warnings(issues);
         Ok(song)
     }
+
+    /// Decode the song in a separate thread, resampling it frame by frame,
+    /// and yield the resampled frames as soon as they're available.
+    ///
+    /// The decoding thread stays at most a few frames ahead of the consumer,
+    /// and stops if the returned iterator is dropped.
+    fn decode_stream(path: &Path) -> BlissResult<PreAnalyzedSongStream> {
+        let (song_tx, song_rx) = mpsc::channel();
+        let (samples_tx, samples_rx) = mpsc::sync_channel(STREAM_CHANNEL_BOUND);
+        let owned_path = path.to_owned();
+        let loudness = Arc::new(OnceLock::new());
+        let thread_loudness = loudness.clone();
+        let warnings = Arc::new(OnceLock::new());
+        let thread_warnings = warnings.clone();
+        thread::spawn(move || {
+            if let Err(e) = FFmpegDecoder::stream_samples(
+                &owned_path,
+                song_tx,
+                &samples_tx,
+                &thread_loudness,
+                &thread_warnings,
+            ) {
+                let _ = samples_tx.send(Err(e));
+            }
+        });
+        let song = song_rx.recv().map_err(|e| {
+            BlissError::DecodingError(format!(
+                "while receiving the metadata of file '{}': {:?}",
+                path.display(),
+                e,
+            ))
+        })??;
+        Ok(PreAnalyzedSongStream {
+            song,
+            samples: Box::new(samples_rx.into_iter()),
+            loudness,
+            warnings,
+        })
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::decoder::ffmpeg::FFmpegDecoder as Decoder;
     use crate::decoder::Decoder as DecoderTrait;
-    use crate::decoder::PreAnalyzedSong;
+    use crate::decoder::{sample_index, PreAnalyzedSong};
+    use crate::AnalysisEvent;
     use crate::AnalysisOptions;
+    use crate::AnalysisWarning;
     use crate::BlissError;
+    use crate::CancellationToken;
+    use crate::DeliveryOptions;
     use crate::Song;
+    use crate::Tags;
     use crate::SAMPLE_RATE;
     use adler32::RollingAdler32;
     use pretty_assertions::assert_eq;
+    use std::fs;
+    use std::io::Cursor;
     use std::num::NonZero;
-    use std::path::Path;
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
 
     fn _test_decode(path: &Path, expected_hash: u32) {
         let song = Decoder::decode(path).unwrap();
@@ -399,6 +1033,7 @@ mod tests {
         assert_eq!(song.track_number, Some(2));
         assert_eq!(song.disc_number, Some(1));
         assert_eq!(song.genre, Some(String::from("Pop")));
+        assert_eq!(song.tags, Tags::default());
         // Test that there is less than 10ms of difference between what
         // the song advertises and what we compute.
         assert!((song.duration.as_millis() as f32 - 11070.).abs() < 10.);
@@ -428,6 +1063,7 @@ mod tests {
         assert_eq!(song.track_number, None);
         assert_eq!(song.disc_number, None);
         assert_eq!(song.genre, None);
+        assert_eq!(song.tags, Tags::default());
     }
 
     #[test]
@@ -520,6 +1156,183 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_decode_reader() {
+        for (path_str, hint) in [
+            ("data/s16_mono_22_5kHz.flac", None),
+            ("data/s32_stereo_44_1_kHz.mp3", Some("mp3")),
+            ("data/capacity_fix.ogg", None),
+        ] {
+            let path = Path::new(path_str);
+            let decoded = Decoder::decode(path).unwrap();
+            let reader = Cursor::new(fs::read(path).unwrap());
+            let read = Decoder::decode_reader(Box::new(reader), hint).unwrap();
+            assert_eq!(read.path, PathBuf::new());
+            assert_eq!(decoded.title, read.title, "file: {path_str}");
+            assert_eq!(decoded.duration, read.duration, "file: {path_str}");
+            assert_eq!(decoded.sample_array, read.sample_array, "file: {path_str}");
+        }
+    }
+
+    #[test]
+    fn test_decode_reader_errors() {
+        let reader = Cursor::new(fs::read("data/picture.png").unwrap());
+        assert_eq!(
+            Decoder::decode_reader(Box::new(reader), Some("png")).unwrap_err(),
+            BlissError::DecodingError(String::from("No audio stream found for file ''.")),
+        );
+        let reader = Cursor::new(Vec::new());
+        assert!(Decoder::decode_reader(Box::new(reader), None).is_err());
+    }
+
+    #[test]
+    fn test_decode_warnings() {
+        let path = Path::new("data/s16_mono_22_5kHz.flac");
+        let song = Decoder::decode(path).unwrap();
+        assert_eq!(song.warnings, vec![]);
+
+        let bytes = fs::read(path).unwrap();
+        let truncated = Cursor::new(bytes[..bytes.len() / 2].to_vec());
+        let song = Decoder::decode_reader(Box::new(truncated), Some("flac")).unwrap();
+        assert!(song.warnings.iter().any(|w| matches!(
+            w,
+            AnalysisWarning::SampleCountMismatch { actual, .. }
+                if *actual == song.sample_array.len()
+        )));
+    }
+
+    #[test]
+    fn test_decode_range() {
+        for path_str in ["data/testcue.flac", "data/piano.wav"] {
+            let path = Path::new(path_str);
+            let decoded = Decoder::decode(path).unwrap();
+            for (start, end) in [
+                (Duration::ZERO, Some(Duration::from_millis(1500))),
+                (
+                    Duration::from_secs_f64(11.0667),
+                    Some(Duration::from_secs_f64(16.92)),
+                ),
+                (Duration::from_secs(2), None),
+            ] {
+                let range = Decoder::decode_range(path, start, end).unwrap();
+                let start_index = sample_index(start).min(decoded.sample_array.len());
+                let end_index = end.map_or(decoded.sample_array.len(), |end| {
+                    sample_index(end).clamp(start_index, decoded.sample_array.len())
+                });
+                let expected = &decoded.sample_array[start_index..end_index];
+                assert_eq!(
+                    expected.len(),
+                    range.sample_array.len(),
+                    "file: {path_str}, range: {start:?}..{end:?}"
+                );
+                assert!(
+                    expected
+                        .iter()
+                        .zip(&range.sample_array)
+                        .all(|(a, b)| (a - b).abs() < 1e-4),
+                    "file: {path_str}, range: {start:?}..{end:?}"
+                );
+                assert_eq!(range.loudness, None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_song_from_reader() {
+        let path = Path::new("data/s16_mono_22_5kHz.flac");
+        let song = Decoder::song_from_path(path).unwrap();
+        let reader = Cursor::new(fs::read(path).unwrap());
+        let read_song = Decoder::song_from_reader(reader, Some("flac")).unwrap();
+        assert_eq!(read_song.path, PathBuf::new());
+        assert_eq!(song.analysis, read_song.analysis);
+        assert_eq!(song.artist, read_song.artist);
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let reader = Cursor::new(fs::read(path).unwrap());
+        let analysis_options = AnalysisOptions {
+            cancellation: Some(token),
+            ..Default::default()
+        };
+        assert_eq!(
+            Decoder::song_from_reader_with_options(reader, None, analysis_options).unwrap_err(),
+            BlissError::Cancelled,
+        );
+    }
+
+    #[test]
+    fn test_decode_stream_same_as_decode() {
+        for path_str in [
+            "data/s16_mono_22_5kHz.flac",
+            "data/s32_stereo_44_1_kHz.flac",
+            "data/capacity_fix.ogg",
+            "data/s32_stereo_44_1_kHz.mp3",
+            "data/flush_test_52000.wav",
+            "data/no_channel.wav",
+        ] {
+            let path = Path::new(path_str);
+            let song = Decoder::decode(path).unwrap();
+            let stream = Decoder::decode_stream(path).unwrap();
+            assert_eq!(song.title, stream.song.title);
+            assert_eq!(song.artist, stream.song.artist);
+            assert_eq!(song.track_number, stream.song.track_number);
+            assert!(stream.song.sample_array.is_empty());
+            let streamed = stream
+                .samples
+                .collect::<Result<Vec<Vec<f32>>, _>>()
+                .unwrap()
+                .concat();
+            assert_eq!(song.sample_array, streamed, "file: {path_str}");
+        }
+    }
+
+    #[test]
+    fn test_decode_stream_errors() {
+        assert_eq!(
+            Decoder::decode_stream(Path::new("data/picture.png"))
+                .err()
+                .unwrap(),
+            BlissError::DecodingError(String::from(
+                "No audio stream found for file 'data/picture.png'."
+            )),
+        );
+    }
+
+    #[test]
+    fn test_decode_stream_dropped_early() {
+        let mut stream = Decoder::decode_stream(Path::new("data/piano.flac")).unwrap();
+        assert!(!stream.samples.next().unwrap().unwrap().is_empty());
+        // Dropping the iterator should stop the decoding thread, without panicking.
+        drop(stream);
+    }
+
+    #[test]
+    fn test_decode_expected_duration() {
+        let song = Decoder::decode(Path::new("data/s16_mono_22_5kHz.flac")).unwrap();
+        let duration = song.sample_array.len() as f64 / SAMPLE_RATE as f64;
+        assert!((song.expected_duration.unwrap().as_secs_f64() - duration).abs() < 1e-2);
+    }
+
+    #[test]
+    fn test_song_from_path_streaming() {
+        let path = Path::new("data/s16_mono_22_5kHz.flac");
+        let song = Decoder::song_from_path(path).unwrap();
+        let streamed_song =
+            Decoder::song_from_path_streaming(path, AnalysisOptions::default()).unwrap();
+        assert_eq!(song.title, streamed_song.title);
+        assert!(song.diagnostics.is_empty());
+        assert_eq!(song.diagnostics, streamed_song.diagnostics);
+        assert!((song.duration.as_secs_f32() - streamed_song.duration.as_secs_f32()).abs() < 1e-3);
+        for (x, y) in song
+            .analysis
+            .as_vec()
+            .iter()
+            .zip(streamed_song.analysis.as_vec())
+        {
+            assert!(1e-5 > (x - y).abs());
+        }
+    }
+
     #[test]
     fn test_decode_wav() {
         let expected_hash = 0xde831e82;
@@ -534,16 +1347,24 @@ mod tests {
 
     #[test]
     fn test_analyze_paths() {
-        let analysis = Decoder::analyze_paths(["data/nonexistent", "data/piano.flac"])
-            .map(|s| s.1.is_ok())
+        // Songs are analyzed concurrently, so they can come back in any order.
+        let mut analysis = Decoder::analyze_paths(["data/nonexistent", "data/piano.flac"])
+            .map(|(path, song)| (path, song.is_ok()))
             .collect::<Vec<_>>();
-        assert_eq!(analysis, vec![false, true]);
+        analysis.sort();
+        assert_eq!(
+            analysis,
+            vec![
+                (PathBuf::from("data/nonexistent"), false),
+                (PathBuf::from("data/piano.flac"), true),
+            ]
+        );
     }
 
     #[test]
     fn test_analyze_paths_with_cores() {
         // Analyze with a number of cores greater than the system's number of cores.
-        let analysis = Decoder::analyze_paths_with_options(
+        let mut analysis = Decoder::analyze_paths_with_options(
             [
                 "data/nonexistent",
                 "data/piano.flac",
@@ -554,9 +1375,184 @@ mod tests {
                 ..Default::default()
             },
         )
-        .map(|s| s.1.is_ok())
+        .map(|(path, song)| (path, song.is_ok()))
+        .collect::<Vec<_>>();
+        analysis.sort();
+        assert_eq!(
+            analysis,
+            vec![
+                (PathBuf::from("data/nonexistent"), false),
+                (PathBuf::from("data/nonexistent.cue"), false),
+                (PathBuf::from("data/piano.flac"), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_analyze_paths_single_core() {
+        let analysis = Decoder::analyze_paths_with_options(
+            ["data/piano.flac", "data/s16_mono_22_5kHz.flac"],
+            AnalysisOptions {
+                number_cores: NonZero::new(1).unwrap(),
+                ..Default::default()
+            },
+        )
+        .map(|(path, song)| (path, song.is_ok()))
+        .collect::<Vec<_>>();
+        // A single worker analyzes the songs one after the other.
+        assert_eq!(
+            analysis,
+            vec![
+                (PathBuf::from("data/piano.flac"), true),
+                (PathBuf::from("data/s16_mono_22_5kHz.flac"), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_analyze_paths_ordered() {
+        let analysis = Decoder::analyze_paths_with_options(
+            [
+                "data/testcue.cue",
+                "data/piano.flac",
+                "data/nonexistent",
+                "data/s16_mono_22_5kHz.flac",
+            ],
+            AnalysisOptions {
+                delivery: Some(DeliveryOptions {
+                    ordered: true,
+                    max_pending_results: Some(NonZero::new(1).unwrap()),
+                }),
+                ..Default::default()
+            },
+        )
+        .map(|(path, song)| (path, song.is_ok()))
+        .collect::<Vec<_>>();
+        assert_eq!(
+            analysis,
+            vec![
+                (PathBuf::from("data/testcue.cue"), true),
+                (PathBuf::from("data/testcue.cue"), true),
+                (PathBuf::from("data/testcue.cue"), true),
+                (PathBuf::from("data/testcue.cue"), false),
+                (PathBuf::from("data/piano.flac"), true),
+                (PathBuf::from("data/nonexistent"), false),
+                (PathBuf::from("data/s16_mono_22_5kHz.flac"), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_analyze_paths_bounded() {
+        let (tx, rx) = mpsc::channel();
+        let mut results = Decoder::analyze_paths_with_options(
+            [
+                "data/piano.flac",
+                "data/s16_mono_22_5kHz.flac",
+                "data/s16_stereo_22_5kHz.flac",
+            ],
+            AnalysisOptions {
+                number_cores: NonZero::new(1).unwrap(),
+                delivery: Some(DeliveryOptions {
+                    ordered: false,
+                    max_pending_results: Some(NonZero::new(1).unwrap()),
+                }),
+                progress: Some(tx.into()),
+                ..Default::default()
+            },
+        );
+        // The first song waits to be consumed, and the second one can't
+        // be handed out until it is, so the third one isn't started.
+        let analyzed = rx
+            .iter()
+            .filter(|event| matches!(event, AnalysisEvent::Analyzed { .. }))
+            .nth(1);
+        assert!(analyzed.is_some());
+        thread::sleep(Duration::from_millis(200));
+        assert!(!rx.try_iter().any(|event| matches!(
+            event,
+            AnalysisEvent::Started { path } if path == Path::new("data/s16_stereo_22_5kHz.flac")
+        )));
+        assert_eq!(results.next().unwrap().0, PathBuf::from("data/piano.flac"));
+        assert_eq!(results.count(), 2);
+    }
+
+    #[test]
+    fn test_analyze_paths_progress() {
+        let (tx, rx) = mpsc::channel();
+        let analysis = Decoder::analyze_paths_with_options(
+            ["data/piano.flac", "data/nonexistent"],
+            AnalysisOptions {
+                number_cores: NonZero::new(1).unwrap(),
+                progress: Some(tx.into()),
+                ..Default::default()
+            },
+        )
+        .collect::<Vec<_>>();
+        assert_eq!(analysis.len(), 2);
+        let events = rx.try_iter().collect::<Vec<_>>();
+        assert_eq!(events.len(), 5);
+        assert!(matches!(
+            &events[0],
+            AnalysisEvent::Started { path } if path == Path::new("data/piano.flac")
+        ));
+        assert!(matches!(
+            &events[1],
+            AnalysisEvent::Decoded { path, .. } if path == Path::new("data/piano.flac")
+        ));
+        assert!(matches!(
+            &events[2],
+            AnalysisEvent::Analyzed { path, elapsed }
+                if path == Path::new("data/piano.flac") && *elapsed > Duration::ZERO
+        ));
+        assert!(matches!(
+            &events[3],
+            AnalysisEvent::Started { path } if path == Path::new("data/nonexistent")
+        ));
+        assert!(matches!(
+            &events[4],
+            AnalysisEvent::Failed { path, error: BlissError::DecodingError(_), .. }
+                if path == Path::new("data/nonexistent")
+        ));
+    }
+
+    #[test]
+    fn test_analyze_paths_cancelled() {
+        let token = CancellationToken::new();
+        token.cancel();
+        let analysis = Decoder::analyze_paths_with_options(
+            ["data/piano.flac", "data/s16_mono_22_5kHz.flac"],
+            AnalysisOptions {
+                cancellation: Some(token),
+                ..Default::default()
+            },
+        )
         .collect::<Vec<_>>();
-        assert_eq!(analysis, vec![false, true, false]);
+        assert_eq!(analysis, vec![]);
+    }
+
+    #[test]
+    fn test_song_from_path_timed_out() {
+        let analysis_options = AnalysisOptions {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        assert_eq!(
+            Decoder::song_from_path_with_options("data/piano.flac", analysis_options.clone()),
+            Err(BlissError::TimedOut(Duration::ZERO)),
+        );
+        assert_eq!(
+            Decoder::song_from_path_streaming("data/piano.flac", analysis_options).unwrap_err(),
+            BlissError::TimedOut(Duration::ZERO),
+        );
+        assert!(Decoder::song_from_path_with_options(
+            "data/piano.flac",
+            AnalysisOptions {
+                timeout: Some(Duration::from_secs(3600)),
+                ..Default::default()
+            },
+        )
+        .is_ok());
     }
 
     #[test]
@@ -2,28 +2,37 @@
 //!
 //! Upstreamed from the `mecomp-analysis` crate.
 
-use std::{f32::consts::SQRT_2, fs::File};
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
 
-use audioadapter_buffers::direct::InterleavedSlice;
-use rubato::{Fft, FixedSync, Resampler};
 use symphonia::{
     core::{
         audio::{layouts::CHANNEL_LAYOUT_STEREO, AudioSpec, GenericAudioBufferRef},
         codecs::audio::AudioDecoderOptions,
-        errors::Error,
+        errors::{Error, SeekErrorKind},
         formats::probe::Hint,
-        formats::{FormatReader, TrackType},
-        io::{MediaSourceStream, MediaSourceStreamOptions},
-        meta::MetadataOptions,
+        formats::{FormatReader, SeekMode, SeekTo, TrackType},
+        io::{MediaSource, MediaSourceStream, MediaSourceStreamOptions},
+        meta::{MetadataOptions, StandardTag, Tag},
         units,
     },
     default::get_probe,
 };
 use thiserror::Error;
 
-use crate::{BlissError, BlissResult, SAMPLE_RATE};
+use crate::song::decoder::{range_duration, DecodingRange};
+use crate::song::pcm::{downmix, MonoResampler};
+use crate::song::DecodingIssues;
+use crate::{
+    AnalysisBudget, AnalysisWarning, BlissError, BlissResult, Loudness, LoudnessMeter, SAMPLE_RATE,
+};
 
-use super::{Decoder, PreAnalyzedSong};
+use super::{Decoder, PreAnalyzedSong, PreAnalyzedSongStream, ReadSeek};
 
 #[derive(Debug, Error, PartialEq, Eq, Clone)]
 /// Error raised when trying to decode a song with the `SymphoniaDecoder`.
@@ -84,21 +93,31 @@ impl From<SymphoniaDecoderError> for BlissError {
 }
 
 const MAX_DECODE_RETRIES: usize = 3;
-const CHUNK_SIZE: usize = 4096;
 
 /// Struct used by the symphonia-based bliss decoders to decode audio files
 struct SymphoniaSource {
     decoder: Box<dyn symphonia::core::codecs::audio::AudioDecoder>,
     current_span_offset: usize,
     format: Box<dyn FormatReader>,
+    track_id: u32,
+    time_base: Option<units::TimeBase>,
     total_duration: Option<units::Time>,
     buffer: Vec<f32>,
+    // The timestamp of the packet `buffer` was decoded from.
+    buffer_ts: Option<units::Timestamp>,
     spec: AudioSpec,
+    // The song's metadata, with an empty sample array.
+    metadata: PreAnalyzedSong,
+    // The packets skipped so far, and whether decoding stopped early.
+    issues: DecodingIssues,
 }
 
 impl SymphoniaSource {
-    pub fn new(mss: MediaSourceStream<'static>) -> Result<Self, SymphoniaDecoderError> {
-        match Self::init(mss) {
+    pub fn new(
+        mss: MediaSourceStream<'static>,
+        hint: &Hint,
+    ) -> Result<Self, SymphoniaDecoderError> {
+        match Self::init(mss, hint) {
             Err(e) => match e {
                 Error::IoError(e) => Err(SymphoniaDecoderError::IoError(e.to_string())),
                 Error::SeekError(_) => {
@@ -114,11 +133,13 @@ impl SymphoniaSource {
     /// A "substantial portion" of this implementation comes from the `rodio` crate,
     /// <https://github.com/RustAudio/rodio/blob/1c2cd2f6d99c005533b7a2b4c19ef41728f62116/src/decoder/symphonia.rs>
     /// and is licensed under the MIT License.
-    fn init(mss: MediaSourceStream<'static>) -> symphonia::core::errors::Result<Option<Self>> {
-        let hint = Hint::new();
+    fn init(
+        mss: MediaSourceStream<'static>,
+        hint: &Hint,
+    ) -> symphonia::core::errors::Result<Option<Self>> {
         let format_opts = Default::default();
         let metadata_opts = MetadataOptions::default();
-        let mut format = get_probe().probe(&hint, mss, format_opts, metadata_opts)?;
+        let mut format = get_probe().probe(hint, mss, format_opts, metadata_opts)?;
 
         if format.default_track(TrackType::Audio).is_none() {
             return Ok(None);
@@ -138,6 +159,7 @@ impl SymphoniaSource {
             .ok_or(Error::Unsupported("No track with supported codec"))?;
 
         let track_id = track.id;
+        let time_base = track.time_base;
 
         let mut decoder = symphonia::default::get_codecs().make_audio_decoder(
             track
@@ -155,7 +177,10 @@ impl SymphoniaSource {
             tb.calc_time(ts)
         });
 
+        let metadata = Self::read_metadata(format.as_mut(), track_id);
+
         let mut decode_errors: usize = 0;
+        let mut buffer_ts = None;
         let decoded = loop {
             let current_span = match format.next_packet() {
                 Ok(Some(packet)) => packet,
@@ -167,6 +192,7 @@ impl SymphoniaSource {
             if current_span.track_id != track_id {
                 continue;
             }
+            buffer_ts = Some(current_span.pts);
 
             match decoder.decode(&current_span) {
                 Ok(decoded) => break decoded,
@@ -184,12 +210,178 @@ impl SymphoniaSource {
             decoder,
             current_span_offset: 0,
             format,
+            track_id,
+            time_base,
             total_duration,
             buffer,
+            buffer_ts,
             spec,
+            metadata,
+            issues: DecodingIssues {
+                skipped: decode_errors,
+                truncated: false,
+            },
         }))
     }
 
+    /// Read the song's tags and whether it has a cover art, from the
+    /// metadata of the whole file and of the track `track_id`. The first
+    /// value found for each tag is kept, like FFmpeg does.
+    fn read_metadata(format: &mut dyn FormatReader, track_id: u32) -> PreAnalyzedSong {
+        let mut song = PreAnalyzedSong::default();
+        let mut metadata = format.metadata();
+        loop {
+            if let Some(revision) = metadata.current() {
+                let containers = std::iter::once(&revision.media).chain(
+                    revision
+                        .per_track
+                        .iter()
+                        .filter(|t| t.track_id == u64::from(track_id))
+                        .map(|t| &t.metadata),
+                );
+                for container in containers {
+                    for tag in &container.tags {
+                        Self::read_tag(&mut song, tag);
+                    }
+                    song.tags.has_cover_art |= !container.visuals.is_empty();
+                }
+            }
+            if metadata.pop().is_none() {
+                break;
+            }
+        }
+        song
+    }
+
+    /// Store `tag` in `song`, if it is one of the tags bliss reads.
+    fn read_tag(song: &mut PreAnalyzedSong, tag: &Tag) {
+        fn set_string(field: &mut Option<String>, value: &str) {
+            if field.is_none() && !value.is_empty() {
+                *field = Some(value.to_owned());
+            }
+        }
+        fn set_number(field: &mut Option<i32>, value: u64) {
+            if field.is_none() {
+                *field = i32::try_from(value).ok();
+            }
+        }
+        // Use the same names as the tags read by `Tags::set`.
+        let (key, value) = match &tag.std {
+            Some(StandardTag::TrackTitle(title)) => return set_string(&mut song.title, title),
+            Some(StandardTag::Artist(artist)) => return set_string(&mut song.artist, artist),
+            Some(StandardTag::Album(album)) => return set_string(&mut song.album, album),
+            Some(StandardTag::AlbumArtist(album_artist)) => {
+                return set_string(&mut song.album_artist, album_artist)
+            }
+            Some(StandardTag::Genre(genre)) => return set_string(&mut song.genre, genre),
+            Some(StandardTag::TrackNumber(number)) => {
+                return set_number(&mut song.track_number, *number)
+            }
+            Some(StandardTag::DiscNumber(number)) => {
+                return set_number(&mut song.disc_number, *number)
+            }
+            Some(StandardTag::RecordingDate(date) | StandardTag::ReleaseDate(date)) => {
+                ("date", date.to_string())
+            }
+            Some(StandardTag::RecordingYear(year) | StandardTag::ReleaseYear(year)) => {
+                ("year", year.to_string())
+            }
+            Some(StandardTag::Composer(composer)) => ("composer", composer.to_string()),
+            Some(StandardTag::MusicBrainzRecordingId(id) | StandardTag::MusicBrainzTrackId(id)) => {
+                ("musicbrainz_trackid", id.to_string())
+            }
+            Some(StandardTag::MusicBrainzAlbumId(id)) => ("musicbrainz_albumid", id.to_string()),
+            Some(StandardTag::MusicBrainzArtistId(id)) => ("musicbrainz_artistid", id.to_string()),
+            Some(StandardTag::IdentIsrc(isrc)) => ("isrc", isrc.to_string()),
+            Some(StandardTag::Bpm(bpm)) => ("bpm", bpm.to_string()),
+            Some(StandardTag::InitialKey(key)) => ("initialkey", key.to_string()),
+            Some(StandardTag::ReplayGainTrackGain(gain)) => {
+                ("replaygain_track_gain", gain.to_string())
+            }
+            Some(StandardTag::ReplayGainTrackPeak(peak)) => {
+                ("replaygain_track_peak", peak.to_string())
+            }
+            Some(StandardTag::ReplayGainAlbumGain(gain)) => {
+                ("replaygain_album_gain", gain.to_string())
+            }
+            Some(StandardTag::ReplayGainAlbumPeak(peak)) => {
+                ("replaygain_album_peak", peak.to_string())
+            }
+            // E.g. a BPM that isn't an integer.
+            _ => (tag.raw.key.as_str(), tag.raw.value.to_string()),
+        };
+        song.tags.set(key, &value);
+    }
+
+    /// The duration of the track, as announced by the container.
+    fn expected_duration(&self) -> Option<Duration> {
+        self.total_duration
+            .and_then(|duration| u64::try_from(duration.as_nanos()).ok())
+            .map(Duration::from_nanos)
+    }
+
+    /// Return the samples of the current buffer that haven't been read yet,
+    /// or decode the next packet. Returns `None` at the end of the stream.
+    fn next_buffer(&mut self) -> Option<Vec<f32>> {
+        if self.current_span_offset < self.buffer.len() {
+            let buffer = self.buffer.split_off(self.current_span_offset);
+            self.current_span_offset = self.buffer.len();
+            return Some(buffer);
+        }
+        let mut decode_errors = 0;
+        let decoded = loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet?,
+                Err(_) => {
+                    self.issues.truncated = true;
+                    return None;
+                }
+            };
+            self.buffer_ts = Some(packet.pts);
+            match self.decoder.decode(&packet) {
+                // Same as `Iterator::next`, skip packets without audio frames.
+                Ok(decoded) if decoded.frames() > 0 => break decoded,
+                Ok(_) => continue,
+                Err(Error::DecodeError(_)) if decode_errors < MAX_DECODE_RETRIES => {
+                    decode_errors += 1;
+                    self.issues.skipped += 1;
+                    continue;
+                }
+                Err(_) => {
+                    self.issues.truncated = true;
+                    return None;
+                }
+            }
+        };
+        decoded.spec().clone_into(&mut self.spec);
+        Some(Self::get_buffer(decoded))
+    }
+
+    /// The index of the first sample of the last decoded packet, computed
+    /// from its timestamp, if known.
+    fn buffer_index(&self) -> Option<u64> {
+        let time = self.time_base?.calc_time(self.buffer_ts?)?;
+        let index = (time.as_nanos() * i128::from(self.spec.rate()) + 500_000_000) / 1_000_000_000;
+        u64::try_from(index).ok()
+    }
+
+    /// Seek to `time`, so that the next decoded packets start at or before
+    /// it, dropping the samples decoded so far.
+    fn seek(&mut self, time: Duration) -> symphonia::core::errors::Result<()> {
+        let time = units::Time::try_from_nanos_u128(time.as_nanos())
+            .ok_or(Error::SeekError(SeekErrorKind::OutOfRange))?;
+        self.format.seek(
+            SeekMode::Accurate,
+            SeekTo::Time {
+                time,
+                track_id: Some(self.track_id),
+            },
+        )?;
+        self.decoder.reset();
+        self.current_span_offset = self.buffer.len();
+        Ok(())
+    }
+
     #[inline]
     fn get_buffer(decoded: GenericAudioBufferRef) -> Vec<f32> {
         let mut buffer: Vec<f32> = vec![0.0; decoded.samples_interleaved()];
@@ -249,7 +441,110 @@ impl Iterator for SymphoniaSource {
     }
 }
 
+/// Makes a [ReadSeek] reader usable as a symphonia [MediaSource], used by
+/// [SymphoniaDecoder::decode_reader].
+struct ReaderSource {
+    reader: Box<dyn ReadSeek>,
+    byte_len: Option<u64>,
+}
+
+impl ReaderSource {
+    fn new(mut reader: Box<dyn ReadSeek>) -> io::Result<Self> {
+        // `MediaSource::byte_len` can't seek, so compute it right away.
+        let position = reader.stream_position()?;
+        let byte_len = reader.seek(SeekFrom::End(0))?;
+        reader.seek(SeekFrom::Start(position))?;
+        Ok(ReaderSource {
+            reader,
+            byte_len: Some(byte_len),
+        })
+    }
+}
+
+impl Read for ReaderSource {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl Seek for ReaderSource {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.reader.seek(pos)
+    }
+}
+
+impl MediaSource for ReaderSource {
+    fn is_seekable(&self) -> bool {
+        true
+    }
+
+    fn byte_len(&self) -> Option<u64> {
+        self.byte_len
+    }
+}
+
+/// Iterator over the decoded samples of a song, downmixed to mono and
+/// resampled to 22050 Hz, used by [SymphoniaDecoder::decode_stream].
+struct SymphoniaStream {
+    source: SymphoniaSource,
+    num_channels: usize,
+    resampler: Option<MonoResampler>,
+    loudness_meter: LoudnessMeter,
+    loudness: Arc<OnceLock<Loudness>>,
+    warnings: Arc<OnceLock<Vec<AnalysisWarning>>>,
+    finished: bool,
+}
+
+impl SymphoniaStream {
+    fn next_chunk(&mut self) -> BlissResult<Option<Vec<f32>>> {
+        while let Some(buffer) = self.source.next_buffer() {
+            self.loudness_meter.push(&buffer);
+            let mono_samples = downmix(&buffer, self.num_channels);
+            let resampled = match self.resampler.as_mut() {
+                Some(resampler) => resampler.process(&mono_samples)?,
+                None => mono_samples,
+            };
+            if !resampled.is_empty() {
+                return Ok(Some(resampled));
+            }
+        }
+        self.finished = true;
+        if let Some(loudness) = self.loudness_meter.finish() {
+            let _ = self.loudness.set(loudness);
+        }
+        let _ = self.warnings.set(self.source.issues.get_warnings());
+        match self.resampler.take() {
+            Some(resampler) => Ok(Some(resampler.finish()?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl Iterator for SymphoniaStream {
+    type Item = BlissResult<Vec<f32>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        match self.next_chunk() {
+            Ok(Some(chunk)) => Some(Ok(chunk)),
+            Ok(None) => None,
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
 /// Sequential, single-threaded decoder based on Symphonia
+///
+/// The problems met while decoding a song are reported in
+/// [PreAnalyzedSong::warnings]. Symphonia also logs some of them by itself,
+/// e.g. when a FLAC file has fewer frames than announced, through the `log`
+/// crate with targets starting with `symphonia`: applications that don't
+/// want them can filter these targets out in their logger.
 pub struct SymphoniaDecoder;
 
 impl SymphoniaDecoder {
@@ -262,144 +557,95 @@ impl SymphoniaDecoder {
     /// ffmpeg might be doing something different, and I'm not sure what that is (don't have a 5.1 surround sound file to test with)
     ///
     /// TODO: Figure out how ffmpeg does it for 2.1 and 5.1 surround sound, and do it the same way
+    ///
+    /// The loudness of the song is measured on the way, before downmixing.
+    /// Stops as soon as `budget` runs out.
     #[inline]
-    fn into_mono_samples(source: SymphoniaSource) -> Result<Vec<f32>, SymphoniaDecoderError> {
+    fn into_mono_samples(
+        source: &mut SymphoniaSource,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<(Vec<f32>, Option<Loudness>)> {
         let num_channels = source.spec.channels().count();
         if source.total_duration.is_none() {
-            return Err(SymphoniaDecoderError::IndeterminantDuration);
+            return Err(SymphoniaDecoderError::IndeterminantDuration.into());
         }
-
-        match num_channels {
-            // no channels
-            0 => Err(SymphoniaDecoderError::NoStreams),
-            // mono
-            1 => Ok(source.collect()),
-            // stereo
-            2 => {
-                assert!(*source.spec.channels() == CHANNEL_LAYOUT_STEREO);
-
-                let mono_samples = source
-                    .collect::<Vec<_>>()
-                    .chunks_exact(2)
-                    .map(|chunk| (chunk[0] + chunk[1]) * SQRT_2 / 2.)
-                    .collect();
-
-                Ok(mono_samples)
-            }
+        if num_channels == 0 {
+            return Err(SymphoniaDecoderError::NoStreams.into());
+        }
+        let mut loudness_meter = Self::loudness_meter(&source.spec)?;
+        if num_channels == 2 {
+            assert!(*source.spec.channels() == CHANNEL_LAYOUT_STEREO);
+        } else if num_channels > 2 {
             // 2.1 or 5.1 surround
-            _ => {
-                log::warn!("The audio source has more than 2 channels (might be 2.1 or 5.1 surround sound), will collapse to mono by averaging the channels");
-
-                let mono_samples = source
-                    .collect::<Vec<_>>()
-                    .chunks_exact(num_channels)
-                    .map(|chunk| chunk.iter().sum::<f32>() / num_channels as f32)
-                    .collect();
-
-                Ok(mono_samples)
-            }
+            log::warn!("The audio source has more than 2 channels (might be 2.1 or 5.1 surround sound), will collapse to mono by averaging the channels");
         }
-    }
 
-    /// Resample the given mono samples to 22050 Hz
-    #[inline]
-    fn resample_mono_samples(
-        mut samples: Vec<f32>,
-        sample_rate: u32,
-    ) -> Result<Vec<f32>, SymphoniaDecoderError> {
-        if sample_rate == SAMPLE_RATE {
-            samples.shrink_to_fit();
-            return Ok(samples);
+        let mut samples = Vec::with_capacity(source.size_hint().0);
+        while let Some(buffer) = source.next_buffer() {
+            budget.check()?;
+            samples.extend_from_slice(&buffer);
         }
+        loudness_meter.push(&samples);
+        let mono_samples = match num_channels {
+            1 => samples,
+            _ => downmix(&samples, num_channels),
+        };
+        Ok((mono_samples, loudness_meter.finish()))
+    }
 
-        let mut resampler = Fft::new(
-            sample_rate as usize,
-            SAMPLE_RATE as usize,
-            CHUNK_SIZE,
-            4,
-            1,
-            FixedSync::Input,
-        )
-        .map_err(SymphoniaDecoderError::from)?;
-
-        let capacity = resampler.process_all_needed_output_len(samples.len());
-        let mut resampled = Vec::with_capacity(capacity);
-
-        let delay = resampler.output_delay();
-
-        // Since this is mono
-        let output_chunk_size = resampler.output_frames_max();
-        let input_chunk_size = resampler.input_frames_next();
-        let mut output_buffer = vec![0.0; output_chunk_size];
-
-        // chunks of frames, each being CHUNKSIZE long.
-        let sample_chunks = samples.chunks_exact(input_chunk_size);
-        let remainder = sample_chunks.remainder();
-
-        for chunk in sample_chunks {
-            debug_assert!(resampler.input_frames_next() == input_chunk_size);
+    /// Decode and resample the song in `mss`, checking `budget` after
+    /// decoding each packet. `path` is the path given to the returned song.
+    fn decode_media_source(
+        mss: MediaSourceStream<'static>,
+        hint: &Hint,
+        path: &Path,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<PreAnalyzedSong> {
+        let mut source = SymphoniaSource::new(mss, hint)?;
+        let metadata = std::mem::take(&mut source.metadata);
 
-            let input = InterleavedSlice::new(chunk, 1, input_chunk_size)
-                .map_err(|e| SymphoniaDecoderError::ResampleError(e.to_string()))?;
+        // Convert the audio source into a mono channel
+        let sample_rate = source.spec.rate();
+        if source.total_duration.is_none() {
+            return Err(SymphoniaDecoderError::IndeterminantDuration.into());
+        };
 
-            let mut output_adapter =
-                InterleavedSlice::new_mut(&mut output_buffer, 1, output_chunk_size)
-                    .map_err(|e| SymphoniaDecoderError::ResampleError(e.to_string()))?;
-            let (_, output_written) =
-                resampler.process_into_buffer(&input, &mut output_adapter, None)?;
-            resampled.extend_from_slice(&output_buffer[..output_written]);
-        }
+        let expected_duration = source.expected_duration();
+        let (mono_sample_array, loudness) = Self::into_mono_samples(&mut source, budget)?;
 
-        // process the remainder
-        if !remainder.is_empty() {
-            let remainder_indexing = rubato::Indexing {
-                input_offset: 0,
-                output_offset: 0,
-                partial_len: Some(remainder.len()),
-                active_channels_mask: None,
-            };
-            let input = InterleavedSlice::new(remainder, 1, remainder.len())
-                .map_err(|e| SymphoniaDecoderError::ResampleError(e.to_string()))?;
-            let mut output_adapter =
-                InterleavedSlice::new_mut(&mut output_buffer, 1, output_chunk_size)
-                    .map_err(|e| SymphoniaDecoderError::ResampleError(e.to_string()))?;
-
-            let (_, output_written) = resampler.process_into_buffer(
-                &input,
-                &mut output_adapter,
-                Some(&remainder_indexing),
-            )?;
-            resampled.extend_from_slice(&output_buffer[..output_written]);
-        }
+        // then we need to resample the audio source into 22050 Hz
+        let resampled_array = Self::resample_mono_samples(mono_sample_array, sample_rate)?;
+        budget.check()?;
 
-        let flush_indexing = rubato::Indexing {
-            input_offset: 0,
-            output_offset: 0,
-            partial_len: Some(0),
-            active_channels_mask: None,
+        let mut song = PreAnalyzedSong {
+            path: path.to_owned(),
+            expected_duration,
+            loudness,
+            sample_array: resampled_array,
+            ..metadata
         };
+        song.set_warnings(source.issues);
+        Ok(song)
+    }
+
+    /// Create a [LoudnessMeter] for samples in the format given by `spec`.
+    fn loudness_meter(spec: &AudioSpec) -> Result<LoudnessMeter, SymphoniaDecoderError> {
+        LoudnessMeter::new(spec.rate(), spec.channels().count())
+            .map_err(|e| SymphoniaDecoderError::DecodeError(e.to_string()))
+    }
 
-        let expected_output_len =
-            (resampler.resample_ratio() * samples.len() as f64).ceil() as usize;
-
-        // Flush the remaining samples
-        let padded_zeros = vec![0.0; input_chunk_size];
-        while resampled.len() < expected_output_len + delay {
-            let input = InterleavedSlice::new(&padded_zeros, 1, input_chunk_size)
-                .map_err(|e| SymphoniaDecoderError::ResampleError(e.to_string()))?;
-            let mut output_adapter =
-                InterleavedSlice::new_mut(&mut output_buffer, 1, output_chunk_size)
-                    .map_err(|e| SymphoniaDecoderError::ResampleError(e.to_string()))?;
-
-            let (_, output_written) = resampler.process_into_buffer(
-                &input,
-                &mut output_adapter,
-                Some(&flush_indexing),
-            )?;
-            resampled.extend_from_slice(&output_buffer[..output_written]);
+    /// Resample the given mono samples to 22050 Hz
+    #[inline]
+    fn resample_mono_samples(mut samples: Vec<f32>, sample_rate: u32) -> BlissResult<Vec<f32>> {
+        if sample_rate == SAMPLE_RATE {
+            samples.shrink_to_fit();
+            return Ok(samples);
         }
 
-        Ok(resampled[delay..expected_output_len + delay].to_vec())
+        let mut resampler = MonoResampler::new(sample_rate)?;
+        let mut resampled = resampler.process(&samples)?;
+        resampled.extend(resampler.finish()?);
+        Ok(resampled)
     }
 }
 
@@ -411,28 +657,180 @@ impl Decoder for SymphoniaDecoder {
     /// of 22050 Hz. Anything other than that will yield wrong results.
     #[allow(clippy::missing_inline_in_public_items)]
     fn decode(path: &std::path::Path) -> BlissResult<PreAnalyzedSong> {
+        Self::decode_with_budget(path, &AnalysisBudget::default())
+    }
+
+    /// Decode the song, checking `budget` after decoding each packet.
+    #[allow(clippy::missing_inline_in_public_items)]
+    fn decode_with_budget(
+        path: &std::path::Path,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<PreAnalyzedSong> {
+        budget.check()?;
         // open the file
         let file = File::open(path).map_err(SymphoniaDecoderError::from)?;
         // create the media source stream
         let mss = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions::default());
+        Self::decode_media_source(mss, &Hint::new(), path, budget)
+    }
 
-        let source = SymphoniaSource::new(mss)?;
+    #[allow(clippy::missing_inline_in_public_items)]
+    fn decode_reader(
+        reader: Box<dyn ReadSeek>,
+        hint: Option<&str>,
+    ) -> BlissResult<PreAnalyzedSong> {
+        Self::decode_reader_with_budget(reader, hint, &AnalysisBudget::default())
+    }
 
-        // Convert the audio source into a mono channel
-        let sample_rate = source.spec.rate();
+    /// Decode the song read from `reader`, checking `budget` after
+    /// decoding each packet.
+    #[allow(clippy::missing_inline_in_public_items)]
+    fn decode_reader_with_budget(
+        reader: Box<dyn ReadSeek>,
+        hint: Option<&str>,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<PreAnalyzedSong> {
+        budget.check()?;
+        let source = ReaderSource::new(reader).map_err(SymphoniaDecoderError::from)?;
+        let mss = MediaSourceStream::new(Box::new(source), MediaSourceStreamOptions::default());
+        let mut format_hint = Hint::new();
+        if let Some(extension) = hint {
+            format_hint.with_extension(extension);
+        }
+        Self::decode_media_source(mss, &format_hint, Path::new(""), budget)
+    }
+
+    /// Decode the song between `start` and `end`, seeking to `start`
+    /// instead of decoding the whole song, and checking `budget` after
+    /// decoding each packet.
+    #[allow(clippy::missing_inline_in_public_items)]
+    fn decode_range_with_budget(
+        path: &Path,
+        start: Duration,
+        end: Option<Duration>,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<PreAnalyzedSong> {
+        budget.check()?;
+        let file = File::open(path).map_err(SymphoniaDecoderError::from)?;
+        let mss = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions::default());
+        let mut source = SymphoniaSource::new(mss, &Hint::new())?;
         if source.total_duration.is_none() {
             return Err(SymphoniaDecoderError::IndeterminantDuration.into());
         };
+        let num_channels = source.spec.channels().count();
+        if num_channels == 0 {
+            return Err(SymphoniaDecoderError::NoStreams.into());
+        }
 
-        let mono_sample_array = Self::into_mono_samples(source)?;
+        let sample_rate = source.spec.rate();
+        let mut resampler = if sample_rate == SAMPLE_RATE {
+            None
+        } else {
+            Some(MonoResampler::new(sample_rate)?)
+        };
+        let alignment = resampler
+            .as_ref()
+            .map_or(1, |resampler| resampler.input_chunk_size() as u64);
+        let mut range = DecodingRange::new(start, end, sample_rate, alignment);
+        let seeking = !range.seek_time().is_zero()
+            && match source.seek(range.seek_time()) {
+                Ok(()) => true,
+                // Decode the song from its beginning instead, e.g. if it
+                // is shorter than `start`.
+                Err(Error::SeekError(_)) => false,
+                Err(e) => return Err(SymphoniaDecoderError::from(e).into()),
+            };
 
-        // then we need to resample the audio source into 22050 Hz
-        let resampled_array = Self::resample_mono_samples(mono_sample_array, sample_rate)?;
+        let mut index = 0;
+        let mut resampled = Vec::new();
+        while let Some(buffer) = source.next_buffer() {
+            budget.check()?;
+            // Without seeking, count the samples from the first packet, like
+            // when decoding the whole song.
+            if let Some(buffer_index) = source.buffer_index().filter(|_| seeking) {
+                index = buffer_index;
+            }
+            let buffer_index = index;
+            index += (buffer.len() / num_channels) as u64;
+            if range.is_past_end(buffer_index) {
+                break;
+            }
+            let Some(offset) = range.start_offset(buffer_index, buffer.len() / num_channels) else {
+                continue;
+            };
+            let mono_samples = downmix(&buffer[offset * num_channels..], num_channels);
+            match resampler.as_mut() {
+                Some(resampler) => resampled.extend(resampler.process(&mono_samples)?),
+                None => resampled.extend(mono_samples),
+            }
+        }
+        if let Some(resampler) = resampler {
+            resampled.extend(resampler.finish()?);
+        }
+        budget.check()?;
 
-        Ok(PreAnalyzedSong {
+        let sample_array = range.cut(resampled);
+        let mut song = PreAnalyzedSong {
             path: path.to_owned(),
-            sample_array: resampled_array,
-            ..Default::default()
+            duration: Duration::from_secs_f64(sample_array.len() as f64 / SAMPLE_RATE as f64),
+            expected_duration: range_duration(source.expected_duration(), start, end),
+            sample_array,
+            ..std::mem::take(&mut source.metadata)
+        };
+        song.set_warnings(source.issues);
+        Ok(song)
+    }
+
+    /// Decode a song packet by packet, downmixing and resampling each
+    /// packet's samples as soon as they're decoded.
+    ///
+    /// The concatenation of the yielded chunks is the same as the sample
+    /// array returned by [decode](SymphoniaDecoder::decode).
+    #[allow(clippy::missing_inline_in_public_items)]
+    fn decode_stream(path: &std::path::Path) -> BlissResult<PreAnalyzedSongStream> {
+        let file = File::open(path).map_err(SymphoniaDecoderError::from)?;
+        let mss = MediaSourceStream::new(Box::new(file), MediaSourceStreamOptions::default());
+
+        let mut source = SymphoniaSource::new(mss, &Hint::new())?;
+        if source.total_duration.is_none() {
+            return Err(SymphoniaDecoderError::IndeterminantDuration.into());
+        };
+        let metadata = std::mem::take(&mut source.metadata);
+
+        let sample_rate = source.spec.rate();
+        let num_channels = source.spec.channels().count();
+        match num_channels {
+            0 => return Err(SymphoniaDecoderError::NoStreams.into()),
+            1 => (),
+            2 => assert!(*source.spec.channels() == CHANNEL_LAYOUT_STEREO),
+            _ => log::warn!("The audio source has more than 2 channels (might be 2.1 or 5.1 surround sound), will collapse to mono by averaging the channels"),
+        }
+        let resampler = if sample_rate == SAMPLE_RATE {
+            None
+        } else {
+            Some(MonoResampler::new(sample_rate)?)
+        };
+        let loudness_meter = Self::loudness_meter(&source.spec)?;
+        let loudness = Arc::new(OnceLock::new());
+        let warnings = Arc::new(OnceLock::new());
+
+        Ok(PreAnalyzedSongStream {
+            song: PreAnalyzedSong {
+                path: path.to_owned(),
+                expected_duration: source.expected_duration(),
+                ..metadata
+            },
+            samples: Box::new(SymphoniaStream {
+                source,
+                num_channels,
+                resampler,
+                loudness_meter,
+                loudness: loudness.clone(),
+                warnings: warnings.clone(),
+                finished: false,
+            }),
+            loudness,
+            warnings,
         })
     }
 }
@@ -440,9 +838,17 @@ impl Decoder for SymphoniaDecoder {
 #[cfg(test)]
 mod tests {
     use super::{Decoder as DecoderTrait, SymphoniaDecoder as Decoder};
+    use crate::song::decoder::sample_index;
+    use crate::{
+        AnalysisBudget, AnalysisOptions, AnalysisWarning, BlissError, CancellationToken, Tags,
+        SAMPLE_RATE,
+    };
     use adler32::RollingAdler32;
     use pretty_assertions::assert_eq;
+    use std::fs;
+    use std::io::Cursor;
     use std::path::Path;
+    use std::time::Duration;
 
     fn _test_decode(path: &Path, expected_hash: u32) {
         let song = Decoder::decode(path).unwrap();
@@ -748,4 +1154,272 @@ mod tests {
             );
         }
     }
+
+    #[cfg(all(
+        feature = "symphonia-flac",
+        feature = "symphonia-ogg",
+        feature = "symphonia-vorbis",
+        feature = "symphonia-wav",
+        feature = "symphonia-mp3"
+    ))]
+    #[test]
+    fn test_decode_stream_same_as_decode() {
+        for path_str in [
+            "data/piano.wav",
+            "data/s16_mono_22_5kHz.flac",
+            "data/s16_stereo_22_5kHz.flac",
+            "data/capacity_fix.ogg",
+            "data/s32_mono_44_1_kHz.flac",
+            "data/s32_stereo_44_1_kHz.mp3",
+            "data/flush_test_52000.wav",
+            "data/no_channel.wav",
+        ] {
+            let path = Path::new(path_str);
+            let decoded = Decoder::decode(path).unwrap();
+            let stream = Decoder::decode_stream(path).unwrap();
+            assert_eq!(stream.song.path, path);
+            assert!(stream.song.sample_array.is_empty());
+            let streamed = stream
+                .samples
+                .collect::<Result<Vec<Vec<f32>>, _>>()
+                .unwrap()
+                .concat();
+            assert_eq!(decoded.sample_array, streamed, "file: {path_str}");
+            assert_eq!(
+                decoded.loudness,
+                stream.loudness.get().copied(),
+                "file: {path_str}"
+            );
+        }
+    }
+
+    #[cfg(all(
+        feature = "symphonia-flac",
+        feature = "symphonia-ogg",
+        feature = "symphonia-vorbis",
+        feature = "symphonia-mp3"
+    ))]
+    #[test]
+    fn test_decode_reader() {
+        for (path_str, hint) in [
+            ("data/s16_mono_22_5kHz.flac", None),
+            ("data/s32_stereo_44_1_kHz.mp3", Some("mp3")),
+            ("data/capacity_fix.ogg", None),
+        ] {
+            let path = Path::new(path_str);
+            let decoded = Decoder::decode(path).unwrap();
+            let reader = Cursor::new(fs::read(path).unwrap());
+            let read = Decoder::decode_reader(Box::new(reader), hint).unwrap();
+            assert_eq!(read.path, Path::new(""));
+            assert_eq!(decoded.expected_duration, read.expected_duration);
+            assert_eq!(decoded.loudness, read.loudness, "file: {path_str}");
+            assert_eq!(decoded.sample_array, read.sample_array, "file: {path_str}");
+        }
+    }
+
+    #[cfg(feature = "symphonia-flac")]
+    #[test]
+    fn test_song_from_reader() {
+        let path = Path::new("data/s16_mono_22_5kHz.flac");
+        let song = Decoder::song_from_path(path).unwrap();
+        let reader = Cursor::new(fs::read(path).unwrap());
+        let read_song = Decoder::song_from_reader(reader, Some("flac")).unwrap();
+        assert_eq!(read_song.path, Path::new(""));
+        assert_eq!(song.analysis, read_song.analysis);
+
+        let reader = Cursor::new(fs::read(path).unwrap());
+        let analysis_options = AnalysisOptions {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        assert_eq!(
+            Decoder::song_from_reader_with_options(reader, None, analysis_options).unwrap_err(),
+            BlissError::TimedOut(Duration::ZERO),
+        );
+        assert!(Decoder::song_from_reader(Cursor::new(Vec::new()), None).is_err());
+    }
+
+    #[cfg(all(
+        feature = "symphonia-flac",
+        feature = "symphonia-ogg",
+        feature = "symphonia-vorbis",
+        feature = "symphonia-wav",
+        feature = "symphonia-mp3"
+    ))]
+    #[test]
+    fn test_decode_range_same_as_decode() {
+        for path_str in [
+            "data/testcue.flac",
+            "data/s16_mono_22_5kHz.flac",
+            "data/s32_stereo_44_1_kHz.mp3",
+            "data/capacity_fix.ogg",
+            "data/piano.wav",
+            "data/flush_test_52000.wav",
+        ] {
+            let path = Path::new(path_str);
+            let decoded = Decoder::decode(path).unwrap();
+            for (start, end) in [
+                (Duration::ZERO, Some(Duration::from_millis(1500))),
+                (
+                    Duration::from_secs_f64(11.0667),
+                    Some(Duration::from_secs_f64(16.92)),
+                ),
+                (Duration::from_secs(2), None),
+                (Duration::from_secs(120), Some(Duration::from_secs(130))),
+            ] {
+                let range = Decoder::decode_range(path, start, end).unwrap();
+                let start_index = sample_index(start).min(decoded.sample_array.len());
+                let end_index = end.map_or(decoded.sample_array.len(), |end| {
+                    sample_index(end).clamp(start_index, decoded.sample_array.len())
+                });
+                let expected = &decoded.sample_array[start_index..end_index];
+                assert_eq!(
+                    expected.len(),
+                    range.sample_array.len(),
+                    "file: {path_str}, range: {start:?}..{end:?}"
+                );
+                assert!(
+                    expected == range.sample_array,
+                    "file: {path_str}, range: {start:?}..{end:?}"
+                );
+            }
+        }
+    }
+
+    #[cfg(feature = "symphonia-flac")]
+    #[test]
+    fn test_decode_with_budget() {
+        let path = Path::new("data/s16_mono_22_5kHz.flac");
+        let token = CancellationToken::new();
+        let analysis_options = AnalysisOptions {
+            cancellation: Some(token.clone()),
+            ..Default::default()
+        };
+        let budget = AnalysisBudget::new(&analysis_options);
+        assert_eq!(
+            Decoder::decode_with_budget(path, &budget)
+                .unwrap()
+                .sample_array,
+            Decoder::decode(path).unwrap().sample_array,
+        );
+        token.cancel();
+        assert_eq!(
+            Decoder::decode_with_budget(path, &budget).unwrap_err(),
+            BlissError::Cancelled,
+        );
+
+        let budget = AnalysisBudget::new(&AnalysisOptions {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        });
+        assert_eq!(
+            Decoder::decode_with_budget(path, &budget).unwrap_err(),
+            BlissError::TimedOut(Duration::ZERO),
+        );
+    }
+
+    #[cfg(feature = "symphonia-flac")]
+    #[test]
+    fn test_tags() {
+        let song = Decoder::decode(Path::new("data/s16_mono_22_5kHz.flac")).unwrap();
+        assert_eq!(song.artist, Some(String::from("David TMX")));
+        assert_eq!(
+            song.album_artist,
+            Some(String::from("David TMX - Album Artist"))
+        );
+        assert_eq!(song.title, Some(String::from("Renaissance")));
+        assert_eq!(song.album, Some(String::from("Renaissance")));
+        assert_eq!(song.track_number, Some(2));
+        assert_eq!(song.disc_number, Some(1));
+        assert_eq!(song.genre, Some(String::from("Pop")));
+        assert_eq!(song.tags, Tags::default());
+
+        let song = Decoder::decode(Path::new("data/no_tags.flac")).unwrap();
+        assert_eq!(song.artist, None);
+        assert_eq!(song.title, None);
+        assert_eq!(song.track_number, None);
+        assert_eq!(song.tags, Tags::default());
+    }
+
+    #[cfg(feature = "symphonia-mp3")]
+    #[test]
+    fn test_special_tags() {
+        // This file has tags like `DISC: 02/05` and `TRACK: 06/24`.
+        let song = Decoder::decode(Path::new("data/special-tags.mp3")).unwrap();
+        assert_eq!(song.disc_number, Some(2));
+        assert_eq!(song.track_number, Some(6));
+
+        let song = Decoder::decode(Path::new("data/s16_mono_22_5kHz.mp3")).unwrap();
+        assert_eq!(song.title, Some(String::from("Renaissance")));
+        assert_eq!(song.genre, Some(String::from("Pop")));
+    }
+
+    #[cfg(all(feature = "symphonia-flac", feature = "symphonia-wav"))]
+    #[test]
+    fn test_decode_warnings() {
+        for path in ["data/s16_mono_22_5kHz.flac", "data/piano.wav"] {
+            let song = Decoder::decode(Path::new(path)).unwrap();
+            assert_eq!(song.warnings, vec![], "file: {path}");
+
+            let bytes = fs::read(path).unwrap();
+            let truncated = Cursor::new(bytes[..bytes.len() / 2].to_vec());
+            let song = Decoder::decode_reader(Box::new(truncated), None).unwrap();
+            assert_eq!(song.warnings.len(), 2, "file: {path}");
+            assert_eq!(song.warnings[0], AnalysisWarning::Truncated);
+            assert!(matches!(
+                song.warnings[1],
+                AnalysisWarning::SampleCountMismatch { expected, actual }
+                    if actual == song.sample_array.len() && actual < expected
+            ));
+        }
+    }
+
+    #[cfg(feature = "symphonia-flac")]
+    #[test]
+    fn test_decode_expected_duration() {
+        let song = Decoder::decode(Path::new("data/s16_mono_22_5kHz.flac")).unwrap();
+        let duration = song.sample_array.len() as f64 / SAMPLE_RATE as f64;
+        assert!((song.expected_duration.unwrap().as_secs_f64() - duration).abs() < 1e-2);
+    }
+
+    #[cfg(feature = "symphonia-flac")]
+    #[test]
+    fn test_decode_loudness() {
+        let loudness = |path: &str| Decoder::decode(Path::new(path)).unwrap().loudness.unwrap();
+        let mono = loudness("data/s16_mono_22_5kHz.flac");
+        let mono_44_1 = loudness("data/s16_mono_44_1_kHz.flac");
+        assert!(0.1 > (mono.integrated - mono_44_1.integrated).abs());
+        assert!(0.1 > (mono.range - mono_44_1.range).abs());
+        // Same signal in both channels, so 3 LU louder.
+        let stereo_44_1 = loudness("data/s32_stereo_44_1_kHz.flac");
+        assert!(0.1 > (mono_44_1.integrated + 3.01 - stereo_44_1.integrated).abs());
+        assert!(0.1 > (mono_44_1.true_peak - stereo_44_1.true_peak).abs());
+    }
+
+    #[cfg(feature = "symphonia-flac")]
+    #[test]
+    fn test_song_from_path_streaming() {
+        let path = Path::new("data/s16_mono_22_5kHz.flac");
+        let song = Decoder::song_from_path(path).unwrap();
+        let streamed_song =
+            Decoder::song_from_path_streaming(path, AnalysisOptions::default()).unwrap();
+        assert_eq!(song.path, streamed_song.path);
+        assert!(song.diagnostics.is_empty());
+        assert_eq!(song.diagnostics, streamed_song.diagnostics);
+        assert!(song.loudness.is_some());
+        assert_eq!(song.loudness, streamed_song.loudness);
+        let sample_array = Decoder::decode(path).unwrap().sample_array;
+        assert_eq!(
+            Duration::from_secs_f64(sample_array.len() as f64 / SAMPLE_RATE as f64),
+            streamed_song.duration
+        );
+        for (x, y) in song
+            .analysis
+            .as_vec()
+            .iter()
+            .zip(streamed_song.analysis.as_vec())
+        {
+            assert!(1e-5 > (x - y).abs());
+        }
+    }
 }
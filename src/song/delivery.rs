@@ -0,0 +1,130 @@
+//! Delivery module.
+//!
+//! Holds the options controlling how bulk analyses, such as
+//! [Decoder::analyze_paths](crate::decoder::Decoder::analyze_paths), hand out
+//! the songs they analyze, and the [AnalysisEvent]s they report their
+//! progress with.
+use crate::BlissError;
+use std::fmt;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Default, PartialEq, Eq, Debug, Clone, Copy)]
+/// How bulk analyses hand out the songs they analyze. See
+/// [AnalysisOptions::delivery](crate::AnalysisOptions::delivery).
+pub struct DeliveryOptions {
+    /// Hand out the songs in the order of the paths they were given,
+    /// instead of as soon as they are analyzed. A song taking a long time
+    /// to be analyzed then holds back the songs coming after it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub ordered: bool,
+    /// If set, the analysis pauses once that many analyzed songs are
+    /// waiting to be consumed, until the consumer catches up. Otherwise,
+    /// analyzed songs pile up in memory if they are consumed slower than
+    /// they are analyzed, e.g. by a slow database writer.
+    ///
+    /// If the songs are [ordered](DeliveryOptions::ordered), it also caps
+    /// how many songs can be analyzed ahead of the oldest song that is
+    /// still being analyzed.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub max_pending_results: Option<NonZeroUsize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An event sent while analyzing songs in bulk, to report the analysis'
+/// progress. See [AnalysisOptions::progress](crate::AnalysisOptions::progress).
+///
+/// `path` is always the path that was given to the analysis, i.e. the
+/// path of the CUE sheet for the songs of a CUE sheet.
+pub enum AnalysisEvent {
+    /// The decoding of the song(s) at `path` started.
+    Started {
+        /// The path of the song.
+        path: PathBuf,
+    },
+    /// The song at `path` has been decoded, and is about to be analyzed.
+    /// Not sent for CUE sheets, whose songs are decoded and analyzed at
+    /// the same time.
+    Decoded {
+        /// The path of the song.
+        path: PathBuf,
+        /// The time it took to decode the song.
+        elapsed: Duration,
+    },
+    /// A song at `path` has been analyzed successfully.
+    Analyzed {
+        /// The path of the song.
+        path: PathBuf,
+        /// The time it took to decode and analyze the song, or all the songs
+        /// of the CUE sheet up to this one.
+        elapsed: Duration,
+    },
+    /// A song at `path` could not be decoded or analyzed.
+    Failed {
+        /// The path of the song.
+        path: PathBuf,
+        /// Why the song could not be decoded or analyzed.
+        error: BlissError,
+        /// The time spent on the song before it failed.
+        elapsed: Duration,
+    },
+}
+
+/// Where the [AnalysisEvent]s of bulk analyses are sent.
+///
+/// Made from the sending half of an [mpsc::channel], whose receiving half
+/// can be listened to, e.g. to update a progress bar in a GUI. Events
+/// are silently dropped once the receiving half is dropped.
+///
+/// * Example:
+/// ```
+/// use bliss_audio::{AnalysisEvent, AnalysisOptions};
+/// use std::sync::mpsc;
+///
+/// let (tx, rx) = mpsc::channel::<AnalysisEvent>();
+/// let analysis_options = AnalysisOptions {
+///     progress: Some(tx.into()),
+///     ..Default::default()
+/// };
+/// // Then, e.g. in another thread.
+/// for event in rx.try_iter() {
+///     println!("{event:?}");
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ProgressSender(Arc<mpsc::Sender<AnalysisEvent>>);
+
+impl ProgressSender {
+    /// Send `event`, if someone is still listening.
+    pub fn send(&self, event: AnalysisEvent) {
+        // The receiving half being dropped just means that nobody is
+        // interested in the progress anymore.
+        let _ = self.0.send(event);
+    }
+}
+
+impl From<mpsc::Sender<AnalysisEvent>> for ProgressSender {
+    fn from(sender: mpsc::Sender<AnalysisEvent>) -> Self {
+        ProgressSender(Arc::new(sender))
+    }
+}
+
+impl PartialEq for ProgressSender {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ProgressSender {}
+
+impl fmt::Debug for ProgressSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProgressSender").finish_non_exhaustive()
+    }
+}
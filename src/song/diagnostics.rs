@@ -0,0 +1,322 @@
+//! Analysis diagnostics module.
+//!
+//! Holds the warnings raised while decoding or analyzing a song, so that
+//! songs with suspicious features (a silent file, a truncated decode...)
+//! can be told apart from the others, even when analyzing a whole library.
+#[cfg(feature = "analysis")]
+use crate::SAMPLE_RATE;
+#[cfg(feature = "analysis")]
+use std::time::Duration;
+
+/// Absolute value under which a sample is considered silent (-60 dBFS).
+#[cfg(feature = "analysis")]
+const SILENCE_THRESHOLD: f32 = 0.001;
+/// Proportion of silent samples above which a song is considered
+/// mostly silent.
+#[cfg(feature = "analysis")]
+const MOSTLY_SILENT_RATIO: f32 = 0.5;
+/// Absolute value from which a sample is considered clipped.
+#[cfg(feature = "analysis")]
+const CLIPPING_THRESHOLD: f32 = 0.999;
+/// Proportion of clipped samples above which a song is considered clipped.
+#[cfg(feature = "analysis")]
+const CLIPPED_RATIO: f32 = 0.01;
+/// Beat tracker confidence, between 0 and 1, under which a song's tempo
+/// is considered unreliable. Songs with a clear beat are usually above 0.5,
+/// while noise or ambient songs are around 0.2.
+#[cfg(feature = "analysis")]
+const LOW_TEMPO_CONFIDENCE: f32 = 0.3;
+/// Number of samples the decoded song can differ from the duration
+/// announced by its container without being reported (one second).
+#[cfg(feature = "analysis")]
+const SAMPLE_COUNT_TOLERANCE: usize = SAMPLE_RATE as usize;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Clone)]
+/// A warning raised while decoding or analyzing a song, hinting that
+/// some of its features might not be meaningful.
+pub enum AnalysisWarning {
+    /// No beats were found in the song, so its tempo feature was set to
+    /// its lowest value, or the beat tracker never locked onto a tempo,
+    /// e.g. because the song is only a few seconds long, so its tempo
+    /// feature is not meaningful.
+    NoBeatsFound,
+    /// The beat tracker was not confident in the song's tempo, e.g.
+    /// because the song has no clear beat, so its tempo feature is not
+    /// very meaningful. It is given less weight by the distance metrics,
+    /// see [Analysis::tempo_confidence](crate::Analysis::tempo_confidence).
+    LowTempoConfidence {
+        /// The median confidence of the beat tracker, see
+        /// [TempoEstimate::confidence](crate::TempoEstimate::confidence).
+        confidence: f32,
+    },
+    /// Most of the song is silent, e.g. a hidden track preceded by a long
+    /// silence, or a corrupted file.
+    MostlySilent {
+        /// The proportion of silent samples (under -60 dBFS) in the song,
+        /// between 0 and 1.
+        silent_ratio: f32,
+    },
+    /// A significant part of the song is at full scale, which usually
+    /// means the signal is clipped.
+    Clipped {
+        /// The proportion of samples at full scale in the song,
+        /// between 0 and 1.
+        clipped_ratio: f32,
+    },
+    /// The number of decoded samples doesn't match the duration announced
+    /// by the file, which usually means the file is truncated or
+    /// was not fully decoded.
+    SampleCountMismatch {
+        /// The number of samples expected from the file's duration, at
+        /// 22050 Hz.
+        expected: usize,
+        /// The number of samples that were actually decoded, at 22050 Hz.
+        actual: usize,
+    },
+    /// Some packets or frames of the song could not be decoded and were
+    /// skipped, which usually means the file is damaged.
+    DecodeErrors {
+        /// The number of packets or frames that were skipped.
+        skipped: usize,
+    },
+    /// Decoding stopped before the end of the song, e.g. because the file
+    /// ended unexpectedly. Only the samples decoded until then were
+    /// analyzed.
+    Truncated,
+    /// The song could not be decoded by the first decoder of a
+    /// `FallbackDecoder`, and was decoded by its second one.
+    DecodedByFallback {
+        /// The name of the decoder that decoded the song, e.g.
+        /// `bliss_audio::song::decoder::symphonia::SymphoniaDecoder`.
+        decoder: String,
+        /// The error returned by the first decoder.
+        error: String,
+    },
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Default, Debug, PartialEq, Clone)]
+/// Diagnostics gathered while decoding and analyzing a song.
+///
+/// Useful to show the user which songs have suspicious features, since
+/// these warnings would otherwise get lost while analyzing a lot of songs
+/// at once.
+pub struct AnalysisDiagnostics {
+    /// The warnings raised for the song, if any.
+    pub warnings: Vec<AnalysisWarning>,
+}
+
+impl AnalysisDiagnostics {
+    /// Returns true if no warnings were raised for the song.
+    pub fn is_empty(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    /// Report a [AnalysisWarning::NoBeatsFound] if no beats were found or
+    /// if the beat tracker never locked onto a tempo, i.e. if its
+    /// `confidence` is zero, or a [AnalysisWarning::LowTempoConfidence] if
+    /// its `confidence` is too low.
+    #[cfg(feature = "analysis")]
+    pub(crate) fn check_tempo(&mut self, found_beats: bool, confidence: f32) {
+        if !found_beats || confidence == 0. {
+            self.warnings.push(AnalysisWarning::NoBeatsFound);
+        } else if confidence < LOW_TEMPO_CONFIDENCE {
+            self.warnings
+                .push(AnalysisWarning::LowTempoConfidence { confidence });
+        }
+    }
+
+    /// Report a [AnalysisWarning::SampleCountMismatch] if `actual_samples`
+    /// differs from `expected_duration` by more than a second, unless one
+    /// was already reported, e.g. by the decoder.
+    #[cfg(feature = "analysis")]
+    pub(crate) fn check_sample_count(
+        &mut self,
+        expected_duration: Option<Duration>,
+        actual_samples: usize,
+    ) {
+        let already_reported = self
+            .warnings
+            .iter()
+            .any(|w| matches!(w, AnalysisWarning::SampleCountMismatch { .. }));
+        if already_reported {
+            return;
+        }
+        self.warnings
+            .extend(sample_count_mismatch(expected_duration, actual_samples));
+    }
+}
+
+/// A [AnalysisWarning::SampleCountMismatch] if `actual_samples` differs
+/// from `expected_duration` by more than a second.
+#[cfg(feature = "analysis")]
+pub(crate) fn sample_count_mismatch(
+    expected_duration: Option<Duration>,
+    actual_samples: usize,
+) -> Option<AnalysisWarning> {
+    let expected = (expected_duration?.as_secs_f64() * SAMPLE_RATE as f64).round() as usize;
+    (expected.abs_diff(actual_samples) > SAMPLE_COUNT_TOLERANCE).then_some(
+        AnalysisWarning::SampleCountMismatch {
+            expected,
+            actual: actual_samples,
+        },
+    )
+}
+
+/// The problems met by a decoder while decoding a song, reported as
+/// [AnalysisWarning]s.
+#[cfg(feature = "analysis")]
+#[derive(Default, Debug, Clone, Copy)]
+pub(crate) struct DecodingIssues {
+    /// The number of packets or frames that couldn't be decoded, and were
+    /// skipped.
+    pub skipped: usize,
+    /// Whether decoding stopped before the end of the song because of an
+    /// error.
+    pub truncated: bool,
+}
+
+#[cfg(feature = "analysis")]
+impl DecodingIssues {
+    pub fn get_warnings(&self) -> Vec<AnalysisWarning> {
+        let mut warnings = Vec::new();
+        if self.skipped > 0 {
+            warnings.push(AnalysisWarning::DecodeErrors {
+                skipped: self.skipped,
+            });
+        }
+        if self.truncated {
+            warnings.push(AnalysisWarning::Truncated);
+        }
+        warnings
+    }
+}
+
+/// Running statistics about a signal, used to detect silent or clipped
+/// songs. Can be fed the signal chunk by chunk.
+#[cfg(feature = "analysis")]
+#[derive(Default)]
+pub(crate) struct SignalStats {
+    number_samples: usize,
+    silent_samples: usize,
+    clipped_samples: usize,
+}
+
+#[cfg(feature = "analysis")]
+impl SignalStats {
+    pub fn do_(&mut self, chunk: &[f32]) {
+        self.number_samples += chunk.len();
+        for sample in chunk {
+            let amplitude = sample.abs();
+            if amplitude < SILENCE_THRESHOLD {
+                self.silent_samples += 1;
+            } else if amplitude >= CLIPPING_THRESHOLD {
+                self.clipped_samples += 1;
+            }
+        }
+    }
+
+    pub fn get_warnings(&self) -> Vec<AnalysisWarning> {
+        let mut warnings = Vec::new();
+        if self.number_samples == 0 {
+            return warnings;
+        }
+        let silent_ratio = self.silent_samples as f32 / self.number_samples as f32;
+        if silent_ratio > MOSTLY_SILENT_RATIO {
+            warnings.push(AnalysisWarning::MostlySilent { silent_ratio });
+        }
+        let clipped_ratio = self.clipped_samples as f32 / self.number_samples as f32;
+        if clipped_ratio > CLIPPED_RATIO {
+            warnings.push(AnalysisWarning::Clipped { clipped_ratio });
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "analysis")]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_signal_stats() {
+        let mut stats = SignalStats::default();
+        stats.do_(&[]);
+        assert_eq!(stats.get_warnings(), vec![]);
+
+        stats.do_(&[0.5; 100]);
+        assert_eq!(stats.get_warnings(), vec![]);
+
+        stats.do_(&[0.; 300]);
+        stats.do_(&[-1.; 100]);
+        assert_eq!(
+            stats.get_warnings(),
+            vec![
+                AnalysisWarning::MostlySilent { silent_ratio: 0.6 },
+                AnalysisWarning::Clipped { clipped_ratio: 0.2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_tempo() {
+        let mut diagnostics = AnalysisDiagnostics::default();
+        diagnostics.check_tempo(true, 0.8);
+        assert!(diagnostics.is_empty());
+        diagnostics.check_tempo(false, 0.);
+        // Beats were found, but the beat tracker never locked onto a tempo.
+        diagnostics.check_tempo(true, 0.);
+        diagnostics.check_tempo(true, 0.2);
+        assert_eq!(
+            diagnostics.warnings,
+            vec![
+                AnalysisWarning::NoBeatsFound,
+                AnalysisWarning::NoBeatsFound,
+                AnalysisWarning::LowTempoConfidence { confidence: 0.2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_sample_count() {
+        let mut diagnostics = AnalysisDiagnostics::default();
+        diagnostics.check_sample_count(None, 10);
+        diagnostics.check_sample_count(Some(Duration::from_secs(10)), 10 * SAMPLE_RATE as usize);
+        diagnostics.check_sample_count(
+            Some(Duration::from_secs(10)),
+            9 * SAMPLE_RATE as usize + 100,
+        );
+        assert!(diagnostics.is_empty());
+
+        diagnostics.check_sample_count(Some(Duration::from_secs(10)), 5 * SAMPLE_RATE as usize);
+        assert_eq!(
+            diagnostics.warnings,
+            vec![AnalysisWarning::SampleCountMismatch {
+                expected: 10 * SAMPLE_RATE as usize,
+                actual: 5 * SAMPLE_RATE as usize,
+            }]
+        );
+
+        // Mismatches are only reported once.
+        diagnostics.check_sample_count(Some(Duration::from_secs(20)), 5 * SAMPLE_RATE as usize);
+        assert_eq!(diagnostics.warnings.len(), 1);
+    }
+
+    #[test]
+    fn test_decoding_issues() {
+        assert_eq!(DecodingIssues::default().get_warnings(), vec![]);
+        let issues = DecodingIssues {
+            skipped: 3,
+            truncated: true,
+        };
+        assert_eq!(
+            issues.get_warnings(),
+            vec![
+                AnalysisWarning::DecodeErrors { skipped: 3 },
+                AnalysisWarning::Truncated,
+            ]
+        );
+    }
+}
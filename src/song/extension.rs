@@ -0,0 +1,313 @@
+//! Extension descriptors module.
+//!
+//! Allows to compute custom, domain-specific features alongside bliss' own
+//! ones, without having to fork the crate. These features are stored in a
+//! separately versioned part of a song's [Analysis](super::Analysis), so
+//! that changing them doesn't require reanalyzing bliss' own features.
+#[cfg(feature = "analysis")]
+use crate::BlissError;
+use crate::BlissResult;
+use std::fmt;
+use std::sync::Arc;
+
+/// A descriptor computing custom features from a song's samples, to be
+/// registered in [ExtensionOptions].
+///
+/// A new descriptor is created for each analyzed song (or segment). It is
+/// fed the song's samples chunk by chunk using [Descriptor::do_], and
+/// [Descriptor::finish] is called once the whole song has been fed.
+///
+/// * Example:
+/// ```
+/// use bliss_audio::{BlissResult, Descriptor};
+///
+/// // Proportion of samples louder than -20 dBFS.
+/// #[derive(Default)]
+/// struct LoudSamples {
+///     number_samples: usize,
+///     loud_samples: usize,
+/// }
+///
+/// impl Descriptor for LoudSamples {
+///     fn do_(&mut self, chunk: &[f32]) -> BlissResult<()> {
+///         self.number_samples += chunk.len();
+///         self.loud_samples += chunk.iter().filter(|x| x.abs() > 0.1).count();
+///         Ok(())
+///     }
+///
+///     fn finish(&mut self) -> BlissResult<Vec<f32>> {
+///         Ok(vec![self.loud_samples as f32 / self.number_samples as f32])
+///     }
+///
+///     fn feature_names(&self) -> Vec<String> {
+///         vec![String::from("loud_samples")]
+///     }
+/// }
+/// ```
+pub trait Descriptor: Send {
+    /// Feed the next chunk of the song's samples to the descriptor.
+    ///
+    /// The samples have the same format as the ones given to
+    /// [Song::analyze](crate::Song::analyze), i.e. f32, one channel, at
+    /// 22050 Hz. Chunks can have any size, so descriptors working on
+    /// windows of samples should buffer them.
+    fn do_(&mut self, chunk: &[f32]) -> BlissResult<()>;
+
+    /// Compute the descriptor's features, once the whole song has been fed.
+    ///
+    /// Should return as many values as [Descriptor::feature_names] returns
+    /// names, in the same order.
+    fn finish(&mut self) -> BlissResult<Vec<f32>>;
+
+    /// The names of the features computed by the descriptor.
+    fn feature_names(&self) -> Vec<String>;
+}
+
+type DescriptorBuilder = Arc<dyn Fn() -> Box<dyn Descriptor> + Send + Sync>;
+
+/// Custom descriptors to run while analyzing songs, in addition to bliss'
+/// own descriptors. See [AnalysisOptions::extensions](crate::AnalysisOptions::extensions).
+///
+/// The features they compute are stored in [Analysis::extension](crate::Analysis::extension).
+///
+/// * Example:
+/// ```
+/// use bliss_audio::{AnalysisOptions, BlissResult, Descriptor, ExtensionOptions};
+///
+/// #[derive(Default)]
+/// struct Peak(f32);
+///
+/// impl Descriptor for Peak {
+///     fn do_(&mut self, chunk: &[f32]) -> BlissResult<()> {
+///         self.0 = chunk.iter().fold(self.0, |peak, x| peak.max(x.abs()));
+///         Ok(())
+///     }
+///
+///     fn finish(&mut self) -> BlissResult<Vec<f32>> {
+///         Ok(vec![self.0])
+///     }
+///
+///     fn feature_names(&self) -> Vec<String> {
+///         vec![String::from("peak")]
+///     }
+/// }
+///
+/// let analysis_options = AnalysisOptions {
+///     extensions: Some(ExtensionOptions::new(1).with_descriptor(Peak::default)),
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Clone)]
+pub struct ExtensionOptions {
+    /// The version of the extension features. Bump it every time the
+    /// descriptors change in a way that makes their features incompatible
+    /// with previously computed ones, so that
+    /// [Library::update_library](crate::library::Library::update_library)
+    /// knows which songs need to be reanalyzed.
+    pub version: u32,
+    descriptors: Vec<DescriptorBuilder>,
+}
+
+impl ExtensionOptions {
+    /// Create extension options without any descriptor, whose features
+    /// have version `version`.
+    pub fn new(version: u32) -> Self {
+        ExtensionOptions {
+            version,
+            descriptors: Vec::new(),
+        }
+    }
+
+    /// Register a descriptor, created by calling `new_descriptor`.
+    ///
+    /// Since songs can be analyzed in parallel, `new_descriptor` is called
+    /// once for every analyzed song (or segment). The descriptor's features
+    /// are appended to the ones of the previously registered descriptors.
+    pub fn with_descriptor<D, F>(mut self, new_descriptor: F) -> Self
+    where
+        D: Descriptor + 'static,
+        F: Fn() -> D + Send + Sync + 'static,
+    {
+        self.descriptors
+            .push(Arc::new(move || Box::new(new_descriptor())));
+        self
+    }
+
+    /// The names of the features computed by the registered descriptors,
+    /// in the order they are stored in [AnalysisExtension::features].
+    pub fn feature_names(&self) -> Vec<String> {
+        self.descriptors
+            .iter()
+            .flat_map(|new_descriptor| new_descriptor().feature_names())
+            .collect()
+    }
+}
+
+impl PartialEq for ExtensionOptions {
+    fn eq(&self, other: &Self) -> bool {
+        self.version == other.version
+            && self.descriptors.len() == other.descriptors.len()
+            && self
+                .descriptors
+                .iter()
+                .zip(&other.descriptors)
+                .all(|(a, b)| Arc::ptr_eq(a, b))
+    }
+}
+
+impl Eq for ExtensionOptions {}
+
+impl fmt::Debug for ExtensionOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ExtensionOptions")
+            .field("version", &self.version)
+            .field("feature_names", &self.feature_names())
+            .finish()
+    }
+}
+
+/// The features computed by the descriptors registered in
+/// [ExtensionOptions].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct AnalysisExtension {
+    /// The version of the extension features, taken from
+    /// [ExtensionOptions::version].
+    pub version: u32,
+    /// The features computed by the descriptors, named by
+    /// [ExtensionOptions::feature_names].
+    pub features: Vec<f32>,
+}
+
+/// The descriptors of some [ExtensionOptions], instantiated to analyze
+/// a song.
+#[cfg(feature = "analysis")]
+pub(crate) struct ExtensionRunner {
+    version: u32,
+    descriptors: Vec<Box<dyn Descriptor>>,
+}
+
+#[cfg(feature = "analysis")]
+impl ExtensionRunner {
+    pub fn new(extension_options: &ExtensionOptions) -> Self {
+        ExtensionRunner {
+            version: extension_options.version,
+            descriptors: extension_options
+                .descriptors
+                .iter()
+                .map(|new_descriptor| new_descriptor())
+                .collect(),
+        }
+    }
+
+    pub fn do_(&mut self, chunk: &[f32]) -> BlissResult<()> {
+        for descriptor in &mut self.descriptors {
+            descriptor.do_(chunk)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> BlissResult<AnalysisExtension> {
+        let mut features = Vec::new();
+        for descriptor in &mut self.descriptors {
+            let values = descriptor.finish()?;
+            let number_features = descriptor.feature_names().len();
+            if values.len() != number_features {
+                return Err(BlissError::AnalysisError(format!(
+                    "in extension: a descriptor returned {} features instead of {}.",
+                    values.len(),
+                    number_features,
+                )));
+            }
+            features.extend(values);
+        }
+        Ok(AnalysisExtension {
+            version: self.version,
+            features,
+        })
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "analysis")]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[derive(Default)]
+    struct Sum(f32);
+
+    impl Descriptor for Sum {
+        fn do_(&mut self, chunk: &[f32]) -> BlissResult<()> {
+            self.0 += chunk.iter().sum::<f32>();
+            Ok(())
+        }
+
+        fn finish(&mut self) -> BlissResult<Vec<f32>> {
+            Ok(vec![self.0, -self.0])
+        }
+
+        fn feature_names(&self) -> Vec<String> {
+            vec![String::from("sum"), String::from("opposite_sum")]
+        }
+    }
+
+    struct Wrong;
+
+    impl Descriptor for Wrong {
+        fn do_(&mut self, _: &[f32]) -> BlissResult<()> {
+            Ok(())
+        }
+
+        fn finish(&mut self) -> BlissResult<Vec<f32>> {
+            Ok(vec![])
+        }
+
+        fn feature_names(&self) -> Vec<String> {
+            vec![String::from("wrong")]
+        }
+    }
+
+    #[test]
+    fn test_extension_runner() {
+        let options = ExtensionOptions::new(2)
+            .with_descriptor(Sum::default)
+            .with_descriptor(|| Sum(1.));
+        assert_eq!(
+            options.feature_names(),
+            vec!["sum", "opposite_sum", "sum", "opposite_sum"]
+        );
+        let mut runner = ExtensionRunner::new(&options);
+        runner.do_(&[1., 2.]).unwrap();
+        runner.do_(&[]).unwrap();
+        runner.do_(&[3.]).unwrap();
+        assert_eq!(
+            runner.finish().unwrap(),
+            AnalysisExtension {
+                version: 2,
+                features: vec![6., -6., 7., -7.],
+            }
+        );
+
+        let options = options.with_descriptor(|| Wrong);
+        assert_eq!(
+            ExtensionRunner::new(&options).finish(),
+            Err(BlissError::AnalysisError(String::from(
+                "in extension: a descriptor returned 0 features instead of 1."
+            )))
+        );
+    }
+
+    #[test]
+    fn test_extension_options_eq() {
+        let options = ExtensionOptions::new(1).with_descriptor(Sum::default);
+        assert_eq!(options, options.clone());
+        assert_ne!(
+            options,
+            ExtensionOptions::new(1).with_descriptor(Sum::default)
+        );
+        assert_ne!(options, ExtensionOptions::new(1));
+        assert_eq!(ExtensionOptions::new(1), ExtensionOptions::new(1));
+        assert_ne!(ExtensionOptions::new(1), ExtensionOptions::new(2));
+    }
+}
@@ -0,0 +1,430 @@
+//! Acoustic fingerprinting module.
+//!
+//! Computes a compact fingerprint of the beginning of a song from its
+//! chromagram, in the spirit of Chromaprint, to find duplicate songs even
+//! if they come from different files (different encodings, remasters,
+//! compilations...).
+#[cfg(feature = "analysis")]
+use super::boundaries::BoundaryDesc;
+#[cfg(feature = "analysis")]
+use crate::aubio::is_silence;
+#[cfg(feature = "analysis")]
+use crate::chroma::{apply_chroma_filter, chroma_filter, frames_to_spectrum, ChromaDesc};
+#[cfg(feature = "analysis")]
+use crate::utils::StftStream;
+#[cfg(feature = "analysis")]
+use crate::{BlissResult, SAMPLE_RATE};
+#[cfg(feature = "analysis")]
+use ndarray::{Array1, Array2, Axis};
+#[cfg(feature = "library")]
+use std::collections::HashMap;
+
+/// A compact acoustic fingerprint of a song, see [Song::fingerprint](crate::Song::fingerprint).
+///
+/// Use [Fingerprint::matches] to know whether two songs are most likely
+/// the same recording.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Fingerprint {
+    /// One code per [Fingerprint::CODE_DURATION_MS] of audio, starting at the
+    /// end of the song's leading silence.
+    ///
+    /// Each code is made of [Fingerprint::CODE_BITS] bits, telling which
+    /// pitch classes are the most prominent.
+    pub codes: Vec<u32>,
+}
+
+impl Fingerprint {
+    /// The duration of the audio summarized by each code, in milliseconds.
+    pub const CODE_DURATION_MS: u32 = 300;
+    /// The number of meaningful bits in each code.
+    pub const CODE_BITS: u32 = 24;
+    /// The similarity above which two fingerprints are considered to come
+    /// from the same recording, see [Fingerprint::matches].
+    pub const MATCH_THRESHOLD: f32 = 0.85;
+    // The maximum offset between two fingerprints' codes tried while
+    // matching them, i.e. about 10 seconds.
+    const MAX_OFFSET: usize = 33;
+    // The minimum number of codes two fingerprints must have in common to
+    // be compared, i.e. about 3 seconds.
+    const MIN_OVERLAP: usize = 10;
+    // Candidate duplicates must have at least that many codes in common.
+    #[cfg(feature = "library")]
+    const MIN_COMMON_CODES: usize = 3;
+
+    /**
+     * How similar this fingerprint is to `other`, from 0 (nothing in
+     * common) to 1 (same fingerprint).
+     *
+     * The fingerprints are aligned with an offset of up to about 10
+     * seconds, so songs with a slightly longer intro, or more leading
+     * silence, still match. The similarity is then the proportion of
+     * identical bits in the codes that overlap, for the best offset.
+     *
+     * Unrelated songs usually have a similarity around 0.6, since music
+     * tends to use the same few pitch classes.
+     */
+    pub fn similarity(&self, other: &Fingerprint) -> f32 {
+        let mut best_similarity = 0.;
+        for offset in -(Fingerprint::MAX_OFFSET as isize)..=Fingerprint::MAX_OFFSET as isize {
+            let (a, b) = if offset < 0 {
+                (&self.codes[..], other.codes.get(offset.unsigned_abs()..))
+            } else {
+                (&other.codes[..], self.codes.get(offset as usize..))
+            };
+            let Some(b) = b else {
+                continue;
+            };
+            let overlap = a.len().min(b.len());
+            if overlap < Fingerprint::MIN_OVERLAP {
+                continue;
+            }
+            let different_bits = a
+                .iter()
+                .zip(b)
+                .map(|(a, b)| (a ^ b).count_ones())
+                .sum::<u32>();
+            let similarity =
+                1. - different_bits as f32 / (overlap as u32 * Fingerprint::CODE_BITS) as f32;
+            if similarity > best_similarity {
+                best_similarity = similarity;
+            }
+        }
+        best_similarity
+    }
+
+    /// Whether this fingerprint and `other` most likely come from the same
+    /// recording, i.e. whether their [similarity](Fingerprint::similarity)
+    /// is above [Fingerprint::MATCH_THRESHOLD].
+    ///
+    /// This holds even if the songs were encoded differently, have a
+    /// different volume, or are offset by a few seconds.
+    pub fn matches(&self, other: &Fingerprint) -> bool {
+        self.similarity(other) >= Fingerprint::MATCH_THRESHOLD
+    }
+
+    /// Group the indices of the fingerprints matching each other.
+    ///
+    /// Only fingerprints sharing a few identical codes are compared, so
+    /// this doesn't need to compare every pair of fingerprints. Groups are
+    /// sorted, and ordered by their first index; fingerprints without any
+    /// match are left out.
+    #[cfg(feature = "library")]
+    pub(crate) fn group_duplicates(fingerprints: &[&Fingerprint]) -> Vec<Vec<usize>> {
+        let mut songs_by_code: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (index, fingerprint) in fingerprints.iter().enumerate() {
+            let mut codes = fingerprint.codes.clone();
+            codes.sort_unstable();
+            codes.dedup();
+            for code in codes {
+                songs_by_code.entry(code).or_default().push(index);
+            }
+        }
+
+        // Each group is represented by its smallest index.
+        let mut groups = (0..fingerprints.len()).collect::<Vec<usize>>();
+        fn find(groups: &mut [usize], index: usize) -> usize {
+            let mut root = index;
+            while groups[root] != root {
+                root = groups[root];
+            }
+            groups[index] = root;
+            root
+        }
+
+        for (index, fingerprint) in fingerprints.iter().enumerate() {
+            let mut common_codes: HashMap<usize, usize> = HashMap::new();
+            let mut codes = fingerprint.codes.clone();
+            codes.sort_unstable();
+            codes.dedup();
+            for code in codes {
+                for &other in &songs_by_code[&code] {
+                    if other > index {
+                        *common_codes.entry(other).or_default() += 1;
+                    }
+                }
+            }
+            let mut candidates = common_codes
+                .into_iter()
+                .filter(|(_, count)| *count >= Fingerprint::MIN_COMMON_CODES)
+                .map(|(other, _)| other)
+                .collect::<Vec<usize>>();
+            candidates.sort_unstable();
+            for other in candidates {
+                let (root, other_root) = (find(&mut groups, index), find(&mut groups, other));
+                if root != other_root && fingerprint.matches(fingerprints[other]) {
+                    groups[root.max(other_root)] = root.min(other_root);
+                }
+            }
+        }
+
+        let mut duplicates: Vec<Vec<usize>> = Vec::new();
+        let mut group_indices = HashMap::new();
+        for index in 0..fingerprints.len() {
+            let root = find(&mut groups, index);
+            let group_index = *group_indices.entry(root).or_insert_with(|| {
+                duplicates.push(Vec::new());
+                duplicates.len() - 1
+            });
+            duplicates[group_index].push(index);
+        }
+        duplicates.retain(|group| group.len() > 1);
+        duplicates
+    }
+}
+
+/**
+ * Fingerprint computation object.
+ *
+ * Skips the song's leading silence, then computes the chromagram of its
+ * first two minutes the same way [ChromaDesc] does. Consecutive chroma
+ * vectors are averaged, and each average is turned into a code comparing
+ * the energy of its pitch classes, which is independent of the song's
+ * volume and robust to lossy encoding.
+ */
+#[cfg(feature = "analysis")]
+pub(crate) struct FingerprintDesc {
+    stft: StftStream,
+    chroma_filter: Array2<f64>,
+    // Samples waiting to be checked for silence, until the song starts.
+    leading_frame: Vec<f32>,
+    started: bool,
+    // Number of samples given to the STFT so far.
+    number_samples: usize,
+    // Sum of the chroma vectors of the current code.
+    chroma_sum: Array1<f64>,
+    number_chroma_frames: usize,
+    codes: Vec<u32>,
+}
+
+#[cfg(feature = "analysis")]
+impl FingerprintDesc {
+    // Duration of the fingerprinted audio, in seconds.
+    const MAX_LENGTH: usize = 120;
+    // Number of chroma frames averaged in each code.
+    const FRAMES_PER_CODE: usize = 3;
+
+    pub fn new() -> BlissResult<Self> {
+        Ok(FingerprintDesc {
+            stft: StftStream::new(ChromaDesc::WINDOW_SIZE, ChromaDesc::HOP_SIZE),
+            // The tuning is not estimated, since two recordings of the same
+            // song are tuned the same way anyway.
+            chroma_filter: chroma_filter(SAMPLE_RATE, ChromaDesc::WINDOW_SIZE, 12, 0.)?,
+            leading_frame: Vec::with_capacity(BoundaryDesc::FRAME_SIZE),
+            started: false,
+            number_samples: 0,
+            chroma_sum: Array1::zeros(12),
+            number_chroma_frames: 0,
+            codes: Vec::new(),
+        })
+    }
+
+    pub fn do_(&mut self, mut chunk: &[f32]) -> BlissResult<()> {
+        while !self.started && !chunk.is_empty() {
+            let missing = (BoundaryDesc::FRAME_SIZE - self.leading_frame.len()).min(chunk.len());
+            self.leading_frame.extend_from_slice(&chunk[..missing]);
+            chunk = &chunk[missing..];
+            if self.leading_frame.len() == BoundaryDesc::FRAME_SIZE {
+                if is_silence(&self.leading_frame, BoundaryDesc::SILENCE_DB) {
+                    self.leading_frame.clear();
+                } else {
+                    self.started = true;
+                    let leading_frame = std::mem::take(&mut self.leading_frame);
+                    self.push_samples(&leading_frame)?;
+                }
+            }
+        }
+        if self.started {
+            self.push_samples(chunk)?;
+        }
+        Ok(())
+    }
+
+    fn push_samples(&mut self, chunk: &[f32]) -> BlissResult<()> {
+        let max_samples = FingerprintDesc::MAX_LENGTH * SAMPLE_RATE as usize;
+        let length = chunk.len().min(max_samples - self.number_samples);
+        if length == 0 {
+            return Ok(());
+        }
+        self.number_samples += length;
+        let frames = self.stft.push(&chunk[..length]);
+        self.process_frames(frames)
+    }
+
+    fn process_frames(&mut self, frames: Vec<Array1<f64>>) -> BlissResult<()> {
+        if frames.is_empty() {
+            return Ok(());
+        }
+        let mut spectrum = frames_to_spectrum(&frames)?;
+        let chroma = apply_chroma_filter(&self.chroma_filter, &mut spectrum);
+        for chroma_frame in chroma.axis_iter(Axis(1)) {
+            self.chroma_sum += &chroma_frame;
+            self.number_chroma_frames += 1;
+            if self.number_chroma_frames == FingerprintDesc::FRAMES_PER_CODE {
+                self.codes.push(FingerprintDesc::code(&self.chroma_sum));
+                self.chroma_sum.fill(0.);
+                self.number_chroma_frames = 0;
+            }
+        }
+        Ok(())
+    }
+
+    // Bit `i` tells whether pitch class `i` is more prominent than the next
+    // one, and bit `12 + i` whether it is more prominent than average.
+    fn code(chroma: &Array1<f64>) -> u32 {
+        let mean = chroma.sum() / 12.;
+        let mut code = 0;
+        for i in 0..12 {
+            if chroma[i] > chroma[(i + 1) % 12] {
+                code |= 1 << i;
+            }
+            if chroma[i] > mean {
+                code |= 1 << (12 + i);
+            }
+        }
+        code
+    }
+
+    /// The song's fingerprint, or `None` if the song is silent, or too
+    /// short to be fingerprinted reliably.
+    pub fn get_value(mut self) -> BlissResult<Option<Fingerprint>> {
+        if let Some(frames) = self.stft.finish() {
+            self.process_frames(frames)?;
+        }
+        if self.codes.len() < Fingerprint::MIN_OVERLAP {
+            return Ok(None);
+        }
+        Ok(Some(Fingerprint { codes: self.codes }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_fingerprint_similarity() {
+        let codes = (0..100).map(|i| i * 7919 % (1 << 24)).collect::<Vec<u32>>();
+        let fingerprint = Fingerprint {
+            codes: codes.clone(),
+        };
+        assert_eq!(fingerprint.similarity(&fingerprint), 1.);
+
+        // Offset by a few codes.
+        let offset = Fingerprint {
+            codes: [vec![0; 5], codes[..90].to_vec()].concat(),
+        };
+        assert_eq!(fingerprint.similarity(&offset), 1.);
+        assert_eq!(offset.similarity(&fingerprint), 1.);
+        assert!(fingerprint.matches(&offset));
+
+        // A few flipped bits in every code.
+        let noisy = Fingerprint {
+            codes: codes.iter().map(|c| c ^ 0b1000_0000_0001).collect(),
+        };
+        assert!(0.0001 > (fingerprint.similarity(&noisy) - 11. / 12.).abs());
+        assert!(fingerprint.matches(&noisy));
+
+        // Everything flipped.
+        let opposite = Fingerprint {
+            codes: codes.iter().map(|c| !c & 0xFFFFFF).collect(),
+        };
+        assert!(!fingerprint.matches(&opposite));
+
+        // Too short to overlap.
+        let short = Fingerprint {
+            codes: codes[..5].to_vec(),
+        };
+        assert_eq!(fingerprint.similarity(&short), 0.);
+    }
+
+    #[test]
+    #[cfg(feature = "library")]
+    fn test_group_duplicates() {
+        let first = Fingerprint {
+            codes: (0..100).map(|i| i * 7919 % (1 << 24)).collect(),
+        };
+        let second = Fingerprint {
+            codes: (0..100).map(|i| (i + 17) * 104729 % (1 << 24)).collect(),
+        };
+        let offset_first = Fingerprint {
+            codes: first.codes[3..].to_vec(),
+        };
+        let noisy_second = Fingerprint {
+            codes: second
+                .codes
+                .iter()
+                .enumerate()
+                .map(|(i, c)| if i % 2 == 0 { c ^ 1 } else { *c })
+                .collect(),
+        };
+        let unrelated = Fingerprint {
+            codes: (0..100).map(|i| (i + 1) * 31337 % (1 << 24)).collect(),
+        };
+        let fingerprints = [&first, &second, &unrelated, &noisy_second, &offset_first];
+        assert_eq!(
+            Fingerprint::group_duplicates(&fingerprints),
+            vec![vec![0, 4], vec![1, 3]],
+        );
+        assert!(Fingerprint::group_duplicates(&[&first, &second]).is_empty());
+        assert!(Fingerprint::group_duplicates(&[]).is_empty());
+    }
+
+    // A sequence of random triads, changing every half second.
+    #[cfg(feature = "analysis")]
+    fn chords(seed: u32, number_chords: usize) -> Vec<f32> {
+        let chord_length = SAMPLE_RATE as usize / 2;
+        let mut state = seed;
+        (0..number_chords)
+            .flat_map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                let root = (state >> 16) % 12;
+                (0..chord_length).map(move |i| {
+                    [0, 4, 7]
+                        .iter()
+                        .map(|interval| {
+                            let frequency = 261.63 * 2_f32.powf((root + interval) as f32 / 12.);
+                            0.2 * (2. * std::f32::consts::PI * frequency * i as f32
+                                / SAMPLE_RATE as f32)
+                                .sin()
+                        })
+                        .sum::<f32>()
+                })
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "analysis")]
+    fn fingerprint(sample_array: &[f32]) -> Option<Fingerprint> {
+        let mut fingerprint_desc = FingerprintDesc::new().unwrap();
+        for chunk in sample_array.chunks(1000) {
+            fingerprint_desc.do_(chunk).unwrap();
+        }
+        fingerprint_desc.get_value().unwrap()
+    }
+
+    #[test]
+    #[cfg(feature = "analysis")]
+    fn test_fingerprint_desc() {
+        let song = chords(1, 40);
+        let expected = fingerprint(&song).unwrap();
+        assert_eq!(expected.codes.len(), 66);
+
+        // Quieter, with some leading silence and a bit of its intro cut.
+        let other_version = [
+            vec![0.; SAMPLE_RATE as usize],
+            song[1000..].iter().map(|x| x * 0.3).collect(),
+        ]
+        .concat();
+        let other_version = fingerprint(&other_version).unwrap();
+        assert!(expected.similarity(&other_version) > 0.95);
+        assert!(expected.matches(&other_version));
+
+        let other_song = fingerprint(&chords(2, 40)).unwrap();
+        assert!(!expected.matches(&other_song));
+
+        assert_eq!(fingerprint(&vec![0.; 10 * SAMPLE_RATE as usize]), None);
+        assert_eq!(fingerprint(&song[..SAMPLE_RATE as usize]), None);
+    }
+}
@@ -0,0 +1,142 @@
+//! Musical key module.
+//!
+//! Holds the key of a song, as estimated from its chromagram, along with
+//! the notations DJs use for harmonic mixing (Camelot wheel, Open Key).
+use std::fmt;
+
+const PITCH_CLASS_NAMES: [&str; 12] = [
+    "C", "C#", "D", "Eb", "E", "F", "F#", "G", "Ab", "A", "Bb", "B",
+];
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+/// The mode of a [Key].
+pub enum Mode {
+    /// Major mode.
+    Major,
+    /// Minor mode.
+    Minor,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// The musical key of a song, e.g. A minor.
+///
+/// Estimated by correlating the song's average chroma with the
+/// Krumhansl-Kessler key profiles.
+pub struct Key {
+    /// The pitch class of the key's tonic, from 0 (C) to 11 (B),
+    /// e.g. 9 for A minor.
+    pub tonic: u8,
+    /// The key's mode, major or minor.
+    pub mode: Mode,
+    /// The correlation between the song's average chroma and the
+    /// key's profile, between -1 and 1. The higher it is, the more
+    /// reliable the estimated key.
+    pub confidence: f32,
+}
+
+impl Key {
+    /// The key's position on the Camelot wheel, from 1 to 12.
+    fn camelot_number(&self) -> u8 {
+        // Relative major and minor keys share the same number.
+        let major_tonic = match self.mode {
+            Mode::Major => self.tonic,
+            Mode::Minor => (self.tonic + 3) % 12,
+        };
+        // Each step on the wheel is a fifth away, C major being 8B.
+        (7 * major_tonic + 7) % 12 + 1
+    }
+
+    /// The key in Camelot notation, e.g. "8A" for A minor, or "8B" for
+    /// C major.
+    pub fn camelot(&self) -> String {
+        let letter = match self.mode {
+            Mode::Major => 'B',
+            Mode::Minor => 'A',
+        };
+        format!("{}{}", self.camelot_number(), letter)
+    }
+
+    /// The key in Open Key notation, e.g. "1m" for A minor, or "1d" for
+    /// C major.
+    pub fn open_key(&self) -> String {
+        let letter = match self.mode {
+            Mode::Major => 'd',
+            Mode::Minor => 'm',
+        };
+        format!("{}{}", (self.camelot_number() + 4) % 12 + 1, letter)
+    }
+
+    /// Whether two songs in these keys can be mixed harmonically, i.e.
+    /// if the keys are the same, relative (e.g. A minor and C major),
+    /// or next to each other on the Camelot wheel (e.g. C major and
+    /// G major).
+    pub fn is_compatible_with(&self, other: &Key) -> bool {
+        let (number, other_number) = (self.camelot_number(), other.camelot_number());
+        if self.mode != other.mode {
+            return number == other_number;
+        }
+        let distance = (12 + number - other_number) % 12;
+        distance <= 1 || distance == 11
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mode = match self.mode {
+            Mode::Major => "major",
+            Mode::Minor => "minor",
+        };
+        write!(
+            f,
+            "{} {}",
+            PITCH_CLASS_NAMES[self.tonic as usize % 12],
+            mode
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn key(tonic: u8, mode: Mode) -> Key {
+        Key {
+            tonic,
+            mode,
+            confidence: 1.,
+        }
+    }
+
+    #[test]
+    fn test_key_notations() {
+        let expected = [
+            (key(0, Mode::Major), "C major", "8B", "1d"),
+            (key(9, Mode::Minor), "A minor", "8A", "1m"),
+            (key(7, Mode::Major), "G major", "9B", "2d"),
+            (key(11, Mode::Major), "B major", "1B", "6d"),
+            (key(8, Mode::Minor), "Ab minor", "1A", "6m"),
+            (key(5, Mode::Major), "F major", "7B", "12d"),
+            (key(1, Mode::Minor), "C# minor", "12A", "5m"),
+        ];
+        for (key, name, camelot, open_key) in expected {
+            assert_eq!(key.to_string(), name);
+            assert_eq!(key.camelot(), camelot);
+            assert_eq!(key.open_key(), open_key);
+        }
+    }
+
+    #[test]
+    fn test_key_compatibility() {
+        let c_major = key(0, Mode::Major);
+        assert!(c_major.is_compatible_with(&c_major));
+        assert!(c_major.is_compatible_with(&key(9, Mode::Minor)));
+        assert!(c_major.is_compatible_with(&key(7, Mode::Major)));
+        assert!(c_major.is_compatible_with(&key(5, Mode::Major)));
+        assert!(!c_major.is_compatible_with(&key(2, Mode::Major)));
+        assert!(!c_major.is_compatible_with(&key(4, Mode::Minor)));
+        assert!(key(11, Mode::Major).is_compatible_with(&key(4, Mode::Major)));
+    }
+}
@@ -0,0 +1,451 @@
+//! Loudness measurement module.
+//!
+//! Measures the loudness of a song as specified by EBU R128 and
+//! ITU-R BS.1770 (integrated loudness, loudness range and true peak),
+//! using the decoded samples at their native sample rate, before they are
+//! downmixed and resampled for the analysis.
+//!
+//! The result can be used to normalize the volume of songs, like
+//! ReplayGain does, without having to scan the songs a second time.
+#[cfg(feature = "analysis")]
+use crate::{BlissError, BlissResult};
+
+/// Absolute gate of the integrated loudness and the loudness range, in LUFS.
+#[cfg(feature = "analysis")]
+const ABSOLUTE_GATE: f64 = -70.;
+/// Relative gate of the integrated loudness, in LU.
+#[cfg(feature = "analysis")]
+const INTEGRATED_RELATIVE_GATE: f64 = -10.;
+/// Relative gate of the loudness range, in LU.
+#[cfg(feature = "analysis")]
+const RANGE_RELATIVE_GATE: f64 = -20.;
+/// Number of 100ms sub-blocks in a momentary (400ms) block.
+#[cfg(feature = "analysis")]
+const MOMENTARY_SUB_BLOCKS: usize = 4;
+/// Number of 100ms sub-blocks in a short-term (3s) block.
+#[cfg(feature = "analysis")]
+const SHORT_TERM_SUB_BLOCKS: usize = 30;
+/// Number of taps of each phase of the true peak interpolation filter.
+#[cfg(feature = "analysis")]
+const TRUE_PEAK_TAPS: usize = 12;
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// The loudness of a song, measured as specified by EBU R128.
+///
+/// Computed while decoding, on the song's samples at their native sample
+/// rate: see [LoudnessMeter].
+pub struct Loudness {
+    /// The integrated (i.e. gated average) loudness of the song, in LUFS.
+    pub integrated: f32,
+    /// The loudness range (LRA) of the song, in LU, i.e. how much its
+    /// loudness varies between its quiet and its loud parts.
+    pub range: f32,
+    /// The true peak of the song, in dBTP, i.e. the maximum level of the
+    /// signal once reconstructed, which can be higher than the
+    /// maximum sample value.
+    pub true_peak: f32,
+}
+
+impl Loudness {
+    /// The reference loudness of ReplayGain 2.0, in LUFS.
+    pub const REPLAY_GAIN_REFERENCE: f32 = -18.;
+
+    /// The gain to apply to the song to play it at the ReplayGain 2.0
+    /// reference loudness, in dB.
+    pub fn replay_gain(&self) -> f32 {
+        Loudness::REPLAY_GAIN_REFERENCE - self.integrated
+    }
+
+    /// The true peak of the song as a linear amplitude (1 being full
+    /// scale), i.e. the ReplayGain peak, used by players to avoid
+    /// clipping when applying [Loudness::replay_gain].
+    pub fn true_peak_amplitude(&self) -> f32 {
+        10f32.powf(self.true_peak / 20.)
+    }
+}
+
+/// One of the biquad filters of the K-weighting filter, in transposed
+/// direct form II.
+#[cfg(feature = "analysis")]
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    state: [f64; 2],
+}
+
+#[cfg(feature = "analysis")]
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.state[0];
+        self.state[0] = self.b[1] * x - self.a[0] * y + self.state[1];
+        self.state[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+}
+
+/// The K-weighting filter of ITU-R BS.1770, computed for any sample rate.
+///
+/// The coefficients are derived from the analog prototypes of the shelving
+/// and high-pass filters, the same way libebur128 does.
+#[cfg(feature = "analysis")]
+#[derive(Clone)]
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+#[cfg(feature = "analysis")]
+impl KWeighting {
+    fn new(sample_rate: u32) -> Self {
+        let sample_rate = sample_rate as f64;
+
+        let f0 = 1681.974450955533;
+        let gain = 3.999843853973347;
+        let q = 0.7071752369554196;
+        let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+        let vh = 10f64.powf(gain / 20.);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1. + k / q + k * k;
+        let shelf = Biquad {
+            b: [
+                (vh + vb * k / q + k * k) / a0,
+                2. * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            a: [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+            state: [0.; 2],
+        };
+
+        let f0 = 38.13547087602444;
+        let q = 0.5003270373238773;
+        let k = (std::f64::consts::PI * f0 / sample_rate).tan();
+        let a0 = 1. + k / q + k * k;
+        let high_pass = Biquad {
+            b: [1., -2., 1.],
+            a: [2. * (k * k - 1.) / a0, (1. - k / q + k * k) / a0],
+            state: [0.; 2],
+        };
+        KWeighting { shelf, high_pass }
+    }
+
+    fn process(&mut self, x: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(x))
+    }
+}
+
+/// True peak meter, oversampling the signal with a polyphase windowed-sinc
+/// interpolation filter, as recommended by ITU-R BS.1770 annex 2.
+#[cfg(feature = "analysis")]
+struct TruePeak {
+    // One filter of `TRUE_PEAK_TAPS` taps per phase.
+    phases: Vec<[f32; TRUE_PEAK_TAPS]>,
+    // The last `TRUE_PEAK_TAPS` samples of each channel, most recent last.
+    history: Vec<[f32; TRUE_PEAK_TAPS]>,
+    peak: f32,
+}
+
+#[cfg(feature = "analysis")]
+impl TruePeak {
+    fn new(sample_rate: u32, channels: usize) -> Self {
+        // Oversample to at least 192kHz.
+        let factor = match sample_rate {
+            0..96000 => 4,
+            96000..192000 => 2,
+            _ => 1,
+        };
+        let length = factor * TRUE_PEAK_TAPS;
+        let center = (length - 1) as f64 / 2.;
+        let coefficients = (0..length)
+            .map(|i| {
+                let x = (i as f64 - center) / factor as f64;
+                let sinc = if x == 0. {
+                    1.
+                } else {
+                    (std::f64::consts::PI * x).sin() / (std::f64::consts::PI * x)
+                };
+                let window = 0.5
+                    - 0.5 * (2. * std::f64::consts::PI * (i as f64 + 0.5) / length as f64).cos();
+                sinc * window
+            })
+            .collect::<Vec<f64>>();
+        let phases = (0..factor)
+            .map(|phase| {
+                let mut taps = [0.; TRUE_PEAK_TAPS];
+                for (tap, value) in taps.iter_mut().enumerate() {
+                    *value = coefficients[tap * factor + phase];
+                }
+                // Unity gain for each phase, so that a constant signal
+                // keeps its level.
+                let sum = taps.iter().sum::<f64>();
+                let mut phase_taps = [0.; TRUE_PEAK_TAPS];
+                for (tap, value) in taps.iter().enumerate() {
+                    // Most recent sample last in the history.
+                    phase_taps[TRUE_PEAK_TAPS - 1 - tap] = (value / sum) as f32;
+                }
+                phase_taps
+            })
+            .collect();
+        TruePeak {
+            phases,
+            history: vec![[0.; TRUE_PEAK_TAPS]; channels],
+            peak: 0.,
+        }
+    }
+
+    fn process(&mut self, channel: usize, sample: f32) {
+        let history = &mut self.history[channel];
+        history.copy_within(1.., 0);
+        history[TRUE_PEAK_TAPS - 1] = sample;
+        self.peak = self.peak.max(sample.abs());
+        for phase in &self.phases {
+            let interpolated = phase
+                .iter()
+                .zip(history.iter())
+                .map(|(a, b)| a * b)
+                .sum::<f32>();
+            self.peak = self.peak.max(interpolated.abs());
+        }
+    }
+}
+
+/**
+ * EBU R128 loudness meter.
+ *
+ * Fed with the decoded samples of a song, interleaved, at their native
+ * sample rate, chunk by chunk, it computes the song's [Loudness].
+ *
+ * Useful for custom [Decoder](crate::decoder::Decoder) implementations,
+ * which can use it to fill
+ * [PreAnalyzedSong::loudness](crate::decoder::PreAnalyzedSong::loudness).
+ */
+#[cfg(feature = "analysis")]
+pub struct LoudnessMeter {
+    channels: usize,
+    filters: Vec<KWeighting>,
+    weights: Vec<f64>,
+    true_peak: TruePeak,
+    // The channel of the next sample.
+    channel: usize,
+    frames_per_sub_block: usize,
+    sub_block_frames: usize,
+    sub_block_energy: f64,
+    // The channel-weighted sum of the squared K-weighted samples of each
+    // complete 100ms sub-block.
+    sub_block_energies: Vec<f64>,
+}
+
+#[cfg(feature = "analysis")]
+impl LoudnessMeter {
+    /// Create a loudness meter for samples with `channels` interleaved
+    /// channels, sampled at `sample_rate`.
+    ///
+    /// Channels are expected to be in the usual order, i.e. left, right,
+    /// center, low-frequency effects, left surround, right surround for
+    /// 5.1 songs. Low-frequency effects channels are ignored, and surround
+    /// channels weighted as specified by ITU-R BS.1770.
+    pub fn new(sample_rate: u32, channels: usize) -> BlissResult<Self> {
+        if sample_rate == 0 || channels == 0 {
+            return Err(BlissError::AnalysisError(format!(
+                "cannot measure the loudness of samples with {channels} channels at {sample_rate} Hz."
+            )));
+        }
+        // Same channel mapping as libebur128's default one.
+        let weights = (0..channels)
+            .map(|channel| match (channels, channel) {
+                (4, 2 | 3) | (5, 3 | 4) | (6.., 4 | 5) => 1.41,
+                (6.., 3) => 0.,
+                _ => 1.,
+            })
+            .collect();
+        Ok(LoudnessMeter {
+            channels,
+            filters: vec![KWeighting::new(sample_rate); channels],
+            weights,
+            true_peak: TruePeak::new(sample_rate, channels),
+            channel: 0,
+            frames_per_sub_block: (sample_rate as usize / 10).max(1),
+            sub_block_frames: 0,
+            sub_block_energy: 0.,
+            sub_block_energies: Vec::new(),
+        })
+    }
+
+    /// Measure the loudness of `samples`, which are interleaved. Chunks
+    /// don't need to contain whole frames.
+    pub fn push(&mut self, samples: &[f32]) {
+        for &sample in samples {
+            let filtered = self.filters[self.channel].process(sample as f64);
+            self.sub_block_energy += self.weights[self.channel] * filtered * filtered;
+            self.true_peak.process(self.channel, sample);
+
+            self.channel += 1;
+            if self.channel == self.channels {
+                self.channel = 0;
+                self.sub_block_frames += 1;
+                if self.sub_block_frames == self.frames_per_sub_block {
+                    self.sub_block_energies.push(self.sub_block_energy);
+                    self.sub_block_energy = 0.;
+                    self.sub_block_frames = 0;
+                }
+            }
+        }
+    }
+
+    /// Compute the loudness of all the samples pushed so far.
+    ///
+    /// Returns `None` if the song is too short (less than 400ms) or too
+    /// quiet (under -70 LUFS) for its loudness to be measured.
+    pub fn finish(&self) -> Option<Loudness> {
+        let block_loudness = |blocks: &[f64], sub_blocks: usize| {
+            blocks
+                .windows(sub_blocks)
+                .map(|window| {
+                    window.iter().sum::<f64>() / (sub_blocks * self.frames_per_sub_block) as f64
+                })
+                .collect::<Vec<f64>>()
+        };
+
+        let momentary = block_loudness(&self.sub_block_energies, MOMENTARY_SUB_BLOCKS);
+        let integrated = gated_energies(&momentary, INTEGRATED_RELATIVE_GATE)?;
+        let integrated =
+            energy_to_loudness(integrated.iter().sum::<f64>() / integrated.len() as f64);
+
+        let short_term = block_loudness(&self.sub_block_energies, SHORT_TERM_SUB_BLOCKS);
+        let range = match gated_energies(&short_term, RANGE_RELATIVE_GATE) {
+            Some(energies) => {
+                let mut loudness = energies
+                    .into_iter()
+                    .map(energy_to_loudness)
+                    .collect::<Vec<f64>>();
+                loudness.sort_by(|a, b| a.total_cmp(b));
+                let percentile =
+                    |p: f64| loudness[((loudness.len() - 1) as f64 * p).round() as usize];
+                percentile(0.95) - percentile(0.1)
+            }
+            None => 0.,
+        };
+
+        Some(Loudness {
+            integrated: integrated as f32,
+            range: range as f32,
+            true_peak: 20. * self.true_peak.peak.max(1e-10).log10(),
+        })
+    }
+}
+
+#[cfg(feature = "analysis")]
+fn energy_to_loudness(energy: f64) -> f64 {
+    -0.691 + 10. * energy.log10()
+}
+
+// Keep the energies of the blocks above the absolute gate, and above the
+// relative gate `relative_gate` (relative to the mean energy of the blocks
+// above the absolute gate). Returns `None` if no blocks are left.
+#[cfg(feature = "analysis")]
+fn gated_energies(energies: &[f64], relative_gate: f64) -> Option<Vec<f64>> {
+    let above_absolute = energies
+        .iter()
+        .copied()
+        .filter(|&energy| energy_to_loudness(energy) > ABSOLUTE_GATE)
+        .collect::<Vec<f64>>();
+    if above_absolute.is_empty() {
+        return None;
+    }
+    let mean = above_absolute.iter().sum::<f64>() / above_absolute.len() as f64;
+    let threshold = energy_to_loudness(mean) + relative_gate;
+    let gated = above_absolute
+        .into_iter()
+        .filter(|&energy| energy_to_loudness(energy) > threshold)
+        .collect::<Vec<f64>>();
+    (!gated.is_empty()).then_some(gated)
+}
+
+#[cfg(test)]
+#[cfg(feature = "analysis")]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    // A stereo sine at `frequency`, with a peak level of `level` dBFS.
+    fn stereo_sine(sample_rate: u32, frequency: f32, level: f32, seconds: f32) -> Vec<f32> {
+        let amplitude = 10f32.powf(level / 20.);
+        (0..(sample_rate as f32 * seconds) as usize)
+            .flat_map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                let sample = amplitude * (2. * std::f32::consts::PI * frequency * t).sin();
+                [sample, sample]
+            })
+            .collect()
+    }
+
+    // Test signals from EBU Tech 3341 and 3342.
+    #[test]
+    fn test_loudness_sine() {
+        for (sample_rate, level) in [(48000, -23.), (44100, -33.)] {
+            let mut meter = LoudnessMeter::new(sample_rate, 2).unwrap();
+            let signal = stereo_sine(sample_rate, 1000., level, 5.);
+            for chunk in signal.chunks(1001) {
+                meter.push(chunk);
+            }
+            let loudness = meter.finish().unwrap();
+            assert!(0.1 > (level - loudness.integrated).abs());
+            assert!(0.1 > loudness.range);
+            assert!(0.1 > (level - loudness.true_peak).abs());
+        }
+    }
+
+    #[test]
+    fn test_loudness_range() {
+        let mut meter = LoudnessMeter::new(48000, 2).unwrap();
+        meter.push(&stereo_sine(48000, 1000., -20., 6.));
+        meter.push(&stereo_sine(48000, 1000., -30., 6.));
+        let loudness = meter.finish().unwrap();
+        assert!(1. > (10. - loudness.range).abs());
+    }
+
+    #[test]
+    fn test_true_peak() {
+        // A sine at a quarter of the sample rate, whose samples are all
+        // 3dB under its actual peak.
+        let signal = (0..48000)
+            .map(|i| (std::f32::consts::PI / 2. * i as f32 + std::f32::consts::PI / 4.).sin() * 0.5)
+            .collect::<Vec<f32>>();
+        let mut meter = LoudnessMeter::new(48000, 1).unwrap();
+        meter.push(&signal);
+        let loudness = meter.finish().unwrap();
+        let sample_peak = 20. * signal.iter().fold(0f32, |a, b| a.max(b.abs())).log10();
+        assert!(0.1 > (sample_peak + 9.03).abs());
+        assert!(0.5 > (loudness.true_peak + 6.02).abs());
+    }
+
+    #[test]
+    fn test_loudness_too_short_or_quiet() {
+        let mut meter = LoudnessMeter::new(48000, 2).unwrap();
+        meter.push(&stereo_sine(48000, 1000., -23., 0.3));
+        assert_eq!(meter.finish(), None);
+
+        let mut meter = LoudnessMeter::new(48000, 2).unwrap();
+        meter.push(&stereo_sine(48000, 1000., -80., 1.));
+        assert_eq!(meter.finish(), None);
+
+        assert_eq!(
+            LoudnessMeter::new(48000, 0).err(),
+            Some(BlissError::AnalysisError(String::from(
+                "cannot measure the loudness of samples with 0 channels at 48000 Hz."
+            )))
+        );
+    }
+
+    #[test]
+    fn test_replay_gain() {
+        let loudness = Loudness {
+            integrated: -23.,
+            range: 5.,
+            true_peak: -6.0206,
+        };
+        assert_eq!(loudness.replay_gain(), 5.);
+        assert!(0.001 > (loudness.true_peak_amplitude() - 0.5).abs());
+    }
+}
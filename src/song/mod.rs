@@ -18,9 +18,9 @@ use crate::cue::CueInfo;
 #[cfg(feature = "analysis")]
 use crate::misc::LoudnessDesc;
 #[cfg(feature = "analysis")]
-use crate::temporal::BPMDesc;
+use crate::temporal::{BPMDesc, BeatDesc, BeatTrack, TempoEstimate};
 #[cfg(feature = "analysis")]
-use crate::timbral::{SpectralDesc, ZeroCrossingRateDesc};
+use crate::timbral::{MfccDesc, SpectralDesc, ZeroCrossingRateDesc};
 #[cfg(feature = "analysis")]
 use crate::SAMPLE_RATE;
 use crate::{BlissError, BlissResult, FeaturesVersion};
@@ -35,8 +35,46 @@ use std::time::Duration;
 use strum::IntoEnumIterator;
 use strum_macros::{EnumCount, EnumIter};
 
+mod boundaries;
+mod cancellation;
 #[cfg(feature = "analysis")]
 pub mod decoder;
+mod delivery;
+mod diagnostics;
+mod extension;
+mod fingerprint;
+mod key;
+mod loudness;
+#[cfg(feature = "analysis")]
+pub(crate) mod pcm;
+#[cfg(feature = "analysis")]
+mod streaming;
+mod tags;
+
+#[cfg(feature = "analysis")]
+use boundaries::BoundaryDesc;
+pub use boundaries::{Boundaries, TrimOptions};
+pub use cancellation::{AnalysisBudget, CancellationToken};
+pub use delivery::{AnalysisEvent, DeliveryOptions, ProgressSender};
+#[cfg(feature = "analysis")]
+pub(crate) use diagnostics::{sample_count_mismatch, DecodingIssues, SignalStats};
+pub use diagnostics::{AnalysisDiagnostics, AnalysisWarning};
+#[cfg(feature = "analysis")]
+pub(crate) use extension::ExtensionRunner;
+pub use extension::{AnalysisExtension, Descriptor, ExtensionOptions};
+pub use fingerprint::Fingerprint;
+#[cfg(feature = "analysis")]
+use fingerprint::FingerprintDesc;
+pub use key::{Key, Mode};
+pub use loudness::Loudness;
+#[cfg(feature = "analysis")]
+pub use loudness::LoudnessMeter;
+#[cfg(feature = "analysis")]
+pub use pcm::SampleLayout;
+
+#[cfg(feature = "analysis")]
+pub use streaming::StreamingAnalyzer;
+pub use tags::Tags;
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Default, Debug, PartialEq, Clone)]
@@ -73,6 +111,55 @@ pub struct Song {
     /// `path/to/cue_file.wav/CUE_TRACK00<track_number>`. Using this field,
     /// you can change `song.path` to fit your needs.
     pub cue_info: Option<CueInfo>,
+    /// The analysis of consecutive segments of the song, used for instance
+    /// to tell a quiet intro apart from a loud chorus.
+    ///
+    /// Populated only if [AnalysisOptions::segments] was set when the song
+    /// was analyzed. See [Song::analyze_segments] for more details.
+    pub segments: Option<Vec<AnalysisSegment>>,
+    /// Warnings raised while decoding and analyzing the song, e.g. if
+    /// no beats were found or if the song is mostly silent.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub diagnostics: AnalysisDiagnostics,
+    /// The song's estimated musical key, e.g. to make harmonic-mixing
+    /// playlists. `None` if it could not be estimated, or if the song
+    /// was analyzed before keys were estimated.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub key: Option<Key>,
+    /// The song's loudness, measured while decoding it, e.g. to normalize
+    /// its volume using [Loudness::replay_gain]. `None` if the song is too
+    /// short or too quiet to be measured, if it was extracted from a CUE
+    /// sheet, or if its decoder doesn't measure loudness.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub loudness: Option<Loudness>,
+    /// Where the song's audio starts and ends once its leading and
+    /// trailing silence are left out, and where its fade-out starts, e.g.
+    /// for gapless playback or crossfading. `None` if the song is silent,
+    /// or if it was analyzed before boundaries were detected.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub boundaries: Option<Boundaries>,
+    /// A compact acoustic fingerprint of the beginning of the song, to
+    /// find duplicates across albums (remasters, compilations...) using
+    /// [Fingerprint::matches]. `None` if the song is silent or too short,
+    /// or if it was analyzed before fingerprints were computed.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fingerprint: Option<Fingerprint>,
+    /// The song's other tags, such as its year, its composer or its
+    /// MusicBrainz identifiers, read from the metadata.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tags: Tags,
+}
+
+/// Everything computed while analyzing a song's samples, besides
+/// per-segment analysis.
+#[cfg(feature = "analysis")]
+#[derive(Debug)]
+pub(crate) struct AnalysisOutput {
+    pub analysis: Analysis,
+    pub diagnostics: AnalysisDiagnostics,
+    pub key: Option<Key>,
+    pub boundaries: Option<Boundaries>,
+    pub fingerprint: Option<Fingerprint>,
 }
 
 impl AsRef<Song> for Song {
@@ -101,7 +188,7 @@ impl AsRef<Song> for Song {
 /// customization purposes.
 #[derive(Debug, EnumIter, EnumCount)]
 pub enum AnalysisIndex {
-    /// The song's tempo.
+    /// The song's tempo, corrected for half / double tempo errors.
     Tempo,
     /// The song's zero-crossing rate.
     Zcr,
@@ -153,6 +240,81 @@ pub enum AnalysisIndex {
     Chroma12,
     /// The ratio of the L2-norm of IC7-10 and IC1-6 (proportion of triads vs dyads).
     Chroma13,
+    /// The mean of the song's 1st mel-frequency cepstral coefficient (MFCC).
+    /// The 0th coefficient, which mostly reflects the song's loudness, is
+    /// left out.
+    MeanMfcc1,
+    /// The standard deviation of the song's 1st mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc1,
+    /// The mean of the song's 2nd mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc2,
+    /// The standard deviation of the song's 2nd mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc2,
+    /// The mean of the song's 3rd mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc3,
+    /// The standard deviation of the song's 3rd mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc3,
+    /// The mean of the song's 4th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc4,
+    /// The standard deviation of the song's 4th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc4,
+    /// The mean of the song's 5th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc5,
+    /// The standard deviation of the song's 5th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc5,
+    /// The mean of the song's 6th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc6,
+    /// The standard deviation of the song's 6th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc6,
+    /// The mean of the song's 7th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc7,
+    /// The standard deviation of the song's 7th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc7,
+    /// The mean of the song's 8th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc8,
+    /// The standard deviation of the song's 8th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc8,
+    /// The mean of the song's 9th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc9,
+    /// The standard deviation of the song's 9th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc9,
+    /// The mean of the song's 10th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc10,
+    /// The standard deviation of the song's 10th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc10,
+    /// The mean of the song's 11th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc11,
+    /// The standard deviation of the song's 11th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc11,
+    /// The mean of the song's 12th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc12,
+    /// The standard deviation of the song's 12th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc12,
+    /// The mean of the song's 13th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc13,
+    /// The standard deviation of the song's 13th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc13,
+    /// How clearly the song's pulse stands out, from the autocorrelation
+    /// of its onset strength at the most salient beat period.
+    PulseClarity,
+    /// How much the strength of the song's beats varies from one beat to
+    /// another (coefficient of variation).
+    BeatStrengthVariance,
+    /// The number of onsets per second in the song.
+    OnsetDensity,
 }
 
 impl AnalysisIndex {
@@ -160,6 +322,195 @@ impl AnalysisIndex {
     pub const FEATURES_VERSION: FeaturesVersion = FeaturesVersion::LATEST;
 }
 
+#[derive(Debug, EnumIter, EnumCount)]
+pub enum AnalysisIndexv3 {
+    /// The song's tempo, corrected for half / double tempo errors.
+    Tempo,
+    /// The song's zero-crossing rate.
+    Zcr,
+    /// The mean of the song's spectral centroid.
+    MeanSpectralCentroid,
+    /// The standard deviation of the song's spectral centroid.
+    StdDeviationSpectralCentroid,
+    /// The mean of the song's spectral rolloff.
+    MeanSpectralRolloff,
+    /// The standard deviation of the song's spectral rolloff.
+    StdDeviationSpectralRolloff,
+    /// The mean of the song's spectral flatness.
+    MeanSpectralFlatness,
+    /// The standard deviation of the song's spectral flatness.
+    StdDeviationSpectralFlatness,
+    /// The mean of the song's loudness.
+    MeanLoudness,
+    /// The standard deviation of the song's loudness.
+    StdDeviationLoudness,
+    /// The proportion of pitch class set 1 (IC1) compared to the 6 other pitch class sets,
+    /// per this paper <https://speech.di.uoa.gr/ICMC-SMC-2014/images/VOL_2/1461.pdf>
+    Chroma1,
+    /// The proportion of pitch class set 2 (IC2) compared to the 6 other pitch class sets,
+    /// per this paper <https://speech.di.uoa.gr/ICMC-SMC-2014/images/VOL_2/1461.pdf>
+    Chroma2,
+    /// The proportion of pitch class set 3 (IC3) compared to the 6 other pitch class sets,
+    /// per this paper <https://speech.di.uoa.gr/ICMC-SMC-2014/images/VOL_2/1461.pdf>
+    Chroma3,
+    /// The proportion of pitch class set 4 (IC4) compared to the 6 other pitch class sets,
+    /// per this paper <https://speech.di.uoa.gr/ICMC-SMC-2014/images/VOL_2/1461.pdf>
+    Chroma4,
+    /// The proportion of pitch class set 5 (IC5) compared to the 6 other pitch class sets,
+    /// per this paper <https://speech.di.uoa.gr/ICMC-SMC-2014/images/VOL_2/1461.pdf>
+    Chroma5,
+    /// The proportion of pitch class set 6 (IC6) compared to the 6 other pitch class sets,
+    /// per this paper <https://speech.di.uoa.gr/ICMC-SMC-2014/images/VOL_2/1461.pdf>
+    Chroma6,
+    /// The proportion of major triads in the song, compared to the other triads.
+    Chroma7,
+    /// The proportion of minor triads in the song, compared to the other triads.
+    Chroma8,
+    /// The proportion of diminished triads in the song, compared to the other triads.
+    Chroma9,
+    /// The proportion of augmented triads in the song, compared to the other triads.
+    Chroma10,
+    /// The L2-norm of the IC1-6 (see above).
+    Chroma11,
+    /// The L2-norm of the IC7-10 (see above).
+    Chroma12,
+    /// The ratio of the L2-norm of IC7-10 and IC1-6 (proportion of triads vs dyads).
+    Chroma13,
+    /// The mean of the song's 1st mel-frequency cepstral coefficient (MFCC).
+    /// The 0th coefficient, which mostly reflects the song's loudness, is
+    /// left out.
+    MeanMfcc1,
+    /// The standard deviation of the song's 1st mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc1,
+    /// The mean of the song's 2nd mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc2,
+    /// The standard deviation of the song's 2nd mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc2,
+    /// The mean of the song's 3rd mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc3,
+    /// The standard deviation of the song's 3rd mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc3,
+    /// The mean of the song's 4th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc4,
+    /// The standard deviation of the song's 4th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc4,
+    /// The mean of the song's 5th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc5,
+    /// The standard deviation of the song's 5th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc5,
+    /// The mean of the song's 6th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc6,
+    /// The standard deviation of the song's 6th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc6,
+    /// The mean of the song's 7th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc7,
+    /// The standard deviation of the song's 7th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc7,
+    /// The mean of the song's 8th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc8,
+    /// The standard deviation of the song's 8th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc8,
+    /// The mean of the song's 9th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc9,
+    /// The standard deviation of the song's 9th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc9,
+    /// The mean of the song's 10th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc10,
+    /// The standard deviation of the song's 10th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc10,
+    /// The mean of the song's 11th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc11,
+    /// The standard deviation of the song's 11th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc11,
+    /// The mean of the song's 12th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc12,
+    /// The standard deviation of the song's 12th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc12,
+    /// The mean of the song's 13th mel-frequency cepstral coefficient (MFCC).
+    MeanMfcc13,
+    /// The standard deviation of the song's 13th mel-frequency cepstral
+    /// coefficient (MFCC).
+    StdDeviationMfcc13,
+}
+
+impl AnalysisIndexv3 {
+    /// The features version associated with this analysis index.
+    pub const FEATURES_VERSION: FeaturesVersion = FeaturesVersion::Version3;
+}
+
+#[derive(Debug, EnumIter, EnumCount)]
+pub enum AnalysisIndexv2 {
+    /// The song's tempo.
+    Tempo,
+    /// The song's zero-crossing rate.
+    Zcr,
+    /// The mean of the song's spectral centroid.
+    MeanSpectralCentroid,
+    /// The standard deviation of the song's spectral centroid.
+    StdDeviationSpectralCentroid,
+    /// The mean of the song's spectral rolloff.
+    MeanSpectralRolloff,
+    /// The standard deviation of the song's spectral rolloff.
+    StdDeviationSpectralRolloff,
+    /// The mean of the song's spectral flatness.
+    MeanSpectralFlatness,
+    /// The standard deviation of the song's spectral flatness.
+    StdDeviationSpectralFlatness,
+    /// The mean of the song's loudness.
+    MeanLoudness,
+    /// The standard deviation of the song's loudness.
+    StdDeviationLoudness,
+    /// The proportion of pitch class set 1 (IC1) compared to the 6 other pitch class sets,
+    /// per this paper <https://speech.di.uoa.gr/ICMC-SMC-2014/images/VOL_2/1461.pdf>
+    Chroma1,
+    /// The proportion of pitch class set 2 (IC2) compared to the 6 other pitch class sets,
+    /// per this paper <https://speech.di.uoa.gr/ICMC-SMC-2014/images/VOL_2/1461.pdf>
+    Chroma2,
+    /// The proportion of pitch class set 3 (IC3) compared to the 6 other pitch class sets,
+    /// per this paper <https://speech.di.uoa.gr/ICMC-SMC-2014/images/VOL_2/1461.pdf>
+    Chroma3,
+    /// The proportion of pitch class set 4 (IC4) compared to the 6 other pitch class sets,
+    /// per this paper <https://speech.di.uoa.gr/ICMC-SMC-2014/images/VOL_2/1461.pdf>
+    Chroma4,
+    /// The proportion of pitch class set 5 (IC5) compared to the 6 other pitch class sets,
+    /// per this paper <https://speech.di.uoa.gr/ICMC-SMC-2014/images/VOL_2/1461.pdf>
+    Chroma5,
+    /// The proportion of pitch class set 6 (IC6) compared to the 6 other pitch class sets,
+    /// per this paper <https://speech.di.uoa.gr/ICMC-SMC-2014/images/VOL_2/1461.pdf>
+    Chroma6,
+    /// The proportion of major triads in the song, compared to the other triads.
+    Chroma7,
+    /// The proportion of minor triads in the song, compared to the other triads.
+    Chroma8,
+    /// The proportion of diminished triads in the song, compared to the other triads.
+    Chroma9,
+    /// The proportion of augmented triads in the song, compared to the other triads.
+    Chroma10,
+    /// The L2-norm of the IC1-6 (see above).
+    Chroma11,
+    /// The L2-norm of the IC7-10 (see above).
+    Chroma12,
+    /// The ratio of the L2-norm of IC7-10 and IC1-6 (proportion of triads vs dyads).
+    Chroma13,
+}
+
+impl AnalysisIndexv2 {
+    /// The features version associated with this analysis index.
+    pub const FEATURES_VERSION: FeaturesVersion = FeaturesVersion::Version2;
+}
+
 #[derive(Debug, EnumIter, EnumCount)]
 pub enum AnalysisIndexv1 {
     /// The song's tempo.
@@ -243,10 +594,140 @@ pub struct Analysis {
     /// It is bumped every time a change is introduced in the
     /// features that makes them incompatible with previous versions.
     pub features_version: FeaturesVersion,
+    /// The features computed by custom descriptors, if any were registered
+    /// in [AnalysisOptions::extensions].
+    ///
+    /// They are versioned separately from bliss' own features, and are not
+    /// taken into account by the distance metrics.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub extension: Option<AnalysisExtension>,
+    /// Whether only an excerpt of the song was analyzed, because
+    /// [AnalysisOptions::excerpt] was set. Such an analysis is less
+    /// accurate than the analysis of the whole song, and can be upgraded
+    /// later on, e.g. using
+    /// [Library::upgrade_partial_songs](crate::library::Library::upgrade_partial_songs).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub partial: bool,
+    /// The median confidence of the beat tracker in the song's tempo,
+    /// between 0 and 1, see
+    /// [TempoEstimate::confidence](crate::TempoEstimate::confidence).
+    /// `None` for features versions older than [FeaturesVersion::Version3],
+    /// or if the song was analyzed before it was stored.
+    ///
+    /// The tempo feature is scaled by it in [Analysis::as_arr1], so that the
+    /// distance metrics give less weight to tempos the beat tracker is
+    /// unsure of.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tempo_confidence: Option<f32>,
+}
+
+/// The analysis of a segment of a song, computed by
+/// [Song::analyze_segments].
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct AnalysisSegment {
+    /// The timestamp at which the segment starts, from the beginning of
+    /// the song.
+    pub start: Duration,
+    /// The timestamp at which the segment ends, from the beginning of
+    /// the song.
+    pub end: Duration,
+    /// The analysis of the segment, computed the same way a whole song's
+    /// analysis is.
+    pub analysis: Analysis,
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+/// How a song should be split in segments, to compute per-segment
+/// analysis. See [Song::analyze_segments].
+pub struct SegmentOptions {
+    /// The length of each segment.
+    pub segment_length: Duration,
+    /// The duration between the start of two consecutive segments.
+    /// Segments overlap if it is smaller than `segment_length`.
+    pub hop: Duration,
+}
+
+impl Default for SegmentOptions {
+    fn default() -> Self {
+        SegmentOptions {
+            segment_length: Duration::from_secs(10),
+            hop: Duration::from_secs(5),
+        }
+    }
 }
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
+/// Which excerpt of a song should be analyzed instead of the whole song,
+/// to quickly get a first analysis of a large library.
+/// See [AnalysisOptions::excerpt].
+///
+/// Songs shorter than the excerpt are analyzed as a whole.
+pub enum ExcerptOptions {
+    /// Analyze `number_windows` windows of `window_length` each, spread
+    /// evenly across the song, as if they were one continuous excerpt.
+    Windows {
+        /// The number of windows to analyze.
+        number_windows: usize,
+        /// The length of each window.
+        window_length: Duration,
+    },
+    /// Analyze the given duration in the middle of the song.
+    Middle(Duration),
+}
+
+impl Default for ExcerptOptions {
+    fn default() -> Self {
+        ExcerptOptions::Windows {
+            number_windows: 3,
+            window_length: Duration::from_secs(20),
+        }
+    }
+}
+
+#[cfg(feature = "analysis")]
+impl ExcerptOptions {
+    /// The samples of `sample_array` that should be analyzed, or `None` if
+    /// the excerpt would cover the whole song.
+    pub(crate) fn excerpt(&self, sample_array: &[f32]) -> BlissResult<Option<Vec<f32>>> {
+        let (number_windows, window_length) = match *self {
+            ExcerptOptions::Windows {
+                number_windows,
+                window_length,
+            } => (number_windows, window_length),
+            ExcerptOptions::Middle(length) => (1, length),
+        };
+        if number_windows == 0 {
+            return Err(BlissError::AnalysisError(String::from(
+                "in excerpt: the number of windows should not be zero.",
+            )));
+        }
+        let window_length = (window_length.as_secs_f64() * SAMPLE_RATE as f64).round() as usize;
+        if window_length < largest_window() {
+            return Err(BlissError::AnalysisError(String::from(
+                "in excerpt: the windows are too short to be analyzed.",
+            )));
+        }
+        if number_windows.saturating_mul(window_length) >= sample_array.len() {
+            return Ok(None);
+        }
+        // Each window is centered in its share of the song.
+        let share = sample_array.len() / number_windows;
+        let excerpt = (0..number_windows)
+            .flat_map(|i| {
+                let start = i * share + (share - window_length) / 2;
+                &sample_array[start..start + window_length]
+            })
+            .copied()
+            .collect();
+        Ok(Some(excerpt))
+    }
+}
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(PartialEq, Eq, Debug, Clone)]
 /// Various options bliss should be aware of while performing the analysis
 /// of a song.
 pub struct AnalysisOptions {
@@ -254,8 +735,82 @@ pub struct AnalysisOptions {
     /// Should be kept as the default [FeaturesVersion::LATEST](crate::FeaturesVersion::LATEST).
     pub features_version: FeaturesVersion,
     /// The number of computer cores that should be used when performing the
-    /// analysis of multiple songs.
+    /// analysis of multiple songs. It caps the total number of threads used,
+    /// including the ones computing each song's descriptors.
     pub number_cores: NonZeroUsize,
+    /// If set, also analyze each song segment by segment, and store the
+    /// results in [Song::segments]. Disabled by default, since it makes
+    /// the analysis several times slower.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub segments: Option<SegmentOptions>,
+    /// Custom descriptors to run alongside bliss' own descriptors, whose
+    /// features are stored in [Analysis::extension].
+    ///
+    /// Since they can't be serialized, they are not saved in a library's
+    /// configuration file, and have to be passed to each analysis.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub extensions: Option<ExtensionOptions>,
+    /// If set, only analyze an excerpt of each song, marking the resulting
+    /// analysis as [partial](Analysis::partial). Makes a first analysis of
+    /// a large library much faster, at the cost of accuracy.
+    ///
+    /// Segments (see [AnalysisOptions::segments]) are still computed on the
+    /// whole song, and the [StreamingAnalyzer] always analyzes whole songs.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub excerpt: Option<ExcerptOptions>,
+    /// If set, leave the leading and trailing silence of each song out of
+    /// its analysis, and possibly its fade-out, so that they don't drag
+    /// its loudness and spectral features down. See [Song::boundaries]
+    /// for the detected boundaries, which are computed either way.
+    ///
+    /// The [StreamingAnalyzer] detects the boundaries too, but always
+    /// analyzes whole songs.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub trim: Option<TrimOptions>,
+    /// If set, cancel the analysis once [CancellationToken::cancel] is
+    /// called. Songs being analyzed at that time come back as
+    /// [Cancelled](BlissError::Cancelled) errors, and the songs that weren't
+    /// started yet are not analyzed at all.
+    ///
+    /// Since it can't be serialized, it is not saved in a library's
+    /// configuration file, and has to be passed to each analysis.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub cancellation: Option<CancellationToken>,
+    /// If set, give up on songs whose decoding and analysis take longer
+    /// than this, e.g. very long recordings, or corrupted files that
+    /// make the decoder spin. These songs come back as
+    /// [TimedOut](BlissError::TimedOut) errors.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub timeout: Option<Duration>,
+    /// How bulk analyses, such as
+    /// [Decoder::analyze_paths](crate::decoder::Decoder::analyze_paths),
+    /// hand out the songs they analyze. If not set, they are handed out
+    /// as soon as they are analyzed, without limiting how many of them
+    /// can wait to be consumed.
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub delivery: Option<DeliveryOptions>,
+    /// If set, bulk analyses send [AnalysisEvent]s there as they decode
+    /// and analyze each song, e.g. to display their progress.
+    ///
+    /// Since it can't be serialized, it is not saved in a library's
+    /// configuration file, and has to be passed to each analysis.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub progress: Option<ProgressSender>,
 }
 
 impl Default for AnalysisOptions {
@@ -264,6 +819,14 @@ impl Default for AnalysisOptions {
         AnalysisOptions {
             features_version: FeaturesVersion::LATEST,
             number_cores: cores,
+            segments: None,
+            extensions: None,
+            excerpt: None,
+            trim: None,
+            cancellation: None,
+            timeout: None,
+            delivery: None,
+            progress: None,
         }
     }
 }
@@ -280,6 +843,28 @@ impl Index<AnalysisIndex> for Analysis {
     }
 }
 
+impl Index<AnalysisIndexv3> for Analysis {
+    type Output = f32;
+
+    fn index(&self, index: AnalysisIndexv3) -> &f32 {
+        if self.features_version != AnalysisIndexv3::FEATURES_VERSION {
+            panic!("Tried to index features with incompatible indexes");
+        }
+        &self.internal_analysis[index as usize]
+    }
+}
+
+impl Index<AnalysisIndexv2> for Analysis {
+    type Output = f32;
+
+    fn index(&self, index: AnalysisIndexv2) -> &f32 {
+        if self.features_version != AnalysisIndexv2::FEATURES_VERSION {
+            panic!("Tried to index features with incompatible indexes");
+        }
+        &self.internal_analysis[index as usize]
+    }
+}
+
 impl Index<AnalysisIndexv1> for Analysis {
     type Output = f32;
 
@@ -301,16 +886,38 @@ impl fmt::Debug for Analysis {
         let mut debug_struct = f.debug_struct(&format!("Analysis (Version {version})"));
         // If all is good, keep on printing.
         if self.features_version.feature_count() == self.internal_analysis.len() {
-            if self.features_version == FeaturesVersion::Version1 {
-                for feature in AnalysisIndexv1::iter() {
-                    debug_struct.field(&format!("{feature:?}"), &self[feature]);
+            match self.features_version {
+                FeaturesVersion::Version1 => {
+                    for feature in AnalysisIndexv1::iter() {
+                        debug_struct.field(&format!("{feature:?}"), &self[feature]);
+                    }
                 }
-            } else {
-                for feature in AnalysisIndex::iter() {
-                    debug_struct.field(&format!("{feature:?}"), &self[feature]);
+                FeaturesVersion::Version2 => {
+                    for feature in AnalysisIndexv2::iter() {
+                        debug_struct.field(&format!("{feature:?}"), &self[feature]);
+                    }
+                }
+                FeaturesVersion::Version3 => {
+                    for feature in AnalysisIndexv3::iter() {
+                        debug_struct.field(&format!("{feature:?}"), &self[feature]);
+                    }
+                }
+                FeaturesVersion::Version4 => {
+                    for feature in AnalysisIndex::iter() {
+                        debug_struct.field(&format!("{feature:?}"), &self[feature]);
+                    }
                 }
             }
         }
+        if let Some(extension) = &self.extension {
+            debug_struct.field("extension", extension);
+        }
+        if self.partial {
+            debug_struct.field("partial", &self.partial);
+        }
+        if let Some(tempo_confidence) = self.tempo_confidence {
+            debug_struct.field("tempo_confidence", &tempo_confidence);
+        }
 
         debug_struct.finish()?;
         f.write_str(&format!(" /* {:?} */", self.as_vec()))
@@ -334,14 +941,28 @@ impl Analysis {
         Ok(Analysis {
             internal_analysis: analysis,
             features_version,
+            extension: None,
+            partial: false,
+            tempo_confidence: None,
         })
     }
 
     /// Return an ndarray `Array1` representing the analysis' features.
     ///
     /// Particularly useful if you want to make a custom distance metric.
+    ///
+    /// From [FeaturesVersion::Version3] on, the tempo feature is scaled by
+    /// the [tempo confidence](Analysis::tempo_confidence), if known, which
+    /// brings unreliable tempos closer to the middle of the tempo range.
+    /// Use [as_vec](Analysis::as_vec) to get the unscaled features.
     pub fn as_arr1(&self) -> Array1<f32> {
-        arr1(&self.internal_analysis)
+        let mut features = arr1(&self.internal_analysis);
+        if let Some(tempo_confidence) = self.tempo_confidence {
+            if self.features_version >= FeaturesVersion::Version3 {
+                features[AnalysisIndex::Tempo as usize] *= tempo_confidence;
+            }
+        }
+        features
     }
 
     /// Return a `Vec<f32>` representing the analysis' features.
@@ -370,6 +991,22 @@ impl Analysis {
     }
 }
 
+/// Size of the largest window used by the descriptors, i.e. the minimum
+/// number of samples a song needs to have to be analyzed.
+#[cfg(feature = "analysis")]
+fn largest_window() -> usize {
+    [
+        BPMDesc::WINDOW_SIZE,
+        ChromaDesc::WINDOW_SIZE,
+        SpectralDesc::WINDOW_SIZE,
+        LoudnessDesc::WINDOW_SIZE,
+        MfccDesc::WINDOW_SIZE,
+    ]
+    .into_iter()
+    .max()
+    .unwrap()
+}
+
 impl Song {
     /**
      * Analyze a song decoded in `sample_array`. This function should NOT
@@ -378,13 +1015,9 @@ impl Song {
      * [Decoder::song_from_path](crate::decoder::Decoder::song_from_path)
      * instead to just analyze a file from its path.
      *
-     * The current implementation doesn't make use of it,
-     * but the song can also be streamed wrt.
-     * each descriptor (with the exception of the chroma descriptor which
-     * yields worse results when streamed).
-     *
-     * Useful in the rare cases where the full song is not
-     * completely available.
+     * If the full song is not available at once, or is too big to be kept
+     * in memory (e.g. long DJ mixes), see [StreamingAnalyzer], which can
+     * be fed the samples chunk by chunk.
      *
      * If you *do* want to use this with a song already decoded by yourself,
      * the sample format of `sample_array` should be f32le, one channel, and
@@ -404,6 +1037,63 @@ impl Song {
         Self::analyze_with_options(sample_array, &AnalysisOptions::default())
     }
 
+    /// Analyze raw samples, at any sample rate and with any number of
+    /// channels, e.g. samples already decoded by an audio player for
+    /// playback.
+    ///
+    /// The samples are downmixed to one channel and resampled to 22050 Hz
+    /// the same way the symphonia decoder does, before being analyzed
+    /// with [Song::analyze]. `samples` holds the samples of all the
+    /// `channels` channels, laid out as `layout`.
+    ///
+    /// * Example:
+    /// ```
+    /// use bliss_audio::{BlissResult, SampleLayout, Song};
+    ///
+    /// fn main() -> BlissResult<()> {
+    ///     // Five seconds of a stereo A4 at 44100 Hz, as a player would
+    ///     // decode it.
+    ///     let samples = (0..44100 * 5)
+    ///         .flat_map(|i| {
+    ///             let sample = (2. * std::f32::consts::PI * 440. * i as f32 / 44100.).sin();
+    ///             [sample, sample]
+    ///         })
+    ///         .collect::<Vec<f32>>();
+    ///     let analysis = Song::analyze_pcm(&samples, 44100, 2, SampleLayout::Interleaved)?;
+    ///     println!("{analysis:?}");
+    ///     Ok(())
+    /// }
+    /// ```
+    #[cfg(feature = "analysis")]
+    pub fn analyze_pcm(
+        samples: &[f32],
+        sample_rate: u32,
+        channels: u16,
+        layout: SampleLayout,
+    ) -> BlissResult<Analysis> {
+        Self::analyze_pcm_with_options(
+            samples,
+            sample_rate,
+            channels,
+            layout,
+            &AnalysisOptions::default(),
+        )
+    }
+
+    /// Same as [Song::analyze_pcm], but allows to use custom analysis
+    /// options, e.g. to use an old features version.
+    #[cfg(feature = "analysis")]
+    pub fn analyze_pcm_with_options(
+        samples: &[f32],
+        sample_rate: u32,
+        channels: u16,
+        layout: SampleLayout,
+        analysis_options: &AnalysisOptions,
+    ) -> BlissResult<Analysis> {
+        let sample_array = pcm::to_bliss_samples(samples, sample_rate, channels, layout)?;
+        Self::analyze_with_options(&sample_array, analysis_options)
+    }
+
     /**
      * This function is the same as [Song::analyze], but allows to compute an
      * analysis using old features_version. Do not use, unless for backwards
@@ -414,97 +1104,435 @@ impl Song {
         sample_array: &[f32],
         analysis_options: &AnalysisOptions,
     ) -> BlissResult<Analysis> {
-        let largest_window = vec![
-            BPMDesc::WINDOW_SIZE,
-            ChromaDesc::WINDOW_SIZE,
-            SpectralDesc::WINDOW_SIZE,
-            LoudnessDesc::WINDOW_SIZE,
-        ]
-        .into_iter()
-        .max()
-        .unwrap();
-        if sample_array.len() < largest_window {
+        Self::analyze_with_diagnostics(sample_array, analysis_options).map(|(analysis, _)| analysis)
+    }
+
+    /**
+     * Same as [Song::analyze_with_options], but also returns the
+     * [AnalysisDiagnostics] gathered during the analysis, e.g. whether
+     * beats were found, or whether the song is mostly silent or clipped.
+     **/
+    #[cfg(feature = "analysis")]
+    pub fn analyze_with_diagnostics(
+        sample_array: &[f32],
+        analysis_options: &AnalysisOptions,
+    ) -> BlissResult<(Analysis, AnalysisDiagnostics)> {
+        let budget = AnalysisBudget::new(analysis_options);
+        Self::analyze_output(sample_array, analysis_options, &budget)
+            .map(|output| (output.analysis, output.diagnostics))
+    }
+
+    /// Analyze `sample_array`, returning everything that is computed along
+    /// the way (diagnostics, key...).
+    ///
+    /// `budget` is checked regularly by the descriptors, which stop as soon
+    /// as the analysis is cancelled or times out.
+    #[cfg(feature = "analysis")]
+    pub(crate) fn analyze_output(
+        sample_array: &[f32],
+        analysis_options: &AnalysisOptions,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<AnalysisOutput> {
+        budget.check()?;
+        if sample_array.len() < largest_window() {
             return Err(BlissError::AnalysisError(String::from(
                 "empty or too short song.",
             )));
         }
 
-        thread::scope(|s| -> BlissResult<Analysis> {
-            let child_tempo = s.spawn(|| -> BlissResult<f32> {
-                let mut tempo_desc = BPMDesc::new(SAMPLE_RATE)?;
-                let windows = sample_array
-                    .windows(BPMDesc::WINDOW_SIZE)
-                    .step_by(BPMDesc::HOP_SIZE);
-
-                for window in windows {
-                    tempo_desc.do_(window)?;
-                }
-                Ok(tempo_desc.get_value())
-            });
-
-            let child_chroma = s.spawn(|| -> BlissResult<Vec<f32>> {
-                let mut chroma_desc = ChromaDesc::new(SAMPLE_RATE, 12);
-                chroma_desc.do_(sample_array)?;
-                if analysis_options.features_version == FeaturesVersion::Version1 {
-                    Ok(chroma_desc.get_values_version_1()?)
+        let mut boundary_desc = BoundaryDesc::default();
+        boundary_desc.do_(sample_array);
+        let boundaries = boundary_desc.get_value();
+        // The fingerprint is always computed on the whole song, so that it
+        // doesn't depend on the analysis options.
+        let whole_song = sample_array;
+        let sample_array = match (analysis_options.trim, boundaries) {
+            (Some(trim), Some(boundaries)) => {
+                let trimmed = &sample_array[boundaries.trimmed_range(trim)];
+                // Songs made of little more than silence are analyzed whole.
+                if trimmed.len() < largest_window() {
+                    sample_array
                 } else {
-                    Ok(chroma_desc.get_values()?)
+                    trimmed
                 }
-            });
+            }
+            _ => sample_array,
+        };
 
-            #[allow(clippy::type_complexity)]
-            let child_timbral = s.spawn(|| -> BlissResult<(Vec<f32>, Vec<f32>, Vec<f32>)> {
-                let mut spectral_desc = SpectralDesc::new(SAMPLE_RATE)?;
-                let windows = sample_array
-                    .windows(SpectralDesc::WINDOW_SIZE)
-                    .step_by(SpectralDesc::HOP_SIZE);
-                for window in windows {
-                    spectral_desc.do_(window)?;
-                }
-                let centroid = spectral_desc.get_centroid();
-                let rolloff = spectral_desc.get_rolloff();
-                let flatness = spectral_desc.get_flatness();
-                Ok((centroid, rolloff, flatness))
-            });
+        let excerpt = analysis_options
+            .excerpt
+            .map(|excerpt| excerpt.excerpt(sample_array))
+            .transpose()?
+            .flatten();
+        let sample_array = excerpt.as_deref().unwrap_or(sample_array);
+
+        let child_tempo = || -> BlissResult<(f32, Vec<f32>, bool, f32)> {
+            let mut tempo_desc = BPMDesc::new(SAMPLE_RATE)?;
+            let windows = sample_array
+                .windows(BPMDesc::WINDOW_SIZE)
+                .step_by(BPMDesc::HOP_SIZE);
+
+            for window in windows {
+                budget.check()?;
+                tempo_desc.do_(window)?;
+            }
+            let tempo = if analysis_options.features_version >= FeaturesVersion::Version3 {
+                tempo_desc.get_corrected_value()
+            } else {
+                tempo_desc.get_value()
+            };
+            let rhythm = if analysis_options.features_version >= FeaturesVersion::Version4 {
+                tempo_desc.get_rhythm_values()
+            } else {
+                Vec::new()
+            };
+            Ok((
+                tempo,
+                rhythm,
+                tempo_desc.found_beats(),
+                tempo_desc.get_confidence(),
+            ))
+        };
 
-            let child_zcr = s.spawn(|| -> BlissResult<f32> {
-                let mut zcr_desc = ZeroCrossingRateDesc::default();
-                zcr_desc.do_(sample_array);
-                Ok(zcr_desc.get_value())
-            });
+        let child_chroma = || -> BlissResult<(Vec<f32>, Option<Key>)> {
+            let mut chroma_desc = ChromaDesc::new(SAMPLE_RATE, 12);
+            // The chroma is computed on the whole song at once, so the
+            // budget can only be checked once it's done.
+            chroma_desc.do_(sample_array)?;
+            budget.check()?;
+            let values = if analysis_options.features_version == FeaturesVersion::Version1 {
+                chroma_desc.get_values_version_1()?
+            } else {
+                chroma_desc.get_values()?
+            };
+            Ok((values, chroma_desc.get_key()?))
+        };
 
-            let child_loudness = s.spawn(|| -> BlissResult<Vec<f32>> {
-                let mut loudness_desc = LoudnessDesc::default();
-                let windows = sample_array.chunks(LoudnessDesc::WINDOW_SIZE);
+        #[allow(clippy::type_complexity)]
+        let child_timbral = || -> BlissResult<(Vec<f32>, Vec<f32>, Vec<f32>)> {
+            let mut spectral_desc = SpectralDesc::new(SAMPLE_RATE)?;
+            let windows = sample_array
+                .windows(SpectralDesc::WINDOW_SIZE)
+                .step_by(SpectralDesc::HOP_SIZE);
+            for window in windows {
+                budget.check()?;
+                spectral_desc.do_(window)?;
+            }
+            let centroid = spectral_desc.get_centroid();
+            let rolloff = spectral_desc.get_rolloff();
+            let flatness = spectral_desc.get_flatness();
+            Ok((centroid, rolloff, flatness))
+        };
 
-                for window in windows {
-                    loudness_desc.do_(window);
-                }
-                Ok(loudness_desc.get_value())
-            });
+        let child_zcr = || -> BlissResult<(f32, SignalStats)> {
+            let mut zcr_desc = ZeroCrossingRateDesc::default();
+            zcr_desc.do_(sample_array);
+            let mut signal_stats = SignalStats::default();
+            signal_stats.do_(sample_array);
+            Ok((zcr_desc.get_value(), signal_stats))
+        };
 
-            // Non-streaming approach for that one
-            let tempo = child_tempo.join().unwrap()?;
-            let chroma = child_chroma.join().unwrap()?;
-            let (centroid, rolloff, flatness) = child_timbral.join().unwrap()?;
-            let loudness = child_loudness.join().unwrap()?;
-            let zcr = child_zcr.join().unwrap()?;
-
-            let mut result = vec![tempo, zcr];
-            result.extend_from_slice(&centroid);
-            result.extend_from_slice(&rolloff);
-            result.extend_from_slice(&flatness);
-            result.extend_from_slice(&loudness);
-            result.extend_from_slice(&chroma);
-            if result.len() != analysis_options.features_version.feature_count() {
-                return Err(BlissError::AnalysisError(
-                    "Too many or too little features were provided at the end of
-                        the analysis."
-                        .to_string(),
-                ));
-            };
-            Analysis::new(result, analysis_options.features_version)
-        })
+        let child_mfcc = || -> BlissResult<Vec<f32>> {
+            if analysis_options.features_version < FeaturesVersion::Version3 {
+                return Ok(Vec::new());
+            }
+            let mut mfcc_desc = MfccDesc::new(SAMPLE_RATE);
+            mfcc_desc.do_(sample_array)?;
+            budget.check()?;
+            mfcc_desc.get_values()
+        };
+
+        let child_extension = || -> BlissResult<Option<AnalysisExtension>> {
+            analysis_options
+                .extensions
+                .as_ref()
+                .map(|extension_options| {
+                    let mut runner = ExtensionRunner::new(extension_options);
+                    runner.do_(sample_array)?;
+                    budget.check()?;
+                    runner.finish()
+                })
+                .transpose()
+        };
+
+        let child_fingerprint = || -> BlissResult<Option<Fingerprint>> {
+            let mut fingerprint_desc = FingerprintDesc::new()?;
+            fingerprint_desc.do_(whole_song)?;
+            budget.check()?;
+            fingerprint_desc.get_value()
+        };
+
+        let child_loudness = || -> BlissResult<Vec<f32>> {
+            let mut loudness_desc = LoudnessDesc::default();
+            let windows = sample_array.chunks(LoudnessDesc::WINDOW_SIZE);
+
+            for window in windows {
+                budget.check()?;
+                loudness_desc.do_(window);
+            }
+            Ok(loudness_desc.get_value())
+        };
+
+        // The descriptors run as tasks of the current rayon thread pool, so
+        // that analyzing several songs at once (see
+        // [Decoder::analyze_paths](crate::decoder::Decoder::analyze_paths))
+        // doesn't spawn more threads than there are cores.
+        let (mut tempo, mut chroma, mut timbral, mut loudness) = (None, None, None, None);
+        let (mut zcr, mut mfcc, mut extension, mut fingerprint) = (None, None, None, None);
+        rayon::scope(|s| {
+            s.spawn(|_| tempo = Some(child_tempo()));
+            s.spawn(|_| chroma = Some(child_chroma()));
+            s.spawn(|_| timbral = Some(child_timbral()));
+            s.spawn(|_| loudness = Some(child_loudness()));
+            s.spawn(|_| zcr = Some(child_zcr()));
+            s.spawn(|_| mfcc = Some(child_mfcc()));
+            s.spawn(|_| extension = Some(child_extension()));
+            s.spawn(|_| fingerprint = Some(child_fingerprint()));
+        });
+
+        // Non-streaming approach for that one
+        let (tempo, rhythm, found_beats, tempo_confidence) = tempo.unwrap()?;
+        let (chroma, key) = chroma.unwrap()?;
+        let (centroid, rolloff, flatness) = timbral.unwrap()?;
+        let loudness = loudness.unwrap()?;
+        let (zcr, signal_stats) = zcr.unwrap()?;
+        let mfcc = mfcc.unwrap()?;
+        let extension = extension.unwrap()?;
+        let fingerprint = fingerprint.unwrap()?;
+
+        let mut result = vec![tempo, zcr];
+        result.extend_from_slice(&centroid);
+        result.extend_from_slice(&rolloff);
+        result.extend_from_slice(&flatness);
+        result.extend_from_slice(&loudness);
+        result.extend_from_slice(&chroma);
+        result.extend_from_slice(&mfcc);
+        result.extend_from_slice(&rhythm);
+        if result.len() != analysis_options.features_version.feature_count() {
+            return Err(BlissError::AnalysisError(
+                "Too many or too little features were provided at the end of
+                        the analysis."
+                    .to_string(),
+            ));
+        };
+        let mut diagnostics = AnalysisDiagnostics::default();
+        diagnostics.check_tempo(found_beats, tempo_confidence);
+        diagnostics.warnings.extend(signal_stats.get_warnings());
+        let mut analysis = Analysis::new(result, analysis_options.features_version)?;
+        analysis.extension = extension;
+        analysis.partial = excerpt.is_some();
+        if analysis_options.features_version >= FeaturesVersion::Version3 {
+            analysis.tempo_confidence = Some(tempo_confidence);
+        }
+        Ok(AnalysisOutput {
+            analysis,
+            diagnostics,
+            key,
+            boundaries,
+            fingerprint,
+        })
+    }
+
+    /// Analyze a song decoded in `sample_array` segment by segment, returning
+    /// the [Analysis] of each segment along with its timestamps.
+    ///
+    /// Segments are `segment_length` long, and start every `hop`. The last
+    /// segment is cut at the end of the song; if what remains of the song
+    /// after the previous segment is too short to be analyzed, it is
+    /// dropped. Songs shorter than `segment_length` yield a single segment.
+    ///
+    /// Useful to tell the different parts of a song apart, e.g. a quiet
+    /// intro from a loud chorus, or to compare the end of a song with the
+    /// beginning of another one (see
+    /// [outro_to_intro](crate::playlist::outro_to_intro)).
+    ///
+    /// The sample format of `sample_array` is the same as for [Song::analyze].
+    #[cfg(feature = "analysis")]
+    pub fn analyze_segments(
+        sample_array: &[f32],
+        segment_length: Duration,
+        hop: Duration,
+    ) -> BlissResult<Vec<AnalysisSegment>> {
+        Self::analyze_segments_with_options(
+            sample_array,
+            segment_length,
+            hop,
+            &AnalysisOptions::default(),
+        )
+    }
+
+    /// Same as [Song::analyze_segments], but allows to use custom analysis
+    /// options, e.g. to use an old features version.
+    #[cfg(feature = "analysis")]
+    pub fn analyze_segments_with_options(
+        sample_array: &[f32],
+        segment_length: Duration,
+        hop: Duration,
+        analysis_options: &AnalysisOptions,
+    ) -> BlissResult<Vec<AnalysisSegment>> {
+        let budget = AnalysisBudget::new(analysis_options);
+        Self::analyze_segments_with_budget(
+            sample_array,
+            segment_length,
+            hop,
+            analysis_options,
+            &budget,
+        )
+    }
+
+    /// Same as [Song::analyze_segments_with_options], checking `budget`
+    /// while analyzing each segment.
+    #[cfg(feature = "analysis")]
+    fn analyze_segments_with_budget(
+        sample_array: &[f32],
+        segment_length: Duration,
+        hop: Duration,
+        analysis_options: &AnalysisOptions,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<Vec<AnalysisSegment>> {
+        let to_samples = |d: Duration| (d.as_secs_f64() * SAMPLE_RATE as f64).round() as usize;
+        let to_duration = |s: usize| Duration::from_secs_f64(s as f64 / SAMPLE_RATE as f64);
+        let segment_length = to_samples(segment_length);
+        let hop = to_samples(hop);
+        if hop == 0 {
+            return Err(BlissError::AnalysisError(String::from(
+                "in segments: the hop between segments should not be zero.",
+            )));
+        }
+        if segment_length < largest_window() {
+            return Err(BlissError::AnalysisError(String::from(
+                "in segments: the segments are too short to be analyzed.",
+            )));
+        }
+
+        // Segments are short enough to be analyzed as a whole, and
+        // shouldn't be shifted by trimming.
+        let analysis_options = &AnalysisOptions {
+            excerpt: None,
+            trim: None,
+            ..analysis_options.clone()
+        };
+        let mut segments = Vec::new();
+        let mut start = 0;
+        while start < sample_array.len() {
+            let end = (start + segment_length).min(sample_array.len());
+            if end - start < largest_window() && !segments.is_empty() {
+                break;
+            }
+            segments.push(AnalysisSegment {
+                start: to_duration(start),
+                end: to_duration(end),
+                analysis: Self::analyze_output(
+                    &sample_array[start..end],
+                    analysis_options,
+                    budget,
+                )?
+                .analysis,
+            });
+            if end == sample_array.len() {
+                break;
+            }
+            start += hop;
+        }
+        if segments.is_empty() {
+            return Err(BlissError::AnalysisError(String::from(
+                "empty or too short song.",
+            )));
+        }
+        Ok(segments)
+    }
+
+    /// Compute the segments of `sample_array` if `analysis_options` asks for
+    /// them, returning `None` otherwise.
+    #[cfg(feature = "analysis")]
+    pub(crate) fn segments_from_options(
+        sample_array: &[f32],
+        analysis_options: &AnalysisOptions,
+        budget: &AnalysisBudget,
+    ) -> BlissResult<Option<Vec<AnalysisSegment>>> {
+        analysis_options
+            .segments
+            .map(|segment_options| {
+                Self::analyze_segments_with_budget(
+                    sample_array,
+                    segment_options.segment_length,
+                    segment_options.hop,
+                    analysis_options,
+                    budget,
+                )
+            })
+            .transpose()
+    }
+
+    /// Find the beats and onsets of a song decoded in `sample_array`,
+    /// using the same beat tracker as the one used to compute the song's
+    /// tempo feature.
+    ///
+    /// Useful e.g. to align transitions between songs on their beats, or
+    /// to draw a song's tempo curve using [Beat::bpm](crate::Beat::bpm).
+    ///
+    /// The sample format of `sample_array` is the same as for [Song::analyze].
+    #[cfg(feature = "analysis")]
+    pub fn track_beats(sample_array: &[f32]) -> BlissResult<BeatTrack> {
+        if sample_array.len() < BeatDesc::WINDOW_SIZE {
+            return Err(BlissError::AnalysisError(String::from(
+                "empty or too short song.",
+            )));
+        }
+        let mut beat_desc = BeatDesc::new(SAMPLE_RATE)?;
+        let windows = sample_array
+            .windows(BeatDesc::WINDOW_SIZE)
+            .step_by(BeatDesc::HOP_SIZE);
+        for window in windows {
+            beat_desc.do_(window)?;
+        }
+        Ok(beat_desc.get_value())
+    }
+
+    /// Estimate the tempo of a song decoded in `sample_array`, along with
+    /// the beat tracker's confidence in it.
+    ///
+    /// Unlike the song's tempo feature, the tempo is corrected if the beat
+    /// tracker locked onto half or double the actual tempo.
+    ///
+    /// Returns `None` if no beats were found in the song.
+    ///
+    /// The sample format of `sample_array` is the same as for [Song::analyze].
+    #[cfg(feature = "analysis")]
+    pub fn estimate_tempo(sample_array: &[f32]) -> BlissResult<Option<TempoEstimate>> {
+        if sample_array.len() < BPMDesc::WINDOW_SIZE {
+            return Err(BlissError::AnalysisError(String::from(
+                "empty or too short song.",
+            )));
+        }
+        let mut tempo_desc = BPMDesc::new(SAMPLE_RATE)?;
+        let windows = sample_array
+            .windows(BPMDesc::WINDOW_SIZE)
+            .step_by(BPMDesc::HOP_SIZE);
+        for window in windows {
+            tempo_desc.do_(window)?;
+        }
+        Ok(tempo_desc.get_tempo())
+    }
+
+    /// The analysis of the beginning of the song, i.e. the analysis of its
+    /// first segment, or the whole song's analysis if the song has no
+    /// segments.
+    pub fn intro_analysis(&self) -> &Analysis {
+        self.segments
+            .as_ref()
+            .and_then(|segments| segments.first())
+            .map_or(&self.analysis, |segment| &segment.analysis)
+    }
+
+    /// The analysis of the end of the song, i.e. the analysis of its
+    /// last segment, or the whole song's analysis if the song has no
+    /// segments.
+    pub fn outro_analysis(&self) -> &Analysis {
+        self.segments
+            .as_ref()
+            .and_then(|segments| segments.last())
+            .map_or(&self.analysis, |segment| &segment.analysis)
     }
 
     /// Return the distance between the song and another song, using
@@ -550,6 +1578,591 @@ mod tests {
         );
     }
 
+    // Half a second of a quiet A4, followed by white-ish noise.
+    #[cfg(feature = "analysis")]
+    fn quiet_intro_loud_outro(number_samples: usize) -> Vec<f32> {
+        let mut seed: u32 = 1;
+        (0..number_samples)
+            .map(|i| {
+                if i < SAMPLE_RATE as usize / 2 {
+                    let t = i as f32 / SAMPLE_RATE as f32;
+                    0.05 * (2. * std::f32::consts::PI * 440. * t).sin()
+                } else {
+                    seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                    (seed >> 16) as f32 / 32768. - 1.
+                }
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_analyze_with_diagnostics() {
+        let options = AnalysisOptions::default();
+        let sample_array = quiet_intro_loud_outro(SAMPLE_RATE as usize * 2);
+        let (analysis, diagnostics) =
+            Song::analyze_with_diagnostics(&sample_array, &options).unwrap();
+        assert_eq!(analysis, Song::analyze(&sample_array).unwrap());
+        // Too short for the beat tracker to lock onto a tempo.
+        assert_eq!(diagnostics.warnings, vec![AnalysisWarning::NoBeatsFound]);
+
+        let (_, diagnostics) =
+            Song::analyze_with_diagnostics(&[0.; SAMPLE_RATE as usize], &options).unwrap();
+        assert_eq!(
+            diagnostics.warnings,
+            vec![
+                AnalysisWarning::NoBeatsFound,
+                AnalysisWarning::MostlySilent { silent_ratio: 1. },
+            ]
+        );
+
+        let clipped = sample_array
+            .iter()
+            .map(|x| (x * 2.).clamp(-1., 1.))
+            .collect::<Vec<_>>();
+        let (_, diagnostics) = Song::analyze_with_diagnostics(&clipped, &options).unwrap();
+        assert!(diagnostics.warnings.iter().any(
+            |w| matches!(w, AnalysisWarning::Clipped { clipped_ratio } if *clipped_ratio > 0.3)
+        ));
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_song_diagnostics_sample_count_mismatch() {
+        let sample_array = quiet_intro_loud_outro(SAMPLE_RATE as usize * 2);
+        let song = Song::try_from(crate::decoder::PreAnalyzedSong {
+            expected_duration: Some(Duration::from_secs(2)),
+            sample_array: sample_array.clone(),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            song.diagnostics.warnings,
+            vec![AnalysisWarning::NoBeatsFound]
+        );
+
+        let song = Song::try_from(crate::decoder::PreAnalyzedSong {
+            expected_duration: Some(Duration::from_secs(60)),
+            sample_array,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            song.diagnostics.warnings,
+            vec![
+                AnalysisWarning::NoBeatsFound,
+                AnalysisWarning::SampleCountMismatch {
+                    expected: 60 * SAMPLE_RATE as usize,
+                    actual: 2 * SAMPLE_RATE as usize,
+                }
+            ]
+        );
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_song_diagnostics_decoder_warnings() {
+        let sample_array = quiet_intro_loud_outro(SAMPLE_RATE as usize * 2);
+        let mismatch = AnalysisWarning::SampleCountMismatch {
+            expected: 60 * SAMPLE_RATE as usize,
+            actual: 2 * SAMPLE_RATE as usize,
+        };
+        // The decoder's sample count mismatch is not reported twice.
+        let song = Song::try_from(crate::decoder::PreAnalyzedSong {
+            expected_duration: Some(Duration::from_secs(60)),
+            warnings: vec![
+                AnalysisWarning::DecodeErrors { skipped: 2 },
+                AnalysisWarning::Truncated,
+                mismatch.clone(),
+            ],
+            sample_array,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            song.diagnostics.warnings,
+            vec![
+                AnalysisWarning::NoBeatsFound,
+                AnalysisWarning::DecodeErrors { skipped: 2 },
+                AnalysisWarning::Truncated,
+                mismatch,
+            ]
+        );
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_track_beats() {
+        let beat_track =
+            Song::track_beats(&quiet_intro_loud_outro(SAMPLE_RATE as usize * 5)).unwrap();
+        assert!(!beat_track.onsets.is_empty());
+        assert!(beat_track.onsets.is_sorted());
+        assert!(beat_track
+            .beats
+            .windows(2)
+            .all(|beats| beats[0].position < beats[1].position));
+
+        assert_eq!(
+            Song::track_beats(&[0.; 100]),
+            Err(BlissError::AnalysisError(String::from(
+                "empty or too short song."
+            ))),
+        );
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_estimate_tempo() {
+        // Two clicks per second, so 120 BPM
+        let mut one_beat = vec![0.; SAMPLE_RATE as usize / 2 - 100];
+        one_beat.extend_from_slice(&[1.; 100]);
+        let sample_array = std::iter::repeat_n(one_beat, 60)
+            .flatten()
+            .collect::<Vec<f32>>();
+        let tempo = Song::estimate_tempo(&sample_array).unwrap().unwrap();
+        assert!(3. > (120. - tempo.bpm).abs());
+        assert!(tempo.confidence > 0.3);
+
+        assert_eq!(
+            Song::estimate_tempo(&[0.; SAMPLE_RATE as usize]).unwrap(),
+            None
+        );
+        assert_eq!(
+            Song::estimate_tempo(&[0.; 100]),
+            Err(BlissError::AnalysisError(String::from(
+                "empty or too short song."
+            ))),
+        );
+    }
+
+    // Mean of the absolute value of the samples.
+    #[cfg(feature = "analysis")]
+    #[derive(Default)]
+    struct MeanAmplitude {
+        sum: f32,
+        number_samples: usize,
+    }
+
+    #[cfg(feature = "analysis")]
+    impl Descriptor for MeanAmplitude {
+        fn do_(&mut self, chunk: &[f32]) -> BlissResult<()> {
+            self.sum += chunk.iter().map(|x| x.abs()).sum::<f32>();
+            self.number_samples += chunk.len();
+            Ok(())
+        }
+
+        fn finish(&mut self) -> BlissResult<Vec<f32>> {
+            Ok(vec![self.sum / self.number_samples as f32])
+        }
+
+        fn feature_names(&self) -> Vec<String> {
+            vec![String::from("mean_amplitude")]
+        }
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_analyze_with_extensions() {
+        let sample_array = quiet_intro_loud_outro(SAMPLE_RATE as usize * 2);
+        let analysis_options = AnalysisOptions {
+            extensions: Some(ExtensionOptions::new(1).with_descriptor(MeanAmplitude::default)),
+            ..Default::default()
+        };
+        let analysis = Song::analyze_with_options(&sample_array, &analysis_options).unwrap();
+        let expected = Song::analyze(&sample_array).unwrap();
+        assert_eq!(expected.extension, None);
+        // bliss' own features are left untouched.
+        assert_eq!(analysis.as_vec(), expected.as_vec());
+        let extension = analysis.extension.unwrap();
+        assert_eq!(extension.version, 1);
+        assert_eq!(extension.features.len(), 1);
+        assert!(0.4 > (0.5 - extension.features[0]).abs());
+
+        // Segments get their own extension features.
+        let segments = Song::analyze_segments_with_options(
+            &sample_array,
+            Duration::from_secs(1),
+            Duration::from_secs(1),
+            &analysis_options,
+        )
+        .unwrap();
+        let intro = segments[0].analysis.extension.as_ref().unwrap();
+        let outro = segments[1].analysis.extension.as_ref().unwrap();
+        assert!(intro.features[0] < outro.features[0]);
+    }
+
+    // A few seconds of a C major chord, sampled at `sample_rate`.
+    #[cfg(feature = "analysis")]
+    fn c_major_chord(sample_rate: u32) -> Vec<f32> {
+        (0..sample_rate * 5)
+            .map(|i| {
+                let t = i as f32 / sample_rate as f32;
+                [261.63, 329.63, 392.]
+                    .iter()
+                    .map(|f| 0.2 * (2. * std::f32::consts::PI * f * t).sin())
+                    .sum()
+            })
+            .collect()
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_analyze_pcm() {
+        let expected = Song::analyze(&c_major_chord(SAMPLE_RATE)).unwrap();
+        let analysis = Song::analyze_pcm(
+            &c_major_chord(SAMPLE_RATE),
+            SAMPLE_RATE,
+            1,
+            SampleLayout::Planar,
+        )
+        .unwrap();
+        assert_eq!(analysis, expected);
+
+        // Stereo at 44100 Hz, with both channels scaled so that the
+        // downmix gives back the chord.
+        let chord = c_major_chord(44100)
+            .iter()
+            .map(|x| x * std::f32::consts::SQRT_2 / 2.)
+            .collect::<Vec<f32>>();
+        let interleaved = chord.iter().flat_map(|x| [*x, *x]).collect::<Vec<f32>>();
+        let analysis =
+            Song::analyze_pcm(&interleaved, 44100, 2, SampleLayout::Interleaved).unwrap();
+        let planar = [chord.clone(), chord].concat();
+        assert_eq!(
+            Song::analyze_pcm(&planar, 44100, 2, SampleLayout::Planar).unwrap(),
+            analysis
+        );
+        for (x, y) in analysis.as_vec().iter().zip(expected.as_vec()) {
+            assert!(0.1 > (x - y).abs(), "{}", (x - y).abs());
+        }
+
+        assert_eq!(
+            Song::analyze_pcm(&interleaved, 0, 2, SampleLayout::Interleaved),
+            Err(BlissError::AnalysisError(String::from(
+                "in pcm: the sample rate should not be zero."
+            )))
+        );
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_excerpt_options() {
+        let sample_array = (0..SAMPLE_RATE * 10)
+            .map(|i| i as f32)
+            .collect::<Vec<f32>>();
+        let second = SAMPLE_RATE as usize;
+
+        let excerpt = ExcerptOptions::Middle(Duration::from_secs(2))
+            .excerpt(&sample_array)
+            .unwrap()
+            .unwrap();
+        assert_eq!(excerpt, sample_array[4 * second..6 * second]);
+
+        let excerpt = ExcerptOptions::Windows {
+            number_windows: 2,
+            window_length: Duration::from_secs(1),
+        }
+        .excerpt(&sample_array)
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            excerpt,
+            [
+                &sample_array[2 * second..3 * second],
+                &sample_array[7 * second..8 * second],
+            ]
+            .concat()
+        );
+
+        // The excerpt would be the whole song.
+        let excerpt = ExcerptOptions::Windows {
+            number_windows: 5,
+            window_length: Duration::from_secs(2),
+        };
+        assert_eq!(excerpt.excerpt(&sample_array).unwrap(), None);
+
+        let excerpt = ExcerptOptions::Windows {
+            number_windows: 0,
+            window_length: Duration::from_secs(2),
+        };
+        assert_eq!(
+            excerpt.excerpt(&sample_array),
+            Err(BlissError::AnalysisError(String::from(
+                "in excerpt: the number of windows should not be zero."
+            )))
+        );
+        assert_eq!(
+            ExcerptOptions::Middle(Duration::from_millis(10)).excerpt(&sample_array),
+            Err(BlissError::AnalysisError(String::from(
+                "in excerpt: the windows are too short to be analyzed."
+            )))
+        );
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_analyze_cancelled() {
+        let sample_array = quiet_intro_loud_outro(SAMPLE_RATE as usize * 4);
+        let token = CancellationToken::new();
+        let analysis_options = AnalysisOptions {
+            cancellation: Some(token.clone()),
+            segments: Some(SegmentOptions::default()),
+            ..Default::default()
+        };
+        assert!(Song::analyze_with_options(&sample_array, &analysis_options).is_ok());
+        token.cancel();
+        assert_eq!(
+            Song::analyze_with_options(&sample_array, &analysis_options),
+            Err(BlissError::Cancelled),
+        );
+        assert_eq!(
+            Song::analyze_segments_with_options(
+                &sample_array,
+                Duration::from_secs(2),
+                Duration::from_secs(1),
+                &analysis_options,
+            ),
+            Err(BlissError::Cancelled),
+        );
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_analyze_timed_out() {
+        let sample_array = quiet_intro_loud_outro(SAMPLE_RATE as usize * 4);
+        let analysis_options = AnalysisOptions {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        assert_eq!(
+            Song::analyze_with_options(&sample_array, &analysis_options),
+            Err(BlissError::TimedOut(Duration::ZERO)),
+        );
+        let analysis_options = AnalysisOptions {
+            timeout: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        assert!(Song::analyze_with_options(&sample_array, &analysis_options).is_ok());
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_analyze_single_thread_pool() {
+        let sample_array = quiet_intro_loud_outro(SAMPLE_RATE as usize * 2);
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap();
+        let analysis = pool.install(|| Song::analyze(&sample_array)).unwrap();
+        assert_eq!(analysis, Song::analyze(&sample_array).unwrap());
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_analyze_excerpt() {
+        let sample_array = quiet_intro_loud_outro(SAMPLE_RATE as usize * 4);
+        let analysis_options = AnalysisOptions {
+            excerpt: Some(ExcerptOptions::Middle(Duration::from_secs(2))),
+            segments: Some(SegmentOptions {
+                segment_length: Duration::from_secs(3),
+                hop: Duration::from_secs(3),
+            }),
+            ..Default::default()
+        };
+        let analysis = Song::analyze_with_options(&sample_array, &analysis_options).unwrap();
+        assert!(analysis.partial);
+        // Only the noisy part of the song was analyzed.
+        let expected =
+            Song::analyze(&sample_array[SAMPLE_RATE as usize..SAMPLE_RATE as usize * 3]).unwrap();
+        assert_eq!(analysis.as_vec(), expected.as_vec());
+        assert!(!expected.partial);
+        // The fingerprint is computed on the whole song either way.
+        let output =
+            Song::analyze_output(&sample_array, &analysis_options, &AnalysisBudget::default())
+                .unwrap();
+        assert!(output.fingerprint.is_some());
+        assert_eq!(
+            output.fingerprint,
+            Song::analyze_output(
+                &sample_array,
+                &AnalysisOptions::default(),
+                &AnalysisBudget::default()
+            )
+            .unwrap()
+            .fingerprint,
+        );
+
+        // Segments are analyzed as a whole.
+        let segments = Song::segments_from_options(
+            &sample_array,
+            &analysis_options,
+            &AnalysisBudget::default(),
+        )
+        .unwrap()
+        .unwrap();
+        assert!(segments.iter().all(|segment| !segment.analysis.partial));
+
+        // Songs shorter than the excerpt are analyzed as a whole.
+        let analysis_options = AnalysisOptions {
+            excerpt: Some(ExcerptOptions::Middle(Duration::from_secs(5))),
+            ..Default::default()
+        };
+        let analysis = Song::analyze_with_options(&sample_array, &analysis_options).unwrap();
+        assert_eq!(analysis, Song::analyze(&sample_array).unwrap());
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_analyze_trim() {
+        let frame = BoundaryDesc::FRAME_SIZE;
+        let song = quiet_intro_loud_outro(100 * frame);
+        let sample_array = [vec![0.; 40 * frame], song.to_owned(), vec![0.; 30 * frame]].concat();
+
+        let output = Song::analyze_output(
+            &sample_array,
+            &AnalysisOptions::default(),
+            &AnalysisBudget::default(),
+        )
+        .unwrap();
+        let to_duration = |s: usize| Duration::from_secs_f64(s as f64 / SAMPLE_RATE as f64);
+        assert_eq!(
+            output.boundaries,
+            Some(Boundaries {
+                audio_start: to_duration(40 * frame),
+                audio_end: to_duration(140 * frame),
+                fade_out_start: None,
+            })
+        );
+        // Silence drags the loudness down if it is not trimmed.
+        let expected = Song::analyze(&song).unwrap();
+        assert!(
+            output.analysis[AnalysisIndex::MeanLoudness] < expected[AnalysisIndex::MeanLoudness]
+        );
+
+        let analysis_options = AnalysisOptions {
+            trim: Some(TrimOptions::Silence),
+            ..Default::default()
+        };
+        let analysis = Song::analyze_with_options(&sample_array, &analysis_options).unwrap();
+        assert_eq!(analysis, expected);
+
+        // Silent songs are analyzed whole.
+        let silence = vec![0.; 10 * frame];
+        assert_eq!(
+            Song::analyze_with_options(&silence, &analysis_options).unwrap(),
+            Song::analyze(&silence).unwrap(),
+        );
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_analyze_segments() {
+        let sample_array = quiet_intro_loud_outro(SAMPLE_RATE as usize * 2);
+        let segments = Song::analyze_segments(
+            &sample_array,
+            Duration::from_millis(500),
+            Duration::from_millis(400),
+        )
+        .unwrap();
+        let timestamps = segments
+            .iter()
+            .map(|s| (s.start.as_millis(), s.end.as_millis()))
+            .collect::<Vec<_>>();
+        // The last bit of the song, starting at 2000ms, is too short to be
+        // analyzed on its own.
+        assert_eq!(
+            timestamps,
+            vec![
+                (0, 500),
+                (400, 900),
+                (800, 1300),
+                (1200, 1700),
+                (1600, 2000)
+            ],
+        );
+        assert_eq!(
+            segments[1].analysis,
+            Song::analyze(&sample_array[8820..19845]).unwrap(),
+        );
+        // The intro is quieter than the outro.
+        assert!(
+            segments[0].analysis[AnalysisIndex::MeanLoudness]
+                < segments[4].analysis[AnalysisIndex::MeanLoudness]
+        );
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_analyze_segments_short_song() {
+        let sample_array = quiet_intro_loud_outro(SAMPLE_RATE as usize);
+        let segments = Song::analyze_segments(
+            &sample_array,
+            Duration::from_secs(10),
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].start, Duration::ZERO);
+        assert_eq!(segments[0].end, Duration::from_secs(1));
+        assert_eq!(segments[0].analysis, Song::analyze(&sample_array).unwrap());
+    }
+
+    #[cfg(feature = "analysis")]
+    #[test]
+    fn test_analyze_segments_errors() {
+        let sample_array = quiet_intro_loud_outro(SAMPLE_RATE as usize);
+        assert_eq!(
+            Song::analyze_segments(&sample_array, Duration::from_secs(1), Duration::ZERO),
+            Err(BlissError::AnalysisError(String::from(
+                "in segments: the hop between segments should not be zero."
+            ))),
+        );
+        assert_eq!(
+            Song::analyze_segments(
+                &sample_array,
+                Duration::from_millis(10),
+                Duration::from_millis(10)
+            ),
+            Err(BlissError::AnalysisError(String::from(
+                "in segments: the segments are too short to be analyzed."
+            ))),
+        );
+        assert_eq!(
+            Song::analyze_segments(&[0.; 100], Duration::from_secs(1), Duration::from_secs(1)),
+            Err(BlissError::AnalysisError(String::from(
+                "empty or too short song."
+            ))),
+        );
+    }
+
+    #[test]
+    fn test_intro_outro_analysis() {
+        let analysis = |value: f32| {
+            Analysis::new(vec![value; NUMBER_FEATURES], FeaturesVersion::LATEST).unwrap()
+        };
+        let mut song = Song {
+            analysis: analysis(1.),
+            ..Default::default()
+        };
+        assert_eq!(song.intro_analysis(), &analysis(1.));
+        assert_eq!(song.outro_analysis(), &analysis(1.));
+
+        song.segments = Some(vec![
+            AnalysisSegment {
+                start: Duration::ZERO,
+                end: Duration::from_secs(10),
+                analysis: analysis(2.),
+            },
+            AnalysisSegment {
+                start: Duration::from_secs(5),
+                end: Duration::from_secs(15),
+                analysis: analysis(3.),
+            },
+        ]);
+        assert_eq!(song.intro_analysis(), &analysis(2.));
+        assert_eq!(song.outro_analysis(), &analysis(3.));
+    }
+
     const SONG_AND_EXPECTED_ANALYSIS: (&str, [f32; NUMBER_FEATURES]) = (
         "data/s16_mono_22_5kHz.flac",
         [
@@ -576,6 +2189,35 @@ mod tests {
             -0.06000763,
             -0.58485717,
             -0.07880378,
+            0.6326873,
+            -0.55831903,
+            -0.1545276,
+            -0.26300412,
+            0.1622928,
+            -0.7151735,
+            -0.019680252,
+            -0.67757356,
+            -0.123931274,
+            -0.7403809,
+            -0.010414681,
+            -0.8290915,
+            -0.07043903,
+            -0.82814413,
+            -0.075385205,
+            -0.8760666,
+            -0.0630605,
+            -0.88455683,
+            -0.087047815,
+            -0.88437575,
+            -0.07069456,
+            -0.89538753,
+            -0.034040097,
+            -0.886425,
+            -0.06280625,
+            -0.895442,
+            0.027273059,
+            -0.098917425,
+            0.15808833,
         ],
     );
 
@@ -677,6 +2319,35 @@ mod tests {
                 -0.060014784,
                 -0.5848569,
                 -0.07879859,
+                0.6356982,
+                -0.5581219,
+                -0.15719844,
+                -0.2625687,
+                0.16473822,
+                -0.7148805,
+                -0.021982802,
+                -0.6764958,
+                -0.121674486,
+                -0.7404599,
+                -0.012598763,
+                -0.82878387,
+                -0.06832142,
+                -0.82790756,
+                -0.07762446,
+                -0.8764231,
+                -0.06085376,
+                -0.8845904,
+                -0.08920768,
+                -0.88408864,
+                -0.068569824,
+                -0.89518887,
+                -0.03612476,
+                -0.8862322,
+                -0.060834497,
+                -0.8955291,
+                0.027363658,
+                -0.09864515,
+                0.15808833,
             ],
         );
 
@@ -694,6 +2365,8 @@ mod tests {
         let song = Decoder::song_from_path("data/s16_mono_22_5kHz.flac").unwrap();
         assert_eq!(song.analysis[AnalysisIndex::Tempo], 0.3846389);
         assert_eq!(song.analysis[AnalysisIndex::Chroma10], -0.06784296);
+        assert_eq!(song.analysis[AnalysisIndex::MeanMfcc1], 0.6326873);
+        assert_eq!(song.analysis[AnalysisIndex::PulseClarity], 0.027273059);
     }
 
     #[test]
@@ -712,7 +2385,7 @@ mod tests {
     fn test_debug_analysis() {
         let song = Decoder::song_from_path("data/s16_mono_22_5kHz.flac").unwrap();
         assert_eq!(
-            "Analysis (Version 2) { Tempo: 0.3846389, Zcr: -0.849141, MeanSpectralCentroid: -0.7548105, StdDeviationSpectralCentroid: -0.8790748, MeanSpectralRolloff: -0.63258266, StdDeviationSpectralRolloff: -0.7258959, MeanSpectralFlatness: -0.775738, StdDeviationSpectralFlatness: -0.8146726, MeanLoudness: 0.2716726, StdDeviationLoudness: 0.25779057, Chroma1: -0.34292513, Chroma2: -0.62803423, Chroma3: -0.28095096, Chroma4: 0.08686459, Chroma5: 0.24446082, Chroma6: -0.5723257, Chroma7: 0.23292065, Chroma8: 0.19981146, Chroma9: -0.58594406, Chroma10: -0.06784296, Chroma11: -0.06000763, Chroma12: -0.58485717, Chroma13: -0.07880378 } /* [0.3846389, -0.849141, -0.7548105, -0.8790748, -0.63258266, -0.7258959, -0.775738, -0.8146726, 0.2716726, 0.25779057, -0.34292513, -0.62803423, -0.28095096, 0.08686459, 0.24446082, -0.5723257, 0.23292065, 0.19981146, -0.58594406, -0.06784296, -0.06000763, -0.58485717, -0.07880378] */",
+            "Analysis (Version 4) { Tempo: 0.3846389, Zcr: -0.849141, MeanSpectralCentroid: -0.7548105, StdDeviationSpectralCentroid: -0.8790748, MeanSpectralRolloff: -0.63258266, StdDeviationSpectralRolloff: -0.7258959, MeanSpectralFlatness: -0.775738, StdDeviationSpectralFlatness: -0.8146726, MeanLoudness: 0.2716726, StdDeviationLoudness: 0.25779057, Chroma1: -0.34292513, Chroma2: -0.62803423, Chroma3: -0.28095096, Chroma4: 0.08686459, Chroma5: 0.24446082, Chroma6: -0.5723257, Chroma7: 0.23292065, Chroma8: 0.19981146, Chroma9: -0.58594406, Chroma10: -0.06784296, Chroma11: -0.06000763, Chroma12: -0.58485717, Chroma13: -0.07880378, MeanMfcc1: 0.6326873, StdDeviationMfcc1: -0.55831903, MeanMfcc2: -0.1545276, StdDeviationMfcc2: -0.26300412, MeanMfcc3: 0.1622928, StdDeviationMfcc3: -0.7151735, MeanMfcc4: -0.019680252, StdDeviationMfcc4: -0.67757356, MeanMfcc5: -0.123931274, StdDeviationMfcc5: -0.7403809, MeanMfcc6: -0.010414681, StdDeviationMfcc6: -0.8290915, MeanMfcc7: -0.07043903, StdDeviationMfcc7: -0.82814413, MeanMfcc8: -0.075385205, StdDeviationMfcc8: -0.8760666, MeanMfcc9: -0.0630605, StdDeviationMfcc9: -0.88455683, MeanMfcc10: -0.087047815, StdDeviationMfcc10: -0.88437575, MeanMfcc11: -0.07069456, StdDeviationMfcc11: -0.89538753, MeanMfcc12: -0.034040097, StdDeviationMfcc12: -0.886425, MeanMfcc13: -0.06280625, StdDeviationMfcc13: -0.895442, PulseClarity: 0.027273059, BeatStrengthVariance: -0.098917425, OnsetDensity: 0.15808833 } /* [0.3846389, -0.849141, -0.7548105, -0.8790748, -0.63258266, -0.7258959, -0.775738, -0.8146726, 0.2716726, 0.25779057, -0.34292513, -0.62803423, -0.28095096, 0.08686459, 0.24446082, -0.5723257, 0.23292065, 0.19981146, -0.58594406, -0.06784296, -0.06000763, -0.58485717, -0.07880378, 0.6326873, -0.55831903, -0.1545276, -0.26300412, 0.1622928, -0.7151735, -0.019680252, -0.67757356, -0.123931274, -0.7403809, -0.010414681, -0.8290915, -0.07043903, -0.82814413, -0.075385205, -0.8760666, -0.0630605, -0.88455683, -0.087047815, -0.88437575, -0.07069456, -0.89538753, -0.034040097, -0.886425, -0.06280625, -0.895442, 0.027273059, -0.098917425, 0.15808833] */",
             format!("{:?}", song.analysis),
         );
     }
@@ -744,6 +2417,9 @@ mod tests {
         let analysis = Analysis {
             internal_analysis: vec![0.; 10],
             features_version: FeaturesVersion::Version1,
+            extension: None,
+            partial: false,
+            tempo_confidence: None,
         };
         assert_eq!(
             "Analysis (Version ?) /* [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0] */",
@@ -784,6 +2460,32 @@ mod tests {
         assert_eq!(4.472136, first_analysis.distance(&second_analysis));
     }
 
+    #[test]
+    fn test_analysis_as_arr1_tempo_confidence() {
+        let mut analysis =
+            Analysis::new(vec![1.; NUMBER_FEATURES], FeaturesVersion::LATEST).unwrap();
+        assert_eq!(analysis.as_arr1(), arr1(&[1.; NUMBER_FEATURES]));
+
+        analysis.tempo_confidence = Some(0.25);
+        let mut expected = vec![1.; NUMBER_FEATURES];
+        expected[AnalysisIndex::Tempo as usize] = 0.25;
+        assert_eq!(analysis.as_arr1(), arr1(&expected));
+        // The unscaled features are still there.
+        assert_eq!(analysis.as_vec(), vec![1.; NUMBER_FEATURES]);
+
+        // Older features versions don't use it.
+        let mut analysis = Analysis::new(
+            vec![1.; FeaturesVersion::Version2.feature_count()],
+            FeaturesVersion::Version2,
+        )
+        .unwrap();
+        analysis.tempo_confidence = Some(0.25);
+        assert_eq!(
+            analysis.as_arr1(),
+            arr1(&[1.; FeaturesVersion::Version2.feature_count()])
+        );
+    }
+
     #[test]
     fn test_song_distance() {
         let first_song = Song {
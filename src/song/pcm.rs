@@ -0,0 +1,299 @@
+//! Raw PCM conversion module.
+//!
+//! Downmixes and resamples raw samples to the format bliss analyzes, i.e.
+//! f32, one channel, at 22050 Hz. Used by the symphonia decoder, and by
+//! [Song::analyze_pcm](crate::Song::analyze_pcm) for samples that were
+//! decoded elsewhere.
+use std::f32::consts::SQRT_2;
+use std::fmt::Display;
+
+use audioadapter_buffers::direct::InterleavedSlice;
+use rubato::{Fft, FixedSync, Resampler};
+
+use crate::{BlissError, BlissResult, SAMPLE_RATE};
+
+const CHUNK_SIZE: usize = 4096;
+
+/// How the samples of the different channels are laid out in a buffer
+/// of raw samples, see [Song::analyze_pcm](crate::Song::analyze_pcm).
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SampleLayout {
+    /// The samples of the different channels alternate, e.g.
+    /// `[left, right, left, right...]` for stereo. This is what most
+    /// decoders and audio APIs output.
+    Interleaved,
+    /// The samples of each channel follow each other, e.g.
+    /// `[left, left..., right, right...]` for stereo.
+    Planar,
+}
+
+/// Convert raw samples with `channels` channels laid out as `layout`, at
+/// `sample_rate`, to mono samples at 22050 Hz.
+pub(crate) fn to_bliss_samples(
+    samples: &[f32],
+    sample_rate: u32,
+    channels: u16,
+    layout: SampleLayout,
+) -> BlissResult<Vec<f32>> {
+    if sample_rate == 0 {
+        return Err(BlissError::AnalysisError(String::from(
+            "in pcm: the sample rate should not be zero.",
+        )));
+    }
+    if channels == 0 {
+        return Err(BlissError::AnalysisError(String::from(
+            "in pcm: the number of channels should not be zero.",
+        )));
+    }
+    let channels = channels as usize;
+    if !samples.len().is_multiple_of(channels) {
+        return Err(BlissError::AnalysisError(String::from(
+            "in pcm: the number of samples is not a multiple of the number of channels.",
+        )));
+    }
+    let mono_samples = match layout {
+        SampleLayout::Interleaved => downmix(samples, channels),
+        SampleLayout::Planar => {
+            let number_frames = samples.len() / channels;
+            let interleaved = (0..samples.len())
+                .map(|i| samples[(i % channels) * number_frames + i / channels])
+                .collect::<Vec<f32>>();
+            downmix(&interleaved, channels)
+        }
+    };
+    if sample_rate == SAMPLE_RATE {
+        return Ok(mono_samples);
+    }
+    let mut resampler = MonoResampler::new(sample_rate)?;
+    let mut resampled = resampler.process(&mono_samples)?;
+    resampled.extend(resampler.finish()?);
+    Ok(resampled)
+}
+
+/// Collapse interleaved samples with `num_channels` channels to mono.
+///
+/// For stereo sound, the channels are averaged and multiplied by the square
+/// root of 2, which recovers the exact behavior of ffmpeg when converting
+/// stereo to mono. Other channel counts are simply averaged.
+pub(crate) fn downmix(samples: &[f32], num_channels: usize) -> Vec<f32> {
+    match num_channels {
+        0 | 1 => samples.to_vec(),
+        2 => samples
+            .chunks_exact(2)
+            .map(|chunk| (chunk[0] + chunk[1]) * SQRT_2 / 2.)
+            .collect(),
+        _ => samples
+            .chunks_exact(num_channels)
+            .map(|chunk| chunk.iter().sum::<f32>() / num_channels as f32)
+            .collect(),
+    }
+}
+
+fn resample_error(error: impl Display) -> BlissError {
+    BlissError::DecodingError(format!("Failed to resample audio: {error}"))
+}
+
+/// Resampler turning mono samples into 22050 Hz mono samples, chunk by chunk.
+///
+/// Feeding a whole song at once or chunk by chunk gives the same result.
+pub(crate) struct MonoResampler {
+    resampler: Fft<f32>,
+    input_chunk_size: usize,
+    output_buffer: Vec<f32>,
+    // Input samples waiting for a full chunk to be available.
+    pending: Vec<f32>,
+    number_input_samples: usize,
+    // Number of samples output by the resampler, including its delay.
+    number_output_samples: usize,
+    delay: usize,
+}
+
+impl MonoResampler {
+    pub fn new(sample_rate: u32) -> BlissResult<Self> {
+        let resampler = Fft::new(
+            sample_rate as usize,
+            SAMPLE_RATE as usize,
+            CHUNK_SIZE,
+            4,
+            1,
+            FixedSync::Input,
+        )
+        .map_err(|e| BlissError::DecodingError(format!("Failed to create resampler: {e}")))?;
+        let delay = resampler.output_delay();
+        let input_chunk_size = resampler.input_frames_next();
+        // Since this is mono
+        let output_buffer = vec![0.0; resampler.output_frames_max()];
+        Ok(Self {
+            resampler,
+            input_chunk_size,
+            output_buffer,
+            pending: Vec::with_capacity(input_chunk_size),
+            number_input_samples: 0,
+            number_output_samples: 0,
+            delay,
+        })
+    }
+
+    /// The number of input samples resampled at once. Resampling a song
+    /// from a multiple of it gives the same samples as resampling it from
+    /// its beginning, once the resampler has warmed up.
+    pub fn input_chunk_size(&self) -> usize {
+        self.input_chunk_size
+    }
+
+    /// Resample `samples`, returning the samples that could be resampled
+    /// so far.
+    pub fn process(&mut self, samples: &[f32]) -> BlissResult<Vec<f32>> {
+        self.number_input_samples += samples.len();
+        let mut resampled = Vec::with_capacity(
+            (self.resampler.resample_ratio() * (samples.len() + self.pending.len()) as f64).ceil()
+                as usize,
+        );
+        let mut samples = samples;
+        if !self.pending.is_empty() {
+            let missing = (self.input_chunk_size - self.pending.len()).min(samples.len());
+            self.pending.extend_from_slice(&samples[..missing]);
+            samples = &samples[missing..];
+            if self.pending.len() < self.input_chunk_size {
+                return Ok(resampled);
+            }
+            let pending = std::mem::take(&mut self.pending);
+            self.process_chunk(&pending, None, &mut resampled)?;
+            self.pending = pending;
+            self.pending.clear();
+        }
+
+        // chunks of frames, each being CHUNKSIZE long.
+        let sample_chunks = samples.chunks_exact(self.input_chunk_size);
+        let remainder = sample_chunks.remainder();
+        for chunk in sample_chunks {
+            debug_assert!(self.resampler.input_frames_next() == self.input_chunk_size);
+            self.process_chunk(chunk, None, &mut resampled)?;
+        }
+        self.pending.extend_from_slice(remainder);
+        Ok(resampled)
+    }
+
+    /// Resample the remaining samples and flush the resampler, returning
+    /// the last resampled samples.
+    pub fn finish(mut self) -> BlissResult<Vec<f32>> {
+        let mut resampled = Vec::new();
+        // process the remainder
+        if !self.pending.is_empty() {
+            let remainder = std::mem::take(&mut self.pending);
+            let remainder_indexing = rubato::Indexing {
+                input_offset: 0,
+                output_offset: 0,
+                partial_len: Some(remainder.len()),
+                active_channels_mask: None,
+            };
+            self.process_chunk(&remainder, Some(&remainder_indexing), &mut resampled)?;
+        }
+
+        let flush_indexing = rubato::Indexing {
+            input_offset: 0,
+            output_offset: 0,
+            partial_len: Some(0),
+            active_channels_mask: None,
+        };
+
+        let expected_output_len =
+            (self.resampler.resample_ratio() * self.number_input_samples as f64).ceil() as usize;
+
+        // Flush the remaining samples
+        let padded_zeros = vec![0.0; self.input_chunk_size];
+        while self.number_output_samples < expected_output_len + self.delay {
+            self.process_chunk(&padded_zeros, Some(&flush_indexing), &mut resampled)?;
+        }
+
+        let extra_samples = self.number_output_samples - (expected_output_len + self.delay);
+        resampled.truncate(resampled.len().saturating_sub(extra_samples));
+        Ok(resampled)
+    }
+
+    // Resample one chunk, pushing the output to `resampled` without the
+    // resampler's delay.
+    fn process_chunk(
+        &mut self,
+        chunk: &[f32],
+        indexing: Option<&rubato::Indexing>,
+        resampled: &mut Vec<f32>,
+    ) -> BlissResult<()> {
+        let input = InterleavedSlice::new(chunk, 1, chunk.len()).map_err(resample_error)?;
+        let output_chunk_size = self.output_buffer.len();
+        let mut output_adapter =
+            InterleavedSlice::new_mut(&mut self.output_buffer, 1, output_chunk_size)
+                .map_err(resample_error)?;
+        let (_, output_written) = self
+            .resampler
+            .process_into_buffer(&input, &mut output_adapter, indexing)
+            .map_err(resample_error)?;
+        let skipped = self.delay.saturating_sub(self.number_output_samples);
+        self.number_output_samples += output_written;
+        if skipped < output_written {
+            resampled.extend_from_slice(&self.output_buffer[skipped..output_written]);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_downmix() {
+        assert_eq!(downmix(&[1., 2., 3.], 1), vec![1., 2., 3.]);
+        assert_eq!(downmix(&[1., 1., 0.5, -0.5], 2), vec![SQRT_2, 0.]);
+        assert_eq!(downmix(&[1., 2., 3., 4., 5., 6.], 3), vec![2., 5.]);
+    }
+
+    #[test]
+    fn test_to_bliss_samples_layouts() {
+        let left = (0..44100)
+            .map(|i| (i as f32 / 100.).sin())
+            .collect::<Vec<f32>>();
+        let right = (0..44100)
+            .map(|i| (i as f32 / 30.).cos())
+            .collect::<Vec<f32>>();
+        let interleaved = left
+            .iter()
+            .zip(&right)
+            .flat_map(|(l, r)| [*l, *r])
+            .collect::<Vec<f32>>();
+        let planar = [left, right].concat();
+
+        let from_interleaved =
+            to_bliss_samples(&interleaved, 44100, 2, SampleLayout::Interleaved).unwrap();
+        let from_planar = to_bliss_samples(&planar, 44100, 2, SampleLayout::Planar).unwrap();
+        assert_eq!(from_interleaved, from_planar);
+        assert_eq!(from_interleaved.len(), SAMPLE_RATE as usize);
+
+        // Mono samples at 22050 Hz are left untouched.
+        let samples = to_bliss_samples(&planar, SAMPLE_RATE, 1, SampleLayout::Planar).unwrap();
+        assert_eq!(samples, planar);
+    }
+
+    #[test]
+    fn test_to_bliss_samples_errors() {
+        assert_eq!(
+            to_bliss_samples(&[0.; 10], 0, 2, SampleLayout::Interleaved),
+            Err(BlissError::AnalysisError(String::from(
+                "in pcm: the sample rate should not be zero."
+            )))
+        );
+        assert_eq!(
+            to_bliss_samples(&[0.; 10], 44100, 0, SampleLayout::Interleaved),
+            Err(BlissError::AnalysisError(String::from(
+                "in pcm: the number of channels should not be zero."
+            )))
+        );
+        assert_eq!(
+            to_bliss_samples(&[0.; 10], 44100, 3, SampleLayout::Planar),
+            Err(BlissError::AnalysisError(String::from(
+                "in pcm: the number of samples is not a multiple of the number of channels."
+            )))
+        );
+    }
+}
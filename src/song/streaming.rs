@@ -0,0 +1,485 @@
+//! Streaming analysis module.
+//!
+//! Allows to compute a song's [Analysis] by feeding its samples chunk by
+//! chunk, instead of having to keep the whole decoded song in memory.
+
+use super::{
+    largest_window, Analysis, AnalysisBudget, AnalysisDiagnostics, AnalysisOptions, AnalysisOutput,
+    BoundaryDesc, ExtensionRunner, FingerprintDesc, SignalStats,
+};
+use crate::chroma::ChromaDesc;
+use crate::misc::LoudnessDesc;
+use crate::temporal::BPMDesc;
+use crate::timbral::{MfccDesc, SpectralDesc, ZeroCrossingRateDesc};
+use crate::{BlissError, BlissResult, FeaturesVersion, SAMPLE_RATE};
+
+/// Buffer keeping the samples that have not been fully consumed by a
+/// windowed descriptor yet, so windows overlapping two chunks are
+/// processed exactly as if the whole signal had been available.
+struct WindowBuffer {
+    window_size: usize,
+    hop_size: usize,
+    buffer: Vec<f32>,
+}
+
+impl WindowBuffer {
+    fn new(window_size: usize, hop_size: usize) -> Self {
+        WindowBuffer {
+            window_size,
+            hop_size,
+            buffer: Vec::with_capacity(2 * window_size),
+        }
+    }
+
+    /// Append `chunk` to the buffer, and call `process` on every complete
+    /// window, the same way `samples.windows(window_size).step_by(hop_size)`
+    /// would.
+    fn push<F>(&mut self, chunk: &[f32], mut process: F) -> BlissResult<()>
+    where
+        F: FnMut(&[f32]) -> BlissResult<()>,
+    {
+        self.buffer.extend_from_slice(chunk);
+        let mut start = 0;
+        while self.buffer.len() - start >= self.window_size {
+            process(&self.buffer[start..start + self.window_size])?;
+            start += self.hop_size;
+        }
+        self.buffer.drain(..start);
+        Ok(())
+    }
+}
+
+/// Object used to analyze a song whose samples arrive in chunks, for
+/// instance while it is being decoded.
+///
+/// Feed it chunks of any size with [StreamingAnalyzer::push], and
+/// call [StreamingAnalyzer::finish] once the whole song has been pushed to
+/// get its [Analysis]. The samples should have the same format as the ones
+/// given to [Song::analyze](crate::Song::analyze), i.e. f32, one channel,
+/// at 22050 Hz.
+///
+/// Windows spanning over two chunks are handled internally, so the tempo,
+/// zero-crossing rate, spectral, MFCC and loudness features are exactly the same
+/// as the ones computed by [Song::analyze](crate::Song::analyze), whatever
+/// the size of the chunks.
+///
+/// The chroma descriptor is the exception: to keep the memory usage bounded,
+/// the song's tuning is estimated on its first 30 seconds or so, instead of
+/// on the whole song. For songs shorter than that, all the features are
+/// the same as the batch ones, within 1e-6. For longer songs, the chroma
+/// features drift a bit: as a reference, estimating the tuning on the first
+/// second only of the test fixtures yields chroma features within 0.07 of
+/// the batch ones.
+///
+/// Contrary to [Song::analyze](crate::Song::analyze), the descriptors are
+/// computed sequentially in the calling thread.
+pub struct StreamingAnalyzer {
+    analysis_options: AnalysisOptions,
+    budget: AnalysisBudget,
+    number_samples: usize,
+
+    tempo_desc: BPMDesc,
+    tempo_buffer: WindowBuffer,
+    spectral_desc: SpectralDesc,
+    spectral_buffer: WindowBuffer,
+    loudness_desc: LoudnessDesc,
+    loudness_buffer: WindowBuffer,
+    zcr_desc: ZeroCrossingRateDesc,
+    chroma_desc: ChromaDesc,
+    mfcc_desc: MfccDesc,
+    signal_stats: SignalStats,
+    boundary_desc: BoundaryDesc,
+    fingerprint_desc: FingerprintDesc,
+    extension: Option<ExtensionRunner>,
+}
+
+impl StreamingAnalyzer {
+    /// Create a new streaming analyzer, using the default analysis options.
+    pub fn new() -> BlissResult<Self> {
+        Self::with_options(AnalysisOptions::default())
+    }
+
+    /// Create a new streaming analyzer, using the specified analysis options.
+    ///
+    /// Only the features version, the extensions, the cancellation token and
+    /// the timeout are taken into account. The timeout starts when the
+    /// analyzer is created.
+    pub fn with_options(analysis_options: AnalysisOptions) -> BlissResult<Self> {
+        Ok(StreamingAnalyzer {
+            budget: AnalysisBudget::new(&analysis_options),
+            extension: analysis_options
+                .extensions
+                .as_ref()
+                .map(ExtensionRunner::new),
+            analysis_options,
+            number_samples: 0,
+            tempo_desc: BPMDesc::new(SAMPLE_RATE)?,
+            tempo_buffer: WindowBuffer::new(BPMDesc::WINDOW_SIZE, BPMDesc::HOP_SIZE),
+            spectral_desc: SpectralDesc::new(SAMPLE_RATE)?,
+            spectral_buffer: WindowBuffer::new(SpectralDesc::WINDOW_SIZE, SpectralDesc::HOP_SIZE),
+            loudness_desc: LoudnessDesc::default(),
+            loudness_buffer: WindowBuffer::new(
+                LoudnessDesc::WINDOW_SIZE,
+                LoudnessDesc::WINDOW_SIZE,
+            ),
+            zcr_desc: ZeroCrossingRateDesc::default(),
+            chroma_desc: ChromaDesc::new_streaming(
+                SAMPLE_RATE,
+                12,
+                ChromaDesc::STREAMING_TUNING_FRAMES,
+            ),
+            mfcc_desc: MfccDesc::new_streaming(SAMPLE_RATE),
+            signal_stats: SignalStats::default(),
+            boundary_desc: BoundaryDesc::default(),
+            fingerprint_desc: FingerprintDesc::new()?,
+        })
+    }
+
+    /// Feed the next chunk of samples to the analyzer.
+    ///
+    /// Chunks can have any size, including zero.
+    ///
+    /// Fails if the analysis was cancelled, or timed out.
+    pub fn push(&mut self, chunk: &[f32]) -> BlissResult<()> {
+        self.budget.check()?;
+        self.number_samples += chunk.len();
+
+        let tempo_desc = &mut self.tempo_desc;
+        self.tempo_buffer
+            .push(chunk, |window| tempo_desc.do_(window))?;
+
+        let spectral_desc = &mut self.spectral_desc;
+        self.spectral_buffer
+            .push(chunk, |window| spectral_desc.do_(window))?;
+
+        let loudness_desc = &mut self.loudness_desc;
+        self.loudness_buffer.push(chunk, |window| {
+            loudness_desc.do_(window);
+            Ok(())
+        })?;
+
+        self.zcr_desc.do_contiguous(chunk);
+        self.chroma_desc.do_(chunk)?;
+        if self.analysis_options.features_version >= FeaturesVersion::Version3 {
+            self.mfcc_desc.do_(chunk)?;
+        }
+        self.signal_stats.do_(chunk);
+        self.boundary_desc.do_(chunk);
+        self.fingerprint_desc.do_(chunk)?;
+        if let Some(extension) = self.extension.as_mut() {
+            extension.do_(chunk)?;
+        }
+        Ok(())
+    }
+
+    /// The number of samples that have been pushed so far.
+    pub fn number_samples(&self) -> usize {
+        self.number_samples
+    }
+
+    /// Finish the analysis, and return the resulting [Analysis].
+    ///
+    /// Fails the same way [Song::analyze](crate::Song::analyze) does if
+    /// not enough samples were pushed.
+    pub fn finish(self) -> BlissResult<Analysis> {
+        self.finish_with_diagnostics().map(|(analysis, _)| analysis)
+    }
+
+    /// Same as [StreamingAnalyzer::finish], but also returns the
+    /// [AnalysisDiagnostics] gathered during the analysis, the same way
+    /// [Song::analyze_with_diagnostics](crate::Song::analyze_with_diagnostics)
+    /// does.
+    pub fn finish_with_diagnostics(self) -> BlissResult<(Analysis, AnalysisDiagnostics)> {
+        self.finish_output()
+            .map(|output| (output.analysis, output.diagnostics))
+    }
+
+    /// Finish the analysis, returning everything that was computed along
+    /// the way (diagnostics, key...).
+    pub(crate) fn finish_output(mut self) -> BlissResult<AnalysisOutput> {
+        self.budget.check()?;
+        if self.number_samples < largest_window() {
+            return Err(BlissError::AnalysisError(String::from(
+                "empty or too short song.",
+            )));
+        }
+        // The loudness descriptor also uses the last, incomplete window.
+        if !self.loudness_buffer.buffer.is_empty() {
+            self.loudness_desc.do_(&self.loudness_buffer.buffer);
+        }
+
+        let chroma = if self.analysis_options.features_version == FeaturesVersion::Version1 {
+            self.chroma_desc.get_values_version_1()?
+        } else {
+            self.chroma_desc.get_values()?
+        };
+
+        let tempo = if self.analysis_options.features_version >= FeaturesVersion::Version3 {
+            self.tempo_desc.get_corrected_value()
+        } else {
+            self.tempo_desc.get_value()
+        };
+        let mut result = vec![tempo, self.zcr_desc.get_value()];
+        result.extend_from_slice(&self.spectral_desc.get_centroid());
+        result.extend_from_slice(&self.spectral_desc.get_rolloff());
+        result.extend_from_slice(&self.spectral_desc.get_flatness());
+        result.extend_from_slice(&self.loudness_desc.get_value());
+        result.extend_from_slice(&chroma);
+        if self.analysis_options.features_version >= FeaturesVersion::Version3 {
+            result.extend_from_slice(&self.mfcc_desc.get_values()?);
+        }
+        if self.analysis_options.features_version >= FeaturesVersion::Version4 {
+            result.extend_from_slice(&self.tempo_desc.get_rhythm_values());
+        }
+        let tempo_confidence = self.tempo_desc.get_confidence();
+        let mut diagnostics = AnalysisDiagnostics::default();
+        diagnostics.check_tempo(self.tempo_desc.found_beats(), tempo_confidence);
+        diagnostics
+            .warnings
+            .extend(self.signal_stats.get_warnings());
+        let mut analysis = Analysis::new(result, self.analysis_options.features_version)?;
+        analysis.extension = self.extension.map(|e| e.finish()).transpose()?;
+        if self.analysis_options.features_version >= FeaturesVersion::Version3 {
+            analysis.tempo_confidence = Some(tempo_confidence);
+        }
+        Ok(AnalysisOutput {
+            analysis,
+            diagnostics,
+            key: self.chroma_desc.get_key()?,
+            boundaries: self.boundary_desc.get_value(),
+            fingerprint: self.fingerprint_desc.get_value()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "ffmpeg")]
+    use crate::decoder::ffmpeg::FFmpegDecoder as Decoder;
+    #[cfg(any(feature = "ffmpeg", feature = "symphonia-flac"))]
+    use crate::decoder::Decoder as DecoderTrait;
+    use crate::{AnalysisWarning, CancellationToken, Song};
+    use pretty_assertions::assert_eq;
+    #[cfg(any(feature = "ffmpeg", feature = "symphonia-flac"))]
+    use std::path::Path;
+    use std::time::Duration;
+
+    // A few seconds of a somewhat musical signal: an A4 with a decaying
+    // "beat" every half second, and some deterministic noise.
+    fn synthetic_signal() -> Vec<f32> {
+        let mut seed: u32 = 42;
+        (0..SAMPLE_RATE as usize * 5)
+            .map(|i| {
+                seed = seed.wrapping_mul(1664525).wrapping_add(1013904223);
+                let noise = (seed >> 16) as f32 / 65536. - 0.5;
+                let t = i as f32 / SAMPLE_RATE as f32;
+                let beat = (-20. * (t % 0.5)).exp();
+                0.3 * (2. * std::f32::consts::PI * 440. * t).sin() + 0.5 * beat * noise
+            })
+            .collect()
+    }
+
+    fn assert_analysis_close(expected: &Analysis, actual: &Analysis, tolerance: f32) {
+        assert_eq!(expected.features_version, actual.features_version);
+        for (x, y) in expected.as_vec().iter().zip(actual.as_vec()) {
+            assert!(tolerance > (x - y).abs(), "{expected:?} != {actual:?}");
+        }
+    }
+
+    #[test]
+    fn test_streaming_analysis_matches_batch() {
+        let signal = synthetic_signal();
+        let expected = Song::analyze(&signal).unwrap();
+        for chunk_size in [1, 100, 513, 4096, signal.len()] {
+            let mut analyzer = StreamingAnalyzer::new().unwrap();
+            for chunk in signal.chunks(chunk_size) {
+                analyzer.push(chunk).unwrap();
+            }
+            assert_eq!(signal.len(), analyzer.number_samples());
+            assert_analysis_close(&expected, &analyzer.finish().unwrap(), 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_streaming_analysis_version_1() {
+        let signal = synthetic_signal();
+        let analysis_options = AnalysisOptions {
+            features_version: FeaturesVersion::Version1,
+            ..Default::default()
+        };
+        let expected = Song::analyze_with_options(&signal, &analysis_options).unwrap();
+        let mut analyzer = StreamingAnalyzer::with_options(analysis_options).unwrap();
+        for chunk in signal.chunks(1000) {
+            analyzer.push(chunk).unwrap();
+        }
+        assert_analysis_close(&expected, &analyzer.finish().unwrap(), 1e-6);
+    }
+
+    // Counts the samples it is fed.
+    #[derive(Default)]
+    struct NumberSamples(usize);
+
+    impl crate::Descriptor for NumberSamples {
+        fn do_(&mut self, chunk: &[f32]) -> BlissResult<()> {
+            self.0 += chunk.len();
+            Ok(())
+        }
+
+        fn finish(&mut self) -> BlissResult<Vec<f32>> {
+            Ok(vec![self.0 as f32])
+        }
+
+        fn feature_names(&self) -> Vec<String> {
+            vec![String::from("number_samples")]
+        }
+    }
+
+    #[test]
+    fn test_streaming_analysis_extensions() {
+        let signal = synthetic_signal();
+        let analysis_options = AnalysisOptions {
+            extensions: Some(
+                crate::ExtensionOptions::new(4).with_descriptor(NumberSamples::default),
+            ),
+            ..Default::default()
+        };
+        let expected = Song::analyze_with_options(&signal, &analysis_options).unwrap();
+        let mut analyzer = StreamingAnalyzer::with_options(analysis_options).unwrap();
+        for chunk in signal.chunks(1000) {
+            analyzer.push(chunk).unwrap();
+        }
+        let analysis = analyzer.finish().unwrap();
+        assert_analysis_close(&expected, &analysis, 1e-6);
+        assert_eq!(expected.extension, analysis.extension);
+        assert_eq!(
+            analysis.extension,
+            Some(crate::AnalysisExtension {
+                version: 4,
+                features: vec![signal.len() as f32],
+            })
+        );
+    }
+
+    #[test]
+    fn test_streaming_analysis_cancelled() {
+        let signal = synthetic_signal();
+        let token = CancellationToken::new();
+        let mut analyzer = StreamingAnalyzer::with_options(AnalysisOptions {
+            cancellation: Some(token.clone()),
+            ..Default::default()
+        })
+        .unwrap();
+        analyzer.push(&signal[..1234]).unwrap();
+        token.cancel();
+        assert_eq!(analyzer.push(&signal[1234..]), Err(BlissError::Cancelled));
+        assert_eq!(analyzer.finish().unwrap_err(), BlissError::Cancelled);
+
+        let analyzer = StreamingAnalyzer::with_options(AnalysisOptions {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            analyzer.finish().unwrap_err(),
+            BlissError::TimedOut(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_streaming_analysis_diagnostics() {
+        let signal = synthetic_signal();
+        let expected =
+            Song::analyze_with_diagnostics(&signal, &AnalysisOptions::default()).unwrap();
+        let mut analyzer = StreamingAnalyzer::new().unwrap();
+        for chunk in signal.chunks(1234) {
+            analyzer.push(chunk).unwrap();
+        }
+        analyzer.push(&vec![0.; 2 * signal.len()]).unwrap();
+        let (_, diagnostics) = analyzer.finish_with_diagnostics().unwrap();
+        // Too short for the beat tracker to lock onto a tempo.
+        assert_eq!(expected.1.warnings, vec![AnalysisWarning::NoBeatsFound]);
+        assert_eq!(diagnostics.warnings.len(), 2);
+        assert_eq!(diagnostics.warnings[0], AnalysisWarning::NoBeatsFound);
+        assert!(matches!(
+            diagnostics.warnings[1],
+            AnalysisWarning::MostlySilent { silent_ratio } if silent_ratio > 0.66
+        ));
+    }
+
+    #[test]
+    fn test_streaming_analysis_boundaries() {
+        let signal = [vec![0.; 30000], synthetic_signal(), vec![0.; 20000]].concat();
+        let expected = Song::analyze_output(
+            &signal,
+            &AnalysisOptions::default(),
+            &AnalysisBudget::default(),
+        )
+        .unwrap();
+        let mut analyzer = StreamingAnalyzer::new().unwrap();
+        for chunk in signal.chunks(1000) {
+            analyzer.push(chunk).unwrap();
+        }
+        let boundaries = analyzer.finish_output().unwrap().boundaries;
+        assert!(boundaries.is_some());
+        assert_eq!(boundaries, expected.boundaries);
+    }
+
+    #[test]
+    fn test_streaming_analysis_fingerprint() {
+        let signal = [vec![0.; 30000], synthetic_signal()].concat();
+        let expected = Song::analyze_output(
+            &signal,
+            &AnalysisOptions::default(),
+            &AnalysisBudget::default(),
+        )
+        .unwrap();
+        let mut analyzer = StreamingAnalyzer::new().unwrap();
+        for chunk in signal.chunks(777) {
+            analyzer.push(chunk).unwrap();
+        }
+        let fingerprint = analyzer.finish_output().unwrap().fingerprint;
+        assert!(fingerprint.is_some());
+        assert_eq!(fingerprint, expected.fingerprint);
+    }
+
+    #[test]
+    fn test_streaming_analysis_too_small() {
+        let mut analyzer = StreamingAnalyzer::new().unwrap();
+        analyzer.push(&[0.; 10]).unwrap();
+        analyzer.push(&[]).unwrap();
+        assert_eq!(
+            analyzer.finish().unwrap_err(),
+            BlissError::AnalysisError(String::from("empty or too short song."))
+        );
+    }
+
+    // The song is shorter than the part of the song the chroma descriptor
+    // estimates its tuning on, so the features are the same as the batch ones.
+    #[cfg(any(feature = "ffmpeg", feature = "symphonia-flac"))]
+    fn assert_real_song_matches_batch<D: DecoderTrait>() {
+        let song = D::decode(Path::new("data/s16_mono_22_5kHz.flac")).unwrap();
+        let expected = Song::analyze(&song.sample_array).unwrap();
+        for chunk_size in [4567, SAMPLE_RATE as usize] {
+            let mut analyzer = StreamingAnalyzer::new().unwrap();
+            for chunk in song.sample_array.chunks(chunk_size) {
+                analyzer.push(chunk).unwrap();
+            }
+            assert_analysis_close(&expected, &analyzer.finish().unwrap(), 1e-6);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "ffmpeg")]
+    fn test_streaming_analysis_real_song() {
+        assert_real_song_matches_batch::<Decoder>();
+    }
+
+    #[test]
+    #[cfg(feature = "symphonia-flac")]
+    fn test_streaming_analysis_real_song_with_symphonia() {
+        use crate::decoder::symphonia::SymphoniaDecoder;
+
+        assert_real_song_matches_batch::<SymphoniaDecoder>();
+    }
+}
@@ -0,0 +1,229 @@
+//! Tags module.
+//!
+//! Holds the tags of a song besides its title, artist, album, etc., such
+//! as its MusicBrainz identifiers or its ReplayGain, read the same way by
+//! every decoder.
+
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Default, Debug, PartialEq, Clone)]
+/// The tags of a song that aren't directly stored in [Song](crate::Song),
+/// read from its metadata.
+///
+/// Contrary to free-text names, the MusicBrainz identifiers and the ISRC
+/// are stable, and can be used to tell artists, albums or recordings
+/// apart, or to find duplicates.
+pub struct Tags {
+    /// Song's year, read from its date or year tag.
+    pub year: Option<i32>,
+    /// Song's date, as written in the metadata, e.g. `2021` or
+    /// `2021-03-14`.
+    pub date: Option<String>,
+    /// Song's composer.
+    pub composer: Option<String>,
+    /// The MusicBrainz identifier of the song's recording.
+    pub musicbrainz_recording_id: Option<String>,
+    /// The MusicBrainz identifier of the song's release, i.e. its album.
+    pub musicbrainz_release_id: Option<String>,
+    /// The MusicBrainz identifier of the song's artist.
+    pub musicbrainz_artist_id: Option<String>,
+    /// The International Standard Recording Code of the song.
+    pub isrc: Option<String>,
+    /// The song's tempo, in BPM, as tagged. See
+    /// [Song::estimate_tempo](crate::Song::estimate_tempo) for the tempo
+    /// estimated by bliss.
+    pub bpm: Option<f32>,
+    /// The song's musical key, as tagged, e.g. `Am` or `8A`. See
+    /// [Song::key](crate::Song::key) for the key estimated by bliss.
+    pub initial_key: Option<String>,
+    /// The ReplayGain track gain of the song, in dB.
+    pub replay_gain_track_gain: Option<f32>,
+    /// The ReplayGain track peak of the song, as a linear amplitude.
+    pub replay_gain_track_peak: Option<f32>,
+    /// The ReplayGain album gain of the song's album, in dB.
+    pub replay_gain_album_gain: Option<f32>,
+    /// The ReplayGain album peak of the song's album, as a linear
+    /// amplitude.
+    pub replay_gain_album_peak: Option<f32>,
+    /// Whether the song has an embedded cover art.
+    pub has_cover_art: bool,
+}
+
+#[cfg(feature = "analysis")]
+impl Tags {
+    /// Set the tag named `key` to `value`, if it is one of the tags stored
+    /// here and it hasn't been set yet.
+    ///
+    /// Keys are case-insensitive, and can be the names of Vorbis comments,
+    /// ID3v2 frames, or the names FFmpeg gives to tags, so that all
+    /// decoders read the same tags. Returns whether the tag was set.
+    pub(crate) fn set(&mut self, key: &str, value: &str) -> bool {
+        let value = value.trim();
+        if value.is_empty() {
+            return false;
+        }
+        let string = || Some(value.to_owned());
+        match key.to_lowercase().as_str() {
+            "date" | "year" | "tdrc" | "tyer" | "©day" => {
+                set_if_none(&mut self.year, || parse_year(value))
+                    | set_if_none(&mut self.date, string)
+            }
+            "composer" | "tcom" | "©wrt" => set_if_none(&mut self.composer, string),
+            "musicbrainz_trackid"
+            | "musicbrainz track id"
+            | "musicbrainz_recordingid"
+            | "musicbrainz recording id" => set_if_none(&mut self.musicbrainz_recording_id, string),
+            "musicbrainz_albumid" | "musicbrainz album id" => {
+                set_if_none(&mut self.musicbrainz_release_id, string)
+            }
+            "musicbrainz_artistid" | "musicbrainz artist id" => {
+                set_if_none(&mut self.musicbrainz_artist_id, string)
+            }
+            "isrc" | "tsrc" => set_if_none(&mut self.isrc, string),
+            "bpm" | "tbpm" | "tmpo" => set_if_none(&mut self.bpm, || {
+                value.parse::<f32>().ok().filter(|bpm| *bpm > 0.)
+            }),
+            "initialkey" | "initial_key" | "tkey" | "key" => {
+                set_if_none(&mut self.initial_key, string)
+            }
+            "replaygain_track_gain" => {
+                set_if_none(&mut self.replay_gain_track_gain, || parse_gain(value))
+            }
+            "replaygain_track_peak" => {
+                set_if_none(&mut self.replay_gain_track_peak, || value.parse().ok())
+            }
+            "replaygain_album_gain" => {
+                set_if_none(&mut self.replay_gain_album_gain, || parse_gain(value))
+            }
+            "replaygain_album_peak" => {
+                set_if_none(&mut self.replay_gain_album_peak, || value.parse().ok())
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Set `field` to the value returned by `value`, if `field` isn't set
+/// yet. Returns whether `field` was set.
+#[cfg(feature = "analysis")]
+fn set_if_none<T>(field: &mut Option<T>, value: impl FnOnce() -> Option<T>) -> bool {
+    if field.is_some() {
+        return false;
+    }
+    *field = value();
+    field.is_some()
+}
+
+/// The year at the beginning of `date`, e.g. `2021` for `2021-03-14`.
+#[cfg(feature = "analysis")]
+fn parse_year(date: &str) -> Option<i32> {
+    let digits = date.chars().take_while(char::is_ascii_digit).count();
+    match digits {
+        4 => date[..4].parse().ok(),
+        _ => None,
+    }
+}
+
+/// A ReplayGain gain, e.g. `-6.48 dB`, in dB.
+#[cfg(feature = "analysis")]
+fn parse_gain(gain: &str) -> Option<f32> {
+    let gain = gain.trim();
+    let gain = gain
+        .strip_suffix("dB")
+        .or_else(|| gain.strip_suffix("db"))
+        .unwrap_or(gain);
+    gain.trim().parse().ok()
+}
+
+#[cfg(test)]
+#[cfg(feature = "analysis")]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_set_tags() {
+        let mut tags = Tags::default();
+        for (key, value) in [
+            ("DATE", "2021-03-14"),
+            ("TCOM", "A Composer"),
+            (
+                "MUSICBRAINZ_TRACKID",
+                "4b4e2bd2-2b44-4f2b-bf25-c3b0e1b8a1a6",
+            ),
+            (
+                "MusicBrainz Album Id",
+                "f1a1d0c2-6c26-4f8c-9a4e-b7f2c3c3d2f1",
+            ),
+            (
+                "musicbrainz_artistid",
+                "0383dadf-2a4e-4d10-a46a-e9e041da8eb3",
+            ),
+            ("TSRC", "USRC17607839"),
+            ("BPM", "128.5"),
+            ("INITIALKEY", "Am"),
+            ("REPLAYGAIN_TRACK_GAIN", "-6.48 dB"),
+            ("REPLAYGAIN_TRACK_PEAK", "0.988553"),
+            ("REPLAYGAIN_ALBUM_GAIN", "+1.20 dB"),
+            ("REPLAYGAIN_ALBUM_PEAK", "1.000000"),
+        ] {
+            assert!(tags.set(key, value), "key: {key}");
+        }
+        assert_eq!(
+            tags,
+            Tags {
+                year: Some(2021),
+                date: Some(String::from("2021-03-14")),
+                composer: Some(String::from("A Composer")),
+                musicbrainz_recording_id: Some(String::from(
+                    "4b4e2bd2-2b44-4f2b-bf25-c3b0e1b8a1a6"
+                )),
+                musicbrainz_release_id: Some(String::from("f1a1d0c2-6c26-4f8c-9a4e-b7f2c3c3d2f1")),
+                musicbrainz_artist_id: Some(String::from("0383dadf-2a4e-4d10-a46a-e9e041da8eb3")),
+                isrc: Some(String::from("USRC17607839")),
+                bpm: Some(128.5),
+                initial_key: Some(String::from("Am")),
+                replay_gain_track_gain: Some(-6.48),
+                replay_gain_track_peak: Some(0.988553),
+                replay_gain_album_gain: Some(1.2),
+                replay_gain_album_peak: Some(1.),
+                has_cover_art: false,
+            }
+        );
+    }
+
+    #[test]
+    fn test_set_tags_keeps_first_value() {
+        let mut tags = Tags::default();
+        assert!(tags.set("year", "1999"));
+        assert!(!tags.set("date", "2001-01-01"));
+        assert!(!tags.set("composer", " "));
+        assert!(!tags.set("bpm", "fast"));
+        assert!(!tags.set("title", "A Title"));
+        assert_eq!(
+            tags,
+            Tags {
+                year: Some(1999),
+                date: Some(String::from("1999")),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_year() {
+        assert_eq!(parse_year("2021"), Some(2021));
+        assert_eq!(parse_year("2021-03-14"), Some(2021));
+        assert_eq!(parse_year("2021-03-14T10:00:00"), Some(2021));
+        assert_eq!(parse_year("20210314"), None);
+        assert_eq!(parse_year("March 2021"), None);
+    }
+
+    #[test]
+    fn test_parse_gain() {
+        assert_eq!(parse_gain("-6.48 dB"), Some(-6.48));
+        assert_eq!(parse_gain("+1.20 dB"), Some(1.2));
+        assert_eq!(parse_gain("-3.1dB"), Some(-3.1));
+        assert_eq!(parse_gain("2.5"), Some(2.5));
+        assert_eq!(parse_gain("loud"), None);
+    }
+}
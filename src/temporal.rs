@@ -11,6 +11,7 @@ use ndarray::arr1;
 use ndarray_stats::interpolate::Midpoint;
 use ndarray_stats::Quantile1dExt;
 use noisy_float::prelude::*;
+use std::time::Duration;
 
 /**
  * Beats per minutes ([BPM](https://en.wikipedia.org/wiki/Tempo#Measurement))
@@ -27,36 +28,111 @@ use noisy_float::prelude::*;
  * Ranges from 0 (theoretically...) to 206 BPM. (Even though aubio apparently
  * has trouble to identify tempo > 190 BPM - did not investigate too much)
  *
+ * It also summarizes the song's rhythmic character from the same onset
+ * detection: how clearly its pulse stands out, how regular the strength of
+ * its beats is, and how many onsets it has per second.
+ *
  */
 #[doc(hidden)]
 pub struct BPMDesc {
     tempo: Tempo,
+    sample_rate: u32,
     bpms: Vec<f32>,
+    confidences: Vec<f32>,
+    onset_strengths: Vec<f32>,
+    // Hop at which each beat was found.
+    beat_hops: Vec<usize>,
+    number_onsets: usize,
+    last_onset: Option<usize>,
 }
 
-// TODO>1.0 use the confidence value to discard this descriptor if confidence
-// is too low.
 impl BPMDesc {
     pub const WINDOW_SIZE: usize = 512;
     pub const HOP_SIZE: usize = BPMDesc::WINDOW_SIZE / 2;
+    // Tempo around which listeners perceive the beat the most easily,
+    // used to break ties between half and double tempo candidates.
+    const PREFERRED_BPM: f32 = 120.;
+    // How much better than the tracked tempo a half or double tempo
+    // candidate must score before the tracked tempo is replaced.
+    const OCTAVE_SWITCH_RATIO: f32 = 1.2;
+    // Slowest tempo considered when looking for the song's pulse.
+    const MIN_PULSE_BPM: f32 = 40.;
+    // Number of hops around a beat in which its strength is looked for,
+    // on each side, to make up for the beat tracker's imprecision.
+    const BEAT_NEIGHBORHOOD: usize = 2;
+    // Beat strength coefficient of variation over which the beats are
+    // considered completely irregular.
+    const MAX_BEAT_STRENGTH_VARIANCE: f32 = 1.5;
+    // Number of onsets per second over which a song is considered as busy
+    // as it gets.
+    const MAX_ONSET_DENSITY: f32 = 10.;
+    // Raw beat tracker confidence over which a tempo is considered as
+    // reliable as it gets. The raw confidence is the height of the
+    // autocorrelation peak at the beat period relative to the whole
+    // autocorrelation: around 0.1 for noise, 0.3 to 0.6 for songs with a
+    // clear beat, but it can be negative or well above 1 while the tracker
+    // locks onto a tempo.
+    const MAX_CONFIDENCE: f32 = 0.5;
 
     pub fn new(sample_rate: u32) -> BlissResult<Self> {
         Ok(BPMDesc {
             tempo: Tempo::new(BPMDesc::WINDOW_SIZE, BPMDesc::HOP_SIZE, sample_rate)?,
+            sample_rate,
             bpms: Vec::new(),
+            confidences: Vec::new(),
+            onset_strengths: Vec::new(),
+            beat_hops: Vec::new(),
+            number_onsets: 0,
+            last_onset: None,
         })
     }
 
     pub fn do_(&mut self, chunk: &[f32]) -> BlissResult<()> {
         let result = self.tempo.do_(chunk)?;
+        let hop = self.onset_strengths.len();
+        self.onset_strengths.push(self.tempo.get_onset_strength());
 
         if result > 0.0 {
             let bpm = self.tempo.get_bpm();
             self.bpms.push(bpm);
+            self.confidences
+                .push(BPMDesc::normalize_confidence(self.tempo.get_confidence()));
+            self.beat_hops
+                .push((self.tempo.get_last() / BPMDesc::HOP_SIZE).min(hop));
+        }
+
+        // Same peak picking as `BeatDesc`, only counting the onsets.
+        let onset = self.tempo.get_onset();
+        if onset > 0.0 {
+            let new_onset = ((hop as f32 + onset) * BPMDesc::HOP_SIZE as f32).round() as usize;
+            let min_interval =
+                (BeatDesc::MIN_INTER_ONSET_INTERVAL * self.sample_rate as f32) as usize;
+            if self
+                .last_onset
+                .is_none_or(|last_onset| last_onset + min_interval < new_onset)
+            {
+                self.last_onset = Some(new_onset);
+                self.number_onsets += 1;
+            }
         }
         Ok(())
     }
 
+    /// Whether at least one beat was found in the samples seen so far.
+    ///
+    /// If not, [BPMDesc::get_value] returns the lowest possible tempo value.
+    pub fn found_beats(&self) -> bool {
+        !self.bpms.is_empty()
+    }
+
+    fn median(values: &[f32]) -> f32 {
+        arr1(values)
+            .mapv(n32)
+            .quantile_mut(n64(0.5), &Midpoint)
+            .unwrap()
+            .into()
+    }
+
     /**
      * Compute score related to tempo.
      * Right now, basically returns the song's BPM.
@@ -68,15 +144,168 @@ impl BPMDesc {
             warn!("Set tempo value to zero because no beats were found.");
             return -1.;
         }
-        let median = arr1(&self.bpms)
-            .mapv(n32)
-            .quantile_mut(n64(0.5), &Midpoint)
-            .unwrap();
+        let median = BPMDesc::median(&self.bpms);
+
+        self.normalize(median)
+    }
+
+    /// Same as `get_value`, but using the tempo corrected for half / double
+    /// tempo errors (see `get_tempo`).
+    pub fn get_corrected_value(&self) -> f32 {
+        match self.get_tempo() {
+            Some(tempo) => self.normalize(tempo.bpm),
+            None => {
+                warn!("Set tempo value to zero because no beats were found.");
+                -1.
+            }
+        }
+    }
 
-        self.normalize(median.into())
+    /// Scale a raw confidence of the beat tracker between 0 and 1.
+    fn normalize_confidence(confidence: f32) -> f32 {
+        (confidence / BPMDesc::MAX_CONFIDENCE).clamp(0., 1.)
+    }
+
+    /// Median confidence of the beat tracker over the song's beats, between
+    /// 0 and 1, or 0 if no beats were found.
+    ///
+    /// The higher the confidence, the more regular the beats are.
+    pub fn get_confidence(&self) -> f32 {
+        if self.confidences.is_empty() {
+            return 0.;
+        }
+        BPMDesc::median(&self.confidences)
+    }
+
+    /// The song's tempo in BPM, corrected if the beat tracker locked onto
+    /// half or double the actual tempo, along with the tracker's confidence.
+    ///
+    /// The median BPM found by the beat tracker is compared with its half
+    /// and its double, using the autocorrelation of the onset strength
+    /// at the corresponding beat periods, weighted by how likely each
+    /// tempo is to be perceived by a listener.
+    ///
+    /// Returns `None` if no beats were found.
+    pub fn get_tempo(&self) -> Option<TempoEstimate> {
+        if self.bpms.is_empty() {
+            return None;
+        }
+        let tracked_bpm = BPMDesc::median(&self.bpms);
+        let tracked_score = self.tempo_score(tracked_bpm);
+        let mut bpm = tracked_bpm;
+        let mut best_score = tracked_score * BPMDesc::OCTAVE_SWITCH_RATIO;
+        for candidate in [tracked_bpm / 2., tracked_bpm * 2.] {
+            if candidate > BPMDesc::MAX_VALUE {
+                continue;
+            }
+            let score = self.tempo_score(candidate);
+            if score > best_score {
+                bpm = candidate;
+                best_score = score;
+            }
+        }
+        Some(TempoEstimate {
+            bpm,
+            tracked_bpm,
+            confidence: self.get_confidence(),
+        })
+    }
+
+    /// The song's rhythm features, normalized: its pulse clarity, beat
+    /// strength variance and onset density, in that order.
+    pub fn get_rhythm_values(&self) -> Vec<f32> {
+        vec![
+            self.get_pulse_clarity(),
+            self.get_beat_strength_variance(),
+            self.get_onset_density(),
+        ]
+    }
+
+    // How clearly the song's pulse stands out: the highest autocorrelation
+    // of the onset strength over the beat periods of the tempos bliss can
+    // find. Close to 1 for a metronome, close to 0 for songs without a beat.
+    fn get_pulse_clarity(&self) -> f32 {
+        let hops_per_minute = 60. * self.sample_rate as f32 / BPMDesc::HOP_SIZE as f32;
+        let min_lag = (hops_per_minute / BPMDesc::MAX_VALUE).floor() as usize;
+        let max_lag = (hops_per_minute / BPMDesc::MIN_PULSE_BPM).ceil() as usize;
+        let pulse_clarity = (min_lag..=max_lag)
+            .map(|lag| autocorrelation(&self.onset_strengths, lag as f32))
+            .fold(0., f32::max);
+        2. * pulse_clarity.min(1.) - 1.
+    }
+
+    // How much the strength of the beats varies from one beat to another,
+    // using the coefficient of variation of the onset strength at each
+    // beat. Songs with less than two beats are considered as irregular as
+    // it gets.
+    fn get_beat_strength_variance(&self) -> f32 {
+        let strengths = self
+            .beat_hops
+            .iter()
+            .map(|&hop| {
+                let start = hop.saturating_sub(BPMDesc::BEAT_NEIGHBORHOOD);
+                let end = (hop + BPMDesc::BEAT_NEIGHBORHOOD + 1).min(self.onset_strengths.len());
+                self.onset_strengths[start..end]
+                    .iter()
+                    .cloned()
+                    .fold(0., f32::max)
+            })
+            .collect::<Vec<f32>>();
+        let mean = strengths.iter().sum::<f32>() / strengths.len() as f32;
+        if strengths.len() < 2 || mean <= 0. {
+            return 1.;
+        }
+        let variance =
+            strengths.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / strengths.len() as f32;
+        let coefficient = variance.sqrt() / mean;
+        (2. * coefficient / BPMDesc::MAX_BEAT_STRENGTH_VARIANCE - 1.).min(1.)
+    }
+
+    // Number of onsets per second.
+    fn get_onset_density(&self) -> f32 {
+        if self.onset_strengths.is_empty() {
+            return -1.;
+        }
+        let duration =
+            (self.onset_strengths.len() * BPMDesc::HOP_SIZE) as f32 / self.sample_rate as f32;
+        let density = self.number_onsets as f32 / duration;
+        (2. * density / BPMDesc::MAX_ONSET_DENSITY - 1.).min(1.)
+    }
+
+    // How well `bpm` explains the onset strength: its autocorrelation at
+    // the matching beat period, weighted by a log-normal prior centered on
+    // `PREFERRED_BPM`.
+    fn tempo_score(&self, bpm: f32) -> f32 {
+        let period = 60. * self.sample_rate as f32 / (BPMDesc::HOP_SIZE as f32 * bpm);
+        let prior = (-0.5 * (bpm / BPMDesc::PREFERRED_BPM).log2().powi(2)).exp();
+        prior * autocorrelation(&self.onset_strengths, period)
     }
 }
 
+// Normalized autocorrelation of `signal` at a possibly fractional `lag`,
+// interpolated linearly between the two closest integer lags.
+fn autocorrelation(signal: &[f32], lag: f32) -> f32 {
+    let mean = signal.iter().sum::<f32>() / signal.len() as f32;
+    let centered = signal.iter().map(|x| x - mean).collect::<Vec<f32>>();
+    let energy = centered.iter().map(|x| x * x).sum::<f32>();
+    if energy == 0. {
+        return 0.;
+    }
+    let at_lag = |lag: usize| {
+        if lag >= centered.len() {
+            return 0.;
+        }
+        centered
+            .iter()
+            .zip(&centered[lag..])
+            .map(|(x, y)| x * y)
+            .sum::<f32>()
+            / energy
+    };
+    let (floor, fraction) = (lag.floor() as usize, lag.fract());
+    (1. - fraction) * at_lag(floor) + fraction * at_lag(floor + 1)
+}
+
 impl Normalize for BPMDesc {
     // See aubio/src/tempo/beattracking.c:387
     // Should really be 413, needs testing
@@ -84,6 +313,130 @@ impl Normalize for BPMDesc {
     const MIN_VALUE: f32 = 0.;
 }
 
+/// A song's tempo, as estimated by
+/// [Song::estimate_tempo](crate::Song::estimate_tempo).
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct TempoEstimate {
+    /// The song's tempo, in beats per minute, after checking whether the
+    /// beat tracker locked onto half or double the actual tempo.
+    pub bpm: f32,
+    /// The median tempo found by the beat tracker, in beats per minute,
+    /// before that correction.
+    pub tracked_bpm: f32,
+    /// The median confidence of the beat tracker in its tempo estimates,
+    /// between 0 and 1. It is close to zero for songs without a clear beat,
+    /// in which case the tempo is not really meaningful, and zero if the
+    /// beat tracker never locked onto a tempo, e.g. for very short songs.
+    pub confidence: f32,
+}
+
+/// A beat found in a song by the beat tracker. See
+/// [Song::track_beats](crate::Song::track_beats).
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, PartialEq, Clone)]
+pub struct Beat {
+    /// The position of the beat, from the beginning of the song.
+    pub position: Duration,
+    /// The confidence of the beat tracker in its current tempo estimate,
+    /// when the beat was found, between 0 and 1. The higher the confidence,
+    /// the more regular the beats around that one are; it is zero while the
+    /// beat tracker hasn't locked onto a tempo yet.
+    pub confidence: f32,
+    /// The tempo estimated by the beat tracker when the beat was found,
+    /// in beats per minute. Useful to draw the tempo curve of a song.
+    pub bpm: f32,
+}
+
+/// The beats and onsets of a song, computed by
+/// [Song::track_beats](crate::Song::track_beats).
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Default, Debug, PartialEq, Clone)]
+pub struct BeatTrack {
+    /// The beats of the song, in chronological order.
+    pub beats: Vec<Beat>,
+    /// The positions of the onsets (i.e. the beginning of notes or
+    /// percussive sounds) of the song, from the beginning of the song,
+    /// in chronological order.
+    pub onsets: Vec<Duration>,
+}
+
+/**
+ * Beat and onset tracking object.
+ *
+ * Uses the same aubio tempo object as [BPMDesc], but keeps track of the
+ * position of each beat and onset instead of only summarizing the tempo.
+ */
+pub(crate) struct BeatDesc {
+    tempo: Tempo,
+    sample_rate: u32,
+    position: usize,
+    last_onset: Option<usize>,
+    beats: Vec<Beat>,
+    onsets: Vec<Duration>,
+}
+
+impl BeatDesc {
+    pub const WINDOW_SIZE: usize = BPMDesc::WINDOW_SIZE;
+    pub const HOP_SIZE: usize = BPMDesc::HOP_SIZE;
+    // Delay between an onset and its detection by the peak picker, in hops.
+    // Same default as aubio/src/onset/onset.c.
+    const ONSET_DELAY: f32 = 4.3;
+    // Minimum interval between two onsets, in seconds.
+    // Same default as aubio/src/onset/onset.c.
+    const MIN_INTER_ONSET_INTERVAL: f32 = 0.05;
+
+    pub fn new(sample_rate: u32) -> BlissResult<Self> {
+        Ok(BeatDesc {
+            tempo: Tempo::new(BeatDesc::WINDOW_SIZE, BeatDesc::HOP_SIZE, sample_rate)?,
+            sample_rate,
+            position: 0,
+            last_onset: None,
+            beats: Vec::new(),
+            onsets: Vec::new(),
+        })
+    }
+
+    fn to_duration(&self, samples: usize) -> Duration {
+        Duration::from_secs_f64(samples as f64 / self.sample_rate as f64)
+    }
+
+    pub fn do_(&mut self, chunk: &[f32]) -> BlissResult<()> {
+        if self.tempo.do_(chunk)? > 0.0 {
+            self.beats.push(Beat {
+                position: self.to_duration(self.tempo.get_last()),
+                confidence: BPMDesc::normalize_confidence(self.tempo.get_confidence()),
+                bpm: self.tempo.get_bpm(),
+            });
+        }
+
+        let onset = self.tempo.get_onset();
+        if onset > 0.0 {
+            let new_onset = self.position + (onset * BeatDesc::HOP_SIZE as f32).round() as usize;
+            let min_interval =
+                (BeatDesc::MIN_INTER_ONSET_INTERVAL * self.sample_rate as f32) as usize;
+            if self
+                .last_onset
+                .is_none_or(|last_onset| last_onset + min_interval < new_onset)
+            {
+                self.last_onset = Some(new_onset);
+                let delay = (BeatDesc::ONSET_DELAY * BeatDesc::HOP_SIZE as f32) as usize;
+                self.onsets
+                    .push(self.to_duration(new_onset.saturating_sub(delay)));
+            }
+        }
+        self.position += BeatDesc::HOP_SIZE;
+        Ok(())
+    }
+
+    pub fn get_value(self) -> BeatTrack {
+        BeatTrack {
+            beats: self.beats,
+            onsets: self.onsets,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -137,6 +490,157 @@ mod tests {
         assert!(0.01 > (-0.416853 - tempo_desc.get_value()).abs());
     }
 
+    #[test]
+    fn test_tempo_confidence_artificial() {
+        let mut tempo_desc = BPMDesc::new(22050).unwrap();
+        assert_eq!(tempo_desc.get_confidence(), 0.);
+        assert_eq!(tempo_desc.get_tempo(), None);
+        // One beat every second, so 60 BPM
+        let mut one_chunk = vec![0.; 22000];
+        one_chunk.append(&mut vec![1.; 100]);
+        let chunks = std::iter::repeat_n(one_chunk.iter(), 30)
+            .flatten()
+            .cloned()
+            .collect::<Vec<f32>>();
+        for chunk in chunks
+            .windows(BPMDesc::WINDOW_SIZE)
+            .step_by(BPMDesc::HOP_SIZE)
+        {
+            tempo_desc.do_(chunk).unwrap();
+        }
+        let tempo = tempo_desc.get_tempo().unwrap();
+        assert!(1. > (60. - tempo.tracked_bpm).abs());
+        // Nothing happens between beats, so there's no reason to double it.
+        assert_eq!(tempo.bpm, tempo.tracked_bpm);
+        assert_eq!(tempo.confidence, 1.);
+    }
+
+    // An onset strength with a peak every `period` hops.
+    fn onset_strengths(period: f32, length: usize) -> Vec<f32> {
+        (0..length)
+            .map(|i| {
+                let phase = i as f32 % period;
+                if phase < 1. || period - phase < 1. {
+                    1.
+                } else {
+                    0.
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tempo_octave_correction() {
+        let hops_per_minute = 60. * 22050. / BPMDesc::HOP_SIZE as f32;
+        let mut tempo_desc = BPMDesc::new(22050).unwrap();
+
+        // The beat tracker found 60 BPM, but there are as many onsets
+        // between the beats as on the beats.
+        tempo_desc.bpms = vec![60.];
+        tempo_desc.onset_strengths = onset_strengths(hops_per_minute / 120., 5000);
+        let tempo = tempo_desc.get_tempo().unwrap();
+        assert_eq!((tempo.tracked_bpm, tempo.bpm), (60., 120.));
+
+        // The beat tracker found 180 BPM, but only every other beat has
+        // an onset.
+        tempo_desc.bpms = vec![180.];
+        tempo_desc.onset_strengths = onset_strengths(hops_per_minute / 90., 5000);
+        let tempo = tempo_desc.get_tempo().unwrap();
+        assert_eq!((tempo.tracked_bpm, tempo.bpm), (180., 90.));
+
+        // 120 BPM is right, and doubling it would go over the maximum tempo.
+        tempo_desc.bpms = vec![120.];
+        tempo_desc.onset_strengths = onset_strengths(hops_per_minute / 120., 5000);
+        let tempo = tempo_desc.get_tempo().unwrap();
+        assert_eq!((tempo.tracked_bpm, tempo.bpm), (120., 120.));
+    }
+
+    #[test]
+    fn test_rhythm_artificial() {
+        let analyze = |samples: &[f32]| {
+            let mut tempo_desc = BPMDesc::new(22050).unwrap();
+            for chunk in samples
+                .windows(BPMDesc::WINDOW_SIZE)
+                .step_by(BPMDesc::HOP_SIZE)
+            {
+                tempo_desc.do_(chunk).unwrap();
+            }
+            tempo_desc.get_rhythm_values()
+        };
+        // One click of the same strength every second.
+        let mut one_chunk = vec![0.; 22000];
+        one_chunk.append(&mut vec![1.; 100]);
+        let clicks = std::iter::repeat_n(one_chunk.iter(), 30)
+            .flatten()
+            .cloned()
+            .collect::<Vec<f32>>();
+        let steady = analyze(&clicks);
+        assert!(steady[0] > 0.);
+        // One onset per second, so 0.1 of the maximum onset density.
+        assert!(0.05 > (-0.8 - steady[2]).abs());
+
+        // One click every half second, alternating strong and weak ones.
+        let mut one_chunk = vec![0.; 11000];
+        one_chunk.append(&mut vec![1.; 100]);
+        one_chunk.append(&mut vec![0.; 11000]);
+        one_chunk.append(&mut vec![0.2; 100]);
+        let clicks = std::iter::repeat_n(one_chunk.iter(), 30)
+            .flatten()
+            .cloned()
+            .collect::<Vec<f32>>();
+        let accented = analyze(&clicks);
+        assert!(accented[1] > steady[1]);
+        assert!(accented[2] > steady[2]);
+
+        // No pulse, no beats, no onsets.
+        assert_eq!(analyze(&vec![0.; 22050 * 30]), vec![-1., 1., -1.]);
+    }
+
+    #[test]
+    fn test_autocorrelation() {
+        assert_eq!(autocorrelation(&[1.; 100], 3.), 0.);
+        let signal = onset_strengths(10., 1000);
+        assert!(autocorrelation(&signal, 10.) > 0.9);
+        assert!(autocorrelation(&signal, 5.) < 0.);
+        assert!(autocorrelation(&signal, 10000.) == 0.);
+    }
+
+    #[test]
+    fn test_beat_tracking_artificial() {
+        let mut beat_desc = BeatDesc::new(22050).unwrap();
+        // One click every 22100 samples, so a bit more than one per second
+        let mut one_chunk = vec![0.; 22000];
+        one_chunk.append(&mut vec![1.; 100]);
+        let chunks = std::iter::repeat_n(one_chunk.iter(), 30)
+            .flatten()
+            .cloned()
+            .collect::<Vec<f32>>();
+        let windows = chunks
+            .windows(BeatDesc::WINDOW_SIZE)
+            .step_by(BeatDesc::HOP_SIZE);
+        for window in windows {
+            beat_desc.do_(window).unwrap();
+        }
+        let beat_track = beat_desc.get_value();
+
+        // Every click is an onset.
+        assert_eq!(beat_track.onsets.len(), 29);
+        for (i, onset) in beat_track.onsets.iter().enumerate() {
+            let click = (22000 + i * 22100) as f32 / 22050.;
+            assert!(0.03 > (click - onset.as_secs_f32()).abs());
+        }
+
+        // The tracker needs a few seconds to lock onto the beat.
+        assert!(beat_track.beats.len() > 20);
+        for beats in beat_track.beats.windows(2).skip(1) {
+            let interval = beats[1].position - beats[0].position;
+            assert!(0.02 > (1.0022 - interval.as_secs_f32()).abs());
+            assert!(0.5 > (60. - beats[1].bpm).abs());
+        }
+        assert_eq!(beat_track.beats[0].confidence, 0.);
+        assert_eq!(beat_track.beats.last().unwrap().confidence, 1.);
+    }
+
     #[test]
     fn test_tempo_boundaries() {
         let mut tempo_desc = BPMDesc::new(10).unwrap();
@@ -1,13 +1,14 @@
 //! Timbral feature extraction module.
 //!
 //! Contains functions to extract & summarize the zero-crossing rate,
-//! spectral centroid, spectral flatness and spectral roll-off of
-//! a given Song.
+//! spectral centroid, spectral flatness, spectral roll-off and
+//! mel-frequency cepstral coefficients of a given Song.
 
 use crate::aubio::{bin_to_freq, PVoc, SpecDesc, SpecShape};
-use ndarray::{arr1, Axis};
+use ndarray::{arr1, Array1, Array2, ArrayView1, Axis};
+use std::f64::consts::PI;
 
-use super::utils::{geometric_mean, mean, number_crossings, Normalize};
+use super::utils::{geometric_mean, mean, number_crossings, stft, Normalize, StftStream};
 use crate::{BlissError, BlissResult, SAMPLE_RATE};
 
 /**
@@ -231,6 +232,7 @@ impl Normalize for SpectralDesc {
 pub struct ZeroCrossingRateDesc {
     values: Vec<u32>,
     number_samples: usize,
+    last_sample_positive: Option<bool>,
 }
 
 impl ZeroCrossingRateDesc {
@@ -245,6 +247,27 @@ impl ZeroCrossingRateDesc {
         self.number_samples += chunk.len();
     }
 
+    /// Count the number of zero-crossings for the current `chunk`, treating
+    /// it as the continuation of the previous chunk fed with this function,
+    /// so that crossings happening right at chunk boundaries are not lost.
+    ///
+    /// Feeding a signal split in several chunks this way gives the same
+    /// result as feeding the whole signal at once with `do_`.
+    pub fn do_contiguous(&mut self, chunk: &[f32]) {
+        let Some(&first) = chunk.first() else {
+            return;
+        };
+        let mut crossings = number_crossings(chunk);
+        if let Some(was_positive) = self.last_sample_positive {
+            if was_positive != (first > 0.) {
+                crossings += 1;
+            }
+        }
+        self.last_sample_positive = chunk.last().map(|&x| x > 0.);
+        self.values.push(crossings);
+        self.number_samples += chunk.len();
+    }
+
     /// Sum the number of zero-crossings witnessed and divide by
     /// the total number of samples.
     pub fn get_value(&mut self) -> f32 {
@@ -257,6 +280,180 @@ impl Normalize for ZeroCrossingRateDesc {
     const MIN_VALUE: f32 = 0.;
 }
 
+/**
+ * [Mel-frequency cepstral coefficients](https://en.wikipedia.org/wiki/Mel-frequency_cepstrum)
+ * (MFCC) descriptor.
+ *
+ * MFCCs describe the overall shape of the spectrum of a signal on the mel
+ * scale, which is close to how pitch is perceived. They are the standard
+ * way of telling timbres apart, e.g. an acoustic guitar from a synth pad,
+ * which the spectral centroid, rolloff and flatness alone can't really do.
+ *
+ * They are computed from the song's STFT, in a similar fashion as the
+ * chroma descriptor. The first coefficient, which mostly reflects the
+ * loudness of the signal, is left out, since loudness already has its own
+ * features. Each of the other coefficients is summarized by its mean and
+ * standard deviation.
+ *
+ * A streaming mode is available through `MfccDesc::new_streaming`, which
+ * yields exactly the same values as feeding the whole song at once.
+ */
+#[doc(hidden)]
+pub struct MfccDesc {
+    mel_filter: Array2<f64>,
+    dct: Array2<f64>,
+    sums: Array1<f64>,
+    squared_sums: Array1<f64>,
+    number_frames: usize,
+    stream: Option<StftStream>,
+}
+
+impl MfccDesc {
+    pub const WINDOW_SIZE: usize = 2048;
+    pub const HOP_SIZE: usize = MfccDesc::WINDOW_SIZE / 4;
+    /// Number of mel bands the spectrum is summarized into.
+    pub const NUMBER_BANDS: usize = 40;
+    /// Number of coefficients kept, not counting the first one.
+    pub const NUMBER_COEFFICIENTS: usize = 13;
+    /// Power under which a mel band is considered silent (-100 dB).
+    const MIN_POWER: f64 = 1e-10;
+    /// Bound used to normalize the coefficients' mean. The mean of the
+    /// 1st coefficient, which is the largest one, stays around 50-140
+    /// for real-life songs, the next ones within +/-60.
+    pub const MAX_MEAN: f32 = 150.;
+    /// Upper bound used to normalize the coefficients' standard deviation,
+    /// which stays under 65 for real-life songs.
+    pub const MAX_STD_DEVIATION: f32 = 75.;
+
+    pub fn new(sample_rate: u32) -> Self {
+        MfccDesc {
+            mel_filter: mel_filter(sample_rate, MfccDesc::WINDOW_SIZE, MfccDesc::NUMBER_BANDS),
+            dct: dct_matrix(MfccDesc::NUMBER_BANDS, MfccDesc::NUMBER_COEFFICIENTS),
+            sums: Array1::zeros(MfccDesc::NUMBER_COEFFICIENTS),
+            squared_sums: Array1::zeros(MfccDesc::NUMBER_COEFFICIENTS),
+            number_frames: 0,
+            stream: None,
+        }
+    }
+
+    /// Create a MFCC descriptor in streaming mode, to which successive
+    /// chunks of a song can be fed using `do_`.
+    pub fn new_streaming(sample_rate: u32) -> Self {
+        MfccDesc {
+            stream: Some(StftStream::new(MfccDesc::WINDOW_SIZE, MfccDesc::HOP_SIZE)),
+            ..MfccDesc::new(sample_rate)
+        }
+    }
+
+    /**
+     * Compute the coefficients of each frame of `signal`.
+     *
+     * In streaming mode, `signal` is treated as the chunk following the
+     * previous ones, and can have any size.
+     */
+    pub fn do_(&mut self, signal: &[f32]) -> BlissResult<()> {
+        match self.stream.as_mut() {
+            Some(stream) => {
+                for frame in stream.push(signal) {
+                    self.add_frame(frame.view());
+                }
+            }
+            None => {
+                let spectrum = stft(signal, MfccDesc::WINDOW_SIZE, MfccDesc::HOP_SIZE);
+                for frame in spectrum.columns() {
+                    self.add_frame(frame);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /**
+     * Get the mean and standard deviation of each coefficient, interleaved,
+     * i.e. the mean of the 1st coefficient (the 0th one being left out),
+     * then its standard deviation, then the mean of the 2nd coefficient, etc.
+     *
+     * Means are normalized between -`MAX_MEAN` and `MAX_MEAN`, and standard
+     * deviations between 0 and `MAX_STD_DEVIATION`.
+     */
+    pub fn get_values(&mut self) -> BlissResult<Vec<f32>> {
+        if let Some(mut stream) = self.stream.take() {
+            let frames = stream.finish().ok_or_else(|| {
+                BlissError::AnalysisError(String::from(
+                    "in mfcc: the signal is too short to be analyzed.",
+                ))
+            })?;
+            for frame in frames {
+                self.add_frame(frame.view());
+            }
+        }
+        if self.number_frames == 0 {
+            return Err(BlissError::AnalysisError(String::from(
+                "in mfcc: tried to get values without analyzing any frame.",
+            )));
+        }
+        let means = &self.sums / self.number_frames as f64;
+        let variances = &self.squared_sums / self.number_frames as f64 - &means * &means;
+        let mut values = Vec::with_capacity(2 * MfccDesc::NUMBER_COEFFICIENTS);
+        for (mean, variance) in means.iter().zip(variances.iter()) {
+            let std_deviation = variance.max(0.).sqrt() as f32;
+            values.push((*mean as f32 / MfccDesc::MAX_MEAN).clamp(-1., 1.));
+            values.push((2. * std_deviation / MfccDesc::MAX_STD_DEVIATION - 1.).min(1.));
+        }
+        Ok(values)
+    }
+
+    fn add_frame(&mut self, magnitudes: ArrayView1<f64>) {
+        let power = magnitudes.mapv(|x| x * x);
+        let log_mel = self
+            .mel_filter
+            .dot(&power)
+            .mapv(|x| 10. * x.max(MfccDesc::MIN_POWER).log10());
+        let coefficients = self.dct.dot(&log_mel);
+        self.squared_sums += &coefficients.mapv(|x| x * x);
+        self.sums += &coefficients;
+        self.number_frames += 1;
+    }
+}
+
+// Triangular filters averaging the power of the STFT bins in `number_bands`
+// bands evenly spaced on the (HTK) mel scale, from 0 Hz to the Nyquist
+// frequency.
+fn mel_filter(sample_rate: u32, window_size: usize, number_bands: usize) -> Array2<f64> {
+    let hz_to_mel = |hz: f64| 2595. * (1. + hz / 700.).log10();
+    let mel_to_hz = |mel: f64| 700. * (10_f64.powf(mel / 2595.) - 1.);
+    let max_mel = hz_to_mel(sample_rate as f64 / 2.);
+    let edges = (0..number_bands + 2)
+        .map(|i| mel_to_hz(max_mel * i as f64 / (number_bands + 1) as f64))
+        .collect::<Vec<f64>>();
+
+    let number_bins = window_size / 2 + 1;
+    let mut filter = Array2::zeros((number_bands, number_bins));
+    for (band, mut weights) in filter.rows_mut().into_iter().enumerate() {
+        let (low, center, high) = (edges[band], edges[band + 1], edges[band + 2]);
+        for (bin, weight) in weights.iter_mut().enumerate() {
+            let frequency = bin as f64 * sample_rate as f64 / window_size as f64;
+            *weight = ((frequency - low) / (center - low))
+                .min((high - frequency) / (high - center))
+                .max(0.);
+        }
+        let sum = weights.sum();
+        if sum > 0. {
+            weights /= sum;
+        }
+    }
+    filter
+}
+
+// Orthonormal DCT-II matrix, without its first row, so that the first
+// coefficient is left out.
+fn dct_matrix(number_bands: usize, number_coefficients: usize) -> Array2<f64> {
+    let scale = (2. / number_bands as f64).sqrt();
+    Array2::from_shape_fn((number_coefficients, number_bands), |(k, n)| {
+        scale * (PI * (k + 1) as f64 * (2 * n + 1) as f64 / (2 * number_bands) as f64).cos()
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -267,6 +464,23 @@ mod tests {
     #[cfg(feature = "ffmpeg")]
     use std::path::Path;
 
+    #[test]
+    fn test_zcr_contiguous() {
+        let signal = (0..1000)
+            .map(|x| ((x as f32) * 0.3).sin())
+            .collect::<Vec<f32>>();
+        let mut zcr_desc = ZeroCrossingRateDesc::default();
+        zcr_desc.do_(&signal);
+        let expected = zcr_desc.get_value();
+
+        let mut zcr_desc = ZeroCrossingRateDesc::default();
+        for chunk in signal.chunks(7) {
+            zcr_desc.do_contiguous(chunk);
+        }
+        zcr_desc.do_contiguous(&[]);
+        assert_eq!(expected, zcr_desc.get_value());
+    }
+
     #[test]
     fn test_zcr_boundaries() {
         let mut zcr_desc = ZeroCrossingRateDesc::new(SAMPLE_RATE);
@@ -440,4 +654,58 @@ mod tests {
             assert!(0.00001 > (expected - actual).abs());
         }
     }
+
+    #[test]
+    fn test_mfcc_silence() {
+        let mut mfcc_desc = MfccDesc::new(SAMPLE_RATE);
+        mfcc_desc
+            .do_(&vec![0.; 10 * MfccDesc::WINDOW_SIZE])
+            .unwrap();
+        let values = mfcc_desc.get_values().unwrap();
+        assert_eq!(values.len(), 2 * MfccDesc::NUMBER_COEFFICIENTS);
+        for pair in values.chunks_exact(2) {
+            assert!(0.0000001 > pair[0].abs());
+            assert_eq!(pair[1], -1.);
+        }
+    }
+
+    #[test]
+    fn test_mfcc_streaming() {
+        let signal = (0..5 * SAMPLE_RATE)
+            .map(|x| (x as f32 * 0.05).sin() * 0.5 + (x as f32 * 0.7).sin() * 0.2)
+            .collect::<Vec<f32>>();
+        let mut mfcc_desc = MfccDesc::new(SAMPLE_RATE);
+        mfcc_desc.do_(&signal).unwrap();
+        let expected_values = mfcc_desc.get_values().unwrap();
+
+        let mut mfcc_desc = MfccDesc::new_streaming(SAMPLE_RATE);
+        for chunk in signal.chunks(3000) {
+            mfcc_desc.do_(chunk).unwrap();
+        }
+        let values = mfcc_desc.get_values().unwrap();
+        for (expected, actual) in expected_values.iter().zip(values.iter()) {
+            assert!(0.000001 > (expected - actual).abs());
+        }
+        // A tone is nothing like silence.
+        assert!(values.iter().skip(1).step_by(2).any(|&x| x > -1.));
+    }
+
+    #[test]
+    fn test_mfcc_empty() {
+        let mut mfcc_desc = MfccDesc::new(SAMPLE_RATE);
+        assert_eq!(
+            mfcc_desc.get_values(),
+            Err(BlissError::AnalysisError(String::from(
+                "in mfcc: tried to get values without analyzing any frame."
+            )))
+        );
+        let mut mfcc_desc = MfccDesc::new_streaming(SAMPLE_RATE);
+        mfcc_desc.do_(&[0.; 10]).unwrap();
+        assert_eq!(
+            mfcc_desc.get_values(),
+            Err(BlissError::AnalysisError(String::from(
+                "in mfcc: the signal is too short to be analyzed."
+            )))
+        );
+    }
 }
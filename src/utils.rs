@@ -2,11 +2,12 @@ extern crate rustfft;
 use ndarray::{arr1, s, Array, Array1, Array2};
 use rustfft::num_complex::Complex;
 use rustfft::num_traits::Zero;
-use rustfft::FftPlanner;
+use rustfft::{Fft, FftPlanner};
 #[cfg(feature = "ffmpeg")]
 extern crate ffmpeg_next as ffmpeg;
 use log::warn;
 use std::f32::consts::PI;
+use std::sync::Arc;
 
 pub(crate) fn reflect_pad(array: &[f32], pad: usize) -> Vec<f32> {
     let prefix = array[1..=pad].iter().rev().copied().collect::<Vec<f32>>();
@@ -31,36 +32,155 @@ pub(crate) fn stft(signal: &[f32], window_length: usize, hop_length: usize) -> A
         window_length / 2 + 1,
     ));
     let signal = reflect_pad(signal, window_length / 2);
-
-    // Periodic, so window_size + 1
-    let mut hann_window = Array::zeros(window_length + 1);
-    for n in 0..window_length {
-        hann_window[[n]] = 0.5 - 0.5 * f32::cos(2. * n as f32 * PI / (window_length as f32));
-    }
-    hann_window = hann_window.slice_move(s![0..window_length]);
-    let mut planner = FftPlanner::new();
-    let fft = planner.plan_fft_forward(window_length);
+    let stft_frame = StftFrame::new(window_length);
 
     for (window, mut stft_col) in signal
         .windows(window_length)
         .step_by(hop_length)
         .zip(stft.rows_mut())
     {
-        let mut signal = (arr1(window) * &hann_window).mapv(|x| Complex::new(x, 0.));
+        stft_col.assign(&stft_frame.process(window));
+    }
+    stft.permuted_axes((1, 0))
+}
+
+/// Compute the magnitude spectrum of single windows of a signal, the same
+/// way [stft] does for each of its frames.
+///
+/// Useful to compute a STFT one frame at a time, when the signal is
+/// streamed.
+pub(crate) struct StftFrame {
+    window_length: usize,
+    hann_window: Array1<f32>,
+    fft: Arc<dyn Fft<f32>>,
+}
+
+impl StftFrame {
+    pub(crate) fn new(window_length: usize) -> Self {
+        // Periodic, so window_size + 1
+        let mut hann_window = Array::zeros(window_length + 1);
+        for n in 0..window_length {
+            hann_window[[n]] = 0.5 - 0.5 * f32::cos(2. * n as f32 * PI / (window_length as f32));
+        }
+        hann_window = hann_window.slice_move(s![0..window_length]);
+        let mut planner = FftPlanner::new();
+        let fft = planner.plan_fft_forward(window_length);
+        StftFrame {
+            window_length,
+            hann_window,
+            fft,
+        }
+    }
+
+    /// Return the magnitude of the positive frequencies of `window`,
+    /// which has to be `window_length` long.
+    pub(crate) fn process(&self, window: &[f32]) -> Array1<f64> {
+        let mut signal = (arr1(window) * &self.hann_window).mapv(|x| Complex::new(x, 0.));
         match signal.as_slice_mut() {
-            Some(s) => fft.process(s),
+            Some(s) => self.fft.process(s),
             None => {
                 warn!("non-contiguous slice found for stft; expect slow performances.");
-                fft.process(&mut signal.to_vec());
+                self.fft.process(&mut signal.to_vec());
             }
         };
-        stft_col.assign(
-            &signal
-                .slice(s![..window_length / 2 + 1])
-                .mapv(|x| (x.re * x.re + x.im * x.im).sqrt() as f64),
-        );
+        signal
+            .slice(s![..self.window_length / 2 + 1])
+            .mapv(|x| (x.re * x.re + x.im * x.im).sqrt() as f64)
+    }
+}
+
+/// Compute the same frames as [stft], from a signal that is fed chunk by
+/// chunk, using a bounded amount of memory.
+///
+/// `hop_length` has to be smaller than `window_length / 2`.
+pub(crate) struct StftStream {
+    window_length: usize,
+    hop_length: usize,
+    number_samples: usize,
+    // Reflect-padded signal, starting at the beginning of the next STFT
+    // frame. Holds the raw signal until there are enough samples to
+    // build the padding.
+    buffer: Vec<f32>,
+    padded: bool,
+    number_frames: usize,
+    stft_frame: StftFrame,
+}
+
+impl StftStream {
+    pub(crate) fn new(window_length: usize, hop_length: usize) -> Self {
+        StftStream {
+            window_length,
+            hop_length,
+            number_samples: 0,
+            buffer: Vec::new(),
+            padded: false,
+            number_frames: 0,
+            stft_frame: StftFrame::new(window_length),
+        }
+    }
+
+    /// Feed the next `chunk` of the signal, and return the magnitude of the
+    /// frames that are now complete.
+    pub(crate) fn push(&mut self, chunk: &[f32]) -> Vec<Array1<f64>> {
+        self.number_samples += chunk.len();
+        self.buffer.extend_from_slice(chunk);
+        let pad = self.window_length / 2;
+        if !self.padded {
+            // Same padding as `reflect_pad`, which needs `pad + 1` samples.
+            if self.buffer.len() <= pad {
+                return Vec::new();
+            }
+            let mut padded = self.buffer[1..=pad]
+                .iter()
+                .rev()
+                .copied()
+                .collect::<Vec<f32>>();
+            padded.append(&mut self.buffer);
+            self.buffer = padded;
+            self.padded = true;
+        }
+        self.next_frames(usize::MAX)
+    }
+
+    /// Pad the end of the signal, and return the magnitude of the
+    /// remaining frames.
+    ///
+    /// Returns `None` if the signal was too short to be padded, i.e. if it
+    /// had `window_length / 2` samples or less.
+    pub(crate) fn finish(&mut self) -> Option<Vec<Array1<f64>>> {
+        if !self.padded {
+            return None;
+        }
+        // Same padding as `reflect_pad`, using the last samples of the signal.
+        let pad = self.window_length / 2;
+        let length = self.buffer.len();
+        let suffix = self.buffer[length - 1 - pad..length - 1]
+            .iter()
+            .rev()
+            .copied()
+            .collect::<Vec<f32>>();
+        self.buffer.extend(suffix);
+        // Same number of frames as `stft`.
+        let total_frames = self.number_samples.div_ceil(self.hop_length);
+        Some(self.next_frames(total_frames))
+    }
+
+    // Compute the magnitude of all the complete frames in the buffer,
+    // without going over `total_frames` frames overall.
+    fn next_frames(&mut self, total_frames: usize) -> Vec<Array1<f64>> {
+        let mut frames = Vec::new();
+        let mut start = 0;
+        while self.buffer.len() - start >= self.window_length && self.number_frames < total_frames {
+            frames.push(
+                self.stft_frame
+                    .process(&self.buffer[start..start + self.window_length]),
+            );
+            self.number_frames += 1;
+            start += self.hop_length;
+        }
+        self.buffer.drain(..start);
+        frames
     }
-    stft.permuted_axes((1, 0))
 }
 
 pub(crate) fn mean<T: Clone + Into<f32>>(input: &[T]) -> f32 {
@@ -540,6 +660,31 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_stft_stream() {
+        let signal = (0..20000)
+            .map(|x| (x as f32 * 0.1).sin())
+            .collect::<Vec<f32>>();
+        let expected_stft = stft(&signal, 2048, 512);
+
+        let mut stream = StftStream::new(2048, 512);
+        let mut frames = Vec::new();
+        for chunk in signal.chunks(777) {
+            frames.extend(stream.push(chunk));
+        }
+        frames.extend(stream.finish().unwrap());
+        assert_eq!(frames.len(), expected_stft.ncols());
+        for (expected, actual) in expected_stft.columns().into_iter().zip(frames.iter()) {
+            for (expected, actual) in expected.iter().zip(actual.iter()) {
+                assert!(0.0000001 > (expected - actual).abs());
+            }
+        }
+
+        let mut stream = StftStream::new(2048, 512);
+        assert!(stream.push(&[0.; 1024]).is_empty());
+        assert_eq!(stream.finish(), None);
+    }
+
     #[test]
     fn test_reflect_pad() {
         let array = Array::range(0., 100000., 1.);